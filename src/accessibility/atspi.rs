@@ -0,0 +1,455 @@
+use std::{future::Future, pin::Pin};
+
+use serde::{de::DeserializeOwned, Serialize};
+use zbus::{
+    zvariant::{DynamicType, OwnedObjectPath, OwnedValue, Type},
+    Connection,
+};
+
+use super::{AccessibleNode, ElementQuery, WindowTarget};
+use crate::state::Geometry;
+
+const REGISTRY: &str = "org.a11y.atspi.Registry";
+const ROOT_PATH: &str = "/org/a11y/atspi/accessible/root";
+const ACCESSIBLE: &str = "org.a11y.atspi.Accessible";
+const COMPONENT: &str = "org.a11y.atspi.Component";
+const ACTION: &str = "org.a11y.atspi.Action";
+
+/// Extents relative to the toplevel rather than the screen, which Wayland
+/// clients don't know their position on.
+const COORD_TYPE_WINDOW: u32 = 1;
+/// Nodes read at most per tree, so huge lists can't stall a call.
+const MAX_NODES: usize = 2000;
+
+/// Where at-spi-bus-launcher is installed across distributions.
+const LAUNCHERS: &[&str] = &[
+    "/usr/libexec/at-spi-bus-launcher",
+    "/usr/lib/at-spi2-core/at-spi-bus-launcher",
+    "/usr/lib/at-spi-bus-launcher",
+];
+
+/// AT-SPI state names, indexed by bit.
+const STATES: &[&str] = &[
+    "invalid",
+    "active",
+    "armed",
+    "busy",
+    "checked",
+    "collapsed",
+    "defunct",
+    "editable",
+    "enabled",
+    "expandable",
+    "expanded",
+    "focusable",
+    "focused",
+    "has_tooltip",
+    "horizontal",
+    "iconified",
+    "modal",
+    "multi_line",
+    "multiselectable",
+    "opaque",
+    "pressed",
+    "resizable",
+    "selectable",
+    "selected",
+    "sensitive",
+    "showing",
+    "single_line",
+    "stale",
+    "transient",
+    "vertical",
+    "visible",
+    "manages_descendants",
+    "indeterminate",
+    "required",
+    "truncated",
+    "animated",
+    "invalid_entry",
+    "supports_autocompletion",
+    "selectable_text",
+    "is_default",
+    "visited",
+    "checkable",
+    "has_popup",
+    "read_only",
+];
+
+/// An accessible object: the bus name of its app and its object path.
+type ObjectRef = (String, OwnedObjectPath);
+
+/// Makes launched apps publish their UI on the accessibility bus. GTK does by
+/// default; Qt only does when asked.
+pub fn enable_for_apps() {
+    std::env::set_var("QT_LINUX_ACCESSIBILITY_ALWAYS_ON", "1");
+    std::env::remove_var("NO_AT_BRIDGE");
+}
+
+/// Starts an AT-SPI bus for the session's apps unless one is already running,
+/// and keeps it running for as long as mcpvil does.
+pub async fn run_bus() {
+    let running = match Connection::session().await {
+        Ok(session) => session
+            .call_method(
+                Some("org.freedesktop.DBus"),
+                "/org/freedesktop/DBus",
+                Some("org.freedesktop.DBus"),
+                "NameHasOwner",
+                &("org.a11y.Bus",),
+            )
+            .await
+            .and_then(|reply| reply.body().deserialize::<bool>()),
+        Err(e) => Err(e),
+    };
+    match running {
+        Ok(true) => {
+            tracing::info!("Using the session's AT-SPI bus");
+            return;
+        }
+        Ok(false) => {}
+        Err(e) => {
+            tracing::warn!("No session bus for accessibility: {}", e);
+            return;
+        }
+    }
+
+    for launcher in LAUNCHERS {
+        let child = tokio::process::Command::new(launcher)
+            .arg("--launch-immediately")
+            .kill_on_drop(true)
+            .spawn();
+        if let Ok(mut child) = child {
+            tracing::info!("Started the AT-SPI bus with {}", launcher);
+            let _ = child.wait().await;
+            tracing::warn!("The AT-SPI bus exited");
+            return;
+        }
+    }
+    tracing::warn!("at-spi-bus-launcher not found; apps won't be accessible");
+}
+
+async fn connect() -> Result<Connection, String> {
+    let session = Connection::session()
+        .await
+        .map_err(|e| format!("Failed to connect to the session bus: {}", e))?;
+    let address: String = session
+        .call_method(
+            Some("org.a11y.Bus"),
+            "/org/a11y/bus",
+            Some("org.a11y.Bus"),
+            "GetAddress",
+            &(),
+        )
+        .await
+        .and_then(|reply| reply.body().deserialize())
+        .map_err(|e| format!("No AT-SPI bus is running: {}", e))?;
+    zbus::connection::Builder::address(address.as_str())
+        .map_err(|e| format!("Invalid AT-SPI bus address {}: {}", address, e))?
+        .build()
+        .await
+        .map_err(|e| format!("Failed to connect to the AT-SPI bus: {}", e))
+}
+
+async fn call<B, R>(
+    conn: &Connection,
+    object: &ObjectRef,
+    interface: &str,
+    method: &str,
+    body: &B,
+) -> zbus::Result<R>
+where
+    B: Serialize + DynamicType,
+    R: DeserializeOwned + Type,
+{
+    let reply = conn
+        .call_method(
+            Some(object.0.as_str()),
+            object.1.as_str(),
+            Some(interface),
+            method,
+            body,
+        )
+        .await?;
+    reply.body().deserialize()
+}
+
+async fn property<T>(
+    conn: &Connection,
+    object: &ObjectRef,
+    interface: &str,
+    name: &str,
+) -> Option<T>
+where
+    T: TryFrom<OwnedValue>,
+{
+    let value: OwnedValue = call(
+        conn,
+        object,
+        "org.freedesktop.DBus.Properties",
+        "Get",
+        &(interface, name),
+    )
+    .await
+    .ok()?;
+    T::try_from(value).ok()
+}
+
+fn parse_id(id: &str) -> Result<ObjectRef, String> {
+    let invalid = || format!("Invalid element id '{}'", id);
+    let slash = id.find('/').ok_or_else(invalid)?;
+    let path = OwnedObjectPath::try_from(&id[slash..]).map_err(|_| invalid())?;
+    Ok((id[..slash].to_string(), path))
+}
+
+fn format_id(object: &ObjectRef) -> String {
+    format!("{}{}", object.0, object.1.as_str())
+}
+
+/// The accessible application owned by `pid`.
+async fn application(conn: &Connection, pid: i32) -> Result<ObjectRef, String> {
+    let root: ObjectRef = (
+        REGISTRY.to_string(),
+        OwnedObjectPath::try_from(ROOT_PATH).expect("valid object path"),
+    );
+    let apps: Vec<ObjectRef> = call(conn, &root, ACCESSIBLE, "GetChildren", &())
+        .await
+        .map_err(|e| format!("Failed to list accessible applications: {}", e))?;
+    for app in apps {
+        let owner = conn
+            .call_method(
+                Some("org.freedesktop.DBus"),
+                "/org/freedesktop/DBus",
+                Some("org.freedesktop.DBus"),
+                "GetConnectionUnixProcessID",
+                &(app.0.as_str(),),
+            )
+            .await
+            .and_then(|reply| reply.body().deserialize::<u32>());
+        if owner.is_ok_and(|owner| owner as i32 == pid) {
+            return Ok(app);
+        }
+    }
+    Err(format!(
+        "Process {} has no accessible application; it may not support AT-SPI",
+        pid
+    ))
+}
+
+/// The application's toplevel for `window`: the one named after its title,
+/// else the active one, else the first.
+async fn toplevel(conn: &Connection, window: &WindowTarget) -> Result<ObjectRef, String> {
+    let app = application(conn, window.pid).await?;
+    let toplevels: Vec<ObjectRef> = call(conn, &app, ACCESSIBLE, "GetChildren", &())
+        .await
+        .map_err(|e| format!("Failed to list the application's windows: {}", e))?;
+
+    let mut active = None;
+    for candidate in &toplevels {
+        let name: Option<String> = property(conn, candidate, ACCESSIBLE, "Name").await;
+        if name.is_some() && name == window.title {
+            return Ok(candidate.clone());
+        }
+        if active.is_none() && states(conn, candidate).await.contains(&"active") {
+            active = Some(candidate.clone());
+        }
+    }
+    active
+        .or_else(|| toplevels.into_iter().next())
+        .ok_or_else(|| "The application has no accessible windows".to_string())
+}
+
+async fn states(conn: &Connection, object: &ObjectRef) -> Vec<&'static str> {
+    let words: Vec<u32> = call(conn, object, ACCESSIBLE, "GetState", &())
+        .await
+        .unwrap_or_default();
+    STATES
+        .iter()
+        .enumerate()
+        .filter(|(bit, _)| {
+            words
+                .get(bit / 32)
+                .is_some_and(|word| word & (1 << (bit % 32)) != 0)
+        })
+        .map(|(_, name)| *name)
+        .collect()
+}
+
+/// Reads an object's own properties, without its children.
+async fn read_node(
+    conn: &Connection,
+    object: &ObjectRef,
+    origin: (i32, i32),
+) -> zbus::Result<AccessibleNode> {
+    let role: String = call(conn, object, ACCESSIBLE, "GetRoleName", &()).await?;
+    let interfaces: Vec<String> = call(conn, object, ACCESSIBLE, "GetInterfaces", &())
+        .await
+        .unwrap_or_default();
+
+    let mut bounds = None;
+    if interfaces.iter().any(|interface| interface == COMPONENT) {
+        let extents: zbus::Result<(i32, i32, i32, i32)> =
+            call(conn, object, COMPONENT, "GetExtents", &(COORD_TYPE_WINDOW,)).await;
+        if let Ok((x, y, width, height)) = extents {
+            if width > 0 && height > 0 {
+                bounds = Some(Geometry {
+                    x: origin.0 + x,
+                    y: origin.1 + y,
+                    width,
+                    height,
+                });
+            }
+        }
+    }
+
+    let mut actions = Vec::new();
+    if interfaces.iter().any(|interface| interface == ACTION) {
+        let count: i32 = property(conn, object, ACTION, "NActions")
+            .await
+            .unwrap_or(0);
+        for i in 0..count {
+            if let Ok(name) = call::<_, String>(conn, object, ACTION, "GetName", &(i,)).await {
+                actions.push(name);
+            }
+        }
+    }
+
+    Ok(AccessibleNode {
+        id: format_id(object),
+        role,
+        name: property(conn, object, ACCESSIBLE, "Name")
+            .await
+            .unwrap_or_default(),
+        description: property(conn, object, ACCESSIBLE, "Description")
+            .await
+            .unwrap_or_default(),
+        states: states(conn, object).await,
+        bounds,
+        actions,
+        children: Vec::new(),
+    })
+}
+
+/// Reads an object and its descendants down to `depth` levels, skipping
+/// hidden ones if `showing_only`, until `budget` nodes have been read.
+fn read_tree<'a>(
+    conn: &'a Connection,
+    object: ObjectRef,
+    origin: (i32, i32),
+    depth: usize,
+    showing_only: bool,
+    budget: &'a mut usize,
+) -> Pin<Box<dyn Future<Output = zbus::Result<AccessibleNode>> + Send + 'a>> {
+    Box::pin(async move {
+        *budget = budget.saturating_sub(1);
+        let mut node = read_node(conn, &object, origin).await?;
+        if depth == 0 {
+            return Ok(node);
+        }
+        let children: Vec<ObjectRef> = call(conn, &object, ACCESSIBLE, "GetChildren", &())
+            .await
+            .unwrap_or_default();
+        for child in children {
+            if *budget == 0 {
+                break;
+            }
+            match read_tree(conn, child, origin, depth - 1, showing_only, budget).await {
+                Ok(child) if showing_only && !child.states.contains(&"showing") => {}
+                Ok(child) => node.children.push(child),
+                // Objects can go away while the tree is read
+                Err(_) => continue,
+            }
+        }
+        Ok(node)
+    })
+}
+
+pub async fn tree(
+    window: &WindowTarget,
+    max_depth: usize,
+    showing_only: bool,
+) -> Result<AccessibleNode, String> {
+    let conn = connect().await?;
+    let toplevel = toplevel(&conn, window).await?;
+    let mut budget = MAX_NODES;
+    read_tree(
+        &conn,
+        toplevel,
+        window.origin,
+        max_depth,
+        showing_only,
+        &mut budget,
+    )
+    .await
+    .map_err(|e| format!("Failed to read the accessibility tree: {}", e))
+}
+
+/// Finds the element `query` selects in the window, searching showing
+/// elements in tree order.
+pub async fn find(window: &WindowTarget, query: &ElementQuery) -> Result<AccessibleNode, String> {
+    let conn = connect().await?;
+    if let Some(id) = &query.id {
+        return read_node(&conn, &parse_id(id)?, window.origin)
+            .await
+            .map_err(|e| format!("Failed to read element {}: {}", id, e));
+    }
+
+    let mut remaining = query.index;
+    let mut pending = vec![toplevel(&conn, window).await?];
+    let mut visited = 0;
+    while let Some(object) = pending.pop() {
+        visited += 1;
+        if visited > MAX_NODES {
+            break;
+        }
+        let Ok(node) = read_node(&conn, &object, window.origin).await else {
+            continue;
+        };
+        if !node.states.contains(&"showing") && visited > 1 {
+            continue;
+        }
+        let role_matches = query
+            .role
+            .as_ref()
+            .is_none_or(|role| role.eq_ignore_ascii_case(&node.role));
+        let name_matches = query
+            .name
+            .as_ref()
+            .is_none_or(|name| name.is_match(&node.name));
+        if role_matches && name_matches && visited > 1 {
+            if remaining == 0 {
+                return Ok(node);
+            }
+            remaining -= 1;
+        }
+        let children: Vec<ObjectRef> = call(&conn, &object, ACCESSIBLE, "GetChildren", &())
+            .await
+            .unwrap_or_default();
+        pending.extend(children.into_iter().rev());
+    }
+    Err("No showing element matches".to_string())
+}
+
+/// Performs the element's first action, returning its name, or None if the
+/// element has no actions and has to be clicked instead.
+pub async fn activate(id: &str) -> Result<Option<String>, String> {
+    let conn = connect().await?;
+    let object = parse_id(id)?;
+    let count: i32 = property(&conn, &object, ACTION, "NActions")
+        .await
+        .unwrap_or(0);
+    if count == 0 {
+        return Ok(None);
+    }
+    let name: String = call(&conn, &object, ACTION, "GetName", &(0i32,))
+        .await
+        .unwrap_or_default();
+    let done: bool = call(&conn, &object, ACTION, "DoAction", &(0i32,))
+        .await
+        .map_err(|e| format!("Failed to perform '{}' on {}: {}", name, id, e))?;
+    if done {
+        Ok(Some(name))
+    } else {
+        Err(format!("{} refused to perform '{}'", id, name))
+    }
+}
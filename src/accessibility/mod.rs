@@ -0,0 +1,72 @@
+//! Semantic access to app UIs over AT-SPI, the accessibility bus GTK and Qt
+//! apps publish their widget trees on. Windows are matched to accessible
+//! applications by the PID of their Wayland client, and element bounds are
+//! translated into compositor coordinates so they can be clicked.
+
+#[cfg(feature = "accessibility")]
+mod atspi;
+
+use rmcp::schemars::{self, JsonSchema};
+use serde::Serialize;
+
+#[cfg(feature = "accessibility")]
+pub use atspi::{activate, enable_for_apps, find, run_bus, tree};
+
+/// An accessible object, as returned by query_accessibility_tree.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct AccessibleNode {
+    /// Identifies the element in click_element: its bus name followed by its object path
+    pub id: String,
+    /// AT-SPI role name, e.g. "push button", "text" or "check box"
+    pub role: String,
+    pub name: String,
+    pub description: String,
+    /// Set AT-SPI states, e.g. "focused", "checked" or "sensitive"
+    pub states: Vec<&'static str>,
+    /// Bounds in compositor coordinates, if the element has any
+    pub bounds: Option<crate::state::Geometry>,
+    /// Names of the actions the element supports, e.g. "click"
+    pub actions: Vec<String>,
+    pub children: Vec<AccessibleNode>,
+}
+
+/// What click_element matches elements by.
+pub struct ElementQuery {
+    /// Exact element id, from query_accessibility_tree
+    pub id: Option<String>,
+    /// Role name, compared case-insensitively
+    pub role: Option<String>,
+    /// Pattern matched against the element's name
+    pub name: Option<regex::Regex>,
+    /// Which of several matches to use, in tree order
+    pub index: usize,
+}
+
+/// The toplevel to search: its owner's PID, title and surface origin.
+pub struct WindowTarget {
+    pub pid: i32,
+    pub title: Option<String>,
+    pub origin: (i32, i32),
+}
+
+#[cfg(not(feature = "accessibility"))]
+const DISABLED: &str = "mcpvil was built without the accessibility feature";
+
+#[cfg(not(feature = "accessibility"))]
+pub async fn tree(
+    _window: &WindowTarget,
+    _max_depth: usize,
+    _showing_only: bool,
+) -> Result<AccessibleNode, String> {
+    Err(DISABLED.to_string())
+}
+
+#[cfg(not(feature = "accessibility"))]
+pub async fn find(_window: &WindowTarget, _query: &ElementQuery) -> Result<AccessibleNode, String> {
+    Err(DISABLED.to_string())
+}
+
+#[cfg(not(feature = "accessibility"))]
+pub async fn activate(_id: &str) -> Result<Option<String>, String> {
+    Err(DISABLED.to_string())
+}
@@ -0,0 +1,249 @@
+use image::{Rgba, RgbaImage};
+use smithay::output::Output;
+
+use crate::{
+    errors::{ErrorKind, ToolError},
+    pixel,
+    screenshot::CaptureMode,
+    state::Geometry,
+    text, Smallvil,
+};
+
+/// Color of annotations that don't set one.
+pub const DEFAULT_COLOR: Rgba<u8> = Rgba([255, 0, 0, 255]);
+
+/// Line width of boxes and arrows that don't set one, in pixels.
+pub const DEFAULT_THICKNESS: u32 = 3;
+
+/// Labels are drawn at twice the font's size.
+const LABEL_SCALE: u32 = 2;
+
+/// Space between a label's text and the edge of its box.
+const LABEL_PADDING: u32 = 3;
+
+/// A callout drawn onto a screenshot before it is saved. Positions are in
+/// pixels of the captured image.
+#[derive(Debug, Clone)]
+pub enum Annotation {
+    /// The outline of a rectangle, with an optional label above it
+    Rect {
+        rect: Geometry,
+        color: Rgba<u8>,
+        thickness: u32,
+        label: Option<String>,
+    },
+    /// Text in a box of `color`, with its top left corner at `x`, `y`
+    Label {
+        x: i32,
+        y: i32,
+        text: String,
+        color: Rgba<u8>,
+    },
+    /// A line from `from` with an arrowhead at `to`
+    Arrow {
+        from: (i32, i32),
+        to: (i32, i32),
+        color: Rgba<u8>,
+        thickness: u32,
+    },
+    /// The outline of a window, resolved to a Rect when the frame is captured
+    Window {
+        id: u64,
+        color: Rgba<u8>,
+        thickness: u32,
+        label: Option<String>,
+    },
+}
+
+/// Parses an annotation color, red if unset.
+pub fn parse_color(color: Option<&str>) -> Result<Rgba<u8>, String> {
+    match color {
+        Some(hex) => pixel::Color::from_hex(hex).map(|c| Rgba([c.r, c.g, c.b, c.a])),
+        None => Ok(DEFAULT_COLOR),
+    }
+}
+
+impl Smallvil {
+    /// Turns window annotations into rectangles in the pixels of a capture
+    /// of `output` in `mode`.
+    pub fn resolve_annotations(
+        &self,
+        output: &Output,
+        mode: CaptureMode,
+        annotations: &mut [Annotation],
+    ) -> Result<(), ToolError> {
+        for annotation in annotations {
+            let Annotation::Window {
+                id,
+                color,
+                thickness,
+                label,
+            } = annotation
+            else {
+                continue;
+            };
+            let window = self.window_by_id(*id).ok_or_else(|| {
+                ToolError::new(
+                    ErrorKind::WindowNotFound,
+                    format!("No window with id {} to annotate", id),
+                )
+            })?;
+            let rect = match mode {
+                // The capture is the window itself
+                CaptureMode::Window(captured) if captured == *id => None,
+                CaptureMode::FocusedWindow if self.focused_window().as_ref() == Some(&window) => {
+                    None
+                }
+                CaptureMode::Window(_) | CaptureMode::FocusedWindow => {
+                    return Err(ToolError::new(
+                        ErrorKind::CaptureFailed,
+                        format!("Window {} is not in a capture of another window", id),
+                    ));
+                }
+                CaptureMode::FullOutput | CaptureMode::Region(_) => {
+                    let geometry = self.space.element_geometry(&window).ok_or_else(|| {
+                        ToolError::new(
+                            ErrorKind::WindowNotFound,
+                            format!("Window {} is not mapped", id),
+                        )
+                    })?;
+                    let output_location = self
+                        .space
+                        .output_geometry(output)
+                        .map(|geometry| geometry.loc)
+                        .unwrap_or_default();
+                    // Captures are in physical pixels, regions relative to the output
+                    let scale = output.current_scale().fractional_scale();
+                    let physical = |logical: i32| (logical as f64 * scale).round() as i32;
+                    let origin = match mode {
+                        CaptureMode::Region(region) => (region.x, region.y),
+                        _ => (0, 0),
+                    };
+                    Some(Geometry {
+                        x: physical(geometry.loc.x - output_location.x) - origin.0,
+                        y: physical(geometry.loc.y - output_location.y) - origin.1,
+                        width: physical(geometry.size.w),
+                        height: physical(geometry.size.h),
+                    })
+                }
+            };
+            let rect = rect.unwrap_or(Geometry {
+                x: 0,
+                y: 0,
+                width: i32::MAX,
+                height: i32::MAX,
+            });
+            *annotation = Annotation::Rect {
+                rect,
+                color: *color,
+                thickness: *thickness,
+                label: label.take(),
+            };
+        }
+        Ok(())
+    }
+}
+
+/// Draws the annotations in order, so later ones go on top.
+pub fn draw(img: &mut RgbaImage, annotations: &[Annotation]) {
+    for annotation in annotations {
+        match annotation {
+            Annotation::Rect {
+                rect,
+                color,
+                thickness,
+                label,
+            } => {
+                // A whole-image rectangle is clamped so its outline stays visible
+                let width = rect.width.min(img.width() as i32 - rect.x).max(0) as u32;
+                let height = rect.height.min(img.height() as i32 - rect.y).max(0) as u32;
+                if width > 0 && height > 0 {
+                    let t = (*thickness).min(width / 2).min(height / 2).max(1);
+                    text::fill_rect(img, rect.x, rect.y, width, t, *color);
+                    text::fill_rect(img, rect.x, rect.y + (height - t) as i32, width, t, *color);
+                    text::fill_rect(img, rect.x, rect.y, t, height, *color);
+                    text::fill_rect(img, rect.x + (width - t) as i32, rect.y, t, height, *color);
+                }
+                if let Some(label) = label {
+                    // Above the box, or inside it when there is no room above
+                    let label_height = (text::line_height(LABEL_SCALE) + 2 * LABEL_PADDING) as i32;
+                    let y = if rect.y >= label_height {
+                        rect.y - label_height
+                    } else {
+                        rect.y.max(0)
+                    };
+                    draw_label(img, rect.x.max(0), y, label, *color);
+                }
+            }
+            Annotation::Label { x, y, text, color } => draw_label(img, *x, *y, text, *color),
+            Annotation::Arrow {
+                from,
+                to,
+                color,
+                thickness,
+            } => draw_arrow(img, *from, *to, *color, *thickness),
+            // Resolved when the frame was captured
+            Annotation::Window { .. } => {}
+        }
+    }
+}
+
+/// White text in a box of `color`.
+fn draw_label(img: &mut RgbaImage, x: i32, y: i32, label: &str, color: Rgba<u8>) {
+    let width = text::text_width(label, LABEL_SCALE) + 2 * LABEL_PADDING;
+    let height = text::line_height(LABEL_SCALE) + 2 * LABEL_PADDING;
+    text::fill_rect(img, x, y, width, height, color);
+    text::draw_text(
+        img,
+        x + LABEL_PADDING as i32,
+        y + LABEL_PADDING as i32,
+        label,
+        LABEL_SCALE,
+        Rgba([255, 255, 255, 255]),
+    );
+}
+
+/// A line with two strokes at its end forming the head.
+fn draw_arrow(
+    img: &mut RgbaImage,
+    from: (i32, i32),
+    to: (i32, i32),
+    color: Rgba<u8>,
+    thickness: u32,
+) {
+    draw_line(img, from, to, color, thickness);
+
+    let (dx, dy) = ((to.0 - from.0) as f64, (to.1 - from.1) as f64);
+    let length = dx.hypot(dy);
+    if length < 1.0 {
+        return;
+    }
+    let head = (thickness as f64 * 4.0 + 10.0).min(length);
+    let angle = dy.atan2(dx);
+    for side in [-1.0, 1.0] {
+        let a = angle + std::f64::consts::PI - side * std::f64::consts::FRAC_PI_6;
+        let end = (
+            (to.0 as f64 + head * a.cos()).round() as i32,
+            (to.1 as f64 + head * a.sin()).round() as i32,
+        );
+        draw_line(img, to, end, color, thickness);
+    }
+}
+
+/// A line `thickness` pixels wide, stamped a square at a time.
+fn draw_line(
+    img: &mut RgbaImage,
+    from: (i32, i32),
+    to: (i32, i32),
+    color: Rgba<u8>,
+    thickness: u32,
+) {
+    let (dx, dy) = (to.0 - from.0, to.1 - from.1);
+    let steps = dx.abs().max(dy.abs()).max(1);
+    let half = thickness as i32 / 2;
+    for step in 0..=steps {
+        let x = from.0 + dx * step / steps;
+        let y = from.1 + dy * step / steps;
+        text::fill_rect(img, x - half, y - half, thickness, thickness, color);
+    }
+}
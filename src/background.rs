@@ -0,0 +1,106 @@
+use smithay::{
+    backend::{
+        allocator::Fourcc,
+        renderer::{
+            element::{
+                memory::{MemoryRenderBuffer, MemoryRenderBufferRenderElement},
+                Kind,
+            },
+            ImportMem, Renderer,
+        },
+    },
+    utils::{Logical, Size, Transform},
+};
+
+use crate::{pixel::Color, Smallvil};
+
+/// Color behind all windows, unless --background or set_background says otherwise.
+const DEFAULT_COLOR: [f32; 4] = [0.1, 0.1, 0.1, 1.0];
+
+/// What is drawn where no window or layer surface covers an output: a solid
+/// color, and optionally a wallpaper stretched over each output on top of it.
+pub struct Background {
+    pub color: [f32; 4],
+    wallpaper: Option<MemoryRenderBuffer>,
+}
+
+impl Default for Background {
+    fn default() -> Self {
+        Self {
+            color: DEFAULT_COLOR,
+            wallpaper: None,
+        }
+    }
+}
+
+impl Background {
+    pub fn set_color(&mut self, color: Color) {
+        self.color = [color.r, color.g, color.b, color.a].map(|channel| channel as f32 / 255.0);
+    }
+
+    /// Loads the image at `path` as the wallpaper.
+    pub fn set_wallpaper(&mut self, path: &str) -> Result<(), String> {
+        let img = image::open(path)
+            .map_err(|e| format!("Failed to load {}: {}", path, e))?
+            .to_rgba8();
+        let size = (img.width() as i32, img.height() as i32);
+        let buffer = MemoryRenderBuffer::from_slice(
+            img.as_raw(),
+            Fourcc::Abgr8888,
+            size,
+            1,
+            Transform::Normal,
+            None,
+        );
+        self.wallpaper = Some(buffer);
+        Ok(())
+    }
+
+    pub fn clear_wallpaper(&mut self) {
+        self.wallpaper = None;
+    }
+
+    /// The wallpaper stretched over an output of `output_size`, if one is set.
+    pub fn element<R>(
+        &self,
+        renderer: &mut R,
+        output_size: Size<i32, Logical>,
+    ) -> Option<MemoryRenderBufferRenderElement<R>>
+    where
+        R: Renderer + ImportMem,
+        R::TextureId: Send + Clone + 'static,
+    {
+        let wallpaper = self.wallpaper.as_ref()?;
+        MemoryRenderBufferRenderElement::from_buffer(
+            renderer,
+            (0.0, 0.0),
+            wallpaper,
+            None,
+            None,
+            Some(output_size),
+            Kind::Unspecified,
+        )
+        .map_err(|e| tracing::warn!("Failed to upload the wallpaper: {:?}", e))
+        .ok()
+    }
+}
+
+impl Smallvil {
+    /// Changes the background color and/or wallpaper. A color alone removes
+    /// the wallpaper.
+    pub fn set_background(
+        &mut self,
+        color: Option<Color>,
+        image_path: Option<&str>,
+    ) -> Result<(), String> {
+        match image_path {
+            Some(path) => self.background.set_wallpaper(path)?,
+            None => self.background.clear_wallpaper(),
+        }
+        if let Some(color) = color {
+            self.background.set_color(color);
+        }
+        self.request_frame();
+        Ok(())
+    }
+}
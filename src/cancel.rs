@@ -0,0 +1,92 @@
+//! Timeouts and cancellation of tool calls. Each call gets until
+//! `--command-timeout` plus the time its arguments ask for to answer; when it
+//! runs out, or the client cancels the request, the call is dropped. Commands
+//! it sent that the event loop hasn't handled yet are skipped, captures and
+//! waits nobody is waiting for anymore are dropped, and input sequences and
+//! replays stop before their next step.
+
+use std::{
+    future::Future,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use crate::Smallvil;
+
+/// How long a tool call may take besides the time it asks for, unless set
+/// with `--command-timeout`.
+pub const DEFAULT_COMMAND_TIMEOUT: Duration = Duration::from_secs(30);
+
+tokio::task_local! {
+    static CALL: ToolCall;
+}
+
+/// A tool call as seen by the commands it sends, so the event loop can tell
+/// when nobody waits for their replies anymore.
+#[derive(Debug, Clone, Default)]
+pub struct ToolCall(Arc<AtomicBool>);
+
+impl ToolCall {
+    /// Runs `f` as this call, for `current` to find.
+    pub async fn scope<F: Future>(self, f: F) -> F::Output {
+        CALL.scope(self, f).await
+    }
+
+    /// The call being handled, or one that never ends outside of tool calls.
+    pub fn current() -> Self {
+        CALL.try_with(Self::clone).unwrap_or_default()
+    }
+
+    pub fn abandon(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether the call was cancelled or timed out.
+    pub fn is_abandoned(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// The time a call's arguments ask for: every duration in milliseconds,
+/// e.g. `timeout_ms`, `duration_ms` or the `ms` of a sequence's wait steps,
+/// and capture_gif's `duration` in seconds, added up.
+pub fn requested_time(arguments: &serde_json::Value) -> Duration {
+    match arguments {
+        serde_json::Value::Object(fields) => fields
+            .iter()
+            .map(|(name, value)| match (name.as_str(), value.as_u64()) {
+                ("ms", Some(ms)) => Duration::from_millis(ms),
+                (name, Some(ms)) if name.ends_with("_ms") => Duration::from_millis(ms),
+                ("duration", _) => value
+                    .as_f64()
+                    .and_then(|secs| Duration::try_from_secs_f64(secs).ok())
+                    .unwrap_or_default(),
+                _ => requested_time(value),
+            })
+            .fold(Duration::ZERO, Duration::saturating_add),
+        serde_json::Value::Array(values) => values
+            .iter()
+            .map(requested_time)
+            .fold(Duration::ZERO, Duration::saturating_add),
+        _ => Duration::ZERO,
+    }
+}
+
+impl Smallvil {
+    /// Drops pending captures, launches and waits whose caller went away,
+    /// so they don't keep frames coming or pile up.
+    pub fn drop_abandoned_requests(&mut self) {
+        self.pending_screenshots
+            .retain(|(_, response_tx)| !response_tx.is_closed());
+        self.pending_launches
+            .retain(|_, response_tx| !response_tx.is_closed());
+        self.window_waits
+            .retain(|_, wait| !wait.response_tx.is_closed());
+        self.idle_waits.retain(|wait| !wait.response_tx.is_closed());
+        self.stability_waits
+            .retain(|wait| !wait.response_tx.is_closed());
+    }
+}
@@ -0,0 +1,312 @@
+use smithay::{
+    backend::{
+        allocator::Fourcc,
+        renderer::{
+            damage::OutputDamageTracker,
+            element::{surface::WaylandSurfaceRenderElement, AsRenderElements, RenderElement},
+            ExportMem,
+        },
+    },
+    desktop::Window,
+    output::Output,
+    utils::{Logical, Physical, Point, Rectangle, Scale, Size, Transform},
+};
+
+use crate::{
+    cursor::{draw_cursor, CursorImage},
+    errors::{ErrorKind, ToolError},
+    render::OffscreenRenderer,
+    screenshot::{self, CaptureMode},
+    transform, Smallvil,
+};
+
+/// Serves pending screenshots and screencopy frames of `output` and feeds the
+/// recording, screencasts, stability waits and frame history from a
+/// just-rendered frame.
+/// Shared by every backend, called while `framebuffer` is still bound.
+pub fn capture_frame<R>(
+    state: &mut Smallvil,
+    renderer: &mut R,
+    framebuffer: &R::Framebuffer<'_>,
+    output: &Output,
+    size: Size<i32, Physical>,
+    flipped: bool,
+) where
+    R: OffscreenRenderer,
+    R::TextureId: Clone + 'static,
+{
+    let is_primary = state.primary_output() == Some(output);
+    let (pending, other_outputs): (Vec<_>, Vec<_>) = std::mem::take(&mut state.pending_screenshots)
+        .into_iter()
+        .partition(|(options, _)| {
+            options
+                .output
+                .as_deref()
+                .map_or(is_primary, |name| name == output.name())
+        });
+    state.pending_screenshots = other_outputs;
+    let (screencopies, other_outputs): (Vec<_>, Vec<_>) =
+        std::mem::take(&mut state.pending_screencopies)
+            .into_iter()
+            .partition(|copy| copy.output() == Some(output));
+    state.pending_screencopies = other_outputs;
+
+    // Read the frame back once for any pending captures and the recording
+    let recording_wants_frame = is_primary
+        && state
+            .recording
+            .as_ref()
+            .is_some_and(|recording| recording.wants_frame());
+    let screencast_wants_frame = state.screencasts.wants_frame(output);
+    let stability_wants_frame = state.wants_stability_frame(output, is_primary);
+    let history_wants_frame = is_primary
+        && state
+            .frame_history
+            .as_ref()
+            .is_some_and(|history| history.wants_frame());
+    if !pending.is_empty()
+        || !screencopies.is_empty()
+        || recording_wants_frame
+        || screencast_wants_frame
+        || stability_wants_frame
+        || history_wants_frame
+    {
+        // Screencopy and screencasts hand clients the buffer as is, along with the
+        // output's transform; everything else sees the screen the right way up
+        let buffer = read_framebuffer(renderer, framebuffer, size, flipped);
+        let output_transform = output.current_transform();
+        let upright = match &buffer {
+            Ok(img) if output_transform != Transform::Normal => {
+                Some(Ok(transform::upright(img, output_transform)))
+            }
+            _ => None,
+        };
+        let frame = upright.as_ref().unwrap_or(&buffer);
+
+        if stability_wants_frame {
+            if let Ok(img) = frame.as_ref() {
+                state.feed_stability_waits(output, is_primary, img);
+            }
+        }
+
+        if screencast_wants_frame {
+            if let Ok(img) = buffer.as_ref() {
+                state.screencasts.push_frame(output, img);
+            }
+        }
+
+        if recording_wants_frame {
+            if let (Some(recording), Ok(img)) = (state.recording.as_mut(), frame.as_ref()) {
+                recording.push_frame(img);
+            }
+        }
+
+        if history_wants_frame {
+            let uptime = state.start_time.elapsed();
+            if let (Some(history), Ok(img)) = (state.frame_history.as_mut(), frame.as_ref()) {
+                history.push_frame(img, uptime);
+            }
+        }
+
+        for copy in screencopies {
+            match buffer.as_ref() {
+                Ok(img) => copy.complete(img),
+                Err(_) => copy.frame.failed(),
+            }
+        }
+
+        // The cursor isn't part of the rendered frame, so it is drawn on request
+        let output_scale = output.current_scale().fractional_scale();
+        let cursor = if pending.iter().any(|(options, _)| options.include_cursor) {
+            state.cursor_image(renderer, output_scale)
+        } else {
+            None
+        };
+        let pointer_location = state.seat.get_pointer().unwrap().current_location();
+        let output_location = state
+            .space
+            .output_geometry(output)
+            .map(|geometry| geometry.loc)
+            .unwrap_or_default();
+
+        for (mut options, response_tx) in pending {
+            let span = options.span.clone().unwrap_or_else(tracing::Span::current);
+            let _span =
+                tracing::info_span!(parent: &span, "capture_screenshot", output = %output.name())
+                    .entered();
+            if let Some(requested_at) = options.requested_at {
+                state.record_screenshot(requested_at);
+            }
+            let cursor = cursor.as_ref().filter(|_| options.include_cursor);
+            let output_frame = || {
+                frame.clone().map(|mut img| {
+                    if let Some(cursor) = cursor {
+                        let position = (pointer_location - output_location.to_f64())
+                            .to_physical(output_scale)
+                            .to_i32_round();
+                        draw_cursor(&mut img, cursor, position);
+                    }
+                    img
+                })
+            };
+            let capture_failed = |e| ToolError::new(ErrorKind::CaptureFailed, e);
+            let img = match options.mode {
+                CaptureMode::FullOutput => output_frame()
+                    .map(image::DynamicImage::ImageRgba8)
+                    .map_err(capture_failed),
+                CaptureMode::Region(region) => output_frame()
+                    .and_then(|img| {
+                        screenshot::crop_to_region(image::DynamicImage::ImageRgba8(img), region)
+                    })
+                    .map_err(capture_failed),
+                CaptureMode::FocusedWindow => match state.focused_window() {
+                    Some(window) => {
+                        capture_window(state, renderer, &window, cursor, pointer_location)
+                            .map(image::DynamicImage::ImageRgba8)
+                            .map_err(capture_failed)
+                    }
+                    None => Err(ToolError::new(
+                        ErrorKind::WindowNotFound,
+                        "No window has keyboard focus",
+                    )),
+                },
+                CaptureMode::Window(id) => match state.window_by_id(id) {
+                    Some(window) => {
+                        capture_window(state, renderer, &window, cursor, pointer_location)
+                            .map(image::DynamicImage::ImageRgba8)
+                            .map_err(capture_failed)
+                    }
+                    None => Err(ToolError::window_not_found(id)),
+                },
+            };
+            let img = img.and_then(|img| {
+                state.resolve_annotations(output, options.mode, &mut options.annotations)?;
+                Ok(img)
+            });
+            screenshot::deliver_in_background(img, options, response_tx);
+        }
+    }
+}
+
+/// Reads back the rendered frame as an upright RGBA image. `flipped` is set for
+/// window-system framebuffers, whose origin is at the bottom left.
+fn read_framebuffer<R: ExportMem>(
+    renderer: &mut R,
+    framebuffer: &R::Framebuffer<'_>,
+    size: Size<i32, Physical>,
+    flipped: bool,
+) -> Result<image::RgbaImage, String> {
+    let region = Rectangle::from_size((size.w, size.h).into());
+
+    let mapping = renderer
+        .copy_framebuffer(framebuffer, region, Fourcc::Abgr8888)
+        .map_err(|e| format!("Failed to copy framebuffer: {}", e))?;
+
+    let pixels = renderer
+        .map_texture(&mapping)
+        .map_err(|e| format!("Failed to map texture: {}", e))?;
+
+    let width = mapping.width();
+    let height = mapping.height();
+
+    // Create image from raw pixels and flip vertically if needed
+    // (OpenGL framebuffer origin is bottom-left)
+    let mut img = image::RgbaImage::from_raw(width, height, pixels.to_vec())
+        .ok_or_else(|| "Failed to create image from pixel data".to_string())?;
+    if flipped {
+        image::imageops::flip_vertical_in_place(&mut img);
+    }
+
+    Ok(img)
+}
+
+/// Renders a window on its own, with `cursor` drawn at the pointer location if
+/// given.
+fn capture_window<R>(
+    state: &Smallvil,
+    renderer: &mut R,
+    window: &Window,
+    cursor: Option<&CursorImage>,
+    pointer_location: Point<f64, Logical>,
+) -> Result<image::RgbaImage, String>
+where
+    R: OffscreenRenderer,
+    R::TextureId: Clone + 'static,
+{
+    let mut img = render_window(renderer, window)?;
+    let origin = state
+        .space
+        .element_geometry(window)
+        .map(|geometry| geometry.loc);
+    if let (Some(cursor), Some(origin)) = (cursor, origin) {
+        let position = (pointer_location - origin.to_f64())
+            .to_physical(1.0)
+            .to_i32_round();
+        draw_cursor(&mut img, cursor, position);
+    }
+    Ok(img)
+}
+
+/// Renders a single window into an offscreen texture and reads it back, so it can
+/// be captured on its own even when other windows overlap it.
+fn render_window<R>(renderer: &mut R, window: &Window) -> Result<image::RgbaImage, String>
+where
+    R: OffscreenRenderer,
+    R::TextureId: Clone + 'static,
+{
+    let geometry = window.geometry();
+    if geometry.size.w <= 0 || geometry.size.h <= 0 {
+        return Err("Window has not been sized yet".to_string());
+    }
+    let size = geometry.size.to_physical(1);
+
+    // Offset by the geometry origin so client-side shadows fall outside the capture
+    let elements: Vec<WaylandSurfaceRenderElement<R>> = AsRenderElements::<R>::render_elements(
+        window,
+        renderer,
+        (-geometry.loc).to_physical(1),
+        Scale::from(1.0),
+        1.0,
+    );
+
+    render_offscreen(renderer, &elements, size)
+}
+
+/// Renders `elements` into a transparent offscreen texture of `size` and reads
+/// it back.
+pub fn render_offscreen<R, E>(
+    renderer: &mut R,
+    elements: &[E],
+    size: Size<i32, Physical>,
+) -> Result<image::RgbaImage, String>
+where
+    R: OffscreenRenderer,
+    E: RenderElement<R>,
+{
+    let mut texture = renderer
+        .create_buffer(Fourcc::Abgr8888, (size.w, size.h).into())
+        .map_err(|e| format!("Failed to create offscreen buffer: {}", e))?;
+    let mut target = renderer
+        .bind(&mut texture)
+        .map_err(|e| format!("Failed to bind offscreen buffer: {}", e))?;
+
+    let mut damage_tracker = OutputDamageTracker::new(size, 1.0, Transform::Normal);
+    damage_tracker
+        .render_output(renderer, &mut target, 0, elements, [0.0, 0.0, 0.0, 0.0])
+        .map_err(|e| format!("Failed to render offscreen: {}", e))?;
+
+    let mapping = renderer
+        .copy_framebuffer(
+            &target,
+            Rectangle::from_size((size.w, size.h).into()),
+            Fourcc::Abgr8888,
+        )
+        .map_err(|e| format!("Failed to copy offscreen buffer: {}", e))?;
+
+    let pixels = renderer
+        .map_texture(&mapping)
+        .map_err(|e| format!("Failed to map texture: {}", e))?;
+
+    image::RgbaImage::from_raw(mapping.width(), mapping.height(), pixels.to_vec())
+        .ok_or_else(|| "Failed to create image from pixel data".to_string())
+}
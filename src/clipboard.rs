@@ -0,0 +1,266 @@
+use std::{
+    io::{Read, Write},
+    os::fd::OwnedFd,
+    sync::Arc,
+};
+
+use smithay::{
+    input::pointer::GrabStartData,
+    reexports::wayland_server::protocol::wl_data_device_manager::DndAction,
+    utils::{Logical, Point, SERIAL_COUNTER},
+    wayland::selection::{
+        data_device::{
+            current_data_device_selection_userdata, request_data_device_client_selection,
+            set_data_device_selection, start_dnd, SourceMetadata,
+        },
+        primary_selection::{
+            current_primary_selection_userdata, request_primary_client_selection,
+            set_primary_selection,
+        },
+        SelectionTarget,
+    },
+};
+
+use crate::Smallvil;
+
+/// MIME types offered for text set through `set_clipboard` and
+/// `set_primary_selection`, including the X11
+/// atoms Xwayland clients look for.
+pub const TEXT_MIME_TYPES: &[&str] = &[
+    "text/plain;charset=utf-8",
+    "text/plain",
+    "UTF8_STRING",
+    "STRING",
+    "TEXT",
+];
+
+pub const PNG_MIME_TYPE: &str = "image/png";
+
+/// Selection contents read back from the selection owner.
+#[derive(Debug, Clone)]
+pub struct ClipboardData {
+    pub mime_type: String,
+    pub data: Vec<u8>,
+}
+
+pub type ClipboardResponse = tokio::sync::oneshot::Sender<Result<ClipboardData, String>>;
+
+/// A selection owned by the compositor itself: the same bytes are served for
+/// every offered MIME type.
+#[derive(Debug, Clone)]
+pub struct CompositorSelection {
+    pub mime_types: Vec<String>,
+    pub data: Arc<[u8]>,
+}
+
+/// A drag-and-drop offered by the compositor itself, carried along a drag
+/// started by the `dnd` tool.
+pub struct PendingDnd {
+    pub mime_types: Vec<String>,
+    pub actions: DndAction,
+    pub data: Arc<[u8]>,
+    pub outcome: DndOutcome,
+    pub outcome_tx: Option<DndResponse>,
+}
+
+/// How the drop target responded to a compositor drag-and-drop.
+#[derive(Debug, Clone, Default)]
+pub struct DndOutcome {
+    /// Last MIME type the target accepted, if any
+    pub accepted: Option<String>,
+    /// Action the target settled on
+    pub action: Option<DndAction>,
+    /// MIME types the target read the payload as
+    pub transferred: Vec<String>,
+    /// Whether the target finished the drop, as opposed to it being cancelled
+    pub completed: bool,
+}
+
+pub type DndResponse = tokio::sync::oneshot::Sender<DndOutcome>;
+
+impl Smallvil {
+    /// Takes over the clipboard or primary selection, offering `data` under each
+    /// of `mime_types`.
+    pub fn set_selection(
+        &mut self,
+        target: SelectionTarget,
+        mime_types: Vec<String>,
+        data: Vec<u8>,
+    ) {
+        let selection = CompositorSelection {
+            mime_types: mime_types.clone(),
+            data: data.into(),
+        };
+        match target {
+            SelectionTarget::Clipboard => {
+                set_data_device_selection(&self.display_handle, &self.seat, mime_types, selection)
+            }
+            SelectionTarget::Primary => {
+                set_primary_selection(&self.display_handle, &self.seat, mime_types, selection)
+            }
+        }
+    }
+
+    /// Reads a selection as `mime_type`, or the best offered type if unset.
+    /// Client-owned selections are read from a pipe on a separate thread, so the
+    /// reply arrives once the owning client has written its data.
+    pub fn get_selection(
+        &mut self,
+        target: SelectionTarget,
+        mime_type: Option<String>,
+        response_tx: ClipboardResponse,
+    ) {
+        let compositor_selection = match target {
+            SelectionTarget::Clipboard => current_data_device_selection_userdata(&self.seat),
+            SelectionTarget::Primary => current_primary_selection_userdata(&self.seat),
+        };
+        if let Some(selection) = compositor_selection {
+            let result =
+                pick_mime_type(&selection.mime_types, mime_type).map(|mime_type| ClipboardData {
+                    mime_type,
+                    data: selection.data.to_vec(),
+                });
+            let _ = response_tx.send(result);
+            return;
+        }
+
+        let offered = match target {
+            SelectionTarget::Clipboard => &self.clipboard_mime_types,
+            SelectionTarget::Primary => &self.primary_mime_types,
+        };
+        let mime_type = match pick_mime_type(offered, mime_type) {
+            Ok(mime_type) => mime_type,
+            Err(e) => {
+                let _ = response_tx.send(Err(e));
+                return;
+            }
+        };
+
+        let (mut reader, writer) = match std::io::pipe() {
+            Ok(pipe) => pipe,
+            Err(e) => {
+                let _ = response_tx.send(Err(format!("Failed to create pipe: {}", e)));
+                return;
+            }
+        };
+        let requested = match target {
+            SelectionTarget::Clipboard => {
+                request_data_device_client_selection(&self.seat, mime_type.clone(), writer.into())
+                    .map_err(|e| format!("{:?}", e))
+            }
+            SelectionTarget::Primary => {
+                request_primary_client_selection(&self.seat, mime_type.clone(), writer.into())
+                    .map_err(|e| format!("{:?}", e))
+            }
+        };
+        if let Err(e) = requested {
+            let _ = response_tx.send(Err(format!("Failed to request selection: {}", e)));
+            return;
+        }
+        // Make sure the owner sees the request before we block on the pipe
+        let _ = self.display_handle.flush_clients();
+
+        std::thread::spawn(move || {
+            let mut data = Vec::new();
+            let result = reader
+                .read_to_end(&mut data)
+                .map(|_| ClipboardData { mime_type, data })
+                .map_err(|e| format!("Failed to read selection: {}", e));
+            let _ = response_tx.send(result);
+        });
+    }
+
+    /// Serves a paste of the compositor-owned selection to a client.
+    pub fn send_compositor_selection(
+        &self,
+        ty: SelectionTarget,
+        fd: OwnedFd,
+        selection: CompositorSelection,
+    ) {
+        // The client may read slowly; don't block the event loop on a full pipe
+        std::thread::spawn(move || {
+            if let Err(e) = std::fs::File::from(fd).write_all(&selection.data) {
+                tracing::warn!("Failed to send {:?} selection: {}", ty, e);
+            }
+        });
+    }
+
+    /// Starts the pending compositor drag-and-drop at `location`. The pointer
+    /// grab it installs sends enter/motion/drop to whatever client is under the
+    /// pointer as the drag moves, until `button` is released.
+    pub fn start_server_dnd(&mut self, location: Point<f64, Logical>, button: u32) {
+        let Some(dnd) = self.pending_dnd.as_ref() else {
+            return;
+        };
+        let metadata = SourceMetadata {
+            mime_types: dnd.mime_types.clone(),
+            dnd_action: dnd.actions,
+        };
+        let start_data = GrabStartData {
+            focus: self.surface_under(location),
+            button,
+            location,
+        };
+
+        let display_handle = self.display_handle.clone();
+        let seat = self.seat.clone();
+        start_dnd(
+            &display_handle,
+            &seat,
+            self,
+            SERIAL_COUNTER.next_serial(),
+            start_data,
+            metadata,
+        );
+    }
+
+    /// Serves the drag-and-drop payload to a drop target reading it as `mime_type`.
+    pub fn send_dnd_data(&mut self, mime_type: String, fd: OwnedFd) {
+        let Some(dnd) = self.pending_dnd.as_mut() else {
+            return;
+        };
+        dnd.outcome.transferred.push(mime_type);
+
+        let data = dnd.data.clone();
+        std::thread::spawn(move || {
+            if let Err(e) = std::fs::File::from(fd).write_all(&data) {
+                tracing::warn!("Failed to send drag-and-drop data: {}", e);
+            }
+        });
+    }
+
+    /// Reports the outcome of the compositor drag-and-drop once the target has
+    /// finished with it or it was cancelled.
+    pub fn finish_dnd(&mut self, completed: bool) {
+        let Some(mut dnd) = self.pending_dnd.take() else {
+            return;
+        };
+        dnd.outcome.completed = completed;
+        if let Some(outcome_tx) = dnd.outcome_tx.take() {
+            let _ = outcome_tx.send(dnd.outcome);
+        }
+    }
+}
+
+/// Picks `requested` if it is offered, otherwise the first offered text type,
+/// then PNG, then whatever comes first.
+fn pick_mime_type(offered: &[String], requested: Option<String>) -> Result<String, String> {
+    if offered.is_empty() {
+        return Err("The selection is empty".to_string());
+    }
+
+    match requested {
+        Some(requested) if offered.contains(&requested) => Ok(requested),
+        Some(requested) => Err(format!(
+            "Selection has no '{}', available: {}",
+            requested,
+            offered.join(", ")
+        )),
+        None => Ok(TEXT_MIME_TYPES
+            .iter()
+            .chain([&PNG_MIME_TYPE])
+            .find(|preferred| offered.iter().any(|o| o == *preferred))
+            .map(|preferred| preferred.to_string())
+            .unwrap_or_else(|| offered[0].clone())),
+    }
+}
@@ -0,0 +1,180 @@
+use std::{
+    collections::HashMap,
+    io::{Read, Write},
+    os::unix::process::{CommandExt, ExitStatusExt},
+    process::{Command, Stdio},
+    time::{Duration, Instant},
+};
+
+use rmcp::schemars::{self, JsonSchema};
+use serde::Serialize;
+
+/// Output kept per stream; the rest is dropped.
+const OUTPUT_LIMIT: usize = 1024 * 1024;
+
+/// How often a running command is checked for having exited.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// How long the output is read for after the command exited, at least, even
+/// if it was killed at the timeout.
+const READ_GRACE: Duration = Duration::from_millis(500);
+
+/// A short shell command run to completion by run_command.
+#[derive(Debug, Clone)]
+pub struct RunOptions {
+    /// Passed to `sh -c`
+    pub command: String,
+    /// Set on top of mcpvil's own environment
+    pub env: HashMap<String, String>,
+    pub cwd: Option<String>,
+    /// Written to the command's stdin, which is then closed
+    pub stdin: Option<String>,
+    pub timeout: Duration,
+}
+
+/// What a command printed and how it ended.
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct CommandOutput {
+    /// Exit code, if the command exited normally
+    pub exit_code: Option<i32>,
+    /// Signal that terminated the command, if any
+    pub signal: Option<i32>,
+    /// The command was still running at the timeout and was killed
+    pub timed_out: bool,
+    pub stdout: String,
+    pub stderr: String,
+    /// Output beyond 1 MiB per stream was dropped
+    pub truncated: bool,
+    pub duration_ms: u64,
+}
+
+/// Runs a command through the shell and waits for it, killing it and
+/// everything it started once `timeout` has passed. Blocks, so call it off
+/// the event loop.
+pub fn run(options: RunOptions) -> Result<CommandOutput, String> {
+    let start = Instant::now();
+    let mut cmd = Command::new("sh");
+    cmd.arg("-c")
+        .arg(&options.command)
+        .envs(&options.env)
+        .stdin(if options.stdin.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        // Its own process group, so the whole pipeline can be killed at the timeout
+        .process_group(0);
+    if let Some(cwd) = &options.cwd {
+        cmd.current_dir(cwd);
+    }
+    // Every mcpvil thread blocks SIGCHLD; don't hand that down
+    unsafe {
+        cmd.pre_exec(|| {
+            let mut set: libc::sigset_t = std::mem::zeroed();
+            libc::sigemptyset(&mut set);
+            libc::pthread_sigmask(libc::SIG_SETMASK, &set, std::ptr::null_mut());
+            Ok(())
+        });
+    }
+
+    let mut child = cmd
+        .spawn()
+        .map_err(|e| format!("Failed to run {}: {}", options.command, e))?;
+    if let (Some(mut pipe), Some(input)) = (child.stdin.take(), options.stdin) {
+        std::thread::spawn(move || {
+            let _ = pipe.write_all(input.as_bytes());
+        });
+    }
+    let stdout = child.stdout.take().map(read_capped);
+    let stderr = child.stderr.take().map(read_capped);
+
+    let deadline = start + options.timeout;
+    let mut timed_out = false;
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break status,
+            Ok(None) if Instant::now() >= deadline => {
+                timed_out = true;
+                unsafe { libc::kill(-(child.id() as i32), libc::SIGKILL) };
+                break child
+                    .wait()
+                    .map_err(|e| format!("Failed to wait for the command: {}", e))?;
+            }
+            Ok(None) => std::thread::sleep(POLL_INTERVAL),
+            Err(e) => return Err(format!("Failed to wait for the command: {}", e)),
+        }
+    };
+
+    // Background jobs it started may hold the pipes open; don't wait on them
+    // past the timeout, or past the grace period once it has passed
+    let read_deadline = deadline.max(Instant::now() + READ_GRACE);
+    let readers = [&stdout, &stderr];
+    while Instant::now() < read_deadline
+        && readers
+            .iter()
+            .any(|reader| reader.as_ref().is_some_and(|reader| !reader.is_finished()))
+    {
+        std::thread::sleep(POLL_INTERVAL);
+    }
+    let collect = |reader: Option<std::thread::JoinHandle<(Vec<u8>, bool)>>| {
+        reader
+            .filter(|reader| reader.is_finished())
+            .and_then(|reader| reader.join().ok())
+            .unwrap_or_default()
+    };
+    let (stdout, stdout_truncated) = collect(stdout);
+    let (stderr, stderr_truncated) = collect(stderr);
+    Ok(CommandOutput {
+        exit_code: status.code(),
+        signal: status.signal(),
+        timed_out,
+        stdout: String::from_utf8_lossy(&stdout).into_owned(),
+        stderr: String::from_utf8_lossy(&stderr).into_owned(),
+        truncated: stdout_truncated || stderr_truncated,
+        duration_ms: start.elapsed().as_millis() as u64,
+    })
+}
+
+/// Reads a pipe to the end on its own thread, keeping the first
+/// OUTPUT_LIMIT bytes. Returns them and whether any were dropped.
+fn read_capped(mut pipe: impl Read + Send + 'static) -> std::thread::JoinHandle<(Vec<u8>, bool)> {
+    std::thread::spawn(move || {
+        let mut output = Vec::new();
+        let mut truncated = false;
+        let mut buf = [0; 4096];
+        while let Ok(n) = pipe.read(&mut buf) {
+            if n == 0 {
+                break;
+            }
+            let room = OUTPUT_LIMIT - output.len();
+            truncated |= n > room;
+            output.extend_from_slice(&buf[..n.min(room)]);
+        }
+        (output, truncated)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(command: &str, timeout: Duration) -> RunOptions {
+        RunOptions {
+            command: command.to_string(),
+            env: HashMap::new(),
+            cwd: None,
+            stdin: None,
+            timeout,
+        }
+    }
+
+    #[test]
+    fn output_survives_timeout() {
+        let output = run(options("echo before; sleep 5", Duration::from_millis(100))).unwrap();
+        assert!(output.timed_out);
+        assert_eq!(output.signal, Some(libc::SIGKILL));
+        assert_eq!(output.stdout, "before\n");
+    }
+}
@@ -0,0 +1,113 @@
+use image::{Rgba, RgbaImage};
+use rmcp::schemars::{self, JsonSchema};
+use serde::Serialize;
+
+use crate::state::Geometry;
+
+/// Outcome of comparing a capture against a baseline image.
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct Comparison {
+    /// Whether the differing fraction is within the threshold
+    pub passed: bool,
+    /// Fraction of pixels that differ, 0.0-1.0
+    pub score: f64,
+    pub differing_pixels: u64,
+    pub total_pixels: u64,
+    /// Largest per-channel difference found, 0-255
+    pub max_difference: u8,
+    /// Bounding box of the differing pixels, if any
+    pub diff_bounds: Option<Geometry>,
+    /// Where the diff image was saved
+    pub diff_path: String,
+    /// MCP resource URI of the diff image
+    pub diff_uri: Option<String>,
+}
+
+/// Compares an encoded capture against the image at `baseline_path`, pixel by
+/// pixel. A pixel differs when any channel differs by more than `tolerance`,
+/// which absorbs antialiasing and dithering noise. Saves an image of the
+/// baseline dimmed to gray with the differing pixels in red to `diff_path`.
+pub fn compare(
+    capture: &[u8],
+    baseline_path: &str,
+    diff_path: &str,
+    tolerance: u8,
+    threshold: f64,
+) -> Result<Comparison, String> {
+    let baseline = image::open(baseline_path)
+        .map_err(|e| format!("Failed to load baseline {}: {}", baseline_path, e))?
+        .to_rgba8();
+    let capture = image::load_from_memory(capture)
+        .map_err(|e| format!("Failed to decode capture: {}", e))?
+        .to_rgba8();
+    if baseline.dimensions() != capture.dimensions() {
+        return Err(format!(
+            "Baseline is {}x{} but the capture is {}x{}",
+            baseline.width(),
+            baseline.height(),
+            capture.width(),
+            capture.height()
+        ));
+    }
+
+    let mut diff = RgbaImage::new(baseline.width(), baseline.height());
+    let mut differing_pixels = 0u64;
+    let mut max_difference = 0u8;
+    let mut bounds: Option<(u32, u32, u32, u32)> = None;
+    for (x, y, expected) in baseline.enumerate_pixels() {
+        let actual = capture.get_pixel(x, y);
+        let difference = expected
+            .0
+            .iter()
+            .zip(actual.0.iter())
+            .map(|(a, b)| a.abs_diff(*b))
+            .max()
+            .unwrap_or(0);
+        max_difference = max_difference.max(difference);
+        if difference > tolerance {
+            differing_pixels += 1;
+            diff.put_pixel(x, y, Rgba([255, 0, 0, 255]));
+            bounds = Some(match bounds {
+                Some((x1, y1, x2, y2)) => (x1.min(x), y1.min(y), x2.max(x), y2.max(y)),
+                None => (x, y, x, y),
+            });
+        } else {
+            let [r, g, b, _] = expected.0;
+            let luma = ((r as u32 * 299 + g as u32 * 587 + b as u32 * 114) / 1000) as u8;
+            let dimmed = 128 + luma / 4;
+            diff.put_pixel(x, y, Rgba([dimmed, dimmed, dimmed, 255]));
+        }
+    }
+    diff.save(diff_path)
+        .map_err(|e| format!("Failed to save diff image to {}: {}", diff_path, e))?;
+
+    let total_pixels = baseline.width() as u64 * baseline.height() as u64;
+    let score = if total_pixels == 0 {
+        0.0
+    } else {
+        differing_pixels as f64 / total_pixels as f64
+    };
+    Ok(Comparison {
+        passed: score <= threshold,
+        score,
+        differing_pixels,
+        total_pixels,
+        max_difference,
+        diff_bounds: bounds.map(|(x1, y1, x2, y2)| Geometry {
+            x: x1 as i32,
+            y: y1 as i32,
+            width: (x2 - x1 + 1) as i32,
+            height: (y2 - y1 + 1) as i32,
+        }),
+        diff_path: diff_path.to_string(),
+        diff_uri: None,
+    })
+}
+
+/// The default diff path: next to the baseline, with a `.diff.png` suffix.
+pub fn default_diff_path(baseline_path: &str) -> String {
+    let path = std::path::Path::new(baseline_path);
+    path.with_extension("diff.png")
+        .to_string_lossy()
+        .into_owned()
+}
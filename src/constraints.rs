@@ -0,0 +1,120 @@
+use smithay::{
+    input::pointer::{MotionEvent, PointerHandle, RelativeMotionEvent},
+    reexports::wayland_server::protocol::wl_surface::WlSurface,
+    utils::{Logical, Point, SERIAL_COUNTER},
+    wayland::pointer_constraints::{with_pointer_constraint, PointerConstraint},
+};
+
+use crate::{input::PointerTarget, macros::MacroEvent, Smallvil};
+
+impl Smallvil {
+    /// Moves the pointer by `delta` the way a mouse does, sending relative
+    /// motion to the surface under it. A locked pointer stays where it is and a
+    /// confined one doesn't leave its region, as games and 3D apps expect.
+    /// Returns what ends up under the cursor.
+    pub fn relative_motion(&mut self, delta: Point<f64, Logical>) -> PointerTarget {
+        let pointer = self.seat.get_pointer().unwrap();
+        let location = pointer.current_location();
+        let under = self.surface_under(location);
+
+        let mut new_location = self.clamp_to_outputs(location + delta);
+        if let Some((surface, origin)) = &under {
+            let target = self.surface_under(new_location);
+            with_pointer_constraint(surface, &pointer, |constraint| {
+                let Some(constraint) = constraint.filter(|c| c.is_active()) else {
+                    return;
+                };
+                let stays = match &*constraint {
+                    PointerConstraint::Locked(_) => false,
+                    PointerConstraint::Confined(confined) => {
+                        target.as_ref().is_some_and(|(s, _)| s == surface)
+                            && confined.region().is_none_or(|region| {
+                                region.contains((new_location - *origin).to_i32_round())
+                            })
+                    }
+                };
+                if !stays {
+                    new_location = location;
+                }
+            });
+        }
+
+        let serial = SERIAL_COUNTER.next_serial();
+        let time = self.start_time.elapsed();
+        if new_location != location {
+            let under = self.surface_under(new_location);
+            self.record_input(MacroEvent::Motion(new_location));
+            pointer.motion(
+                self,
+                under,
+                &MotionEvent {
+                    location: new_location,
+                    serial,
+                    time: time.as_millis() as u32,
+                },
+            );
+        }
+        let under = self.surface_under(new_location);
+        pointer.relative_motion(
+            self,
+            under,
+            &RelativeMotionEvent {
+                delta,
+                delta_unaccel: delta,
+                utime: time.as_micros() as u64,
+            },
+        );
+        pointer.frame(self);
+
+        self.activate_pointer_constraint();
+        self.pointer_target()
+    }
+
+    /// Activates the constraint of the surface under the pointer once the
+    /// pointer is inside its region.
+    pub fn activate_pointer_constraint(&mut self) {
+        let pointer = self.seat.get_pointer().unwrap();
+        let location = pointer.current_location();
+        let Some((surface, origin)) = self.surface_under(location) else {
+            return;
+        };
+
+        with_pointer_constraint(&surface, &pointer, |constraint| {
+            let Some(constraint) = constraint.filter(|c| !c.is_active()) else {
+                return;
+            };
+            let inside = constraint
+                .region()
+                .is_none_or(|region| region.contains((location - origin).to_i32_round()));
+            if inside {
+                constraint.activate();
+            }
+        });
+    }
+
+    /// Moves the cursor to where the client says it is when releasing a locked
+    /// pointer, so it reappears where the app last drew it.
+    pub fn set_cursor_position_hint(
+        &mut self,
+        surface: &WlSurface,
+        pointer: &PointerHandle<Self>,
+        location: Point<f64, Logical>,
+    ) {
+        let active = with_pointer_constraint(surface, pointer, |constraint| {
+            constraint.is_some_and(|c| c.is_active())
+        });
+        let origin = self
+            .space
+            .elements()
+            .find(|window| window.wl_surface().as_deref() == Some(surface))
+            .and_then(|window| {
+                self.space
+                    .element_location(window)
+                    .map(|loc| loc - window.geometry().loc)
+            });
+
+        if let (true, Some(origin)) = (active, origin) {
+            pointer.set_location(origin.to_f64() + location);
+        }
+    }
+}
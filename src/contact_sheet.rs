@@ -0,0 +1,70 @@
+use image::{imageops, Rgba, RgbaImage};
+
+use crate::{state::WindowInfo, text};
+
+/// Space around and between tiles.
+const PADDING: u32 = 8;
+
+/// Labels are drawn at twice the font's size.
+const LABEL_SCALE: u32 = 2;
+
+const BACKGROUND: Rgba<u8> = Rgba([32, 32, 32, 255]);
+const LABEL_COLOR: Rgba<u8> = Rgba([255, 255, 255, 255]);
+
+/// `img` scaled down to fit within `max_size` on both sides; smaller images
+/// are left alone.
+pub fn thumbnail(img: &RgbaImage, max_size: u32) -> RgbaImage {
+    let (width, height) = img.dimensions();
+    if width <= max_size && height <= max_size {
+        return img.clone();
+    }
+    let scale = max_size as f64 / width.max(height) as f64;
+    let width = ((width as f64 * scale).round() as u32).max(1);
+    let height = ((height as f64 * scale).round() as u32).max(1);
+    imageops::thumbnail(img, width, height)
+}
+
+/// "#<id> <title>" for the tile of a window.
+pub fn label(window: &WindowInfo) -> String {
+    match window.title.as_deref().or(window.app_id.as_deref()) {
+        Some(name) if !name.is_empty() => format!("#{} {}", window.id, name),
+        _ => format!("#{}", window.id),
+    }
+}
+
+/// Tiles thumbnails of at most `tile_size` pixels in a grid of `columns`,
+/// left to right and top to bottom, each under its label.
+pub fn contact_sheet(tiles: &[(String, RgbaImage)], tile_size: u32, columns: u32) -> RgbaImage {
+    let columns = columns.clamp(1, tiles.len().max(1) as u32);
+    let rows = (tiles.len() as u32).div_ceil(columns).max(1);
+    let label_height = text::line_height(LABEL_SCALE) + PADDING / 2;
+    let cell_width = tile_size + PADDING;
+    let cell_height = label_height + tile_size + PADDING;
+    let mut sheet = RgbaImage::from_pixel(
+        columns * cell_width + PADDING,
+        rows * cell_height + PADDING,
+        BACKGROUND,
+    );
+
+    for (index, (label, img)) in tiles.iter().enumerate() {
+        let index = index as u32;
+        let x = PADDING + (index % columns) * cell_width;
+        let y = PADDING + (index / columns) * cell_height;
+        let label = text::truncate(label, tile_size, LABEL_SCALE);
+        text::draw_text(
+            &mut sheet,
+            x as i32,
+            y as i32,
+            &label,
+            LABEL_SCALE,
+            LABEL_COLOR,
+        );
+
+        // Centered in the space below the label
+        let thumbnail = thumbnail(img, tile_size);
+        let left = x + (tile_size - thumbnail.width()) / 2;
+        let top = y + label_height + (tile_size - thumbnail.height()) / 2;
+        imageops::overlay(&mut sheet, &thumbnail, left as i64, top as i64);
+    }
+    sheet
+}
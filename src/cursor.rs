@@ -0,0 +1,203 @@
+use std::collections::HashMap;
+
+use image::RgbaImage;
+use smithay::{
+    backend::renderer::element::{
+        surface::{render_elements_from_surface_tree, WaylandSurfaceRenderElement},
+        Kind,
+    },
+    desktop::utils::bbox_from_surface_tree,
+    input::pointer::{CursorIcon, CursorImageStatus, CursorImageSurfaceData},
+    utils::{Physical, Point, Scale},
+    wayland::compositor::with_states,
+};
+
+use crate::{capture, render::OffscreenRenderer, Smallvil};
+
+/// Nominal cursor size at scale 1, when XCURSOR_SIZE isn't set.
+const DEFAULT_CURSOR_SIZE: u32 = 24;
+
+/// Fallback arrow for when no cursor theme is installed: `#` is the outline,
+/// `.` the fill.
+const FALLBACK_ARROW: &[&str] = &[
+    "#",
+    "##",
+    "#.#",
+    "#..#",
+    "#...#",
+    "#....#",
+    "#.....#",
+    "#......#",
+    "#.......#",
+    "#........#",
+    "#.........#",
+    "#......####",
+    "#...#..#",
+    "#..##..#",
+    "#.#  #..#",
+    "##   #..#",
+    "#     #..#",
+    "      #..#",
+    "       ##",
+];
+
+/// A cursor image and the point in it that sits on the pointer location.
+#[derive(Clone)]
+pub struct CursorImage {
+    pub image: RgbaImage,
+    pub hotspot: Point<i32, Physical>,
+}
+
+/// Cursor images loaded from an XCursor theme, by default the one named by
+/// XCURSOR_THEME at XCURSOR_SIZE.
+pub struct CursorTheme {
+    name: String,
+    /// Nominal size at scale 1
+    size: u32,
+    /// Images by icon name and pixel size
+    cache: HashMap<(&'static str, u32), CursorImage>,
+}
+
+impl Default for CursorTheme {
+    fn default() -> Self {
+        Self {
+            name: std::env::var("XCURSOR_THEME").unwrap_or_else(|_| "default".to_string()),
+            size: std::env::var("XCURSOR_SIZE")
+                .ok()
+                .and_then(|size| size.parse().ok())
+                .unwrap_or(DEFAULT_CURSOR_SIZE),
+            cache: HashMap::new(),
+        }
+    }
+}
+
+impl CursorTheme {
+    /// Switches to another theme and size, and exports them to XCURSOR_THEME
+    /// and XCURSOR_SIZE so apps launched afterwards draw the same cursors.
+    pub fn configure(&mut self, name: Option<String>, size: Option<u32>) {
+        if let Some(name) = name {
+            self.name = name;
+        }
+        if let Some(size) = size {
+            self.size = size;
+        }
+        self.cache.clear();
+        std::env::set_var("XCURSOR_THEME", &self.name);
+        std::env::set_var("XCURSOR_SIZE", self.size.to_string());
+    }
+
+    /// The theme's image for `icon` at `scale`, falling back to the default
+    /// arrow and then to a built-in one.
+    pub fn named(&mut self, icon: CursorIcon, scale: f64) -> CursorImage {
+        let size = (self.size as f64 * scale).round() as u32;
+        let name = &self.name;
+        self.cache
+            .entry((icon.name(), size))
+            .or_insert_with(|| {
+                let theme = xcursor::CursorTheme::load(name);
+                std::iter::once(icon.name())
+                    .chain(icon.alt_names().iter().copied())
+                    .chain(["default", "left_ptr"])
+                    .find_map(|name| load_xcursor(&theme, name, size))
+                    .unwrap_or_else(|| fallback_arrow(scale))
+            })
+            .clone()
+    }
+}
+
+fn load_xcursor(theme: &xcursor::CursorTheme, name: &str, size: u32) -> Option<CursorImage> {
+    let data = std::fs::read(theme.load_icon(name)?).ok()?;
+    let images = xcursor::parser::parse_xcursor(&data)?;
+    // Themes ship several sizes; animated cursors use their first frame
+    let image = images
+        .iter()
+        .min_by_key(|image| image.size.abs_diff(size))?;
+    Some(CursorImage {
+        image: RgbaImage::from_raw(image.width, image.height, image.pixels_rgba.clone())?,
+        hotspot: (image.xhot as i32, image.yhot as i32).into(),
+    })
+}
+
+fn fallback_arrow(scale: f64) -> CursorImage {
+    let factor = scale.round().max(1.0) as u32;
+    let width = FALLBACK_ARROW
+        .iter()
+        .map(|row| row.len())
+        .max()
+        .unwrap_or(0) as u32;
+    let mut image = RgbaImage::new(width * factor, FALLBACK_ARROW.len() as u32 * factor);
+    for (y, row) in FALLBACK_ARROW.iter().enumerate() {
+        for (x, cell) in row.chars().enumerate() {
+            let color = match cell {
+                '#' => image::Rgba([0, 0, 0, 255]),
+                '.' => image::Rgba([255, 255, 255, 255]),
+                _ => continue,
+            };
+            for dy in 0..factor {
+                for dx in 0..factor {
+                    image.put_pixel(x as u32 * factor + dx, y as u32 * factor + dy, color);
+                }
+            }
+        }
+    }
+    CursorImage {
+        image,
+        hotspot: (0, 0).into(),
+    }
+}
+
+impl Smallvil {
+    /// The cursor as it would be shown at `scale`: the client's cursor
+    /// surface, or the theme's image for the requested icon. None when the
+    /// client or hide_cursor hid it.
+    pub fn cursor_image<R>(&mut self, renderer: &mut R, scale: f64) -> Option<CursorImage>
+    where
+        R: OffscreenRenderer,
+        R::TextureId: Clone + 'static,
+    {
+        if self.cursor_hidden {
+            return None;
+        }
+        match &self.cursor_status {
+            CursorImageStatus::Hidden => None,
+            CursorImageStatus::Named(icon) => Some(self.cursor_theme.named(*icon, scale)),
+            CursorImageStatus::Surface(surface) => {
+                let hotspot = with_states(surface, |states| {
+                    states
+                        .data_map
+                        .get::<CursorImageSurfaceData>()
+                        .map(|data| data.lock().unwrap().hotspot)
+                })
+                .unwrap_or_default();
+                let bbox = bbox_from_surface_tree(surface, (0, 0));
+                let size = bbox.size.to_physical_precise_ceil(scale);
+                if size.w <= 0 || size.h <= 0 {
+                    return None;
+                }
+                let elements: Vec<WaylandSurfaceRenderElement<R>> =
+                    render_elements_from_surface_tree(
+                        renderer,
+                        surface,
+                        (-bbox.loc).to_physical_precise_round(scale),
+                        Scale::from(scale),
+                        1.0,
+                        Kind::Cursor,
+                    );
+                let image = capture::render_offscreen(renderer, &elements, size)
+                    .map_err(|e| tracing::warn!("Failed to render the cursor: {}", e))
+                    .ok()?;
+                Some(CursorImage {
+                    image,
+                    hotspot: (hotspot - bbox.loc).to_physical_precise_round(scale),
+                })
+            }
+        }
+    }
+}
+
+/// Draws `cursor` into `img` with its hotspot at `position`.
+pub fn draw_cursor(img: &mut RgbaImage, cursor: &CursorImage, position: Point<i32, Physical>) {
+    let x = (position.x - cursor.hotspot.x) as i64;
+    let y = (position.y - cursor.hotspot.y) as i64;
+    image::imageops::overlay(img, &cursor.image, x, y);
+}
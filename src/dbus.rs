@@ -0,0 +1,95 @@
+use std::{
+    io::{BufRead, BufReader},
+    os::unix::process::CommandExt,
+    path::{Path, PathBuf},
+    process::{Child, Command, Stdio},
+};
+
+/// A dbus-daemon serving as the session bus of mcpvil and its apps, so they
+/// don't attach to the host user's: single-instance apps start a fresh
+/// instance inside the compositor, and D-Bus activated services and portals
+/// see mcpvil's WAYLAND_DISPLAY. Stopped when dropped, or when mcpvil dies.
+pub struct SessionBus {
+    child: Child,
+    socket: PathBuf,
+    pub address: String,
+}
+
+impl SessionBus {
+    /// Starts the daemon and waits until it accepts connections. Run it once
+    /// WAYLAND_DISPLAY is set, as activated services inherit its environment.
+    pub fn start() -> Result<Self, String> {
+        let runtime_dir = std::env::var_os("XDG_RUNTIME_DIR")
+            .map(PathBuf::from)
+            .unwrap_or_else(std::env::temp_dir);
+        let socket = runtime_dir.join(format!("mcpvil-bus-{}", std::process::id()));
+        let _ = std::fs::remove_file(&socket);
+
+        let mut cmd = Command::new("dbus-daemon");
+        cmd.args(["--session", "--nofork", "--nopidfile", "--print-address"])
+            .arg(format!("--address=unix:path={}", socket.display()))
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped());
+        unsafe {
+            cmd.pre_exec(|| {
+                // It reaps the services it activates
+                let mut set: libc::sigset_t = std::mem::zeroed();
+                libc::sigemptyset(&mut set);
+                libc::pthread_sigmask(libc::SIG_SETMASK, &set, std::ptr::null_mut());
+                // Don't outlive mcpvil if it is killed before dropping the bus
+                libc::prctl(libc::PR_SET_PDEATHSIG, libc::SIGTERM);
+                Ok(())
+            });
+        }
+        let mut child = cmd
+            .spawn()
+            .map_err(|e| format!("Failed to run dbus-daemon: {}", e))?;
+
+        // The address is printed once the bus is listening
+        let mut address = String::new();
+        if let Some(stdout) = child.stdout.take() {
+            let _ = BufReader::new(stdout).read_line(&mut address);
+        }
+        let address = address.trim().to_string();
+        if address.is_empty() {
+            let status = child.wait().map_err(|e| e.to_string())?;
+            return Err(format!("dbus-daemon exited during startup: {}", status));
+        }
+        Ok(Self {
+            child,
+            socket,
+            address,
+        })
+    }
+
+    /// The socket the bus listens on, for sandboxes to let through.
+    pub fn socket(&self) -> &Path {
+        &self.socket
+    }
+
+    /// Passes variables set after the bus started, such as DISPLAY once
+    /// Xwayland is ready, on to the services it activates.
+    pub fn update_activation_environment(&self, names: &[&str]) {
+        let result = Command::new("dbus-update-activation-environment")
+            .args(names)
+            .env("DBUS_SESSION_BUS_ADDRESS", &self.address)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .spawn();
+        match result {
+            // Reaped on its own thread; the SIGCHLD handler only waits for launched apps
+            Ok(mut child) => {
+                std::thread::spawn(move || child.wait());
+            }
+            Err(e) => tracing::warn!("Failed to update the activation environment: {}", e),
+        }
+    }
+}
+
+impl Drop for SessionBus {
+    fn drop(&mut self) {
+        unsafe { libc::kill(self.child.id() as i32, libc::SIGTERM) };
+        let _ = self.child.wait();
+        let _ = std::fs::remove_file(&self.socket);
+    }
+}
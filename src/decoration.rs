@@ -0,0 +1,178 @@
+use std::cell::RefCell;
+
+use smithay::{
+    backend::renderer::element::{
+        solid::{SolidColorBuffer, SolidColorRenderElement},
+        Kind,
+    },
+    desktop::Window,
+    input::pointer::{Focus, GrabStartData as PointerGrabStartData},
+    reexports::wayland_protocols::xdg::{
+        decoration::zv1::server::zxdg_toplevel_decoration_v1::Mode as DecorationMode,
+        shell::server::xdg_toplevel,
+    },
+    utils::{Logical, Physical, Point, Rectangle, Scale, Serial, Size},
+};
+
+use crate::{grabs::MoveSurfaceGrab, Smallvil};
+
+/// Height of server-side title bars, above the window geometry.
+pub const TITLE_HEIGHT: i32 = 24;
+const BORDER_WIDTH: i32 = 1;
+
+const TITLE_COLOR: [f32; 4] = [0.25, 0.25, 0.28, 1.0];
+const FOCUSED_TITLE_COLOR: [f32; 4] = [0.2, 0.35, 0.6, 1.0];
+const CLOSE_COLOR: [f32; 4] = [0.75, 0.2, 0.2, 1.0];
+const BORDER_COLOR: [f32; 4] = [0.1, 0.1, 0.1, 1.0];
+
+const BTN_LEFT: u32 = 0x110;
+
+/// Part of a server-side decoration under the pointer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecorationPart {
+    /// Dragging it moves the window
+    TitleBar,
+    /// A square at the right end of the title bar that closes the window
+    CloseButton,
+}
+
+/// Solid color buffers kept per window, so unchanged decorations cause no damage.
+#[derive(Default)]
+struct DecorationBuffers {
+    title: RefCell<SolidColorBuffer>,
+    close: RefCell<SolidColorBuffer>,
+    border: RefCell<SolidColorBuffer>,
+}
+
+/// Whether the compositor draws the window's title bar and border: the client
+/// agreed to server-side decorations and the window isn't fullscreen.
+pub fn is_decorated(window: &Window) -> bool {
+    let Some(toplevel) = window.toplevel() else {
+        return false;
+    };
+    let state = toplevel.current_state();
+    state.decoration_mode == Some(DecorationMode::ServerSide)
+        && !state.states.contains(xdg_toplevel::State::Fullscreen)
+}
+
+/// Title bar and close button of a window whose geometry is at `geometry`.
+fn title_bar(
+    geometry: Rectangle<i32, Logical>,
+) -> (Rectangle<i32, Logical>, Rectangle<i32, Logical>) {
+    let title = Rectangle::new(
+        (geometry.loc.x, geometry.loc.y - TITLE_HEIGHT).into(),
+        (geometry.size.w, TITLE_HEIGHT).into(),
+    );
+    let close = Rectangle::new(
+        (title.loc.x + title.size.w - TITLE_HEIGHT, title.loc.y).into(),
+        (TITLE_HEIGHT, TITLE_HEIGHT).into(),
+    );
+    (title, close)
+}
+
+/// Render elements for a decorated window, front to back: close button, title
+/// bar and the border behind everything. `geometry` is relative to the output.
+pub fn decoration_elements(
+    window: &Window,
+    geometry: Rectangle<i32, Logical>,
+    scale: Scale<f64>,
+    focused: bool,
+) -> Vec<SolidColorRenderElement> {
+    if !is_decorated(window) {
+        return Vec::new();
+    }
+    let buffers = window.user_data().get_or_insert(DecorationBuffers::default);
+    let (title, close) = title_bar(geometry);
+    let border = Rectangle::new(
+        title.loc - Point::from((BORDER_WIDTH, BORDER_WIDTH)),
+        Size::from((
+            geometry.size.w + 2 * BORDER_WIDTH,
+            geometry.size.h + TITLE_HEIGHT + 2 * BORDER_WIDTH,
+        )),
+    );
+    let title_color = if focused {
+        FOCUSED_TITLE_COLOR
+    } else {
+        TITLE_COLOR
+    };
+
+    [
+        (&buffers.close, close, CLOSE_COLOR),
+        (&buffers.title, title, title_color),
+        (&buffers.border, border, BORDER_COLOR),
+    ]
+    .into_iter()
+    .map(|(buffer, rect, color)| {
+        let mut buffer = buffer.borrow_mut();
+        buffer.update(rect.size, color);
+        let location: Point<i32, Physical> = rect.loc.to_physical_precise_round(scale);
+        SolidColorRenderElement::from_buffer(&buffer, location, scale, 1.0, Kind::Unspecified)
+    })
+    .collect()
+}
+
+impl Smallvil {
+    /// The decorated window whose title bar is at `location`, unless another
+    /// window covers it there.
+    pub fn decoration_under(
+        &self,
+        location: Point<f64, Logical>,
+    ) -> Option<(Window, DecorationPart)> {
+        for window in self.space.elements().rev() {
+            let Some(geometry) = self.space.element_geometry(window) else {
+                continue;
+            };
+            if geometry.to_f64().contains(location) {
+                return None;
+            }
+            if !is_decorated(window) {
+                continue;
+            }
+            let (title, close) = title_bar(geometry);
+            if close.to_f64().contains(location) {
+                return Some((window.clone(), DecorationPart::CloseButton));
+            }
+            if title.to_f64().contains(location) {
+                return Some((window.clone(), DecorationPart::TitleBar));
+            }
+        }
+        None
+    }
+
+    /// Handles a left-button press on a server-side title bar: the close button
+    /// closes the window, anywhere else starts moving it. Returns whether the
+    /// press hit a title bar.
+    pub fn press_decoration(&mut self, button: u32, serial: Serial) -> bool {
+        let pointer = self.seat.get_pointer().unwrap();
+        if button != BTN_LEFT || pointer.is_grabbed() {
+            return false;
+        }
+        let location = pointer.current_location();
+        let Some((window, part)) = self.decoration_under(location) else {
+            return false;
+        };
+
+        self.focus_window(&window, serial);
+        match part {
+            DecorationPart::CloseButton => {
+                if let Some(toplevel) = window.toplevel() {
+                    toplevel.send_close();
+                }
+            }
+            DecorationPart::TitleBar => {
+                let initial_window_location = self.space.element_location(&window).unwrap();
+                let grab = MoveSurfaceGrab {
+                    start_data: PointerGrabStartData {
+                        focus: None,
+                        button,
+                        location,
+                    },
+                    window,
+                    initial_window_location,
+                };
+                pointer.set_grab(self, grab, serial, Focus::Clear);
+            }
+        }
+        true
+    }
+}
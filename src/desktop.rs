@@ -0,0 +1,422 @@
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+};
+
+use rmcp::schemars::{self, JsonSchema};
+use serde::Serialize;
+
+use crate::flatpak;
+
+/// Where desktop entries live when XDG_DATA_DIRS is unset.
+const DEFAULT_DATA_DIRS: &str = "/usr/local/share:/usr/share";
+
+/// Field codes from older versions of the spec, dropped from Exec lines.
+const DEPRECATED_FIELD_CODES: &[char] = &['d', 'D', 'n', 'N', 'v', 'm'];
+
+/// A parsed freedesktop `.desktop` file of type Application.
+#[derive(Debug, Clone)]
+pub struct DesktopEntry {
+    /// Desktop file id, e.g. "org.gnome.TextEditor"
+    pub id: String,
+    pub path: PathBuf,
+    pub name: Option<String>,
+    pub comment: Option<String>,
+    pub categories: Vec<String>,
+    pub exec: Option<String>,
+    pub icon: Option<String>,
+    pub startup_wm_class: Option<String>,
+    /// Working directory to run the app in (the Path key)
+    pub working_dir: Option<String>,
+    pub terminal: bool,
+    /// Not meant to be shown in menus, e.g. helpers and MIME handlers
+    pub no_display: bool,
+    /// Deleted by the user, hiding entries with the same id in later directories
+    pub hidden: bool,
+    pub actions: Vec<DesktopAction>,
+}
+
+/// An additional entry point, e.g. "new-window", from a [Desktop Action] group.
+#[derive(Debug, Clone)]
+pub struct DesktopAction {
+    pub id: String,
+    pub name: Option<String>,
+    pub exec: Option<String>,
+}
+
+/// An app found by list_installed_apps.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct InstalledApp {
+    pub desktop_id: String,
+    pub name: Option<String>,
+    pub comment: Option<String>,
+    pub categories: Vec<String>,
+    pub icon: Option<String>,
+    /// Ids of the entry's actions, for launch_desktop_app
+    pub actions: Vec<String>,
+    /// Opens files or URLs passed to launch_desktop_app
+    pub takes_files: bool,
+    pub terminal: bool,
+    pub path: PathBuf,
+}
+
+/// The desktop entry a window's app was launched from, as reported by
+/// list_windows.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct DesktopInfo {
+    pub id: String,
+    pub name: Option<String>,
+    pub icon: Option<String>,
+    pub startup_wm_class: Option<String>,
+}
+
+impl DesktopEntry {
+    /// Looks up a desktop file id, with or without ".desktop", in the XDG data
+    /// directories, the user's first.
+    pub fn find(desktop_id: &str) -> Result<Self, String> {
+        let id = desktop_id.strip_suffix(".desktop").unwrap_or(desktop_id);
+        let mut files = Vec::new();
+        for dir in applications_dirs() {
+            files.clear();
+            collect_desktop_files(&dir, "", &mut files);
+            if let Some((_, path)) = files.iter().find(|(candidate, _)| candidate == id) {
+                let entry = Self::load(id, path)?;
+                if entry.hidden {
+                    return Err(format!("{} is hidden by {}", id, path.display()));
+                }
+                return Ok(entry);
+            }
+        }
+        Err(format!("No desktop entry named '{}'", desktop_id))
+    }
+
+    pub fn load(id: &str, path: &Path) -> Result<Self, String> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        let mut entry = Self {
+            id: id.to_string(),
+            path: path.to_path_buf(),
+            name: None,
+            comment: None,
+            categories: Vec::new(),
+            exec: None,
+            icon: None,
+            startup_wm_class: None,
+            working_dir: None,
+            terminal: false,
+            no_display: false,
+            hidden: false,
+            actions: Vec::new(),
+        };
+        let mut group = String::new();
+        let mut entry_type = None;
+        for line in contents.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                group = name.to_string();
+                if let Some(action) = group.strip_prefix("Desktop Action ") {
+                    entry.actions.push(DesktopAction {
+                        id: action.to_string(),
+                        name: None,
+                        exec: None,
+                    });
+                }
+                continue;
+            }
+            // Localized keys such as Name[de] are skipped
+            let Some((key, value)) = line.split_once('=') else {
+                continue;
+            };
+            let value = unescape_value(value.trim());
+            match (group.as_str(), key.trim()) {
+                ("Desktop Entry", "Type") => entry_type = Some(value),
+                ("Desktop Entry", "Name") => entry.name = Some(value),
+                ("Desktop Entry", "Comment") => entry.comment = Some(value),
+                ("Desktop Entry", "Categories") => {
+                    entry.categories = value
+                        .split(';')
+                        .filter(|category| !category.is_empty())
+                        .map(String::from)
+                        .collect()
+                }
+                ("Desktop Entry", "Exec") => entry.exec = Some(value),
+                ("Desktop Entry", "Icon") => entry.icon = Some(value),
+                ("Desktop Entry", "StartupWMClass") => entry.startup_wm_class = Some(value),
+                ("Desktop Entry", "Path") => entry.working_dir = Some(value),
+                ("Desktop Entry", "Terminal") => entry.terminal = value == "true",
+                ("Desktop Entry", "NoDisplay") => entry.no_display = value == "true",
+                ("Desktop Entry", "Hidden") => entry.hidden = value == "true",
+                (group, key) if group.starts_with("Desktop Action ") => {
+                    if let Some(action) = entry.actions.last_mut() {
+                        match key {
+                            "Name" => action.name = Some(value),
+                            "Exec" => action.exec = Some(value),
+                            _ => {}
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        match entry_type.as_deref() {
+            Some("Application") => Ok(entry),
+            Some(other) => Err(format!("{} is a {} entry, not an Application", id, other)),
+            None => Err(format!("{} has no [Desktop Entry] group", path.display())),
+        }
+    }
+
+    /// The command line to run, from the Exec key of the entry or of one of
+    /// its actions, with field codes expanded and `files` (paths or URLs)
+    /// passed where %f, %F, %u or %U ask for them.
+    pub fn command_line(
+        &self,
+        action: Option<&str>,
+        files: &[String],
+    ) -> Result<Vec<String>, String> {
+        let exec = match action {
+            Some(id) => {
+                let action = self
+                    .actions
+                    .iter()
+                    .find(|candidate| candidate.id == id)
+                    .ok_or_else(|| {
+                        let ids: Vec<&str> = self.actions.iter().map(|a| a.id.as_str()).collect();
+                        format!(
+                            "{} has no action '{}'; available: {}",
+                            self.id,
+                            id,
+                            ids.join(", ")
+                        )
+                    })?;
+                action.exec.as_deref()
+            }
+            None => self.exec.as_deref(),
+        }
+        .ok_or_else(|| format!("{} has no Exec line", self.id))?;
+
+        let mut argv = Vec::new();
+        let mut files_used = false;
+        for token in split_exec(exec)? {
+            match token.as_str() {
+                "%f" | "%u" => {
+                    argv.extend(files.first().cloned());
+                    files_used = true;
+                }
+                "%F" | "%U" => {
+                    argv.extend(files.iter().cloned());
+                    files_used = true;
+                }
+                // Flatpak's file forwarding markers, see flatpak::add_run_options
+                "@@" | "@@u" => {}
+                "%i" => {
+                    if let Some(icon) = &self.icon {
+                        argv.push(String::from("--icon"));
+                        argv.push(icon.clone());
+                    }
+                }
+                _ => {
+                    if let Some(arg) = self.expand_field_codes(&token)? {
+                        argv.push(arg);
+                    }
+                }
+            }
+        }
+
+        if !files.is_empty() && !files_used {
+            return Err(format!("{} doesn't take files to open", self.id));
+        }
+        if argv.is_empty() {
+            return Err(format!("{} has an empty Exec line", self.id));
+        }
+        Ok(argv)
+    }
+
+    /// Expands the field codes inside a single argument. Returns None for an
+    /// argument that expands to nothing, such as a lone deprecated code.
+    fn expand_field_codes(&self, token: &str) -> Result<Option<String>, String> {
+        let mut arg = String::new();
+        let mut chars = token.chars();
+        while let Some(c) = chars.next() {
+            if c != '%' {
+                arg.push(c);
+                continue;
+            }
+            match chars.next() {
+                Some('%') => arg.push('%'),
+                Some('c') => arg.push_str(self.name.as_deref().unwrap_or_default()),
+                Some('k') => arg.push_str(&self.path.to_string_lossy()),
+                Some(code) if DEPRECATED_FIELD_CODES.contains(&code) => {}
+                Some(code) => {
+                    return Err(format!(
+                        "Invalid field code %{} in the Exec line of {}",
+                        code, self.id
+                    ))
+                }
+                None => return Err(format!("Stray % in the Exec line of {}", self.id)),
+            }
+        }
+        Ok(Some(arg).filter(|arg| !arg.is_empty() || !token.starts_with('%')))
+    }
+
+    pub fn installed_app(&self) -> InstalledApp {
+        let exec = self.exec.as_deref().unwrap_or_default();
+        InstalledApp {
+            desktop_id: self.id.clone(),
+            name: self.name.clone(),
+            comment: self.comment.clone(),
+            categories: self.categories.clone(),
+            icon: self.icon.clone(),
+            actions: self
+                .actions
+                .iter()
+                .map(|action| action.id.clone())
+                .collect(),
+            takes_files: ["%f", "%F", "%u", "%U"]
+                .iter()
+                .any(|code| exec.contains(code)),
+            terminal: self.terminal,
+            path: self.path.clone(),
+        }
+    }
+
+    pub fn info(&self) -> DesktopInfo {
+        DesktopInfo {
+            id: self.id.clone(),
+            name: self.name.clone(),
+            icon: self.icon.clone(),
+            startup_wm_class: self.startup_wm_class.clone(),
+        }
+    }
+}
+
+/// Every application entry in the XDG data directories, sorted by id. An id
+/// found in several directories is taken from the most important one, and
+/// left out if that copy is Hidden.
+pub fn installed_apps(include_no_display: bool) -> Vec<DesktopEntry> {
+    let mut seen = HashSet::new();
+    let mut apps = Vec::new();
+    let mut files = Vec::new();
+    for dir in applications_dirs() {
+        files.clear();
+        collect_desktop_files(&dir, "", &mut files);
+        for (id, path) in &files {
+            if !seen.insert(id.clone()) {
+                continue;
+            }
+            match DesktopEntry::load(id, path) {
+                Ok(entry) if entry.hidden || (entry.no_display && !include_no_display) => {}
+                Ok(entry) => apps.push(entry),
+                Err(e) => tracing::debug!("Skipping {}: {}", path.display(), e),
+            }
+        }
+    }
+    apps.sort_by(|a, b| a.id.cmp(&b.id));
+    apps
+}
+
+/// `applications` in each XDG data directory, most important first.
+pub fn applications_dirs() -> Vec<PathBuf> {
+    let data_home = std::env::var_os("XDG_DATA_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".local/share")));
+    let data_dirs = std::env::var("XDG_DATA_DIRS")
+        .ok()
+        .filter(|dirs| !dirs.is_empty())
+        .unwrap_or_else(|| DEFAULT_DATA_DIRS.to_string());
+    let mut dirs: Vec<PathBuf> = data_home
+        .into_iter()
+        .chain(data_dirs.split(':').map(PathBuf::from))
+        .collect();
+    // Flatpak apps, if its profile script hasn't already added them
+    for dir in flatpak::export_dirs() {
+        if !dirs.contains(&dir) {
+            dirs.push(dir);
+        }
+    }
+    dirs.into_iter()
+        .map(|dir| dir.join("applications"))
+        .collect()
+}
+
+/// Adds the desktop files under `dir` with their ids; files in subdirectories
+/// get ids with the directory names joined by '-', as the spec says.
+pub fn collect_desktop_files(dir: &Path, prefix: &str, files: &mut Vec<(String, PathBuf)>) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if path.is_dir() {
+            collect_desktop_files(&path, &format!("{}{}-", prefix, name), files);
+        } else if let Some(stem) = name.strip_suffix(".desktop") {
+            files.push((format!("{}{}", prefix, stem), path));
+        }
+    }
+}
+
+/// Undoes the escapes allowed in desktop file string values.
+fn unescape_value(value: &str) -> String {
+    let mut unescaped = String::new();
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('s') => unescaped.push(' '),
+            Some('n') => unescaped.push('\n'),
+            Some('t') => unescaped.push('\t'),
+            Some('r') => unescaped.push('\r'),
+            Some(other) => {
+                // Left for the Exec quoting rules to handle
+                unescaped.push('\\');
+                unescaped.push(other);
+            }
+            None => unescaped.push('\\'),
+        }
+    }
+    unescaped
+}
+
+/// Splits an Exec line into arguments. Arguments may be double-quoted, with
+/// `"`, `` ` ``, `$` and `\` escaped by a backslash inside the quotes.
+fn split_exec(exec: &str) -> Result<Vec<String>, String> {
+    let mut args = Vec::new();
+    let mut chars = exec.chars().peekable();
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        let Some(&first) = chars.peek() else {
+            return Ok(args);
+        };
+        let mut arg = String::new();
+        if first == '"' {
+            chars.next();
+            loop {
+                match chars.next() {
+                    Some('"') => break,
+                    Some('\\') => match chars.next() {
+                        Some(c @ ('"' | '`' | '$' | '\\')) => arg.push(c),
+                        Some(c) => {
+                            arg.push('\\');
+                            arg.push(c);
+                        }
+                        None => return Err(format!("Unterminated quote in Exec line: {}", exec)),
+                    },
+                    Some(c) => arg.push(c),
+                    None => return Err(format!("Unterminated quote in Exec line: {}", exec)),
+                }
+            }
+        } else {
+            while let Some(c) = chars.next_if(|c| !c.is_whitespace()) {
+                arg.push(c);
+            }
+        }
+        args.push(arg);
+    }
+}
@@ -0,0 +1,429 @@
+use std::{cell::RefCell, rc::Rc};
+
+use smithay::{
+    backend::{
+        allocator::{
+            gbm::{GbmAllocator, GbmBufferFlags, GbmDevice},
+            Fourcc,
+        },
+        drm::{DrmDevice, DrmDeviceFd, DrmEvent, GbmBufferedSurface},
+        egl::{EGLContext, EGLDisplay},
+        libinput::{LibinputInputBackend, LibinputSessionInterface},
+        renderer::{damage::OutputDamageTracker, gles::GlesRenderer, Bind, ImportDma},
+        session::{libseat::LibSeatSession, Event as SessionEvent, Session},
+        udev::{all_gpus, primary_gpu},
+    },
+    desktop::utils::OutputPresentationFeedback,
+    output::{Mode, Output, PhysicalProperties, Subpixel},
+    reexports::{
+        calloop::{
+            timer::{TimeoutAction, Timer},
+            EventLoop,
+        },
+        drm::control::{connector, crtc, Device as ControlDevice, ModeTypeFlags},
+        input::Libinput,
+        rustix::fs::OFlags,
+        wayland_server::DisplayHandle,
+    },
+    utils::{DeviceFd, Physical, Size, Transform},
+};
+
+use crate::{
+    capture, presentation,
+    render::{self, VirtualOutput},
+    CalloopData, Smallvil,
+};
+
+/// Formats tried for scanout buffers, in order of preference.
+const COLOR_FORMATS: [Fourcc; 2] = [Fourcc::Argb8888, Fourcc::Xrgb8888];
+
+type ScanoutSurface = GbmBufferedSurface<GbmAllocator<DrmDeviceFd>, OutputPresentationFeedback>;
+
+/// A connected display, driven by one CRTC.
+struct Surface {
+    output: Output,
+    crtc: crtc::Handle,
+    connector: connector::Handle,
+    /// Mode the CRTC is actually running
+    mode: smithay::reexports::drm::control::Mode,
+    surface: ScanoutSurface,
+    damage_tracker: OutputDamageTracker,
+}
+
+/// The primary GPU, its displays and the session they belong to.
+struct Backend {
+    session: LibSeatSession,
+    libinput: Libinput,
+    drm: DrmDevice,
+    renderer: GlesRenderer,
+    /// The first surface is the primary output and paces everything else
+    surfaces: Vec<Surface>,
+    virtual_outputs: Vec<VirtualOutput>,
+    /// False while another VT is in the foreground
+    active: bool,
+}
+
+/// Initializes a backend running directly on the hardware, as the session's
+/// compositor on a TTY: every connected display of the primary GPU becomes an
+/// output, input comes from libinput, and each entry in `extra_outputs` adds a
+/// virtual output rendered offscreen next to them.
+pub fn init_drm(
+    event_loop: &mut EventLoop<CalloopData>,
+    data: &mut CalloopData,
+    extra_outputs: &[Size<i32, Physical>],
+) -> Result<(), Box<dyn std::error::Error>> {
+    let display_handle = &mut data.display_handle;
+    let state = &mut data.state;
+
+    let (mut session, session_notifier) = LibSeatSession::new()?;
+    let seat_name = session.seat();
+    let path = match primary_gpu(&seat_name)? {
+        Some(path) => path,
+        None => all_gpus(&seat_name)?
+            .into_iter()
+            .next()
+            .ok_or("No GPU found")?,
+    };
+
+    let fd = session.open(
+        &path,
+        OFlags::RDWR | OFlags::CLOEXEC | OFlags::NOCTTY | OFlags::NONBLOCK,
+    )?;
+    let fd = DrmDeviceFd::new(DeviceFd::from(fd));
+    let (drm, drm_notifier) = DrmDevice::new(fd.clone(), true)?;
+    let gbm = GbmDevice::new(fd)?;
+
+    let display = unsafe { EGLDisplay::new(gbm.clone())? };
+    let context = EGLContext::new(&display)?;
+    let mut renderer = unsafe { GlesRenderer::new(context)? };
+    render::init_dmabuf(state, &renderer);
+
+    let surfaces = create_surfaces(&drm, &gbm, &renderer, state, display_handle)?;
+    if surfaces.is_empty() {
+        return Err(format!("No connected display on {}", path.display()).into());
+    }
+    render::arrange_outputs(&mut state.space);
+
+    let virtual_outputs = extra_outputs
+        .iter()
+        .enumerate()
+        .map(|(i, size)| {
+            VirtualOutput::new(
+                &mut renderer,
+                state,
+                display_handle,
+                format!("virtual-{}", i + 1),
+                *size,
+            )
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut libinput =
+        Libinput::new_with_udev::<LibinputSessionInterface<LibSeatSession>>(session.clone().into());
+    libinput
+        .udev_assign_seat(&seat_name)
+        .map_err(|_| format!("Failed to assign libinput to seat {}", seat_name))?;
+
+    std::env::set_var("WAYLAND_DISPLAY", &state.socket_name);
+
+    let backend = Rc::new(RefCell::new(Backend {
+        session,
+        libinput: libinput.clone(),
+        drm,
+        renderer,
+        surfaces,
+        virtual_outputs,
+        active: true,
+    }));
+
+    let input_backend = backend.clone();
+    event_loop.handle().insert_source(
+        LibinputInputBackend::new(libinput),
+        move |event, _, data| {
+            data.state.process_input_event(event);
+            if let Some(vt) = data.state.pending_vt_switch.take() {
+                if let Err(e) = input_backend.borrow_mut().session.change_vt(vt) {
+                    tracing::error!("Failed to switch to VT {}: {}", vt, e);
+                }
+            }
+        },
+    )?;
+
+    let drm_backend = backend.clone();
+    event_loop
+        .handle()
+        .insert_source(drm_notifier, move |event, _, data| match event {
+            DrmEvent::VBlank(crtc) => drm_backend.borrow_mut().frame_done(crtc, data),
+            DrmEvent::Error(e) => tracing::error!("DRM error: {}", e),
+        })?;
+
+    let session_backend = backend.clone();
+    event_loop
+        .handle()
+        .insert_source(session_notifier, move |event, _, data| {
+            let mut backend = session_backend.borrow_mut();
+            match event {
+                SessionEvent::PauseSession => backend.pause(),
+                SessionEvent::ActivateSession => {
+                    backend.resume();
+                    backend.render_all(data);
+                }
+            }
+        })?;
+
+    // Start the frame loop; from here on every page flip schedules the next frame
+    event_loop
+        .handle()
+        .insert_source(Timer::immediate(), move |_, _, data| {
+            backend.borrow_mut().render_all(data);
+            TimeoutAction::Drop
+        })?;
+
+    Ok(())
+}
+
+/// Creates an output for every connected connector that a free CRTC can drive,
+/// in its preferred mode.
+fn create_surfaces(
+    drm: &DrmDevice,
+    gbm: &GbmDevice<DrmDeviceFd>,
+    renderer: &GlesRenderer,
+    state: &mut Smallvil,
+    display_handle: &DisplayHandle,
+) -> Result<Vec<Surface>, Box<dyn std::error::Error>> {
+    let resources = drm.resource_handles()?;
+    let mut surfaces: Vec<Surface> = Vec::new();
+
+    for &handle in resources.connectors() {
+        let info = drm.get_connector(handle, false)?;
+        if info.state() != connector::State::Connected {
+            continue;
+        }
+        let Some(&drm_mode) = info
+            .modes()
+            .iter()
+            .find(|mode| mode.mode_type().contains(ModeTypeFlags::PREFERRED))
+            .or_else(|| info.modes().first())
+        else {
+            continue;
+        };
+        let crtc = info
+            .encoders()
+            .iter()
+            .filter_map(|&encoder| drm.get_encoder(encoder).ok())
+            .flat_map(|encoder| resources.filter_crtcs(encoder.possible_crtcs()))
+            .find(|crtc| surfaces.iter().all(|surface| surface.crtc != *crtc));
+        let Some(crtc) = crtc else {
+            tracing::warn!("No free CRTC for connector {:?}", info.interface());
+            continue;
+        };
+
+        let name = format!("{}-{}", info.interface().as_str(), info.interface_id());
+        let drm_surface = drm.create_surface(crtc, drm_mode, &[handle])?;
+        let allocator = GbmAllocator::new(
+            gbm.clone(),
+            GbmBufferFlags::RENDERING | GbmBufferFlags::SCANOUT,
+        );
+        let surface = GbmBufferedSurface::new(
+            drm_surface,
+            allocator,
+            &COLOR_FORMATS,
+            renderer.dmabuf_formats().iter().copied(),
+        )?;
+
+        let (width, height) = info.size().unwrap_or((0, 0));
+        let output = Output::new(
+            name,
+            PhysicalProperties {
+                size: (width as i32, height as i32).into(),
+                subpixel: Subpixel::Unknown,
+                make: "Smithay".into(),
+                model: "DRM".into(),
+            },
+        );
+        let _global = output.create_global::<Smallvil>(display_handle);
+        let mode = Mode::from(drm_mode);
+        output.change_current_state(Some(mode), Some(Transform::Normal), None, None);
+        output.set_preferred(mode);
+        state.space.map_output(&output, (0, 0));
+
+        surfaces.push(Surface {
+            damage_tracker: OutputDamageTracker::from_output(&output),
+            output,
+            crtc,
+            connector: handle,
+            mode: drm_mode,
+            surface,
+        });
+    }
+
+    Ok(surfaces)
+}
+
+impl Backend {
+    /// Renders every output, e.g. to start the frame loop.
+    fn render_all(&mut self, data: &mut CalloopData) {
+        for index in 0..self.surfaces.len() {
+            self.render_surface(index, &mut data.state);
+        }
+    }
+
+    /// A page flip of `crtc` completed: presents its frame and renders the next.
+    /// The primary output's flips also drive input playback, the virtual
+    /// outputs and frame callbacks, as redraws do in the other backends.
+    fn frame_done(&mut self, crtc: crtc::Handle, data: &mut CalloopData) {
+        let Some(index) = self.surfaces.iter().position(|s| s.crtc == crtc) else {
+            return;
+        };
+        let state = &mut data.state;
+
+        match self.surfaces[index].surface.frame_submitted() {
+            Ok(Some(feedback)) => state.present_frame(&self.surfaces[index].output, feedback),
+            Ok(None) => {}
+            Err(e) => tracing::warn!("Failed to submit frame: {}", e),
+        }
+        if index == 0 {
+            render::start_frame(state);
+        }
+
+        self.render_surface(index, state);
+
+        if index == 0 {
+            for virtual_output in &mut self.virtual_outputs {
+                virtual_output.render(&mut self.renderer, state);
+            }
+            let output = self.surfaces[0].output.clone();
+            render::finish_frame(state, &mut data.display_handle, &output);
+        }
+    }
+
+    fn render_surface(&mut self, index: usize, state: &mut Smallvil) {
+        if !self.active {
+            return;
+        }
+        let Backend {
+            drm,
+            renderer,
+            surfaces,
+            ..
+        } = self;
+        let surface = &mut surfaces[index];
+        surface.sync_mode(drm, state);
+
+        let (mut dmabuf, age) = match surface.surface.next_buffer() {
+            Ok(buffer) => buffer,
+            Err(e) => {
+                tracing::error!("No buffer to render {} into: {}", surface.output.name(), e);
+                return;
+            }
+        };
+        let mut framebuffer = match renderer.bind(&mut dmabuf) {
+            Ok(framebuffer) => framebuffer,
+            Err(e) => {
+                tracing::error!("Failed to bind output {}: {}", surface.output.name(), e);
+                return;
+            }
+        };
+
+        let elements = render::output_elements(renderer, state, &surface.output);
+        let rendered = surface.damage_tracker.render_output(
+            renderer,
+            &mut framebuffer,
+            age as usize,
+            &elements,
+            state.background.color,
+        );
+        let result = match rendered {
+            Ok(result) => result,
+            Err(e) => {
+                tracing::error!("Failed to render output {}: {}", surface.output.name(), e);
+                return;
+            }
+        };
+
+        let damage = result.damage.cloned();
+        if damage.as_ref().is_some_and(|damage| !damage.is_empty()) {
+            state.note_damage();
+        }
+        let feedback =
+            presentation::take_presentation_feedback(state, &surface.output, &result.states);
+        let size = Mode::from(surface.mode).size;
+        capture::capture_frame(state, renderer, &framebuffer, &surface.output, size, false);
+
+        // The frame is presented, and its feedback answered, on the next page flip
+        if let Err(e) = surface
+            .surface
+            .queue_buffer(Some(result.sync), damage, feedback)
+        {
+            tracing::error!("Failed to queue frame of {}: {}", surface.output.name(), e);
+        }
+    }
+
+    fn pause(&mut self) {
+        self.libinput.suspend();
+        self.drm.pause();
+        self.active = false;
+    }
+
+    fn resume(&mut self) {
+        if self.libinput.resume().is_err() {
+            tracing::error!("Failed to resume libinput");
+        }
+        if let Err(e) = self.drm.activate(false) {
+            tracing::error!("Failed to resume DRM device: {}", e);
+        }
+        for surface in &mut self.surfaces {
+            surface.surface.reset_buffers();
+        }
+        self.active = true;
+    }
+}
+
+impl Surface {
+    /// Switches the CRTC to a mode set through set_output_mode, or puts the
+    /// output back to the running mode if the display doesn't offer that size.
+    fn sync_mode(&mut self, drm: &DrmDevice, state: &mut Smallvil) {
+        let Some(wanted) = self.output.current_mode() else {
+            return;
+        };
+        if Mode::from(self.mode) == wanted {
+            return;
+        }
+
+        let candidate = drm
+            .get_connector(self.connector, false)
+            .ok()
+            .and_then(|info| {
+                info.modes()
+                    .iter()
+                    .filter(|&&mode| Mode::from(mode).size == wanted.size)
+                    .min_by_key(|&&mode| (Mode::from(mode).refresh - wanted.refresh).abs())
+                    .copied()
+            });
+        match candidate.map(|mode| (mode, self.surface.use_mode(mode))) {
+            Some((mode, Ok(()))) => {
+                self.mode = mode;
+                self.damage_tracker = OutputDamageTracker::from_output(&self.output);
+            }
+            Some((_, Err(e))) => {
+                tracing::warn!("Failed to change mode of {}: {}", self.output.name(), e)
+            }
+            None => tracing::warn!(
+                "{} has no {}x{} mode",
+                self.output.name(),
+                wanted.size.w,
+                wanted.size.h
+            ),
+        }
+
+        // Report the refresh rate the display really runs at, or the old mode
+        let mode = Mode::from(self.mode);
+        if mode != wanted {
+            self.output
+                .change_current_state(Some(mode), None, None, None);
+            self.output.set_preferred(mode);
+            render::arrange_outputs(&mut state.space);
+            state.refit_windows(&self.output);
+        }
+    }
+}
@@ -0,0 +1,164 @@
+use rmcp::schemars::{self, JsonSchema};
+use serde::Serialize;
+use smithay::{
+    desktop::{layer_map_for_output, PopupManager},
+    utils::IsAlive,
+};
+
+use crate::{
+    input::InputState,
+    presentation::FrameTimingInfo,
+    process::ProcessInfo,
+    state::{Geometry, OutputInfo, WindowId, WindowInfo},
+    Smallvil,
+};
+
+/// Everything dump_state reports, for debugging a failed run after the fact.
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct StateDump {
+    /// Seconds since the compositor started
+    pub uptime: f64,
+    pub socket_name: String,
+    /// X11 display number, once Xwayland is ready
+    pub xwayland_display: Option<u32>,
+    pub outputs: Vec<OutputInfo>,
+    pub layer_surfaces: Vec<LayerSurfaceInfo>,
+    /// Windows in stacking order, bottom to top
+    pub windows: Vec<WindowInfo>,
+    pub popups: Vec<PopupInfo>,
+    /// Pointer, keyboard focus and held keys and buttons
+    pub input: InputState,
+    pub processes: Vec<ProcessInfo>,
+    pub pending: PendingState,
+    pub frame_timing: Vec<FrameTimingInfo>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct LayerSurfaceInfo {
+    pub output: String,
+    pub namespace: String,
+    /// "background", "bottom", "top" or "overlay"
+    pub layer: String,
+    /// Position and size relative to the output
+    pub geometry: Option<Geometry>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct PopupInfo {
+    /// Toplevel the popup belongs to
+    pub window: u64,
+    /// Position and size relative to the toplevel's surface
+    pub geometry: Geometry,
+    pub alive: bool,
+}
+
+/// Grabs, playbacks and requests that are still in progress.
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct PendingState {
+    pub pointer_grabbed: bool,
+    pub keyboard_grabbed: bool,
+    pub drag: bool,
+    pub gesture: bool,
+    pub pen_stroke: bool,
+    pub dnd: bool,
+    pub recording: bool,
+    /// Session name of the input recording in progress
+    pub input_recording: Option<String>,
+    pub screenshots: usize,
+    pub screencopies: usize,
+    /// Windows asked to close that haven't gone away yet
+    pub closes: Vec<u64>,
+    /// Pids of launch_app calls waiting for a window
+    pub launches: Vec<u32>,
+    /// Pids of apps restart_app is waiting on to exit
+    pub restarts: Vec<u32>,
+    pub window_waits: usize,
+    pub idle_waits: usize,
+    pub stability_waits: usize,
+}
+
+impl Smallvil {
+    /// Assembles a snapshot of the whole compositor state.
+    pub fn dump_state(&self) -> StateDump {
+        let outputs = self.list_outputs();
+
+        let layer_surfaces = self
+            .space
+            .outputs()
+            .flat_map(|output| {
+                let map = layer_map_for_output(output);
+                map.layers()
+                    .map(|layer| LayerSurfaceInfo {
+                        output: output.name(),
+                        namespace: layer.namespace().to_string(),
+                        layer: format!("{:?}", layer.layer()).to_lowercase(),
+                        geometry: map.layer_geometry(layer).map(Into::into),
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+
+        let popups = self
+            .space
+            .elements()
+            .filter_map(|window| Some((WindowId::of(window).0, window.wl_surface()?)))
+            .flat_map(|(id, surface)| {
+                PopupManager::popups_for_surface(&surface)
+                    .map(|(popup, offset)| {
+                        let mut geometry = popup.geometry();
+                        geometry.loc += offset;
+                        PopupInfo {
+                            window: id,
+                            geometry: geometry.into(),
+                            alive: popup.alive(),
+                        }
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+
+        let pointer = self.seat.get_pointer().unwrap();
+        let keyboard = self.seat.get_keyboard().unwrap();
+        let mut closes: Vec<u64> = self.pending_closes.keys().copied().collect();
+        closes.sort_unstable();
+        let mut launches: Vec<u32> = self.pending_launches.keys().copied().collect();
+        launches.sort_unstable();
+        let mut restarts: Vec<u32> = self.pending_restarts.keys().copied().collect();
+        restarts.sort_unstable();
+        let pending = PendingState {
+            pointer_grabbed: pointer.is_grabbed(),
+            keyboard_grabbed: keyboard.is_grabbed(),
+            drag: self.pending_drag.is_some(),
+            gesture: self.pending_gesture.is_some(),
+            pen_stroke: self.pending_pen_stroke.is_some(),
+            dnd: self.pending_dnd.is_some(),
+            recording: self.recording.is_some(),
+            input_recording: self
+                .macro_recording
+                .as_ref()
+                .map(|recording| recording.session.clone()),
+            screenshots: self.pending_screenshots.len(),
+            screencopies: self.pending_screencopies.len(),
+            closes,
+            launches,
+            restarts,
+            window_waits: self.window_waits.len(),
+            idle_waits: self.idle_waits.len(),
+            stability_waits: self.stability_waits.len(),
+        };
+
+        StateDump {
+            uptime: self.start_time.elapsed().as_secs_f64(),
+            socket_name: self.socket_name.to_string_lossy().into_owned(),
+            xwayland_display: self.xdisplay,
+            outputs,
+            layer_surfaces,
+            windows: self.list_windows(None),
+            popups,
+            input: self.input_state(),
+            processes: self.list_processes(),
+            pending,
+            frame_timing: self.frame_timing(None).unwrap_or_default(),
+        }
+    }
+}
@@ -0,0 +1,81 @@
+//! Machine-readable tool failures. A launch, screenshot, window, input,
+//! recording, output, wait, process, clipboard or drag-and-drop call that
+//! fails returns an error result whose structured content names the kind of
+//! failure, e.g. `{"error": "window_not_found", "message": "No window with
+//! id 7"}`, so agents can branch on it instead of matching the message.
+//! Arguments that can't be used, such as an unknown screenshot format or mouse
+//! button, are rejected as JSON-RPC invalid-params errors before anything runs.
+
+use std::fmt;
+
+use rmcp::{
+    model::{CallToolResult, Content},
+    schemars::{self, JsonSchema},
+};
+use serde::Serialize;
+
+/// What went wrong, as the `error` field of a failed call.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorKind {
+    /// The app could not be started
+    AppLaunchFailed,
+    /// No window has the given id, or none has keyboard focus
+    WindowNotFound,
+    /// No output has the given name
+    OutputNotFound,
+    /// The frame could not be captured, encoded or saved
+    CaptureFailed,
+    /// What was waited for did not happen within the timeout
+    Timeout,
+    /// No app with the given pid was launched, or it has already exited
+    ProcessNotFound,
+    /// Anything else; the message says what
+    Other,
+}
+
+/// A failed tool call, as sent back from the event loop.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct ToolError {
+    #[serde(rename = "error")]
+    pub kind: ErrorKind,
+    pub message: String,
+}
+
+impl ToolError {
+    pub fn new(kind: ErrorKind, message: impl Into<String>) -> Self {
+        Self {
+            kind,
+            message: message.into(),
+        }
+    }
+
+    pub fn window_not_found(id: u64) -> Self {
+        Self::new(
+            ErrorKind::WindowNotFound,
+            format!("No window with id {}", id),
+        )
+    }
+
+    /// Prefixes the message with what was being done, e.g. "Failed to launch foo".
+    pub fn context(self, context: impl fmt::Display) -> Self {
+        Self {
+            message: format!("{}: {}", context, self.message),
+            ..self
+        }
+    }
+
+    /// An error result with the message as text and the error as structured
+    /// content.
+    pub fn into_result(self) -> CallToolResult {
+        let mut result = CallToolResult::error(vec![Content::text(self.message.clone())]);
+        result.structured_content = serde_json::to_value(&self).ok();
+        result
+    }
+}
+
+impl fmt::Display for ToolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
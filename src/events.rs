@@ -0,0 +1,261 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use regex::Regex;
+use rmcp::{
+    model::{LoggingLevel, LoggingMessageNotificationParam},
+    Peer, RoleServer,
+};
+use serde::Serialize;
+use smithay::{
+    desktop::Window,
+    reexports::calloop::timer::{TimeoutAction, Timer},
+};
+use tokio::sync::mpsc::UnboundedReceiver;
+
+use crate::{
+    errors::{ErrorKind, ToolError},
+    notifications::Notification,
+    portal::FileChooserRequest,
+    process::ProcessInfo,
+    state::{WindowId, WindowInfo, WindowLabel},
+    Smallvil,
+};
+
+/// A change to a toplevel, an app exiting, a desktop notification or a portal
+/// dialog, sent to MCP clients as a `windows`, `processes`, `notifications` or
+/// `portals` log notification.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum WindowEvent {
+    Created {
+        window: WindowInfo,
+    },
+    /// The window showed its first buffer
+    Mapped {
+        window: WindowInfo,
+    },
+    Retitled {
+        window: WindowInfo,
+    },
+    Resized {
+        window: WindowInfo,
+    },
+    Destroyed {
+        window: WindowLabel,
+    },
+    /// The window got keyboard focus
+    Focused {
+        window: WindowInfo,
+    },
+    /// The window asked for focus through xdg-activation; `granted` is false
+    /// when its token had expired
+    ActivationRequested {
+        window: WindowInfo,
+        granted: bool,
+    },
+    /// An app started by launch_app exited or crashed
+    AppExited {
+        process: ProcessInfo,
+    },
+    /// An app sent a desktop notification, or replaced one
+    NotificationShown {
+        notification: Notification,
+    },
+    /// A desktop notification was closed by its app or dismissed
+    NotificationClosed {
+        notification: Notification,
+    },
+    /// An app opened a file chooser through the FileChooser portal; answer it
+    /// with stage_file_chooser_response
+    FileChooserOpened {
+        request: FileChooserRequest,
+    },
+}
+
+impl WindowEvent {
+    fn logger(&self) -> &'static str {
+        match self {
+            Self::AppExited { .. } => "processes",
+            Self::NotificationShown { .. } | Self::NotificationClosed { .. } => "notifications",
+            Self::FileChooserOpened { .. } => "portals",
+            _ => "windows",
+        }
+    }
+
+    /// Crashes and apps killed by their resource limits are errors
+    fn level(&self) -> LoggingLevel {
+        match self {
+            Self::AppExited { process } if process.crashed || process.limit_exceeded.is_some() => {
+                LoggingLevel::Error
+            }
+            _ => LoggingLevel::Info,
+        }
+    }
+}
+
+/// A wait_for_window call, answered by the first mapped window matching both
+/// patterns that are set.
+pub struct WindowWait {
+    pub app_id: Option<Regex>,
+    pub title: Option<Regex>,
+    pub response_tx: tokio::sync::oneshot::Sender<Result<WindowInfo, ToolError>>,
+}
+
+impl WindowWait {
+    fn matches(&self, info: &WindowInfo) -> bool {
+        let matches = |pattern: &Option<Regex>, value: &Option<String>| {
+            pattern
+                .as_ref()
+                .is_none_or(|pattern| value.as_deref().is_some_and(|v| pattern.is_match(v)))
+        };
+        matches(&self.app_id, &info.app_id) && matches(&self.title, &info.title)
+    }
+}
+
+impl Smallvil {
+    pub fn emit_window_event(&self, event: WindowEvent) {
+        if let Some(events) = &self.window_events {
+            let _ = events.send(event);
+        }
+    }
+
+    pub fn window_created(&self, window: &Window) {
+        self.emit_window_event(WindowEvent::Created {
+            window: self.window_info(window),
+        });
+    }
+
+    /// Called when a window commits with a buffer attached: the first time
+    /// reports it as mapped, later ones only when its size has changed.
+    pub fn window_committed(&mut self, window: &Window) {
+        let id = WindowId::of(window).0;
+        let size = window.geometry().size;
+        match self.mapped_windows.insert(id, size) {
+            None => {
+                self.window_mapped(window);
+                self.emit_window_event(WindowEvent::Mapped {
+                    window: self.window_info(window),
+                });
+                self.resolve_window_waits(window);
+            }
+            Some(previous) if previous != size => {
+                self.emit_window_event(WindowEvent::Resized {
+                    window: self.window_info(window),
+                });
+            }
+            Some(_) => {}
+        }
+    }
+
+    pub fn window_retitled(&mut self, window: &Window) {
+        self.emit_window_event(WindowEvent::Retitled {
+            window: self.window_info(window),
+        });
+        // Titles are often set after the first buffer
+        if self.mapped_windows.contains_key(&WindowId::of(window).0) {
+            self.resolve_window_waits(window);
+        }
+    }
+
+    pub fn window_destroyed(&mut self, window: &Window) {
+        let id = WindowId::of(window).0;
+        self.mapped_windows.remove(&id);
+        self.window_owners.remove(&id);
+        self.emit_window_event(WindowEvent::Destroyed {
+            window: WindowLabel::from_window(window),
+        });
+    }
+}
+
+impl Smallvil {
+    /// Replies right away if a mapped window already matches, otherwise once
+    /// one maps or gets a matching title, or with an error after `timeout`.
+    pub fn wait_for_window(&mut self, wait: WindowWait, timeout: Duration) {
+        let existing = self
+            .space
+            .elements()
+            .filter(|window| self.mapped_windows.contains_key(&WindowId::of(window).0))
+            .map(|window| self.window_info(window))
+            .find(|info| wait.matches(info));
+        if let Some(info) = existing {
+            let _ = wait.response_tx.send(Ok(info));
+            return;
+        }
+
+        let id = self.next_window_wait;
+        self.next_window_wait += 1;
+        self.window_waits.insert(id, wait);
+
+        let timer =
+            self.loop_handle
+                .insert_source(Timer::from_duration(timeout), move |_, _, data| {
+                    if let Some(wait) = data.state.window_waits.remove(&id) {
+                        let _ = wait.response_tx.send(Err(ToolError::new(
+                            ErrorKind::Timeout,
+                            format!("No matching window within {} ms", timeout.as_millis()),
+                        )));
+                    }
+                    TimeoutAction::Drop
+                });
+        if let Err(e) = timer {
+            tracing::error!("Failed to schedule window wait timeout: {}", e);
+        }
+    }
+
+    fn resolve_window_waits(&mut self, window: &Window) {
+        if self.window_waits.is_empty() {
+            return;
+        }
+        let info = self.window_info(window);
+        let matched: Vec<u64> = self
+            .window_waits
+            .iter()
+            .filter(|(_, wait)| wait.matches(&info))
+            .map(|(id, _)| *id)
+            .collect();
+        for id in matched {
+            let wait = self.window_waits.remove(&id).unwrap();
+            let _ = wait.response_tx.send(Ok(info.clone()));
+        }
+    }
+}
+
+/// MCP clients that window events are forwarded to, added as they finish
+/// initializing.
+pub type Subscribers = Arc<Mutex<Vec<Peer<RoleServer>>>>;
+
+/// Forwards window and process events to every connected MCP client, dropping clients
+/// that have gone away.
+pub async fn forward_window_events(
+    subscribers: Subscribers,
+    mut events: UnboundedReceiver<WindowEvent>,
+) {
+    while let Some(event) = events.recv().await {
+        let data = match serde_json::to_value(&event) {
+            Ok(data) => data,
+            Err(e) => {
+                tracing::warn!("Failed to serialize window event: {}", e);
+                continue;
+            }
+        };
+        let notification = LoggingMessageNotificationParam {
+            level: event.level(),
+            logger: Some(event.logger().to_string()),
+            data,
+        };
+
+        let peers = subscribers.lock().unwrap().clone();
+        for peer in peers {
+            if let Err(e) = peer.notify_logging_message(notification.clone()).await {
+                tracing::warn!("Failed to send window event: {}", e);
+            }
+        }
+        subscribers
+            .lock()
+            .unwrap()
+            .retain(|peer| !peer.is_transport_closed());
+    }
+}
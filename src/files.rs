@@ -0,0 +1,116 @@
+use std::{
+    fs::OpenOptions,
+    io::{Read, Write},
+    os::unix::fs::OpenOptionsExt,
+    path::{Component, Path, PathBuf},
+};
+
+/// Largest file read_file returns.
+const READ_LIMIT: u64 = 16 * 1024 * 1024;
+
+/// The directory agents stage fixtures in and collect artifacts from with
+/// read_file and write_file. Paths are taken relative to it and may not leave
+/// it, through `..` or through symlinks an app created inside it.
+#[derive(Debug, Clone)]
+pub struct SessionDir {
+    root: PathBuf,
+}
+
+impl SessionDir {
+    /// Uses `path`, or a fresh directory under the system temp dir, creating
+    /// it if needed.
+    pub fn create(path: Option<&str>) -> std::io::Result<Self> {
+        let path = match path {
+            Some(path) => PathBuf::from(path),
+            None => std::env::temp_dir().join(format!("mcpvil-session-{}", std::process::id())),
+        };
+        std::fs::create_dir_all(&path)?;
+        Ok(Self {
+            root: path.canonicalize()?,
+        })
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Reads a whole file.
+    pub fn read(&self, path: &str) -> Result<Vec<u8>, String> {
+        let full = self.resolve(path)?;
+        self.contain(&full, path)?;
+        let file = OpenOptions::new()
+            .read(true)
+            .open(&full)
+            .map_err(|e| format!("Failed to open {}: {}", path, e))?;
+        let len = file.metadata().map(|metadata| metadata.len()).unwrap_or(0);
+        if len > READ_LIMIT {
+            return Err(format!(
+                "{} is {} bytes, more than the {} read_file returns",
+                path, len, READ_LIMIT
+            ));
+        }
+        let mut data = Vec::new();
+        file.take(READ_LIMIT)
+            .read_to_end(&mut data)
+            .map_err(|e| format!("Failed to read {}: {}", path, e))?;
+        Ok(data)
+    }
+
+    /// Writes or appends to a file, creating it and its parent directories.
+    /// Returns its absolute path.
+    pub fn write(&self, path: &str, data: &[u8], append: bool) -> Result<PathBuf, String> {
+        let full = self.resolve(path)?;
+        let parent = full.parent().unwrap_or(&self.root);
+        // The deepest part that already exists must be inside; what's created below it is
+        let existing = parent
+            .ancestors()
+            .find(|ancestor| std::fs::symlink_metadata(ancestor).is_ok())
+            .unwrap_or(&self.root);
+        self.contain(existing, path)?;
+        std::fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create the directories for {}: {}", path, e))?;
+
+        // Never write through a symlink in the last component
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .append(append)
+            .truncate(!append)
+            .custom_flags(libc::O_NOFOLLOW)
+            .open(&full)
+            .map_err(|e| format!("Failed to open {}: {}", path, e))?;
+        file.write_all(data)
+            .map_err(|e| format!("Failed to write {}: {}", path, e))?;
+        Ok(full)
+    }
+
+    /// Joins a path given by a client onto the root, rejecting ones that are
+    /// absolute (outside the root) or climb out with `..`.
+    fn resolve(&self, path: &str) -> Result<PathBuf, String> {
+        let requested = Path::new(path);
+        let relative = requested.strip_prefix(&self.root).unwrap_or(requested);
+        let valid = relative
+            .components()
+            .all(|component| matches!(component, Component::Normal(_) | Component::CurDir));
+        if !valid || relative.file_name().is_none() {
+            return Err(format!(
+                "Invalid path '{}': expected a file path relative to the session directory {}",
+                path,
+                self.root.display()
+            ));
+        }
+        Ok(self.root.join(relative))
+    }
+
+    /// Checks that `full` is still inside the root once symlinks are followed.
+    fn contain(&self, full: &Path, path: &str) -> Result<(), String> {
+        let resolved = full
+            .canonicalize()
+            .map_err(|e| format!("Failed to resolve {}: {}", path, e))?;
+        if resolved.starts_with(&self.root) {
+            Ok(())
+        } else {
+            Err(format!("{} leads outside the session directory", path))
+        }
+    }
+}
@@ -0,0 +1,74 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+/// The system-wide installation; the per-user one is under XDG_DATA_HOME.
+const SYSTEM_INSTALLATION: &str = "/var/lib/flatpak";
+
+/// Whether the flatpak command is on PATH.
+pub fn available() -> bool {
+    std::env::var_os("PATH")
+        .is_some_and(|path| std::env::split_paths(&path).any(|dir| dir.join("flatpak").is_file()))
+}
+
+/// The `exports/share` directories of the user and system installations,
+/// where Flatpak puts the desktop entries of installed apps.
+pub fn export_dirs() -> Vec<PathBuf> {
+    let user = std::env::var_os("XDG_DATA_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".local/share")))
+        .map(|data_home| data_home.join("flatpak"));
+    user.into_iter()
+        .chain([PathBuf::from(SYSTEM_INSTALLATION)])
+        .map(|installation| installation.join("exports/share"))
+        .filter(|dir| dir.is_dir())
+        .collect()
+}
+
+/// Whether a command line runs `flatpak run`, as the Exec lines of exported
+/// desktop entries do.
+pub fn is_flatpak_run(argv: &[String]) -> bool {
+    argv.len() >= 2 && argv[0].rsplit('/').next() == Some("flatpak") && argv[1] == "run"
+}
+
+/// The command line that runs Flatpak app `app_id`, or another `command` from
+/// it, with `args`.
+pub fn run_command_line(app_id: &str, command: Option<&str>, args: &[String]) -> Vec<String> {
+    let mut argv = vec![String::from("flatpak"), String::from("run")];
+    if let Some(command) = command {
+        argv.push(format!("--command={}", command));
+    }
+    argv.push(app_id.to_string());
+    argv.extend(args.iter().cloned());
+    argv
+}
+
+/// Adds the options that connect an app to mcpvil to a `flatpak run` command
+/// line: access to the Wayland socket named by WAYLAND_DISPLAY (mcpvil's), the
+/// extra environment, which Flatpak would otherwise partly filter, and the
+/// session directory. File forwarding through the document portal is turned
+/// off, as the portal may not be running; files in the session directory are
+/// reachable anyway.
+pub fn add_run_options(
+    argv: &mut Vec<String>,
+    env: &HashMap<String, String>,
+    session_dir: Option<&Path>,
+) {
+    argv.retain(|arg| arg != "--file-forwarding");
+    let mut options = vec![String::from("--socket=wayland")];
+    options.extend(
+        env.iter()
+            .map(|(key, value)| format!("--env={}={}", key, value)),
+    );
+    if let Some(session_dir) = session_dir {
+        options.push(format!("--filesystem={}", session_dir.display()));
+        options.push(format!(
+            "--env=MCPVIL_SESSION_DIR={}",
+            session_dir.display()
+        ));
+    }
+    // Options go between `run` and the app id
+    argv.splice(2..2, options);
+}
@@ -0,0 +1,160 @@
+use std::{
+    collections::VecDeque,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use image::{DynamicImage, RgbaImage};
+
+use crate::screenshot::{self, ImageFormat};
+
+/// Rate the primary output is sampled at for the history.
+const HISTORY_FPS: u32 = 30;
+
+/// Frames are scaled down to at most this width, keeping their aspect ratio.
+const MAX_WIDTH: u32 = 640;
+
+/// The most seconds --frame-history keeps, bounding its memory use.
+const MAX_SECONDS: f64 = 60.0;
+
+/// A frame kept in the history.
+#[derive(Debug, Clone)]
+pub struct RecentFrame {
+    /// When it was rendered
+    pub rendered: Instant,
+    /// Time since the compositor started, in milliseconds
+    pub uptime_ms: u64,
+    pub image: RgbaImage,
+}
+
+/// A flight recorder of the primary output: the frames rendered in the last
+/// few seconds, downscaled and kept in memory, for get_recent_frames to show
+/// what led up to a failure.
+#[derive(Debug)]
+pub struct FrameHistory {
+    frames: VecDeque<RecentFrame>,
+    capacity: usize,
+    last_frame: Option<Instant>,
+}
+
+impl FrameHistory {
+    /// A history of the last `seconds` of frames.
+    pub fn new(seconds: f64) -> Result<Self, String> {
+        if seconds.is_nan() || seconds <= 0.0 || seconds > MAX_SECONDS {
+            return Err(format!(
+                "Frame history of {} seconds is out of range, expected up to {}",
+                seconds, MAX_SECONDS
+            ));
+        }
+        let capacity = (seconds * HISTORY_FPS as f64).ceil() as usize;
+        Ok(Self {
+            frames: VecDeque::with_capacity(capacity),
+            capacity,
+            last_frame: None,
+        })
+    }
+
+    /// Whether a frame is due at the sampling rate. Redraws in between are
+    /// not read back for the history.
+    pub fn wants_frame(&self) -> bool {
+        let interval = Duration::from_secs(1) / HISTORY_FPS;
+        self.last_frame
+            .is_none_or(|last| last.elapsed() >= interval)
+    }
+
+    /// Keeps a downscaled copy of a rendered frame, dropping the oldest.
+    pub fn push_frame(&mut self, img: &RgbaImage, uptime: Duration) {
+        let now = Instant::now();
+        self.last_frame = Some(now);
+
+        let image = if img.width() > MAX_WIDTH {
+            let height = (img.height() as u64 * MAX_WIDTH as u64 / img.width() as u64).max(1);
+            image::imageops::thumbnail(img, MAX_WIDTH, height as u32)
+        } else {
+            img.clone()
+        };
+        if self.frames.len() == self.capacity {
+            self.frames.pop_front();
+        }
+        self.frames.push_back(RecentFrame {
+            rendered: now,
+            uptime_ms: uptime.as_millis() as u64,
+            image,
+        });
+    }
+
+    /// Up to `max_frames` of the frames rendered in the last `seconds` (or
+    /// all kept), oldest first, evenly spaced when there are more.
+    pub fn recent(&self, seconds: Option<f64>, max_frames: usize) -> Vec<RecentFrame> {
+        let window: Vec<&RecentFrame> = self
+            .frames
+            .iter()
+            .filter(|frame| {
+                seconds.is_none_or(|seconds| frame.rendered.elapsed().as_secs_f64() <= seconds)
+            })
+            .collect();
+        if window.len() <= max_frames {
+            return window.into_iter().cloned().collect();
+        }
+        // Always include the newest frame, the one closest to the failure
+        (0..max_frames)
+            .map(|i| {
+                let index = (window.len() - 1) - i * (window.len() - 1) / (max_frames - 1).max(1);
+                window[index].clone()
+            })
+            .rev()
+            .collect()
+    }
+}
+
+/// A frame encoded for get_recent_frames.
+pub struct EncodedFrame {
+    pub uptime_ms: u64,
+    /// How long before the call it was rendered
+    pub age: Duration,
+    pub width: u32,
+    pub height: u32,
+    pub data: Vec<u8>,
+    /// Where it was saved, if a directory was given
+    pub path: Option<PathBuf>,
+}
+
+/// Encodes frames, saving them into `directory` as frame-<uptime ms>.<ext>
+/// if given.
+pub fn encode_frames(
+    frames: Vec<RecentFrame>,
+    format: ImageFormat,
+    quality: Option<u8>,
+    directory: Option<&Path>,
+) -> Result<Vec<EncodedFrame>, String> {
+    if let Some(directory) = directory {
+        std::fs::create_dir_all(directory)
+            .map_err(|e| format!("Failed to create {}: {}", directory.display(), e))?;
+    }
+    frames
+        .into_iter()
+        .map(|frame| {
+            let age = frame.rendered.elapsed();
+            let (width, height) = frame.image.dimensions();
+            let data = screenshot::encode(&DynamicImage::ImageRgba8(frame.image), format, quality)?;
+            let path = match directory {
+                Some(directory) => {
+                    let path =
+                        directory.join(format!("frame-{}.{}", frame.uptime_ms, format.extension()));
+                    std::fs::write(&path, &data)
+                        .map_err(|e| format!("Failed to save {}: {}", path.display(), e))?;
+                    Some(path)
+                }
+                None => None,
+            };
+            Ok(EncodedFrame {
+                uptime_ms: frame.uptime_ms,
+                age,
+                width,
+                height,
+                data,
+                path,
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,135 @@
+use std::time::{Duration, Instant};
+
+use smithay::{
+    input::pointer::{
+        GesturePinchBeginEvent, GesturePinchEndEvent, GesturePinchUpdateEvent,
+        GestureSwipeBeginEvent, GestureSwipeEndEvent, GestureSwipeUpdateEvent,
+    },
+    utils::{Logical, Point, SERIAL_COUNTER},
+};
+
+use crate::{input::PointerTarget, Smallvil};
+
+/// What a touchpad gesture does over its whole duration.
+#[derive(Debug, Clone, Copy)]
+pub enum GestureKind {
+    /// Fingers moving together by `delta`
+    Swipe {
+        fingers: u32,
+        delta: Point<f64, Logical>,
+    },
+    /// Fingers spreading to `scale` (1.0 is the starting distance) and turning
+    /// by `rotation` degrees clockwise
+    Pinch {
+        fingers: u32,
+        scale: f64,
+        rotation: f64,
+    },
+}
+
+/// A pointer gesture played back over several frames at the pointer location.
+pub struct Gesture {
+    pub kind: GestureKind,
+    pub duration: Duration,
+    pub started: Instant,
+    /// Progress already sent in update events
+    pub progress: f64,
+    pub response_tx: tokio::sync::oneshot::Sender<Result<PointerTarget, String>>,
+}
+
+impl Smallvil {
+    /// Moves the pointer to `pos` and begins `gesture` there. The updates are
+    /// sent by `advance_gesture` on each redraw.
+    pub fn start_gesture(&mut self, pos: Point<f64, Logical>, gesture: Gesture) {
+        self.synthetic_motion(pos);
+
+        let serial = SERIAL_COUNTER.next_serial();
+        let time = self.start_time.elapsed().as_millis() as u32;
+        let pointer = self.seat.get_pointer().unwrap();
+        match gesture.kind {
+            GestureKind::Swipe { fingers, .. } => pointer.gesture_swipe_begin(
+                self,
+                &GestureSwipeBeginEvent {
+                    serial,
+                    time,
+                    fingers,
+                },
+            ),
+            GestureKind::Pinch { fingers, .. } => pointer.gesture_pinch_begin(
+                self,
+                &GesturePinchBeginEvent {
+                    serial,
+                    time,
+                    fingers,
+                },
+            ),
+        }
+
+        self.pending_gesture = Some(gesture);
+    }
+
+    /// Sends the part of the gesture due since the last frame, and ends it
+    /// once its duration has passed.
+    pub fn advance_gesture(&mut self) {
+        let Some(gesture) = self.pending_gesture.as_mut() else {
+            return;
+        };
+
+        let progress = if gesture.duration.is_zero() {
+            1.0
+        } else {
+            (gesture.started.elapsed().as_secs_f64() / gesture.duration.as_secs_f64()).min(1.0)
+        };
+        let step = progress - gesture.progress;
+        gesture.progress = progress;
+        let kind = gesture.kind;
+
+        let time = self.start_time.elapsed().as_millis() as u32;
+        let pointer = self.seat.get_pointer().unwrap();
+        match kind {
+            GestureKind::Swipe { delta, .. } => pointer.gesture_swipe_update(
+                self,
+                &GestureSwipeUpdateEvent {
+                    time,
+                    delta: delta.upscale(step),
+                },
+            ),
+            // Pinch scale is absolute, rotation is relative to the previous update
+            GestureKind::Pinch {
+                scale, rotation, ..
+            } => pointer.gesture_pinch_update(
+                self,
+                &GesturePinchUpdateEvent {
+                    time,
+                    delta: (0.0, 0.0).into(),
+                    scale: 1.0 + (scale - 1.0) * progress,
+                    rotation: rotation * step,
+                },
+            ),
+        }
+
+        if progress >= 1.0 {
+            let gesture = self.pending_gesture.take().unwrap();
+            let serial = SERIAL_COUNTER.next_serial();
+            match gesture.kind {
+                GestureKind::Swipe { .. } => pointer.gesture_swipe_end(
+                    self,
+                    &GestureSwipeEndEvent {
+                        serial,
+                        time,
+                        cancelled: false,
+                    },
+                ),
+                GestureKind::Pinch { .. } => pointer.gesture_pinch_end(
+                    self,
+                    &GesturePinchEndEvent {
+                        serial,
+                        time,
+                        cancelled: false,
+                    },
+                ),
+            }
+            let _ = gesture.response_tx.send(Ok(self.pointer_target()));
+        }
+    }
+}
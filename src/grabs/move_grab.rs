@@ -29,9 +29,16 @@ impl PointerGrab<Smallvil> for MoveSurfaceGrab {
         handle.motion(data, None, event);
 
         let delta = event.location - self.start_data.location;
-        let new_location = self.initial_window_location.to_f64() + delta;
+        let new_location = (self.initial_window_location.to_f64() + delta).to_i32_round();
         data.space
-            .map_element(self.window.clone(), new_location.to_i32_round(), true);
+            .map_element(self.window.clone(), new_location, true);
+
+        // X11 clients position their own popups, so tell them where they are now
+        if let Some(x11) = self.window.x11_surface() {
+            let mut geometry = x11.geometry();
+            geometry.loc = new_location;
+            let _ = x11.configure(geometry);
+        }
     }
 
     fn relative_motion(
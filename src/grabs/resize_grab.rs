@@ -1,6 +1,6 @@
 use crate::Smallvil;
 use smithay::{
-    desktop::{Space, Window},
+    desktop::{Space, Window, WindowSurface},
     input::pointer::{
         AxisFrame, ButtonEvent, GestureHoldBeginEvent, GestureHoldEndEvent, GesturePinchBeginEvent,
         GesturePinchEndEvent, GesturePinchUpdateEvent, GestureSwipeBeginEvent,
@@ -13,6 +13,7 @@ use smithay::{
     },
     utils::{Logical, Point, Rectangle, Size},
     wayland::{compositor, shell::xdg::SurfaceCachedState},
+    xwayland::xwm::ResizeEdge as X11ResizeEdge,
 };
 use std::cell::RefCell;
 
@@ -39,6 +40,22 @@ impl From<xdg_toplevel::ResizeEdge> for ResizeEdge {
     }
 }
 
+impl From<X11ResizeEdge> for ResizeEdge {
+    #[inline]
+    fn from(x: X11ResizeEdge) -> Self {
+        match x {
+            X11ResizeEdge::Top => Self::TOP,
+            X11ResizeEdge::Bottom => Self::BOTTOM,
+            X11ResizeEdge::Left => Self::LEFT,
+            X11ResizeEdge::Right => Self::RIGHT,
+            X11ResizeEdge::TopLeft => Self::TOP_LEFT,
+            X11ResizeEdge::BottomLeft => Self::BOTTOM_LEFT,
+            X11ResizeEdge::TopRight => Self::TOP_RIGHT,
+            X11ResizeEdge::BottomRight => Self::BOTTOM_RIGHT,
+        }
+    }
+}
+
 pub struct ResizeSurfaceGrab {
     start_data: PointerGrabStartData<Smallvil>,
     window: Window,
@@ -58,12 +75,14 @@ impl ResizeSurfaceGrab {
     ) -> Self {
         let initial_rect = initial_window_rect;
 
-        ResizeSurfaceState::with(window.toplevel().unwrap().wl_surface(), |state| {
-            *state = ResizeSurfaceState::Resizing {
-                edges,
-                initial_rect,
-            };
-        });
+        if let Some(toplevel) = window.toplevel() {
+            ResizeSurfaceState::with(toplevel.wl_surface(), |state| {
+                *state = ResizeSurfaceState::Resizing {
+                    edges,
+                    initial_rect,
+                };
+            });
+        }
 
         Self {
             start_data,
@@ -107,12 +126,17 @@ impl PointerGrab<Smallvil> for ResizeSurfaceGrab {
             new_window_height = (self.initial_rect.size.h as f64 + delta.y) as i32;
         }
 
-        let (min_size, max_size) =
-            compositor::with_states(self.window.toplevel().unwrap().wl_surface(), |states| {
+        let (min_size, max_size) = match self.window.underlying_surface() {
+            WindowSurface::Wayland(xdg) => compositor::with_states(xdg.wl_surface(), |states| {
                 let mut guard = states.cached_state.get::<SurfaceCachedState>();
                 let data = guard.current();
                 (data.min_size, data.max_size)
-            });
+            }),
+            WindowSurface::X11(x11) => (
+                x11.min_size().unwrap_or_default(),
+                x11.max_size().unwrap_or_default(),
+            ),
+        };
 
         let min_width = min_size.w.max(1);
         let min_height = min_size.h.max(1);
@@ -133,13 +157,29 @@ impl PointerGrab<Smallvil> for ResizeSurfaceGrab {
             new_window_height.max(min_height).min(max_height),
         ));
 
-        let xdg = self.window.toplevel().unwrap();
-        xdg.with_pending_state(|state| {
-            state.states.set(xdg_toplevel::State::Resizing);
-            state.size = Some(self.last_window_size);
-        });
+        match self.window.underlying_surface() {
+            WindowSurface::Wayland(xdg) => {
+                xdg.with_pending_state(|state| {
+                    state.states.set(xdg_toplevel::State::Resizing);
+                    state.size = Some(self.last_window_size);
+                });
 
-        xdg.send_pending_configure();
+                xdg.send_pending_configure();
+            }
+            WindowSurface::X11(x11) => {
+                // X11 clients take the geometry they are given, so there is no
+                // commit to wait for before moving the top or left edge
+                let mut location = self.initial_rect.loc;
+                if self.edges.intersects(ResizeEdge::LEFT) {
+                    location.x += self.initial_rect.size.w - self.last_window_size.w;
+                }
+                if self.edges.intersects(ResizeEdge::TOP) {
+                    location.y += self.initial_rect.size.h - self.last_window_size.h;
+                }
+                let _ = x11.configure(Rectangle::new(location, self.last_window_size));
+                data.space.map_element(self.window.clone(), location, true);
+            }
+        }
     }
 
     fn relative_motion(
@@ -168,20 +208,21 @@ impl PointerGrab<Smallvil> for ResizeSurfaceGrab {
             // No more buttons are pressed, release the grab.
             handle.unset_grab(self, data, event.serial, event.time, true);
 
-            let xdg = self.window.toplevel().unwrap();
-            xdg.with_pending_state(|state| {
-                state.states.unset(xdg_toplevel::State::Resizing);
-                state.size = Some(self.last_window_size);
-            });
+            if let Some(xdg) = self.window.toplevel() {
+                xdg.with_pending_state(|state| {
+                    state.states.unset(xdg_toplevel::State::Resizing);
+                    state.size = Some(self.last_window_size);
+                });
 
-            xdg.send_pending_configure();
+                xdg.send_pending_configure();
 
-            ResizeSurfaceState::with(xdg.wl_surface(), |state| {
-                *state = ResizeSurfaceState::WaitingForLastCommit {
-                    edges: self.edges,
-                    initial_rect: self.initial_rect,
-                };
-            });
+                ResizeSurfaceState::with(xdg.wl_surface(), |state| {
+                    *state = ResizeSurfaceState::WaitingForLastCommit {
+                        edges: self.edges,
+                        initial_rect: self.initial_rect,
+                    };
+                });
+            }
         }
     }
 
@@ -335,7 +376,7 @@ impl ResizeSurfaceState {
 pub fn handle_commit(space: &mut Space<Window>, surface: &WlSurface) -> Option<()> {
     let window = space
         .elements()
-        .find(|w| w.toplevel().unwrap().wl_surface() == surface)
+        .find(|w| w.toplevel().is_some_and(|t| t.wl_surface() == surface))
         .cloned()?;
 
     let mut window_loc = space.element_location(&window)?;
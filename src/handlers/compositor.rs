@@ -1,6 +1,6 @@
-use crate::{grabs::resize_grab, state::ClientState, Smallvil};
+use crate::{grabs::resize_grab, state::ClientState, CalloopData, Smallvil};
 use smithay::{
-    backend::renderer::utils::on_commit_buffer_handler,
+    backend::renderer::utils::{on_commit_buffer_handler, with_renderer_surface_state},
     delegate_compositor, delegate_shm,
     reexports::wayland_server::{
         protocol::{wl_buffer, wl_surface::WlSurface},
@@ -14,9 +14,10 @@ use smithay::{
         },
         shm::{ShmHandler, ShmState},
     },
+    xwayland::{X11Wm, XWaylandClientData},
 };
 
-use super::xdg_shell;
+use super::{layer_shell, xdg_shell};
 
 impl CompositorHandler for Smallvil {
     fn compositor_state(&mut self) -> &mut CompositorState {
@@ -24,10 +25,16 @@ impl CompositorHandler for Smallvil {
     }
 
     fn client_compositor_state<'a>(&self, client: &'a Client) -> &'a CompositorClientState {
+        if let Some(state) = client.get_data::<XWaylandClientData>() {
+            return &state.compositor_state;
+        }
         &client.get_data::<ClientState>().unwrap().compositor_state
     }
 
     fn commit(&mut self, surface: &WlSurface) {
+        X11Wm::commit_hook::<CalloopData>(surface);
+        self.note_commit();
+
         on_commit_buffer_handler::<Self>(surface);
         if !is_sync_subsurface(surface) {
             let mut root = surface.clone();
@@ -37,13 +44,27 @@ impl CompositorHandler for Smallvil {
             if let Some(window) = self
                 .space
                 .elements()
-                .find(|w| w.toplevel().unwrap().wl_surface() == &root)
+                .find(|w| w.wl_surface().as_deref() == Some(&root))
+                .cloned()
+            {
+                window.on_commit();
+
+                let has_buffer =
+                    with_renderer_surface_state(&root, |state| state.buffer().is_some());
+                if has_buffer.unwrap_or(false) {
+                    self.window_committed(&window);
+                }
+            } else if let Some(window) = self
+                .override_redirect_windows
+                .iter()
+                .find(|w| w.wl_surface().as_deref() == Some(&root))
             {
                 window.on_commit();
             }
         };
 
         xdg_shell::handle_commit(&mut self.popups, &self.space, surface);
+        layer_shell::handle_commit(&self.space, surface);
         resize_grab::handle_commit(&mut self.space, surface);
     }
 }
@@ -0,0 +1,45 @@
+use smithay::{
+    delegate_xdg_decoration,
+    reexports::wayland_protocols::xdg::decoration::zv1::server::zxdg_toplevel_decoration_v1::Mode as DecorationMode,
+    wayland::shell::xdg::{decoration::XdgDecorationHandler, ToplevelSurface},
+};
+
+use crate::Smallvil;
+
+impl XdgDecorationHandler for Smallvil {
+    fn new_decoration(&mut self, toplevel: ToplevelSurface) {
+        self.set_decoration_mode(&toplevel, None);
+    }
+
+    fn request_mode(&mut self, toplevel: ToplevelSurface, mode: DecorationMode) {
+        self.set_decoration_mode(&toplevel, Some(mode));
+    }
+
+    fn unset_mode(&mut self, toplevel: ToplevelSurface) {
+        self.set_decoration_mode(&toplevel, None);
+    }
+}
+
+delegate_xdg_decoration!(Smallvil);
+
+impl Smallvil {
+    /// With server-side decorations enabled, clients get the mode they ask for
+    /// and server-side by default; otherwise they always draw their own.
+    fn set_decoration_mode(
+        &mut self,
+        toplevel: &ToplevelSurface,
+        requested: Option<DecorationMode>,
+    ) {
+        let mode = if self.server_decorations {
+            requested.unwrap_or(DecorationMode::ServerSide)
+        } else {
+            DecorationMode::ClientSide
+        };
+        toplevel.with_pending_state(|state| {
+            state.decoration_mode = Some(mode);
+        });
+        if toplevel.is_initial_configure_sent() {
+            toplevel.send_pending_configure();
+        }
+    }
+}
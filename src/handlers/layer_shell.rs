@@ -0,0 +1,87 @@
+use smithay::{
+    delegate_layer_shell,
+    desktop::{layer_map_for_output, LayerSurface, Space, Window, WindowSurfaceType},
+    output::Output,
+    reexports::wayland_server::protocol::{wl_output::WlOutput, wl_surface::WlSurface},
+    wayland::{
+        compositor::with_states,
+        shell::wlr_layer::{
+            Layer, LayerSurface as WlrLayerSurface, LayerSurfaceData, WlrLayerShellHandler,
+            WlrLayerShellState,
+        },
+    },
+};
+
+use crate::Smallvil;
+
+impl WlrLayerShellHandler for Smallvil {
+    fn shell_state(&mut self) -> &mut WlrLayerShellState {
+        &mut self.layer_shell_state
+    }
+
+    fn new_layer_surface(
+        &mut self,
+        surface: WlrLayerSurface,
+        output: Option<WlOutput>,
+        _layer: Layer,
+        namespace: String,
+    ) {
+        // Surfaces that leave the output to the compositor go on the primary one
+        let Some(output) = output
+            .as_ref()
+            .and_then(Output::from_resource)
+            .or_else(|| self.primary_output().cloned())
+        else {
+            return;
+        };
+        let mut map = layer_map_for_output(&output);
+        if let Err(e) = map.map_layer(&LayerSurface::new(surface, namespace)) {
+            tracing::warn!("Failed to map layer surface: {}", e);
+        }
+    }
+
+    fn layer_destroyed(&mut self, surface: WlrLayerSurface) {
+        for output in self.space.outputs() {
+            let mut map = layer_map_for_output(output);
+            let layer = map
+                .layers()
+                .find(|layer| layer.layer_surface() == &surface)
+                .cloned();
+            if let Some(layer) = layer {
+                map.unmap_layer(&layer);
+            }
+        }
+    }
+}
+
+delegate_layer_shell!(Smallvil);
+
+/// Should be called on `WlSurface::commit`: lays out the layer surfaces of the
+/// output the surface is on and sends its initial configure.
+pub fn handle_commit(space: &Space<Window>, surface: &WlSurface) {
+    let Some(output) = space.outputs().find(|output| {
+        layer_map_for_output(output)
+            .layer_for_surface(surface, WindowSurfaceType::TOPLEVEL)
+            .is_some()
+    }) else {
+        return;
+    };
+
+    let initial_configure_sent = with_states(surface, |states| {
+        states
+            .data_map
+            .get::<LayerSurfaceData>()
+            .unwrap()
+            .lock()
+            .unwrap()
+            .initial_configure_sent
+    });
+
+    let mut map = layer_map_for_output(output);
+    map.arrange();
+    if !initial_configure_sent {
+        if let Some(layer) = map.layer_for_surface(surface, WindowSurfaceType::TOPLEVEL) {
+            layer.layer_surface().send_configure();
+        }
+    }
+}
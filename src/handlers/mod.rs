@@ -1,22 +1,46 @@
 mod compositor;
+mod decoration;
+mod layer_shell;
+mod xdg_activation;
 mod xdg_shell;
+mod xwayland;
 
-use crate::Smallvil;
+pub use xdg_shell::{CloseOutcome, CloseResponse, PendingClose, WindowMode};
+
+use crate::{clipboard::CompositorSelection, events::WindowEvent, Smallvil};
 
 //
 // Wl Seat
 //
 
+use std::os::fd::OwnedFd;
+
+use smithay::backend::allocator::dmabuf::Dmabuf;
+use smithay::input::pointer::PointerHandle;
 use smithay::input::{Seat, SeatHandler, SeatState};
+use smithay::reexports::wayland_server::protocol::wl_data_device_manager::DndAction;
 use smithay::reexports::wayland_server::protocol::wl_surface::WlSurface;
 use smithay::reexports::wayland_server::Resource;
+use smithay::utils::{Logical, Point};
+use smithay::wayland::dmabuf::{DmabufGlobal, DmabufHandler, DmabufState, ImportNotifier};
+use smithay::wayland::fractional_scale::FractionalScaleHandler;
 use smithay::wayland::output::OutputHandler;
+use smithay::wayland::pointer_constraints::PointerConstraintsHandler;
 use smithay::wayland::selection::data_device::{
     set_data_device_focus, ClientDndGrabHandler, DataDeviceHandler, DataDeviceState,
     ServerDndGrabHandler,
 };
-use smithay::wayland::selection::SelectionHandler;
-use smithay::{delegate_data_device, delegate_output, delegate_seat};
+use smithay::wayland::selection::primary_selection::{
+    PrimarySelectionHandler, PrimarySelectionState,
+};
+use smithay::wayland::selection::{SelectionHandler, SelectionSource, SelectionTarget};
+use smithay::wayland::tablet_manager::TabletSeatHandler;
+use smithay::{
+    delegate_data_device, delegate_dmabuf, delegate_fractional_scale, delegate_output,
+    delegate_pointer_constraints, delegate_pointer_gestures, delegate_presentation,
+    delegate_primary_selection, delegate_relative_pointer, delegate_seat, delegate_tablet_manager,
+    delegate_viewporter,
+};
 
 impl SeatHandler for Smallvil {
     type KeyboardFocus = WlSurface;
@@ -30,25 +54,83 @@ impl SeatHandler for Smallvil {
     fn cursor_image(
         &mut self,
         _seat: &Seat<Self>,
-        _image: smithay::input::pointer::CursorImageStatus,
+        image: smithay::input::pointer::CursorImageStatus,
     ) {
+        self.cursor_status = image;
     }
 
     fn focus_changed(&mut self, seat: &Seat<Self>, focused: Option<&WlSurface>) {
         let dh = &self.display_handle;
         let client = focused.and_then(|s| dh.get_client(s.id()).ok());
         set_data_device_focus(dh, seat, client);
+
+        let window = focused.and_then(|surface| {
+            self.space
+                .elements()
+                .find(|window| window.wl_surface().as_deref() == Some(surface))
+        });
+        if let Some(window) = window {
+            self.emit_window_event(WindowEvent::Focused {
+                window: self.window_info(window),
+            });
+        }
     }
 }
 
 delegate_seat!(Smallvil);
+delegate_pointer_gestures!(Smallvil);
+delegate_relative_pointer!(Smallvil);
+
+impl PointerConstraintsHandler for Smallvil {
+    fn new_constraint(&mut self, _surface: &WlSurface, _pointer: &PointerHandle<Self>) {
+        self.activate_pointer_constraint();
+    }
+
+    fn cursor_position_hint(
+        &mut self,
+        surface: &WlSurface,
+        pointer: &PointerHandle<Self>,
+        location: Point<f64, Logical>,
+    ) {
+        self.set_cursor_position_hint(surface, pointer, location);
+    }
+}
+
+delegate_pointer_constraints!(Smallvil);
+
+impl TabletSeatHandler for Smallvil {}
+delegate_tablet_manager!(Smallvil);
 
 //
 // Wl Data Device
 //
 
 impl SelectionHandler for Smallvil {
-    type SelectionUserData = ();
+    type SelectionUserData = CompositorSelection;
+
+    fn new_selection(
+        &mut self,
+        ty: SelectionTarget,
+        source: Option<SelectionSource>,
+        _seat: Seat<Self>,
+    ) {
+        let mime_types = source.map(|s| s.mime_types()).unwrap_or_default();
+        match ty {
+            SelectionTarget::Clipboard => self.clipboard_mime_types = mime_types,
+            SelectionTarget::Primary => self.primary_mime_types = mime_types,
+        }
+    }
+
+    fn send_selection(
+        &mut self,
+        ty: SelectionTarget,
+        _mime_type: String,
+        fd: OwnedFd,
+        _seat: Seat<Self>,
+        user_data: &Self::SelectionUserData,
+    ) {
+        self.send_compositor_selection(ty, fd, user_data.clone());
+    }
 }
 
 impl DataDeviceHandler for Smallvil {
@@ -58,13 +140,104 @@ impl DataDeviceHandler for Smallvil {
 }
 
 impl ClientDndGrabHandler for Smallvil {}
-impl ServerDndGrabHandler for Smallvil {}
+impl ServerDndGrabHandler for Smallvil {
+    fn accept(&mut self, mime_type: Option<String>, _seat: Seat<Self>) {
+        if let Some(dnd) = self.pending_dnd.as_mut() {
+            dnd.outcome.accepted = mime_type;
+        }
+    }
+
+    fn action(&mut self, action: DndAction, _seat: Seat<Self>) {
+        if let Some(dnd) = self.pending_dnd.as_mut() {
+            dnd.outcome.action = Some(action);
+        }
+    }
+
+    fn send(&mut self, mime_type: String, fd: OwnedFd, _seat: Seat<Self>) {
+        self.send_dnd_data(mime_type, fd);
+    }
+
+    fn cancelled(&mut self, _seat: Seat<Self>) {
+        self.finish_dnd(false);
+    }
+
+    fn finished(&mut self, _seat: Seat<Self>) {
+        self.finish_dnd(true);
+    }
+}
 
 delegate_data_device!(Smallvil);
 
+//
+// Primary Selection
+//
+
+impl PrimarySelectionHandler for Smallvil {
+    fn primary_selection_state(&self) -> &PrimarySelectionState {
+        &self.primary_selection_state
+    }
+}
+
+delegate_primary_selection!(Smallvil);
+
 //
 // Wl Output & Xdg Output
 //
 
 impl OutputHandler for Smallvil {}
 delegate_output!(Smallvil);
+
+//
+// Linux Dmabuf
+//
+
+impl DmabufHandler for Smallvil {
+    fn dmabuf_state(&mut self) -> &mut DmabufState {
+        &mut self.dmabuf_state
+    }
+
+    fn dmabuf_imported(
+        &mut self,
+        _global: &DmabufGlobal,
+        dmabuf: Dmabuf,
+        notifier: ImportNotifier,
+    ) {
+        // The renderer lives in the backend, so only check the buffer against what it
+        // advertised; the texture is imported when a surface using it is first rendered.
+        let supported = self
+            .dmabuf_formats
+            .as_ref()
+            .is_some_and(|formats| formats.contains(&dmabuf.format()));
+        if supported {
+            let _ = notifier.successful::<Smallvil>();
+        } else {
+            tracing::warn!(
+                "Rejected dmabuf with unsupported format {:?}",
+                dmabuf.format()
+            );
+            notifier.failed();
+        }
+    }
+}
+
+delegate_dmabuf!(Smallvil);
+
+//
+// Viewporter & Fractional Scale
+//
+
+delegate_viewporter!(Smallvil);
+
+impl FractionalScaleHandler for Smallvil {
+    fn new_fractional_scale(&mut self, surface: WlSurface) {
+        self.send_initial_scale(&surface);
+    }
+}
+
+delegate_fractional_scale!(Smallvil);
+
+//
+// Presentation Time
+//
+
+delegate_presentation!(Smallvil);
@@ -0,0 +1,53 @@
+use std::time::Duration;
+
+use smithay::{
+    delegate_xdg_activation,
+    reexports::wayland_server::protocol::wl_surface::WlSurface,
+    utils::SERIAL_COUNTER,
+    wayland::xdg_activation::{
+        XdgActivationHandler, XdgActivationState, XdgActivationToken, XdgActivationTokenData,
+    },
+};
+
+use crate::{events::WindowEvent, Smallvil};
+
+/// How long a token may be used after it was created.
+const TOKEN_LIFETIME: Duration = Duration::from_secs(10);
+
+impl XdgActivationHandler for Smallvil {
+    fn activation_state(&mut self) -> &mut XdgActivationState {
+        &mut self.xdg_activation_state
+    }
+
+    fn token_created(&mut self, _token: XdgActivationToken, _data: XdgActivationTokenData) -> bool {
+        // There is no user to steal focus from, so any client may pass focus on
+        true
+    }
+
+    fn request_activation(
+        &mut self,
+        _token: XdgActivationToken,
+        token_data: XdgActivationTokenData,
+        surface: WlSurface,
+    ) {
+        let Some(window) = self
+            .space
+            .elements()
+            .find(|window| window.wl_surface().as_deref() == Some(&surface))
+            .cloned()
+        else {
+            return;
+        };
+
+        let granted = token_data.timestamp.elapsed() < TOKEN_LIFETIME;
+        if granted {
+            self.focus_window(&window, SERIAL_COUNTER.next_serial());
+        }
+        self.emit_window_event(WindowEvent::ActivationRequested {
+            window: self.window_info(&window),
+            granted,
+        });
+    }
+}
+
+delegate_xdg_activation!(Smallvil);
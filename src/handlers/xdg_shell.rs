@@ -1,20 +1,22 @@
 use smithay::{
     delegate_xdg_shell,
     desktop::{
-        find_popup_root_surface, get_popup_toplevel_coords, PopupKind, PopupManager, Space, Window,
+        find_popup_root_surface, get_popup_toplevel_coords, layer_map_for_output, PopupKind,
+        PopupManager, Space, Window, WindowSurface,
     },
     input::{
         pointer::{Focus, GrabStartData as PointerGrabStartData},
         Seat,
     },
     reexports::{
+        calloop::timer::{TimeoutAction, Timer},
         wayland_protocols::xdg::shell::server::xdg_toplevel,
         wayland_server::{
-            protocol::{wl_seat, wl_surface::WlSurface},
+            protocol::{wl_output::WlOutput, wl_seat, wl_surface::WlSurface},
             Resource,
         },
     },
-    utils::{Rectangle, Serial},
+    utils::{Logical, Point, Rectangle, Serial, Size},
     wayland::{
         compositor::with_states,
         shell::xdg::{
@@ -24,8 +26,13 @@ use smithay::{
     },
 };
 
+use std::{cell::RefCell, time::Duration};
+
 use crate::{
+    decoration,
+    errors::{ErrorKind, ToolError},
     grabs::{MoveSurfaceGrab, ResizeSurfaceGrab},
+    state::WindowId,
     Smallvil,
 };
 
@@ -36,7 +43,16 @@ impl XdgShellHandler for Smallvil {
 
     fn new_toplevel(&mut self, surface: ToplevelSurface) {
         let window = Window::new_wayland_window(surface);
-        self.space.map_element(window, (0, 0), false);
+        // Hand out the id now so ids follow creation order
+        WindowId::of(&window);
+        // Leave room for a server-side title bar
+        let y = if self.server_decorations {
+            decoration::TITLE_HEIGHT
+        } else {
+            0
+        };
+        self.space.map_element(window.clone(), (0, y), false);
+        self.window_created(&window);
     }
 
     fn new_popup(&mut self, surface: PopupSurface, _positioner: PositionerState) {
@@ -70,7 +86,7 @@ impl XdgShellHandler for Smallvil {
             let window = self
                 .space
                 .elements()
-                .find(|w| w.toplevel().unwrap().wl_surface() == wl_surface)
+                .find(|w| w.toplevel().is_some_and(|t| t.wl_surface() == wl_surface))
                 .unwrap()
                 .clone();
             let initial_window_location = self.space.element_location(&window).unwrap();
@@ -102,7 +118,7 @@ impl XdgShellHandler for Smallvil {
             let window = self
                 .space
                 .elements()
-                .find(|w| w.toplevel().unwrap().wl_surface() == wl_surface)
+                .find(|w| w.toplevel().is_some_and(|t| t.wl_surface() == wl_surface))
                 .unwrap()
                 .clone();
             let initial_window_location = self.space.element_location(&window).unwrap();
@@ -128,6 +144,55 @@ impl XdgShellHandler for Smallvil {
     fn grab(&mut self, _surface: PopupSurface, _seat: wl_seat::WlSeat, _serial: Serial) {
         // TODO popup grabs
     }
+
+    fn maximize_request(&mut self, surface: ToplevelSurface) {
+        if let Some(window) = self.window_for_toplevel(&surface) {
+            self.set_window_mode(&window, WindowMode::Maximized);
+        }
+    }
+
+    fn unmaximize_request(&mut self, surface: ToplevelSurface) {
+        if let Some(window) = self.window_for_toplevel(&surface) {
+            self.set_window_mode(&window, WindowMode::Normal);
+        }
+    }
+
+    fn fullscreen_request(&mut self, surface: ToplevelSurface, _output: Option<WlOutput>) {
+        if let Some(window) = self.window_for_toplevel(&surface) {
+            self.set_window_mode(&window, WindowMode::Fullscreen);
+        }
+    }
+
+    fn unfullscreen_request(&mut self, surface: ToplevelSurface) {
+        if let Some(window) = self.window_for_toplevel(&surface) {
+            self.set_window_mode(&window, WindowMode::Normal);
+        }
+    }
+
+    fn toplevel_destroyed(&mut self, surface: ToplevelSurface) {
+        let Some(window) = self
+            .space
+            .elements()
+            .find(|w| {
+                w.toplevel()
+                    .is_some_and(|t| t.wl_surface() == surface.wl_surface())
+            })
+            .cloned()
+        else {
+            return;
+        };
+
+        self.window_destroyed(&window);
+        if let Some(pending) = self.pending_closes.remove(&WindowId::of(&window).0) {
+            let _ = pending.response_tx.send(Ok(CloseOutcome::Graceful));
+        }
+    }
+
+    fn title_changed(&mut self, surface: ToplevelSurface) {
+        if let Some(window) = self.window_for_toplevel(&surface) {
+            self.window_retitled(&window);
+        }
+    }
 }
 
 // Xdg Shell
@@ -161,7 +226,7 @@ pub fn handle_commit(popups: &mut PopupManager, space: &Space<Window>, surface:
     // Handle toplevel commits.
     if let Some(window) = space
         .elements()
-        .find(|w| w.toplevel().unwrap().wl_surface() == surface)
+        .find(|w| w.toplevel().is_some_and(|t| t.wl_surface() == surface))
         .cloned()
     {
         let initial_configure_sent = with_states(surface, |states| {
@@ -203,13 +268,13 @@ impl Smallvil {
         let Some(window) = self
             .space
             .elements()
-            .find(|w| w.toplevel().unwrap().wl_surface() == &root)
+            .find(|w| w.toplevel().is_some_and(|t| t.wl_surface() == &root))
         else {
             return;
         };
 
-        let output = self.space.outputs().next().unwrap();
-        let output_geo = self.space.output_geometry(output).unwrap();
+        let output = self.output_for_window(window).unwrap();
+        let output_geo = self.space.output_geometry(&output).unwrap();
         let window_geo = self.space.element_geometry(window).unwrap();
 
         // The target geometry for the positioner should be relative to its parent's geometry, so
@@ -223,3 +288,212 @@ impl Smallvil {
         });
     }
 }
+
+/// How a window went away in response to `close_window`.
+#[derive(Debug, Clone, Copy)]
+pub enum CloseOutcome {
+    /// The client destroyed the toplevel after `xdg_toplevel.close`
+    Graceful,
+    /// The owning process was killed after the timeout
+    Forced,
+}
+
+pub type CloseResponse = tokio::sync::oneshot::Sender<Result<CloseOutcome, ToolError>>;
+
+pub struct PendingClose {
+    force: bool,
+    pid: Option<i32>,
+    response_tx: CloseResponse,
+}
+
+impl PendingClose {
+    /// Called when the client did not destroy the toplevel in time.
+    fn expire(self) {
+        let result = match self.pid {
+            _ if !self.force => Err(ToolError::new(
+                ErrorKind::Timeout,
+                "Window did not close before the timeout",
+            )),
+            Some(pid) if pid as u32 != std::process::id() => {
+                if unsafe { libc::kill(pid, libc::SIGKILL) } == 0 {
+                    Ok(CloseOutcome::Forced)
+                } else {
+                    let err = std::io::Error::last_os_error();
+                    tracing::error!("Failed to kill process {}: {}", pid, err);
+                    Err(ToolError::new(
+                        ErrorKind::Other,
+                        format!("Failed to kill process {}: {}", pid, err),
+                    ))
+                }
+            }
+            _ => Err(ToolError::new(
+                ErrorKind::Timeout,
+                "Window did not close and its owning process is unknown",
+            )),
+        };
+        let _ = self.response_tx.send(result);
+    }
+}
+
+impl Smallvil {
+    /// Sends `xdg_toplevel.close` (or `WM_DELETE_WINDOW` to X11 windows) and
+    /// replies once the toplevel is destroyed. If it is still around after
+    /// `timeout`, the owning process is killed when `force` is set.
+    pub fn close_window(
+        &mut self,
+        window: &Window,
+        timeout: Duration,
+        force: bool,
+        response_tx: CloseResponse,
+    ) {
+        let id = WindowId::of(window).0;
+        if self.pending_closes.contains_key(&id) {
+            let _ = response_tx.send(Err(ToolError::new(
+                ErrorKind::Other,
+                format!("Window {} is already closing", id),
+            )));
+            return;
+        }
+
+        let pid = self.window_info(window).pid;
+        match window.underlying_surface() {
+            WindowSurface::Wayland(toplevel) => toplevel.send_close(),
+            WindowSurface::X11(surface) => {
+                if let Err(e) = surface.close() {
+                    let _ = response_tx.send(Err(ToolError::new(
+                        ErrorKind::Other,
+                        format!("Failed to close X11 window: {}", e),
+                    )));
+                    return;
+                }
+            }
+        }
+        self.pending_closes.insert(
+            id,
+            PendingClose {
+                force,
+                pid,
+                response_tx,
+            },
+        );
+
+        let timer =
+            self.loop_handle
+                .insert_source(Timer::from_duration(timeout), move |_, _, data| {
+                    if let Some(pending) = data.state.pending_closes.remove(&id) {
+                        pending.expire();
+                    }
+                    TimeoutAction::Drop
+                });
+        if let Err(e) = timer {
+            tracing::error!("Failed to schedule close timeout: {}", e);
+        }
+    }
+}
+
+impl Smallvil {
+    /// Moves a window so its geometry starts at `location`, like a finished move grab.
+    pub fn move_window(&mut self, window: &Window, location: Point<i32, Logical>) {
+        self.space.map_element(window.clone(), location, false);
+    }
+
+    /// Asks a window to take on `size`. The client picks its final size on its next commit.
+    pub fn resize_window(&mut self, window: &Window, size: Size<i32, Logical>) {
+        match window.underlying_surface() {
+            WindowSurface::Wayland(toplevel) => {
+                toplevel.with_pending_state(|state| {
+                    state.size = Some(size);
+                });
+                toplevel.send_pending_configure();
+            }
+            WindowSurface::X11(surface) => {
+                let mut geometry = surface.geometry();
+                geometry.size = size;
+                let _ = surface.configure(geometry);
+            }
+        }
+    }
+
+    fn window_for_toplevel(&self, surface: &ToplevelSurface) -> Option<Window> {
+        self.space
+            .elements()
+            .find(|w| {
+                w.toplevel()
+                    .is_some_and(|t| t.wl_surface() == surface.wl_surface())
+            })
+            .cloned()
+    }
+
+    /// Maximizes, fullscreens or restores a window. The geometry it had before
+    /// leaving the normal state is remembered and restored afterwards.
+    pub fn set_window_mode(&mut self, window: &Window, mode: WindowMode) {
+        let Some(output) = self.output_for_window(window) else {
+            return;
+        };
+        let Some(output_geo) = self.space.output_geometry(&output) else {
+            return;
+        };
+        let saved = window
+            .user_data()
+            .get_or_insert(|| RestoreGeometry(RefCell::new(None)));
+
+        // The output for maximized and fullscreen windows, the remembered geometry when restoring
+        let target = match mode {
+            WindowMode::Maximized | WindowMode::Fullscreen => {
+                if saved.0.borrow().is_none() {
+                    *saved.0.borrow_mut() = self.space.element_geometry(window);
+                }
+                let mut geo = output_geo;
+                if mode == WindowMode::Maximized {
+                    // Leave panels' exclusive zones uncovered
+                    geo = layer_map_for_output(&output).non_exclusive_zone();
+                    geo.loc += output_geo.loc;
+                    // Maximized server-side decorated windows keep their title bar on screen
+                    if decoration::is_decorated(window) {
+                        geo.loc.y += decoration::TITLE_HEIGHT;
+                        geo.size.h -= decoration::TITLE_HEIGHT;
+                    }
+                }
+                Some(geo)
+            }
+            WindowMode::Normal => saved.0.borrow_mut().take(),
+        };
+
+        match window.underlying_surface() {
+            WindowSurface::Wayland(toplevel) => {
+                toplevel.with_pending_state(|state| {
+                    state.states.unset(xdg_toplevel::State::Maximized);
+                    state.states.unset(xdg_toplevel::State::Fullscreen);
+                    match mode {
+                        WindowMode::Maximized => state.states.set(xdg_toplevel::State::Maximized),
+                        WindowMode::Fullscreen => state.states.set(xdg_toplevel::State::Fullscreen),
+                        WindowMode::Normal => {}
+                    }
+                    state.size = target.map(|geo| geo.size);
+                });
+                toplevel.send_pending_configure();
+            }
+            WindowSurface::X11(surface) => {
+                let _ = surface.set_maximized(mode == WindowMode::Maximized);
+                let _ = surface.set_fullscreen(mode == WindowMode::Fullscreen);
+                if let Some(geo) = target {
+                    let _ = surface.configure(geo);
+                }
+            }
+        }
+
+        if let Some(geo) = target {
+            self.space.map_element(window.clone(), geo.loc, true);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WindowMode {
+    Normal,
+    Maximized,
+    Fullscreen,
+}
+
+/// Geometry a window had before it was maximized or made fullscreen.
+struct RestoreGeometry(RefCell<Option<Rectangle<i32, Logical>>>);
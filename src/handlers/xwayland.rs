@@ -0,0 +1,291 @@
+use std::process::Stdio;
+
+use smithay::{
+    delegate_xwayland_shell,
+    desktop::Window,
+    input::pointer::{Focus, GrabStartData as PointerGrabStartData, PointerHandle},
+    reexports::wayland_server::Resource,
+    utils::{Logical, Rectangle, SERIAL_COUNTER},
+    wayland::xwayland_shell::{XWaylandShellHandler, XWaylandShellState},
+    xwayland::{
+        xwm::{Reorder, ResizeEdge as X11ResizeEdge, WmWindowProperty, XwmId},
+        X11Surface, X11Wm, XWayland, XWaylandEvent, XwmHandler,
+    },
+};
+
+use crate::{
+    grabs::{MoveSurfaceGrab, ResizeSurfaceGrab},
+    handlers::{CloseOutcome, WindowMode},
+    state::WindowId,
+    CalloopData, Smallvil,
+};
+
+impl XWaylandShellHandler for Smallvil {
+    fn xwayland_shell_state(&mut self) -> &mut XWaylandShellState {
+        &mut self.xwayland_shell_state
+    }
+}
+
+delegate_xwayland_shell!(Smallvil);
+
+impl Smallvil {
+    /// Spawns Xwayland and starts the X11 window manager once it is ready.
+    /// `DISPLAY` is exported at that point, so apps launched afterwards can use X11.
+    pub fn start_xwayland(&mut self) {
+        let (xwayland, client) = match XWayland::spawn(
+            &self.display_handle,
+            None,
+            std::iter::empty::<(String, String)>(),
+            true,
+            Stdio::null(),
+            Stdio::null(),
+            |_| (),
+        ) {
+            Ok(spawned) => spawned,
+            Err(e) => {
+                tracing::error!("Failed to start Xwayland: {}", e);
+                return;
+            }
+        };
+
+        let result = self
+            .loop_handle
+            .insert_source(xwayland, move |event, _, data| match event {
+                XWaylandEvent::Ready {
+                    x11_socket,
+                    display_number,
+                } => {
+                    match X11Wm::start_wm(
+                        data.state.loop_handle.clone(),
+                        x11_socket,
+                        client.clone(),
+                    ) {
+                        Ok(wm) => {
+                            data.state.xwm = Some(wm);
+                            data.state.xdisplay = Some(display_number);
+                            std::env::set_var("DISPLAY", format!(":{}", display_number));
+                            tracing::info!("Xwayland ready on DISPLAY=:{}", display_number);
+                            if let Some(bus) = &data.state.session_bus {
+                                bus.update_activation_environment(&["DISPLAY"]);
+                            }
+                        }
+                        Err(e) => tracing::error!("Failed to start the X11 window manager: {}", e),
+                    }
+                }
+                XWaylandEvent::Error => tracing::error!("Xwayland exited during startup"),
+            });
+        if let Err(e) = result {
+            tracing::error!("Failed to watch Xwayland: {}", e);
+        }
+    }
+
+    pub fn window_for_x11_surface(&self, surface: &X11Surface) -> Option<Window> {
+        self.space
+            .elements()
+            .find(|w| w.x11_surface() == Some(surface))
+            .cloned()
+    }
+
+    /// The pointer and its click grab, if the button was pressed on `surface`.
+    /// X11 move and resize requests carry no serial, so the button still being
+    /// held is all there is to check.
+    fn x11_grab_start(
+        &self,
+        surface: &X11Surface,
+    ) -> Option<(PointerHandle<Self>, PointerGrabStartData<Self>)> {
+        let pointer = self.seat.get_pointer()?;
+        let start_data = pointer.grab_start_data()?;
+
+        let wl_surface = surface.wl_surface()?;
+        let (focus, _) = start_data.focus.as_ref()?;
+        if !focus.id().same_client_as(&wl_surface.id()) {
+            return None;
+        }
+
+        Some((pointer, start_data))
+    }
+}
+
+impl XwmHandler for CalloopData {
+    fn xwm_state(&mut self, _xwm: XwmId) -> &mut X11Wm {
+        self.state.xwm.as_mut().unwrap()
+    }
+
+    fn new_window(&mut self, _xwm: XwmId, _window: X11Surface) {}
+
+    fn new_override_redirect_window(&mut self, _xwm: XwmId, _window: X11Surface) {}
+
+    fn map_window_request(&mut self, _xwm: XwmId, surface: X11Surface) {
+        if let Err(e) = surface.set_mapped(true) {
+            tracing::warn!("Failed to map X11 window: {}", e);
+            return;
+        }
+        let window = Window::new_x11_window(surface);
+        // Hand out the id now, like new_toplevel does for xdg toplevels
+        WindowId::of(&window);
+
+        let location = window.x11_surface().unwrap().geometry().loc;
+        self.state.space.map_element(window.clone(), location, true);
+        self.state.window_created(&window);
+        self.state.window_committed(&window);
+    }
+
+    fn mapped_override_redirect_window(&mut self, _xwm: XwmId, surface: X11Surface) {
+        // Menus and tooltips place themselves; draw them where they asked to be,
+        // without an id or window events
+        let window = Window::new_x11_window(surface);
+        self.state.override_redirect_windows.push(window);
+    }
+
+    fn unmapped_window(&mut self, _xwm: XwmId, surface: X11Surface) {
+        if surface.is_override_redirect() {
+            self.state
+                .override_redirect_windows
+                .retain(|window| window.x11_surface() != Some(&surface));
+            return;
+        }
+        if let Some(window) = self.state.window_for_x11_surface(&surface) {
+            self.state.space.unmap_elem(&window);
+            self.state.window_destroyed(&window);
+
+            if let Some(pending) = self.state.pending_closes.remove(&WindowId::of(&window).0) {
+                let _ = pending.response_tx.send(Ok(CloseOutcome::Graceful));
+            }
+        }
+        let _ = surface.set_mapped(false);
+    }
+
+    fn destroyed_window(&mut self, _xwm: XwmId, surface: X11Surface) {
+        self.state
+            .override_redirect_windows
+            .retain(|window| window.x11_surface() != Some(&surface));
+    }
+
+    fn property_notify(&mut self, _xwm: XwmId, surface: X11Surface, property: WmWindowProperty) {
+        if property == WmWindowProperty::Title {
+            if let Some(window) = self.state.window_for_x11_surface(&surface) {
+                self.state.window_retitled(&window);
+            }
+        }
+    }
+
+    fn configure_request(
+        &mut self,
+        _xwm: XwmId,
+        surface: X11Surface,
+        x: Option<i32>,
+        y: Option<i32>,
+        w: Option<u32>,
+        h: Option<u32>,
+        _reorder: Option<Reorder>,
+    ) {
+        // No tiling here: X11 clients get whatever geometry they ask for
+        let mut geometry = surface.geometry();
+        if let Some(x) = x {
+            geometry.loc.x = x;
+        }
+        if let Some(y) = y {
+            geometry.loc.y = y;
+        }
+        if let Some(w) = w {
+            geometry.size.w = w as i32;
+        }
+        if let Some(h) = h {
+            geometry.size.h = h as i32;
+        }
+        let _ = surface.configure(geometry);
+    }
+
+    fn configure_notify(
+        &mut self,
+        _xwm: XwmId,
+        surface: X11Surface,
+        geometry: Rectangle<i32, Logical>,
+        _above: Option<u32>,
+    ) {
+        if let Some(window) = self.state.window_for_x11_surface(&surface) {
+            self.state
+                .space
+                .map_element(window.clone(), geometry.loc, false);
+            self.state.window_committed(&window);
+        }
+    }
+
+    fn resize_request(
+        &mut self,
+        _xwm: XwmId,
+        surface: X11Surface,
+        _button: u32,
+        resize_edge: X11ResizeEdge,
+    ) {
+        let Some(window) = self.state.window_for_x11_surface(&surface) else {
+            return;
+        };
+        let Some((pointer, start_data)) = self.state.x11_grab_start(&surface) else {
+            return;
+        };
+        let initial_window_location = self.state.space.element_location(&window).unwrap();
+        let initial_window_size = window.geometry().size;
+
+        let grab = ResizeSurfaceGrab::start(
+            start_data,
+            window,
+            resize_edge.into(),
+            Rectangle::new(initial_window_location, initial_window_size),
+        );
+
+        pointer.set_grab(
+            &mut self.state,
+            grab,
+            SERIAL_COUNTER.next_serial(),
+            Focus::Clear,
+        );
+    }
+
+    fn move_request(&mut self, _xwm: XwmId, surface: X11Surface, _button: u32) {
+        let Some(window) = self.state.window_for_x11_surface(&surface) else {
+            return;
+        };
+        let Some((pointer, start_data)) = self.state.x11_grab_start(&surface) else {
+            return;
+        };
+        let initial_window_location = self.state.space.element_location(&window).unwrap();
+
+        let grab = MoveSurfaceGrab {
+            start_data,
+            window,
+            initial_window_location,
+        };
+
+        pointer.set_grab(
+            &mut self.state,
+            grab,
+            SERIAL_COUNTER.next_serial(),
+            Focus::Clear,
+        );
+    }
+
+    fn maximize_request(&mut self, _xwm: XwmId, surface: X11Surface) {
+        if let Some(window) = self.state.window_for_x11_surface(&surface) {
+            self.state.set_window_mode(&window, WindowMode::Maximized);
+        }
+    }
+
+    fn unmaximize_request(&mut self, _xwm: XwmId, surface: X11Surface) {
+        if let Some(window) = self.state.window_for_x11_surface(&surface) {
+            self.state.set_window_mode(&window, WindowMode::Normal);
+        }
+    }
+
+    fn fullscreen_request(&mut self, _xwm: XwmId, surface: X11Surface) {
+        if let Some(window) = self.state.window_for_x11_surface(&surface) {
+            self.state.set_window_mode(&window, WindowMode::Fullscreen);
+        }
+    }
+
+    fn unfullscreen_request(&mut self, _xwm: XwmId, surface: X11Surface) {
+        if let Some(window) = self.state.window_for_x11_surface(&surface) {
+            self.state.set_window_mode(&window, WindowMode::Normal);
+        }
+    }
+}
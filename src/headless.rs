@@ -0,0 +1,138 @@
+use std::time::Duration;
+
+use smithay::{
+    backend::{
+        egl::{EGLContext, EGLDevice, EGLDisplay},
+        renderer::{gles::GlesRenderer, pixman::PixmanRenderer},
+    },
+    reexports::calloop::{
+        timer::{TimeoutAction, Timer},
+        EventLoop,
+    },
+    utils::{Physical, Size},
+};
+
+use crate::{
+    render::{self, OffscreenRenderer, RendererKind, VirtualOutput},
+    CalloopData,
+};
+
+/// Size of the virtual output when none are configured.
+const DEFAULT_OUTPUT_SIZE: (i32, i32) = (1280, 800);
+const REFRESH_MHZ: i32 = 60_000;
+
+/// Initializes a backend without any host window: one virtual output per entry
+/// in `output_sizes` is rendered on a fixed-rate timer, with GLES on an EGL
+/// device (a GPU render node, or Mesa's software device when there is none) or
+/// with pixman on the CPU, as picked by `renderer`.
+pub fn init_headless(
+    event_loop: &mut EventLoop<CalloopData>,
+    data: &mut CalloopData,
+    output_sizes: &[Size<i32, Physical>],
+    renderer: RendererKind,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let gles = match renderer {
+        RendererKind::Pixman => None,
+        RendererKind::Gles => Some(create_renderer()?),
+        RendererKind::Auto => match create_renderer() {
+            Ok(renderer) => Some(renderer),
+            Err(e) => {
+                tracing::warn!("No usable EGL device ({}), rendering with pixman", e);
+                None
+            }
+        },
+    };
+
+    match gles {
+        Some(renderer) => {
+            render::init_dmabuf(&mut data.state, &renderer);
+            run_headless(event_loop, data, output_sizes, renderer)
+        }
+        None => run_headless(event_loop, data, output_sizes, PixmanRenderer::new()?),
+    }
+}
+
+fn run_headless<R>(
+    event_loop: &mut EventLoop<CalloopData>,
+    data: &mut CalloopData,
+    output_sizes: &[Size<i32, Physical>],
+    mut renderer: R,
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    R: OffscreenRenderer + 'static,
+    R::TextureId: Send + Clone + 'static,
+{
+    let display_handle = &mut data.display_handle;
+    let state = &mut data.state;
+
+    let default_size = [Size::from(DEFAULT_OUTPUT_SIZE)];
+    let output_sizes = if output_sizes.is_empty() {
+        &default_size[..]
+    } else {
+        output_sizes
+    };
+    let mut outputs = output_sizes
+        .iter()
+        .enumerate()
+        .map(|(i, size)| {
+            VirtualOutput::new(
+                &mut renderer,
+                state,
+                display_handle,
+                format!("headless-{}", i + 1),
+                *size,
+            )
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    std::env::set_var("WAYLAND_DISPLAY", &state.socket_name);
+
+    event_loop
+        .handle()
+        .insert_source(Timer::immediate(), move |_, _, data| {
+            let display = &mut data.display_handle;
+            let state = &mut data.state;
+
+            if state.frame_due() {
+                render::start_frame(state);
+
+                for output in &mut outputs {
+                    output.render(&mut renderer, state);
+                }
+
+                render::finish_frame(state, display, &outputs[0].output);
+            }
+
+            // Pace frames by the frame limit, or else the primary output's refresh
+            // rate, which set_output_mode may change
+            let refresh = outputs[0]
+                .output
+                .current_mode()
+                .map_or(REFRESH_MHZ, |mode| mode.refresh.max(1));
+            let interval = state
+                .frame_pacing
+                .interval()
+                .unwrap_or(Duration::from_secs(1000) / refresh as u32);
+            TimeoutAction::ToDuration(interval)
+        })?;
+
+    Ok(())
+}
+
+/// Creates a renderer on the first hardware EGL device, falling back to a
+/// software one so mcpvil runs in containers without a GPU.
+fn create_renderer() -> Result<GlesRenderer, Box<dyn std::error::Error>> {
+    let devices: Vec<EGLDevice> = EGLDevice::enumerate()?.collect();
+    let device = devices
+        .iter()
+        .find(|device| !device.is_software())
+        .or_else(|| devices.first())
+        .cloned()
+        .ok_or("No EGL device available for headless rendering")?;
+
+    let display = unsafe { EGLDisplay::new(device)? };
+    let context = EGLContext::new(&display)?;
+    let renderer = unsafe { GlesRenderer::new(context)? };
+
+    Ok(renderer)
+}
@@ -0,0 +1,101 @@
+//! Access control of the HTTP transport. Every request must carry the bearer
+//! token given with `--token` (or generated at startup), and requests from
+//! web pages or through hostnames other than local ones are rejected, so a
+//! page can't reach the tools through DNS rebinding.
+
+use std::{
+    io::Read,
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+};
+
+use axum::{
+    extract::{Request, State},
+    http::{header, HeaderMap, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+/// A random token for clients to send, 128 bits from the kernel, as hex.
+pub fn generate_token() -> std::io::Result<String> {
+    let mut bytes = [0u8; 16];
+    std::fs::File::open("/dev/urandom")?.read_exact(&mut bytes)?;
+    Ok(bytes.iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+/// Whether `address` only accepts connections from this machine.
+pub fn is_loopback(address: &SocketAddr) -> bool {
+    address.ip().is_loopback()
+}
+
+/// Lets a request through if it has the token and neither its Host nor its
+/// Origin names anything but this machine or an IP address.
+pub async fn check_request(
+    State(token): State<Arc<str>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if let Err((status, reason)) = check_headers(request.headers(), &token) {
+        tracing::warn!("Rejected HTTP request to {}: {}", request.uri(), reason);
+        return (status, reason).into_response();
+    }
+    next.run(request).await
+}
+
+fn check_headers(headers: &HeaderMap, token: &str) -> Result<(), (StatusCode, &'static str)> {
+    // DNS rebinding needs a hostname; IP addresses can't be rebound
+    let host = headers
+        .get(header::HOST)
+        .and_then(|host| host.to_str().ok());
+    if host.is_some_and(|host| !is_local_host(host, true)) {
+        return Err((
+            StatusCode::FORBIDDEN,
+            "Host is not localhost or an IP address",
+        ));
+    }
+
+    // Only browsers send an Origin, and no web page may drive the tools
+    let origin = headers
+        .get(header::ORIGIN)
+        .and_then(|origin| origin.to_str().ok());
+    if let Some(origin) = origin {
+        let host = origin
+            .strip_prefix("http://")
+            .or_else(|| origin.strip_prefix("https://"))
+            .unwrap_or(origin);
+        if !is_local_host(host, false) {
+            return Err((StatusCode::FORBIDDEN, "Origin is not local"));
+        }
+    }
+
+    let given = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .ok_or((StatusCode::UNAUTHORIZED, "Missing bearer token"))?;
+    if !constant_time_eq(given.as_bytes(), token.as_bytes()) {
+        return Err((StatusCode::UNAUTHORIZED, "Wrong bearer token"));
+    }
+    Ok(())
+}
+
+/// Whether `host`, with an optional port, is localhost or a loopback address,
+/// or any IP address if `any_ip` is set.
+fn is_local_host(host: &str, any_ip: bool) -> bool {
+    let name = match host.strip_prefix('[') {
+        // An IPv6 address, e.g. [::1]:8080
+        Some(rest) => rest.split(']').next().unwrap_or(""),
+        None => host.split(':').next().unwrap_or(""),
+    };
+    if name.eq_ignore_ascii_case("localhost") {
+        return true;
+    }
+    name.parse::<IpAddr>()
+        .is_ok_and(|ip| any_ip || ip.is_loopback())
+}
+
+/// Compares without returning early, so timing doesn't reveal how much of a
+/// guessed token was right.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
@@ -0,0 +1,96 @@
+use std::time::{Duration, Instant};
+
+use crate::{
+    errors::{ErrorKind, ToolError},
+    Smallvil,
+};
+
+/// Client and rendering activity, counted so wait_for_idle can tell when the
+/// screen has settled.
+#[derive(Debug)]
+pub struct Activity {
+    /// Surface commits, including those requesting frame callbacks
+    pub commits: u64,
+    /// Rendered frames in which something was damaged
+    pub damaged_frames: u64,
+    pub last: Instant,
+}
+
+impl Default for Activity {
+    fn default() -> Self {
+        Self {
+            commits: 0,
+            damaged_frames: 0,
+            last: Instant::now(),
+        }
+    }
+}
+
+pub type IdleResponse = tokio::sync::oneshot::Sender<Result<Duration, ToolError>>;
+
+/// A wait_for_idle call, checked after every frame.
+pub struct IdleWait {
+    pub quiet: Duration,
+    pub timeout: Duration,
+    pub started: Instant,
+    commits: u64,
+    damaged_frames: u64,
+    pub response_tx: IdleResponse,
+}
+
+impl Smallvil {
+    pub fn note_commit(&mut self) {
+        self.activity.commits += 1;
+        self.activity.last = Instant::now();
+        self.request_frame();
+    }
+
+    pub fn note_damage(&mut self) {
+        self.activity.damaged_frames += 1;
+        self.activity.last = Instant::now();
+    }
+
+    /// Replies once nothing has been committed or damaged for `quiet`, with how
+    /// long that took, or with an error after `timeout`.
+    pub fn wait_for_idle(&mut self, quiet: Duration, timeout: Duration, response_tx: IdleResponse) {
+        self.idle_waits.push(IdleWait {
+            quiet,
+            timeout,
+            started: Instant::now(),
+            commits: self.activity.commits,
+            damaged_frames: self.activity.damaged_frames,
+            response_tx,
+        });
+        self.check_idle_waits();
+    }
+
+    /// Resolves the waits whose quiet period has passed or whose timeout expired.
+    pub fn check_idle_waits(&mut self) {
+        if self.idle_waits.is_empty() {
+            return;
+        }
+
+        let quiet_for = self.activity.last.elapsed();
+        let (done, pending): (Vec<IdleWait>, Vec<IdleWait>) = std::mem::take(&mut self.idle_waits)
+            .into_iter()
+            .partition(|wait| quiet_for >= wait.quiet || wait.started.elapsed() >= wait.timeout);
+        self.idle_waits = pending;
+
+        for wait in done {
+            let result = if quiet_for >= wait.quiet {
+                Ok(wait.started.elapsed())
+            } else {
+                Err(ToolError::new(
+                    ErrorKind::Timeout,
+                    format!(
+                        "Still busy after {} ms ({} commits and {} damaged frames since the wait started)",
+                        wait.timeout.as_millis(),
+                        self.activity.commits - wait.commits,
+                        self.activity.damaged_frames - wait.damaged_frames
+                    ),
+                ))
+            };
+            let _ = wait.response_tx.send(result);
+        }
+    }
+}
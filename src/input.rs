@@ -1,41 +1,190 @@
+use rmcp::schemars::{self, JsonSchema};
+use serde::Serialize;
 use smithay::{
     backend::input::{
         AbsolutePositionEvent, Axis, AxisSource, ButtonState, Event, InputBackend, InputEvent,
-        KeyboardKeyEvent, PointerAxisEvent, PointerButtonEvent,
+        KeyState, KeyboardKeyEvent, PointerAxisEvent, PointerButtonEvent, PointerMotionEvent,
     },
+    desktop::Window,
     input::{
-        keyboard::FilterResult,
+        keyboard::{keysyms, FilterResult, Keycode},
         pointer::{AxisFrame, ButtonEvent, MotionEvent},
+        touch::{DownEvent, MotionEvent as TouchMotionEvent, UpEvent},
     },
-    reexports::wayland_server::protocol::wl_surface::WlSurface,
-    utils::SERIAL_COUNTER,
+    reexports::{
+        calloop::timer::{TimeoutAction, Timer},
+        wayland_server::protocol::wl_surface::WlSurface,
+    },
+    utils::{Logical, Point, Serial, Transform, SERIAL_COUNTER},
+};
+
+use crate::{
+    keybindings::Action,
+    keymap::KeyRepeat,
+    macros::MacroEvent,
+    state::{Smallvil, WindowLabel},
 };
 
-use crate::state::Smallvil;
+/// Where the pointer ended up after a synthetic motion, and what it is hovering.
+#[derive(Debug, Clone)]
+pub struct PointerTarget {
+    pub location: Point<f64, Logical>,
+    pub window: Option<WindowLabel>,
+    /// Pointer position relative to the surface under the cursor
+    pub surface_location: Option<Point<f64, Logical>>,
+}
+
+/// Seat state as returned by `get_input_state`.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct InputState {
+    pub pointer_x: f64,
+    pub pointer_y: f64,
+    /// Linux button codes held down, e.g. 272 for BTN_LEFT
+    pub pressed_buttons: Vec<u32>,
+    /// Window under the pointer
+    pub pointer_window: Option<WindowLabel>,
+    /// Pointer position relative to the surface under it
+    pub surface_x: Option<f64>,
+    pub surface_y: Option<f64>,
+    /// Window with keyboard focus
+    pub focused_window: Option<WindowLabel>,
+    /// Active modifiers, e.g. ["ctrl", "shift"]
+    pub modifiers: Vec<&'static str>,
+    /// Keysym names of the keys held down
+    pub pressed_keys: Vec<String>,
+    /// Repeat rate and delay announced to clients
+    pub key_repeat: KeyRepeat,
+}
+
+/// Physical key presses the compositor handles instead of the focused client.
+enum Intercepted {
+    VtSwitch(i32),
+    Binding(Action),
+}
+
+const BTN_LEFT: u32 = 0x110;
+/// Synthetic touch input uses a single finger
+const TOUCH_SLOT: Option<u32> = Some(0);
+/// Most wheel steps a single scroll may send
+pub const MAX_SCROLL_STEPS: u32 = 1000;
+
+/// What a drag presses at the start of its path and releases at the end.
+pub enum DragSource {
+    Button(u32),
+    /// The compositor's pending drag-and-drop, carried with the left button
+    Dnd,
+    /// A finger on the virtual touchscreen
+    Touch,
+}
+
+/// A press–move–release gesture played back over several frames.
+pub struct Drag {
+    /// Start point, intermediate waypoints and end point
+    pub path: Vec<Point<f64, Logical>>,
+    pub source: DragSource,
+    pub duration: std::time::Duration,
+    pub started: std::time::Instant,
+    pub response_tx: tokio::sync::oneshot::Sender<Result<PointerTarget, String>>,
+}
+
+impl Drag {
+    /// Position along the path after `progress` (0.0 to 1.0) of its total length.
+    fn position_at(&self, progress: f64) -> Point<f64, Logical> {
+        let (segment, t) = segment_at(&self.path, progress);
+        match self.path.get(segment + 1) {
+            Some(&end) => self.path[segment] + (end - self.path[segment]).upscale(t),
+            None => self.path[segment],
+        }
+    }
+}
+
+/// The segment of `path` reached after `progress` (0.0 to 1.0) of its total
+/// length, as the index of its first point and the fraction covered. The end
+/// of the path is the last point with a fraction of 0.
+pub fn segment_at(path: &[Point<f64, Logical>], progress: f64) -> (usize, f64) {
+    let lengths: Vec<f64> = path
+        .windows(2)
+        .map(|w| (w[1].x - w[0].x).hypot(w[1].y - w[0].y))
+        .collect();
+    let mut remaining = lengths.iter().sum::<f64>() * progress;
+
+    for (index, length) in lengths.into_iter().enumerate() {
+        if remaining <= length && length > 0.0 {
+            return (index, remaining / length);
+        }
+        remaining -= length;
+    }
+
+    (path.len() - 1, 0.0)
+}
+
+impl std::fmt::Display for PointerTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "({}, {})", self.location.x, self.location.y)?;
+        match (&self.window, self.surface_location) {
+            (Some(window), Some(local)) => write!(
+                f,
+                " over window {} at surface ({}, {})",
+                window, local.x, local.y
+            ),
+            (Some(window), None) => write!(f, " over window {}", window),
+            _ => write!(f, " over the background"),
+        }
+    }
+}
 
 impl Smallvil {
     pub fn process_input_event<I: InputBackend>(&mut self, event: InputEvent<I>) {
+        self.request_frame();
         match event {
             InputEvent::Keyboard { event, .. } => {
                 let serial = SERIAL_COUNTER.next_serial();
                 let time = Event::time_msec(&event);
 
-                self.seat.get_keyboard().unwrap().input::<(), _>(
+                self.track_key(event.key_code(), event.state());
+
+                let pressed = event.state() == KeyState::Pressed;
+                let intercepted = self.seat.get_keyboard().unwrap().input(
                     self,
                     event.key_code(),
                     event.state(),
                     serial,
                     time,
-                    |_, _, _| FilterResult::Forward,
+                    |state, modifiers, keysym| {
+                        let sym = keysym.modified_sym().raw();
+                        if (keysyms::KEY_XF86Switch_VT_1..=keysyms::KEY_XF86Switch_VT_12)
+                            .contains(&sym)
+                        {
+                            let vt = (sym - keysyms::KEY_XF86Switch_VT_1 + 1) as i32;
+                            return FilterResult::Intercept(Intercepted::VtSwitch(vt));
+                        }
+                        // The release of an intercepted press isn't forwarded either
+                        let action = keysym
+                            .raw_latin_sym_or_raw_current_sym()
+                            .filter(|_| pressed)
+                            .and_then(|sym| state.keybindings.find(modifiers, sym));
+                        match action {
+                            Some(action) => {
+                                FilterResult::Intercept(Intercepted::Binding(action.clone()))
+                            }
+                            None => FilterResult::Forward,
+                        }
+                    },
                 );
+                match intercepted {
+                    Some(Intercepted::VtSwitch(vt)) => self.pending_vt_switch = Some(vt),
+                    Some(Intercepted::Binding(action)) => self.run_keybinding(action),
+                    None => {}
+                }
+            }
+            // Mice on the DRM backend; winit only reports absolute positions
+            InputEvent::PointerMotion { event, .. } => {
+                self.relative_motion(event.delta());
             }
-            InputEvent::PointerMotion { .. } => {}
             InputEvent::PointerMotionAbsolute { event, .. } => {
-                let output = self.space.outputs().next().unwrap();
-
-                let output_geo = self.space.output_geometry(output).unwrap();
-
-                let pos = event.position_transformed(output_geo.size) + output_geo.loc.to_f64();
+                let Some(pos) = self.absolute_position(&event) else {
+                    return;
+                };
 
                 let serial = SERIAL_COUNTER.next_serial();
 
@@ -43,6 +192,7 @@ impl Smallvil {
 
                 let under = self.surface_under(pos);
 
+                self.record_input(MacroEvent::Motion(pos));
                 pointer.motion(
                     self,
                     under,
@@ -53,10 +203,10 @@ impl Smallvil {
                     },
                 );
                 pointer.frame(self);
+                self.activate_pointer_constraint();
             }
             InputEvent::PointerButton { event, .. } => {
                 let pointer = self.seat.get_pointer().unwrap();
-                let keyboard = self.seat.get_keyboard().unwrap();
 
                 let serial = SERIAL_COUNTER.next_serial();
 
@@ -65,28 +215,10 @@ impl Smallvil {
                 let button_state = event.state();
 
                 if ButtonState::Pressed == button_state && !pointer.is_grabbed() {
-                    if let Some((window, _loc)) = self
-                        .space
-                        .element_under(pointer.current_location())
-                        .map(|(w, l)| (w.clone(), l))
-                    {
-                        self.space.raise_element(&window, true);
-                        keyboard.set_focus(
-                            self,
-                            Some(window.toplevel().unwrap().wl_surface().clone()),
-                            serial,
-                        );
-                        self.space.elements().for_each(|window| {
-                            window.toplevel().unwrap().send_pending_configure();
-                        });
-                    } else {
-                        self.space.elements().for_each(|window| {
-                            window.set_activated(false);
-                            window.toplevel().unwrap().send_pending_configure();
-                        });
-                        keyboard.set_focus(self, Option::<WlSurface>::None, serial);
-                    }
+                    self.focus_under_pointer(serial);
+                    self.press_decoration(button, serial);
                 };
+                self.track_button(button, button_state);
 
                 pointer.button(
                     self,
@@ -134,6 +266,11 @@ impl Smallvil {
                     }
                 }
 
+                self.record_input(MacroEvent::Scroll {
+                    horizontal: horizontal_amount,
+                    vertical: vertical_amount,
+                    source,
+                });
                 let pointer = self.seat.get_pointer().unwrap();
                 pointer.axis(self, frame);
                 pointer.frame(self);
@@ -141,4 +278,576 @@ impl Smallvil {
             _ => {}
         }
     }
+
+    /// Where an absolute motion event points within the bounding box of all
+    /// outputs, or None without outputs.
+    fn absolute_position<I: InputBackend>(
+        &self,
+        event: &impl AbsolutePositionEvent<I>,
+    ) -> Option<Point<f64, Logical>> {
+        let mut outputs = self
+            .space
+            .outputs()
+            .filter_map(|output| Some((output, self.space.output_geometry(output)?)));
+        let (output, first) = outputs.next()?;
+        let mut single = true;
+        let bounds = outputs.fold(first, |bounds, (_, geo)| {
+            single = false;
+            bounds.merge(geo)
+        });
+
+        // A lone output fills the host window with its buffer; undo a rotation
+        // set with set_output_transform to get back to its logical space
+        let transform = if single {
+            output.current_transform()
+        } else {
+            Transform::Normal
+        };
+        let buffer_size = transform.transform_size(bounds.size);
+        let pos = transform.invert().transform_point_in(
+            event.position_transformed(buffer_size),
+            &buffer_size.to_f64(),
+        );
+        Some(pos + bounds.loc.to_f64())
+    }
+
+    /// Sends a single synthetic key event to the focused surface, with a fresh serial.
+    pub fn synthetic_key(&mut self, keycode: Keycode, state: KeyState) {
+        let serial = SERIAL_COUNTER.next_serial();
+        let time = self.start_time.elapsed().as_millis() as u32;
+
+        if state == KeyState::Pressed {
+            self.overlay_key(keycode);
+        }
+        self.track_key(keycode, state);
+        self.seat.get_keyboard().unwrap().input::<(), _>(
+            self,
+            keycode,
+            state,
+            serial,
+            time,
+            |_, _, _| FilterResult::Forward,
+        );
+    }
+
+    /// Types `text` into the focused surface, returning the number of characters typed.
+    pub fn type_text(&mut self, text: &str) -> Result<usize, String> {
+        // Resolve everything up front so an untypeable character doesn't leave
+        // half of the text in the client.
+        let strokes = text
+            .chars()
+            .map(|c| {
+                self.keymap
+                    .stroke_for_char(c)
+                    .ok_or_else(|| format!("No key in the current keymap produces {:?}", c))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        for stroke in &strokes {
+            for modifier in &stroke.modifiers {
+                self.synthetic_key(*modifier, KeyState::Pressed);
+            }
+            self.synthetic_key(stroke.keycode, KeyState::Pressed);
+            self.synthetic_key(stroke.keycode, KeyState::Released);
+            for modifier in stroke.modifiers.iter().rev() {
+                self.synthetic_key(*modifier, KeyState::Released);
+            }
+        }
+
+        Ok(strokes.len())
+    }
+
+    /// Presses and releases a key combination such as `ctrl+c`. Modifiers that
+    /// are already held (e.g. by the user in the winit window) are left alone.
+    pub fn press_key_combo(&mut self, combo: &str) -> Result<(), String> {
+        let stroke = self.keymap.stroke_for_combo(combo)?;
+
+        let modifiers: Vec<Keycode> = stroke
+            .modifiers
+            .into_iter()
+            .filter(|keycode| !self.pressed_keys.contains(keycode))
+            .collect();
+
+        for modifier in &modifiers {
+            self.synthetic_key(*modifier, KeyState::Pressed);
+        }
+        self.synthetic_key(stroke.keycode, KeyState::Pressed);
+        self.synthetic_key(stroke.keycode, KeyState::Released);
+        for modifier in modifiers.iter().rev() {
+            self.synthetic_key(*modifier, KeyState::Released);
+        }
+
+        Ok(())
+    }
+
+    /// Presses a key combination and releases it after `duration`, so clients
+    /// see the key held down and repeat it as they would a physical one.
+    /// Replies once it is released with the number of repeats to expect.
+    pub fn hold_key_combo(
+        &mut self,
+        combo: &str,
+        duration: std::time::Duration,
+        response_tx: tokio::sync::oneshot::Sender<Result<u64, String>>,
+    ) {
+        let stroke = match self.keymap.stroke_for_combo(combo) {
+            Ok(stroke) => stroke,
+            Err(e) => {
+                let _ = response_tx.send(Err(e));
+                return;
+            }
+        };
+        if self.pressed_keys.contains(&stroke.keycode) {
+            let _ = response_tx.send(Err(format!("{} is already held down", combo)));
+            return;
+        }
+
+        let modifiers: Vec<Keycode> = stroke
+            .modifiers
+            .into_iter()
+            .filter(|keycode| !self.pressed_keys.contains(keycode))
+            .collect();
+        for modifier in &modifiers {
+            self.synthetic_key(*modifier, KeyState::Pressed);
+        }
+        self.synthetic_key(stroke.keycode, KeyState::Pressed);
+
+        let keycode = stroke.keycode;
+        let held_modifiers = modifiers.clone();
+        let started = std::time::Instant::now();
+        let mut response_tx = Some(response_tx);
+        let timer =
+            self.loop_handle
+                .insert_source(Timer::from_duration(duration), move |_, _, data| {
+                    let state = &mut data.state;
+                    state.synthetic_key(keycode, KeyState::Released);
+                    for modifier in held_modifiers.iter().rev() {
+                        state.synthetic_key(*modifier, KeyState::Released);
+                    }
+                    if let Some(response_tx) = response_tx.take() {
+                        let repeats = state.key_repeat.repeats_within(started.elapsed());
+                        let _ = response_tx.send(Ok(repeats));
+                    }
+                    TimeoutAction::Drop
+                });
+        if let Err(e) = timer {
+            tracing::error!("Failed to schedule key release: {}", e);
+            self.synthetic_key(keycode, KeyState::Released);
+            for modifier in modifiers.iter().rev() {
+                self.synthetic_key(*modifier, KeyState::Released);
+            }
+        }
+    }
+
+    fn track_key(&mut self, keycode: Keycode, state: KeyState) {
+        self.record_input(MacroEvent::Key {
+            keycode,
+            pressed: state == KeyState::Pressed,
+        });
+        match state {
+            KeyState::Pressed => self.pressed_keys.insert(keycode),
+            KeyState::Released => self.pressed_keys.remove(&keycode),
+        };
+    }
+
+    fn track_button(&mut self, button: u32, state: ButtonState) {
+        self.record_input(MacroEvent::Button {
+            button,
+            pressed: state == ButtonState::Pressed,
+        });
+        match state {
+            ButtonState::Pressed => self.pressed_buttons.insert(button),
+            ButtonState::Released => self.pressed_buttons.remove(&button),
+        };
+    }
+
+    /// Snapshot of the pointer, keyboard focus and held keys and buttons.
+    pub fn input_state(&self) -> InputState {
+        let pointer = self.pointer_target();
+        let modifiers = self.seat.get_keyboard().unwrap().modifier_state();
+        let active_modifiers = [
+            ("ctrl", modifiers.ctrl),
+            ("alt", modifiers.alt),
+            ("shift", modifiers.shift),
+            ("super", modifiers.logo),
+            ("caps_lock", modifiers.caps_lock),
+            ("num_lock", modifiers.num_lock),
+        ]
+        .into_iter()
+        .filter(|(_, active)| *active)
+        .map(|(name, _)| name)
+        .collect();
+
+        let mut pressed_buttons: Vec<u32> = self.pressed_buttons.iter().copied().collect();
+        pressed_buttons.sort_unstable();
+        let mut pressed_keys: Vec<String> = self
+            .pressed_keys
+            .iter()
+            .map(|keycode| {
+                self.keymap
+                    .key_name(*keycode)
+                    .unwrap_or_else(|| format!("keycode {}", keycode.raw()))
+            })
+            .collect();
+        pressed_keys.sort();
+
+        InputState {
+            pointer_x: pointer.location.x,
+            pointer_y: pointer.location.y,
+            pressed_buttons,
+            pointer_window: pointer.window,
+            surface_x: pointer.surface_location.map(|p| p.x),
+            surface_y: pointer.surface_location.map(|p| p.y),
+            focused_window: self
+                .focused_window()
+                .map(|window| WindowLabel::from_window(&window)),
+            modifiers: active_modifiers,
+            pressed_keys,
+            key_repeat: self.key_repeat,
+        }
+    }
+
+    /// Raises `window`, gives it keyboard focus and sends the activated state.
+    pub fn focus_window(&mut self, window: &Window, serial: Serial) {
+        let keyboard = self.seat.get_keyboard().unwrap();
+
+        self.space.raise_element(window, true);
+        if let (Some(xwm), Some(surface)) = (self.xwm.as_mut(), window.x11_surface()) {
+            let _ = xwm.raise_window(surface);
+        }
+        keyboard.set_focus(
+            self,
+            window.wl_surface().map(|surface| surface.into_owned()),
+            serial,
+        );
+        self.space.elements().for_each(|window| {
+            if let Some(toplevel) = window.toplevel() {
+                toplevel.send_pending_configure();
+            }
+        });
+    }
+
+    /// Raises and focuses the window under the pointer, or clears keyboard focus
+    /// if the pointer is over the background. Returns the focused window.
+    fn focus_under_pointer(&mut self, serial: Serial) -> Option<Window> {
+        let location = self.seat.get_pointer().unwrap().current_location();
+        self.focus_at(location, serial)
+    }
+
+    /// Raises and focuses the window at `location`, or clears keyboard focus if
+    /// there is none. Returns the focused window.
+    pub fn focus_at(&mut self, location: Point<f64, Logical>, serial: Serial) -> Option<Window> {
+        let keyboard = self.seat.get_keyboard().unwrap();
+
+        if let Some(surface) = self.focusable_layer_at(location) {
+            keyboard.set_focus(self, Some(surface), serial);
+            return None;
+        }
+
+        if let Some((window, _loc)) = self
+            .space
+            .element_under(location)
+            .map(|(w, l)| (w.clone(), l))
+        {
+            self.focus_window(&window, serial);
+            Some(window)
+        } else {
+            self.space.elements().for_each(|window| {
+                window.set_activated(false);
+                if let Some(toplevel) = window.toplevel() {
+                    toplevel.send_pending_configure();
+                }
+            });
+            keyboard.set_focus(self, Option::<WlSurface>::None, serial);
+            None
+        }
+    }
+
+    /// Warps the pointer to `pos` and sends motion to whatever surface is there.
+    pub fn synthetic_motion(&mut self, pos: Point<f64, Logical>) {
+        let serial = SERIAL_COUNTER.next_serial();
+        let time = self.start_time.elapsed().as_millis() as u32;
+        let pointer = self.seat.get_pointer().unwrap();
+        let under = self.surface_under(pos);
+
+        self.record_input(MacroEvent::Motion(pos));
+        pointer.motion(
+            self,
+            under,
+            &MotionEvent {
+                location: pos,
+                serial,
+                time,
+            },
+        );
+        pointer.frame(self);
+        self.activate_pointer_constraint();
+    }
+
+    /// Puts a finger down on the virtual touchscreen at `pos`, focusing the
+    /// window there like a click would.
+    pub fn synthetic_touch_down(&mut self, pos: Point<f64, Logical>) {
+        let serial = SERIAL_COUNTER.next_serial();
+        let time = self.start_time.elapsed().as_millis() as u32;
+        let touch = self.seat.get_touch().unwrap();
+
+        self.focus_at(pos, serial);
+        let under = self.surface_under(pos);
+        touch.down(
+            self,
+            under,
+            &DownEvent {
+                slot: TOUCH_SLOT.into(),
+                location: pos,
+                serial,
+                time,
+            },
+        );
+        touch.frame(self);
+    }
+
+    /// Moves the finger that is down to `pos`. The surface it went down on
+    /// keeps receiving the motion.
+    pub fn synthetic_touch_motion(&mut self, pos: Point<f64, Logical>) {
+        let time = self.start_time.elapsed().as_millis() as u32;
+        let touch = self.seat.get_touch().unwrap();
+        let under = self.surface_under(pos);
+
+        touch.motion(
+            self,
+            under,
+            &TouchMotionEvent {
+                slot: TOUCH_SLOT.into(),
+                location: pos,
+                time,
+            },
+        );
+        touch.frame(self);
+    }
+
+    pub fn synthetic_touch_up(&mut self) {
+        let serial = SERIAL_COUNTER.next_serial();
+        let time = self.start_time.elapsed().as_millis() as u32;
+        let touch = self.seat.get_touch().unwrap();
+
+        touch.up(
+            self,
+            &UpEvent {
+                slot: TOUCH_SLOT.into(),
+                serial,
+                time,
+            },
+        );
+        touch.frame(self);
+    }
+
+    /// Clicks `button` `count` times at `pos`, focusing the window under the pointer
+    /// the same way a real click does. Returns the window that received the click.
+    pub fn synthetic_click(
+        &mut self,
+        pos: Point<f64, Logical>,
+        button: u32,
+        count: u32,
+    ) -> Option<Window> {
+        self.synthetic_motion(pos);
+
+        let pointer = self.seat.get_pointer().unwrap();
+        let serial = SERIAL_COUNTER.next_serial();
+        let target = if !pointer.is_grabbed() {
+            self.focus_under_pointer(serial)
+        } else {
+            self.space
+                .element_under(pos)
+                .map(|(window, _)| window.clone())
+        };
+
+        for _ in 0..count {
+            self.synthetic_button(button, ButtonState::Pressed);
+            self.synthetic_button(button, ButtonState::Released);
+        }
+
+        target
+    }
+
+    pub fn synthetic_button(&mut self, button: u32, state: ButtonState) {
+        let pointer = self.seat.get_pointer().unwrap();
+        let serial = SERIAL_COUNTER.next_serial();
+        let time = self.start_time.elapsed().as_millis() as u32;
+        if state == ButtonState::Pressed {
+            self.press_decoration(button, serial);
+            self.overlay_click();
+        }
+        self.track_button(button, state);
+
+        pointer.button(
+            self,
+            &ButtonEvent {
+                button,
+                state,
+                serial,
+                time,
+            },
+        );
+        pointer.frame(self);
+    }
+
+    /// Starts a drag gesture: moves to the first point of `path` and presses the
+    /// drag's button or finger there. The rest of the path is played back by
+    /// `advance_drag` on each redraw.
+    pub fn start_drag(&mut self, drag: Drag) {
+        let start = drag.path[0];
+        match drag.source {
+            DragSource::Button(button) => {
+                self.pointer_drag_start(start);
+                self.synthetic_button(button, ButtonState::Pressed);
+            }
+            DragSource::Dnd => {
+                self.pointer_drag_start(start);
+                self.start_server_dnd(start, BTN_LEFT);
+            }
+            DragSource::Touch => self.synthetic_touch_down(start),
+        }
+
+        self.pending_drag = Some(drag);
+    }
+
+    /// Moves the pointer to where a drag starts and focuses the window there.
+    fn pointer_drag_start(&mut self, start: Point<f64, Logical>) {
+        self.synthetic_motion(start);
+        let pointer = self.seat.get_pointer().unwrap();
+        if !pointer.is_grabbed() {
+            self.focus_under_pointer(SERIAL_COUNTER.next_serial());
+        }
+    }
+
+    /// Moves an in-progress drag along its path according to the elapsed time,
+    /// releasing the button and replying once the end is reached.
+    pub fn advance_drag(&mut self) {
+        let Some(drag) = self.pending_drag.as_ref() else {
+            return;
+        };
+
+        let progress = if drag.duration.is_zero() {
+            1.0
+        } else {
+            (drag.started.elapsed().as_secs_f64() / drag.duration.as_secs_f64()).min(1.0)
+        };
+        let pos = drag.position_at(progress);
+        let touch = matches!(drag.source, DragSource::Touch);
+        if touch {
+            self.synthetic_touch_motion(pos);
+        } else {
+            self.synthetic_motion(pos);
+        }
+
+        if progress >= 1.0 {
+            let drag = self.pending_drag.take().unwrap();
+            match drag.source {
+                DragSource::Button(button) => self.synthetic_button(button, ButtonState::Released),
+                DragSource::Dnd => self.synthetic_button(BTN_LEFT, ButtonState::Released),
+                DragSource::Touch => self.synthetic_touch_up(),
+            }
+            let _ = drag.response_tx.send(Ok(self.target_at(pos)));
+        }
+    }
+
+    /// Moves the pointer to `pos`, or by `pos` when `relative` is set, keeping it
+    /// inside the output. Returns what ends up under the cursor.
+    pub fn move_pointer(&mut self, pos: Point<f64, Logical>, relative: bool) -> PointerTarget {
+        let pos = if relative {
+            self.seat.get_pointer().unwrap().current_location() + pos
+        } else {
+            pos
+        };
+        let pos = self.clamp_to_outputs(pos);
+
+        self.synthetic_motion(pos);
+        self.pointer_target()
+    }
+
+    pub fn pointer_target(&self) -> PointerTarget {
+        self.target_at(self.seat.get_pointer().unwrap().current_location())
+    }
+
+    /// What is at `location`: the window and the position within its surface.
+    pub fn target_at(&self, location: Point<f64, Logical>) -> PointerTarget {
+        let window = self
+            .space
+            .element_under(location)
+            .map(|(window, _)| WindowLabel::from_window(window));
+        let surface_location = self
+            .surface_under(location)
+            .map(|(_, origin)| location - origin);
+
+        PointerTarget {
+            location,
+            window,
+            surface_location,
+        }
+    }
+
+    /// Clamps `pos` into the nearest output, so the pointer cannot leave the
+    /// outputs or end up in a gap between outputs of different sizes.
+    pub fn clamp_to_outputs(&self, pos: Point<f64, Logical>) -> Point<f64, Logical> {
+        self.space
+            .outputs()
+            .filter_map(|output| self.space.output_geometry(output))
+            .map(|geo| {
+                let max_x = (geo.loc.x + geo.size.w - 1) as f64;
+                let max_y = (geo.loc.y + geo.size.h - 1) as f64;
+                Point::<f64, Logical>::from((
+                    pos.x.clamp(geo.loc.x as f64, max_x),
+                    pos.y.clamp(geo.loc.y as f64, max_y),
+                ))
+            })
+            .min_by(|a, b| {
+                let dist = |p: &Point<f64, Logical>| (p.x - pos.x).powi(2) + (p.y - pos.y).powi(2);
+                dist(a).total_cmp(&dist(b))
+            })
+            .unwrap_or(pos)
+    }
+
+    /// Scrolls along `axis`, optionally moving the pointer to `pos` first.
+    /// `steps` adds discrete (v120) wheel clicks alongside the continuous value.
+    pub fn synthetic_scroll(
+        &mut self,
+        pos: Option<Point<f64, Logical>>,
+        axis: Axis,
+        amount: f64,
+        steps: Option<i32>,
+        source: AxisSource,
+    ) -> PointerTarget {
+        if let Some(pos) = pos {
+            self.synthetic_motion(pos);
+        }
+
+        let time = self.start_time.elapsed().as_millis() as u32;
+        let pointer = self.seat.get_pointer().unwrap();
+
+        self.record_input(match axis {
+            Axis::Horizontal => MacroEvent::Scroll {
+                horizontal: amount,
+                vertical: 0.0,
+                source,
+            },
+            Axis::Vertical => MacroEvent::Scroll {
+                horizontal: 0.0,
+                vertical: amount,
+                source,
+            },
+        });
+        let mut frame = AxisFrame::new(time).source(source).value(axis, amount);
+        if let Some(steps) = steps {
+            frame = frame.v120(axis, steps.saturating_mul(120));
+        }
+        pointer.axis(self, frame);
+        pointer.frame(self);
+
+        // Finger scrolling ends with an axis stop so clients can start kinetic scrolling.
+        if source == AxisSource::Finger {
+            pointer.axis(self, AxisFrame::new(time).source(source).stop(axis));
+            pointer.frame(self);
+        }
+
+        self.pointer_target()
+    }
 }
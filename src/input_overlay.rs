@@ -0,0 +1,312 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use image::{Rgba, RgbaImage};
+use smithay::{
+    backend::{
+        allocator::Fourcc,
+        renderer::{
+            element::{
+                memory::{MemoryRenderBuffer, MemoryRenderBufferRenderElement},
+                Kind,
+            },
+            ImportMem, Renderer,
+        },
+    },
+    input::keyboard::Keycode,
+    utils::{Logical, Point, Rectangle, Scale, Size, Transform},
+};
+
+use crate::{text, Smallvil};
+
+/// How long the caption stays up after the last key press.
+const CAPTION_DURATION: Duration = Duration::from_millis(1500);
+
+/// How long a click marker takes to fade out.
+const CLICK_DURATION: Duration = Duration::from_millis(600);
+
+/// The caption shows at most this many characters, dropping the oldest.
+const MAX_CAPTION_CHARS: usize = 40;
+
+const CAPTION_SCALE: u32 = 3;
+const CAPTION_PADDING: u32 = 12;
+/// Space between the caption and the bottom of the output.
+const CAPTION_MARGIN: i32 = 48;
+const CAPTION_BACKGROUND: Rgba<u8> = Rgba([0, 0, 0, 180]);
+const CAPTION_COLOR: Rgba<u8> = Rgba([255, 255, 255, 255]);
+
+const CLICK_RADIUS: u32 = 20;
+const CLICK_WIDTH: u32 = 4;
+const CLICK_COLOR: Rgba<u8> = Rgba([255, 64, 64, 255]);
+
+/// Keysym names of keys that only modify others; they show up in the
+/// combination of the next key instead of on their own.
+const MODIFIER_PREFIXES: &[&str] = &[
+    "Shift_",
+    "Control_",
+    "Alt_",
+    "Meta_",
+    "Super_",
+    "Hyper_",
+    "ISO_Level3_",
+    "ISO_Level5_",
+    "Caps_Lock",
+    "Num_Lock",
+];
+
+/// A key press in the caption.
+enum KeyLabel {
+    /// A character typed without modifiers other than Shift
+    Char(char),
+    /// A named key or a combination, e.g. "Return" or "Ctrl+Shift+T"
+    Combo(String),
+}
+
+/// An on-screen display of injected input, in the style of screencast
+/// key-overlay tools: recent key presses as a caption at the bottom of the
+/// primary output, and a fading ring wherever a button was pressed. It is
+/// shown while a recording started with `show_input` runs.
+pub struct InputOverlay {
+    keys: VecDeque<KeyLabel>,
+    last_key: Option<Instant>,
+    clicks: VecDeque<(Instant, Point<f64, Logical>)>,
+    // The caption drawn into a buffer, redrawn when a key is added
+    caption: Option<(MemoryRenderBuffer, Size<i32, Logical>)>,
+    ring: MemoryRenderBuffer,
+}
+
+impl Default for InputOverlay {
+    fn default() -> Self {
+        Self {
+            keys: VecDeque::new(),
+            last_key: None,
+            clicks: VecDeque::new(),
+            caption: None,
+            ring: buffer(&ring()),
+        }
+    }
+}
+
+impl InputOverlay {
+    fn add_key(&mut self, label: KeyLabel) {
+        // A pause starts a new caption
+        if self
+            .last_key
+            .is_none_or(|last| last.elapsed() >= CAPTION_DURATION)
+        {
+            self.keys.clear();
+        }
+        self.last_key = Some(Instant::now());
+        if self.keys.len() == MAX_CAPTION_CHARS {
+            self.keys.pop_front();
+        }
+        self.keys.push_back(label);
+
+        let caption = caption_text(&self.keys);
+        let skip = caption.chars().count().saturating_sub(MAX_CAPTION_CHARS);
+        let caption: String = caption.chars().skip(skip).collect();
+        let img = caption_image(&caption);
+        let size = Size::from((img.width() as i32, img.height() as i32));
+        self.caption = Some((buffer(&img), size));
+    }
+
+    fn add_click(&mut self, location: Point<f64, Logical>) {
+        while self
+            .clicks
+            .front()
+            .is_some_and(|(at, _)| at.elapsed() >= CLICK_DURATION)
+        {
+            self.clicks.pop_front();
+        }
+        self.clicks.push_back((Instant::now(), location));
+    }
+
+    /// Render elements of the overlay on an output at `output_geo`, front to
+    /// back. Only the primary output shows the caption.
+    pub fn elements<R>(
+        &self,
+        renderer: &mut R,
+        output_geo: Rectangle<i32, Logical>,
+        scale: Scale<f64>,
+        is_primary: bool,
+    ) -> Vec<MemoryRenderBufferRenderElement<R>>
+    where
+        R: Renderer + ImportMem,
+        R::TextureId: Send + Clone + 'static,
+    {
+        let mut elements = Vec::new();
+        let caption = self.caption.as_ref().filter(|_| is_primary).filter(|_| {
+            self.last_key
+                .is_some_and(|last| last.elapsed() < CAPTION_DURATION)
+        });
+        if let Some((caption, size)) = caption {
+            let location = Point::<f64, Logical>::from((
+                ((output_geo.size.w - size.w) / 2) as f64,
+                (output_geo.size.h - size.h - CAPTION_MARGIN) as f64,
+            ));
+            elements.extend(element(renderer, location, scale, caption, None));
+        }
+
+        // Newest on top
+        let radius = CLICK_RADIUS as f64;
+        for (at, location) in self.clicks.iter().rev() {
+            let age = at.elapsed();
+            if age >= CLICK_DURATION || !output_geo.to_f64().contains(*location) {
+                continue;
+            }
+            let alpha = 1.0 - age.as_secs_f32() / CLICK_DURATION.as_secs_f32();
+            let location = *location - output_geo.loc.to_f64() - Point::from((radius, radius));
+            elements.extend(element(renderer, location, scale, &self.ring, Some(alpha)));
+        }
+        elements
+    }
+}
+
+impl Smallvil {
+    /// Adds an injected key press to the overlay, if it is showing. Modifier
+    /// keys are only shown as part of the next key's combination.
+    pub fn overlay_key(&mut self, keycode: Keycode) {
+        if self.input_overlay.is_none() {
+            return;
+        }
+        let Some(name) = self.keymap.key_name(keycode) else {
+            return;
+        };
+        if MODIFIER_PREFIXES
+            .iter()
+            .any(|prefix| name.starts_with(prefix))
+        {
+            return;
+        }
+
+        let modifiers = self.seat.get_keyboard().unwrap().modifier_state();
+        let mut combo: Vec<&str> = [
+            ("Ctrl", modifiers.ctrl),
+            ("Alt", modifiers.alt),
+            ("Super", modifiers.logo),
+        ]
+        .into_iter()
+        .filter(|(_, active)| *active)
+        .map(|(name, _)| name)
+        .collect();
+        let typed = self
+            .keymap
+            .key_char(keycode, modifiers.shift)
+            .filter(|c| !c.is_control());
+        let label = match typed {
+            Some(c) if combo.is_empty() => KeyLabel::Char(c),
+            _ => {
+                if modifiers.shift {
+                    combo.push("Shift");
+                }
+                let key = match self.keymap.key_char(keycode, false) {
+                    Some(c) if !c.is_control() => c.to_uppercase().to_string(),
+                    _ => name,
+                };
+                combo.push(&key);
+                KeyLabel::Combo(combo.join("+"))
+            }
+        };
+        if let Some(overlay) = &mut self.input_overlay {
+            overlay.add_key(label);
+        }
+    }
+
+    /// Marks an injected button press at the pointer, if the overlay is showing.
+    pub fn overlay_click(&mut self) {
+        let location = self.seat.get_pointer().unwrap().current_location();
+        if let Some(overlay) = &mut self.input_overlay {
+            overlay.add_click(location);
+        }
+    }
+}
+
+/// The keys as they read: typed characters run together, named keys and
+/// combinations stand apart.
+fn caption_text(keys: &VecDeque<KeyLabel>) -> String {
+    let mut caption = String::new();
+    for key in keys {
+        match key {
+            KeyLabel::Char(c) => caption.push(*c),
+            KeyLabel::Combo(combo) => {
+                if !caption.is_empty() && !caption.ends_with(' ') {
+                    caption.push(' ');
+                }
+                caption.push_str(combo);
+                caption.push(' ');
+            }
+        }
+    }
+    caption.trim_end().to_string()
+}
+
+/// The caption in white on a translucent box.
+fn caption_image(caption: &str) -> RgbaImage {
+    let width = text::text_width(caption, CAPTION_SCALE) + 2 * CAPTION_PADDING;
+    let height = text::line_height(CAPTION_SCALE) + 2 * CAPTION_PADDING;
+    let mut img = RgbaImage::from_pixel(width, height, CAPTION_BACKGROUND);
+    let padding = CAPTION_PADDING as i32;
+    text::draw_text(
+        &mut img,
+        padding,
+        padding,
+        caption,
+        CAPTION_SCALE,
+        CAPTION_COLOR,
+    );
+    img
+}
+
+/// A ring of CLICK_RADIUS around the center of the image.
+fn ring() -> RgbaImage {
+    let size = 2 * CLICK_RADIUS;
+    let outer = CLICK_RADIUS as f64;
+    let inner = outer - CLICK_WIDTH as f64;
+    RgbaImage::from_fn(size, size, |x, y| {
+        let dx = x as f64 + 0.5 - outer;
+        let dy = y as f64 + 0.5 - outer;
+        let distance = dx.hypot(dy);
+        if (inner..outer).contains(&distance) {
+            CLICK_COLOR
+        } else {
+            Rgba([0, 0, 0, 0])
+        }
+    })
+}
+
+fn buffer(img: &RgbaImage) -> MemoryRenderBuffer {
+    MemoryRenderBuffer::from_slice(
+        img.as_raw(),
+        Fourcc::Abgr8888,
+        (img.width() as i32, img.height() as i32),
+        1,
+        Transform::Normal,
+        None,
+    )
+}
+
+fn element<R>(
+    renderer: &mut R,
+    location: Point<f64, Logical>,
+    scale: Scale<f64>,
+    buffer: &MemoryRenderBuffer,
+    alpha: Option<f32>,
+) -> Option<MemoryRenderBufferRenderElement<R>>
+where
+    R: Renderer + ImportMem,
+    R::TextureId: Send + Clone + 'static,
+{
+    MemoryRenderBufferRenderElement::from_buffer(
+        renderer,
+        location.to_physical(scale),
+        buffer,
+        alpha,
+        None,
+        None,
+        Kind::Unspecified,
+    )
+    .map_err(|e| tracing::warn!("Failed to upload the input overlay: {:?}", e))
+    .ok()
+}
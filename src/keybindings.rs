@@ -0,0 +1,262 @@
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use smithay::input::keyboard::{Keysym, ModifiersState};
+
+use crate::{
+    handlers::WindowMode, keymap::keysym_for_name, process::LaunchOptions,
+    screenshot::ScreenshotOptions, Smallvil,
+};
+
+/// How long the close binding waits for a window to go away before giving up.
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// What a keybinding does.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Action {
+    /// Asks the focused window to close
+    Close,
+    /// Toggles the focused window between fullscreen and normal
+    Fullscreen,
+    /// Toggles the focused window between maximized and normal
+    Maximize,
+    /// Focuses the next window, cycling through them in stacking order
+    FocusNext,
+    /// Saves a screenshot of the primary output into the session directory
+    Screenshot,
+    /// Runs a shell command
+    Exec(String),
+}
+
+impl Action {
+    fn parse(spec: &str) -> Result<Option<Self>, String> {
+        let (name, rest) = spec
+            .split_once(char::is_whitespace)
+            .map_or((spec, ""), |(name, rest)| (name, rest.trim()));
+        let action = match name {
+            "none" => return Ok(None),
+            "close" => Action::Close,
+            "fullscreen" => Action::Fullscreen,
+            "maximize" => Action::Maximize,
+            "focus_next" => Action::FocusNext,
+            "screenshot" => Action::Screenshot,
+            "exec" if !rest.is_empty() => Action::Exec(rest.to_string()),
+            "exec" => return Err("exec needs a command".to_string()),
+            other => {
+                return Err(format!(
+                    "Unknown action '{}', expected close, fullscreen, maximize, focus_next, \
+                     screenshot, exec or none",
+                    other
+                ))
+            }
+        };
+        if !rest.is_empty() && !matches!(action, Action::Exec(_)) {
+            return Err(format!("{} takes no arguments", name));
+        }
+        Ok(Some(action))
+    }
+}
+
+/// A key combination, matched on the key's unshifted keysym in the first
+/// layout so bindings work regardless of the active layout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Combo {
+    ctrl: bool,
+    alt: bool,
+    shift: bool,
+    logo: bool,
+    keysym: Keysym,
+}
+
+impl Combo {
+    /// Parses combinations like "Super+Shift+Q" or "Ctrl+Alt+Delete".
+    fn parse(spec: &str) -> Result<Self, String> {
+        let mut parts: Vec<&str> = spec.split('+').map(str::trim).collect();
+        let key = parts.pop().filter(|key| !key.is_empty());
+        let key = key.ok_or_else(|| format!("No key in '{}'", spec))?;
+
+        let mut combo = Combo {
+            ctrl: false,
+            alt: false,
+            shift: false,
+            logo: false,
+            keysym: Keysym::NoSymbol,
+        };
+        for modifier in parts {
+            match modifier.to_ascii_lowercase().as_str() {
+                "ctrl" | "control" => combo.ctrl = true,
+                "alt" => combo.alt = true,
+                "shift" => combo.shift = true,
+                "super" | "logo" | "meta" | "win" => combo.logo = true,
+                other => return Err(format!("Unknown modifier '{}' in '{}'", other, spec)),
+            }
+        }
+        // Letters are bound by their lowercase keysym, which the key produces without Shift
+        let key = if key.chars().count() == 1 {
+            key.to_lowercase()
+        } else {
+            key.to_string()
+        };
+        combo.keysym =
+            keysym_for_name(&key).ok_or_else(|| format!("Unknown key '{}' in '{}'", key, spec))?;
+        Ok(combo)
+    }
+
+    fn matches(&self, modifiers: &ModifiersState, keysym: Keysym) -> bool {
+        self.keysym == keysym
+            && self.ctrl == modifiers.ctrl
+            && self.alt == modifiers.alt
+            && self.shift == modifiers.shift
+            && self.logo == modifiers.logo
+    }
+}
+
+/// Key combinations the compositor handles itself; they never reach clients.
+/// Only physical input is matched, so agents' synthetic keys always go to
+/// the focused window.
+#[derive(Debug, Clone)]
+pub struct Keybindings {
+    bindings: Vec<(Combo, Action)>,
+}
+
+impl Default for Keybindings {
+    fn default() -> Self {
+        let defaults = [
+            ("Super+Q", Action::Close),
+            ("Super+F", Action::Fullscreen),
+            ("Super+M", Action::Maximize),
+            ("Super+Tab", Action::FocusNext),
+            ("Super+Print", Action::Screenshot),
+        ];
+        Self {
+            bindings: defaults
+                .into_iter()
+                .map(|(spec, action)| (Combo::parse(spec).unwrap(), action))
+                .collect(),
+        }
+    }
+}
+
+impl Keybindings {
+    /// The config file read when --keybindings isn't given, if it exists.
+    pub fn default_path() -> Option<PathBuf> {
+        let config_home = std::env::var_os("XDG_CONFIG_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+        Some(config_home.join("mcpvil/keybindings")).filter(|path| path.is_file())
+    }
+
+    /// Applies a config file on top of the defaults. Each line binds a key
+    /// combination to an action, e.g. `Super+Return = exec foot`; `none`
+    /// removes a binding. Blank lines and lines starting with `#` are skipped.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        let mut keybindings = Self::default();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            keybindings
+                .apply(line)
+                .map_err(|e| format!("{}:{}: {}", path.display(), number + 1, e))?;
+        }
+        Ok(keybindings)
+    }
+
+    fn apply(&mut self, line: &str) -> Result<(), String> {
+        let (combo, action) = line.split_once('=').ok_or("Expected '<keys> = <action>'")?;
+        let combo = Combo::parse(combo)?;
+        let action = Action::parse(action.trim())?;
+        self.bindings.retain(|(other, _)| *other != combo);
+        if let Some(action) = action {
+            self.bindings.push((combo, action));
+        }
+        Ok(())
+    }
+
+    /// The action bound to `keysym` with exactly `modifiers` held.
+    pub fn find(&self, modifiers: &ModifiersState, keysym: Keysym) -> Option<&Action> {
+        self.bindings
+            .iter()
+            .find(|(combo, _)| combo.matches(modifiers, keysym))
+            .map(|(_, action)| action)
+    }
+}
+
+impl Smallvil {
+    /// Carries out a keybinding's action.
+    pub fn run_keybinding(&mut self, action: Action) {
+        tracing::debug!("Keybinding: {:?}", action);
+        match action {
+            Action::Close => {
+                if let Some(window) = self.focused_window() {
+                    // Nobody waits for the outcome
+                    let (response_tx, _) = tokio::sync::oneshot::channel();
+                    self.close_window(&window, CLOSE_TIMEOUT, false, response_tx);
+                }
+            }
+            Action::Fullscreen | Action::Maximize => {
+                let Some(window) = self.focused_window() else {
+                    return;
+                };
+                let info = self.window_info(&window);
+                let mode = match action {
+                    Action::Fullscreen if !info.fullscreen => WindowMode::Fullscreen,
+                    Action::Maximize if !info.maximized => WindowMode::Maximized,
+                    _ => WindowMode::Normal,
+                };
+                self.set_window_mode(&window, mode);
+            }
+            Action::FocusNext => {
+                let windows: Vec<_> = self.space.elements().cloned().collect();
+                let focused = self.focused_window();
+                let next = match focused.and_then(|f| windows.iter().position(|w| *w == f)) {
+                    // Elements are ordered bottom to top, so the next one down wraps to the top
+                    Some(index) => windows[(index + windows.len() - 1) % windows.len()].clone(),
+                    None => match windows.last() {
+                        Some(window) => window.clone(),
+                        None => return,
+                    },
+                };
+                let serial = smithay::utils::SERIAL_COUNTER.next_serial();
+                self.focus_window(&next, serial);
+            }
+            Action::Screenshot => {
+                let now = std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .map(|d| d.as_millis())
+                    .unwrap_or_default();
+                let dir = self.session_dir.clone().unwrap_or_else(std::env::temp_dir);
+                let path = dir.join(format!("screenshot-{}.png", now));
+                let options = ScreenshotOptions {
+                    filename: Some(path.to_string_lossy().into_owned()),
+                    requested_at: Some(std::time::Instant::now()),
+                    ..Default::default()
+                };
+                let (response_tx, response_rx) = tokio::sync::oneshot::channel();
+                self.pending_screenshots.push((options, response_tx));
+                self.request_frame();
+                std::thread::spawn(move || match response_rx.blocking_recv() {
+                    Ok(Ok(_)) => tracing::info!("Saved screenshot to {}", path.display()),
+                    Ok(Err(e)) => tracing::error!("Failed to take screenshot: {}", e),
+                    Err(_) => {}
+                });
+            }
+            Action::Exec(command) => {
+                let options = LaunchOptions {
+                    command: String::from("sh"),
+                    args: vec![String::from("-c"), command.clone()],
+                    ..Default::default()
+                };
+                if let Err(e) = self.spawn_app(options) {
+                    tracing::error!("Failed to run {}: {}", command, e);
+                }
+            }
+        }
+    }
+}
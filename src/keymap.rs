@@ -0,0 +1,298 @@
+use std::time::Duration;
+
+use rmcp::schemars::{self, JsonSchema};
+use serde::Serialize;
+use smithay::input::keyboard::{xkb, Keycode, Keysym, XkbConfig};
+
+use crate::Smallvil;
+
+/// A key press needed to produce a keysym, together with the modifier keys
+/// that have to be held down while it is pressed.
+#[derive(Debug, Clone)]
+pub struct KeyStroke {
+    pub keycode: Keycode,
+    pub modifiers: Vec<Keycode>,
+}
+
+/// Key repeat settings announced to clients, which repeat held keys
+/// themselves (Xwayland does so for X11 apps).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, JsonSchema)]
+pub struct KeyRepeat {
+    /// Repeats per second; 0 disables repeat
+    pub rate: i32,
+    /// Milliseconds a key is held down before it starts repeating
+    pub delay_ms: i32,
+}
+
+impl Default for KeyRepeat {
+    fn default() -> Self {
+        Self {
+            rate: 25,
+            delay_ms: 200,
+        }
+    }
+}
+
+impl KeyRepeat {
+    pub fn new(rate: i32, delay_ms: i32) -> Result<Self, String> {
+        if !(0..=1000).contains(&rate) {
+            return Err(format!("Repeat rate {} is not between 0 and 1000", rate));
+        }
+        if !(1..=10_000).contains(&delay_ms) {
+            return Err(format!(
+                "Repeat delay {} ms is not between 1 and 10000",
+                delay_ms
+            ));
+        }
+        Ok(Self { rate, delay_ms })
+    }
+
+    /// How many times a key held for `held` repeats.
+    pub fn repeats_within(&self, held: Duration) -> u64 {
+        let delay = Duration::from_millis(self.delay_ms as u64);
+        match held.checked_sub(delay) {
+            Some(repeating) if self.rate > 0 => {
+                1 + (repeating.as_millis() as u64 * self.rate as u64) / 1000
+            }
+            _ => 0,
+        }
+    }
+}
+
+/// Reverse lookup table from keysyms to keycodes, compiled from the same
+/// XKB names as the seat keyboard so synthetic input matches what clients see.
+pub struct Keymap {
+    keymap: xkb::Keymap,
+}
+
+impl Keymap {
+    pub fn new(layout: &str, variant: &str, options: Option<String>) -> Result<Self, String> {
+        let context = xkb::Context::new(xkb::CONTEXT_NO_FLAGS);
+        let keymap = xkb::Keymap::new_from_names(
+            &context,
+            "",
+            "",
+            layout,
+            variant,
+            options,
+            xkb::KEYMAP_COMPILE_NO_FLAGS,
+        )
+        .ok_or_else(|| format!("Failed to compile keymap for layout '{}'", layout))?;
+
+        Ok(Self { keymap })
+    }
+
+    /// Finds the keycode and shift level that produce `keysym` in the first layout,
+    /// preferring the lowest level (i.e. the fewest modifiers).
+    fn find_keysym(&self, keysym: Keysym) -> Option<(Keycode, u32)> {
+        let mut found: Option<(Keycode, u32)> = None;
+
+        self.keymap.key_for_each(|keymap, keycode| {
+            for level in 0..keymap.num_levels_for_key(keycode, 0) {
+                if found.is_some_and(|(_, best)| best <= level) {
+                    break;
+                }
+                if keymap
+                    .key_get_syms_by_level(keycode, 0, level)
+                    .contains(&keysym)
+                {
+                    found = Some((keycode, level));
+                    break;
+                }
+            }
+        });
+
+        found
+    }
+
+    /// Name of the keysym `keycode` produces without modifiers, e.g. "a" or "Shift_L".
+    pub fn key_name(&self, keycode: Keycode) -> Option<String> {
+        self.keymap
+            .key_get_syms_by_level(keycode, 0, 0)
+            .first()
+            .map(|keysym| xkb::keysym_get_name(*keysym))
+    }
+
+    /// Character `keycode` types, with Shift held if `shifted`, e.g. 'A'.
+    pub fn key_char(&self, keycode: Keycode, shifted: bool) -> Option<char> {
+        self.keymap
+            .key_get_syms_by_level(keycode, 0, shifted as u32)
+            .first()
+            .and_then(|keysym| keysym.key_char())
+    }
+
+    /// Returns the unmodified keycode for `keysym`, e.g. a modifier key itself.
+    pub fn keycode_for_keysym(&self, keysym: Keysym) -> Option<Keycode> {
+        self.find_keysym(keysym).map(|(keycode, _)| keycode)
+    }
+
+    /// Resolves `keysym` into a key stroke, adding Shift and/or AltGr for the
+    /// shift level the keysym lives on.
+    pub fn stroke_for_keysym(&self, keysym: Keysym) -> Option<KeyStroke> {
+        let (keycode, level) = self.find_keysym(keysym)?;
+
+        let mut modifiers = Vec::new();
+        if level & 1 != 0 {
+            modifiers.push(self.keycode_for_keysym(Keysym::Shift_L)?);
+        }
+        if level & 2 != 0 {
+            modifiers.push(self.keycode_for_keysym(Keysym::ISO_Level3_Shift)?);
+        }
+
+        Some(KeyStroke { keycode, modifiers })
+    }
+
+    /// Resolves a single character into the key stroke that types it.
+    pub fn stroke_for_char(&self, c: char) -> Option<KeyStroke> {
+        let keysym = match c {
+            '\n' | '\r' => Keysym::Return,
+            '\t' => Keysym::Tab,
+            '\u{8}' => Keysym::BackSpace,
+            _ => xkb::utf32_to_keysym(c as u32),
+        };
+
+        if keysym == Keysym::NoSymbol {
+            return None;
+        }
+
+        self.stroke_for_keysym(keysym)
+    }
+
+    /// Resolves a key combination such as `ctrl+shift+t` or `alt+F4` into a
+    /// key stroke. The last `+`-separated part is the key, the rest are modifiers.
+    pub fn stroke_for_combo(&self, combo: &str) -> Result<KeyStroke, String> {
+        let mut parts: Vec<&str> = combo.split('+').map(str::trim).collect();
+        // "ctrl++" means ctrl and the plus key
+        if combo.ends_with("++") {
+            parts.truncate(parts.len() - 2);
+            parts.push("+");
+        }
+        let key = parts
+            .pop()
+            .filter(|k| !k.is_empty())
+            .ok_or_else(|| format!("Missing key in '{}'", combo))?;
+
+        let keysym = keysym_for_name(key).ok_or_else(|| format!("Unknown key '{}'", key))?;
+        let mut stroke = self
+            .stroke_for_keysym(keysym)
+            .ok_or_else(|| format!("No key in the current keymap produces '{}'", key))?;
+
+        for name in parts {
+            let keysym =
+                modifier_keysym(name).ok_or_else(|| format!("Unknown modifier '{}'", name))?;
+            let keycode = self
+                .keycode_for_keysym(keysym)
+                .ok_or_else(|| format!("No key in the current keymap for modifier '{}'", name))?;
+            if !stroke.modifiers.contains(&keycode) {
+                stroke.modifiers.push(keycode);
+            }
+        }
+
+        Ok(stroke)
+    }
+}
+
+fn modifier_keysym(name: &str) -> Option<Keysym> {
+    match name.to_ascii_lowercase().as_str() {
+        "ctrl" | "control" => Some(Keysym::Control_L),
+        "shift" => Some(Keysym::Shift_L),
+        "alt" => Some(Keysym::Alt_L),
+        "super" | "logo" | "meta" | "win" => Some(Keysym::Super_L),
+        "altgr" => Some(Keysym::ISO_Level3_Shift),
+        _ => None,
+    }
+}
+
+/// Resolves a key name to a keysym, accepting XKB keysym names (case-insensitively),
+/// single characters and a few common aliases.
+pub fn keysym_for_name(name: &str) -> Option<Keysym> {
+    let mut chars = name.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        return Some(xkb::utf32_to_keysym(c as u32)).filter(|k| *k != Keysym::NoSymbol);
+    }
+
+    let name = match name.to_ascii_lowercase().as_str() {
+        "enter" => "Return",
+        "esc" => "Escape",
+        "del" => "Delete",
+        "ins" => "Insert",
+        "pgup" => "Prior",
+        "pgdn" | "pgdown" => "Next",
+        "backspace" => "BackSpace",
+        "capslock" => "Caps_Lock",
+        "printscreen" => "Print",
+        _ => name,
+    };
+
+    let keysym = xkb::keysym_from_name(name, xkb::KEYSYM_NO_FLAGS);
+    let keysym = if keysym == Keysym::NoSymbol {
+        xkb::keysym_from_name(name, xkb::KEYSYM_CASE_INSENSITIVE)
+    } else {
+        keysym
+    };
+
+    Some(keysym).filter(|k| *k != Keysym::NoSymbol)
+}
+
+impl Smallvil {
+    /// Switches the seat keyboard, and the reverse lookup used for synthetic
+    /// text input, to another XKB layout. Clients get the new keymap right away.
+    pub fn set_keyboard_layout(
+        &mut self,
+        layout: &str,
+        variant: &str,
+        options: Option<String>,
+    ) -> Result<(), String> {
+        let keymap = Keymap::new(layout, variant, options.clone())?;
+
+        let keyboard = self.seat.get_keyboard().unwrap();
+        keyboard
+            .set_xkb_config(
+                self,
+                XkbConfig {
+                    layout,
+                    variant,
+                    options,
+                    ..Default::default()
+                },
+            )
+            .map_err(|e| format!("Failed to set keymap: {:?}", e))?;
+
+        self.keymap = keymap;
+        Ok(())
+    }
+
+    /// Changes the repeat rate and delay, telling every client with a keyboard.
+    pub fn set_key_repeat(&mut self, repeat: KeyRepeat) {
+        self.seat
+            .get_keyboard()
+            .unwrap()
+            .change_repeat_info(repeat.rate, repeat.delay_ms);
+        self.key_repeat = repeat;
+    }
+}
+
+/// Parses a `--keymap` value: `LAYOUT[:VARIANT[:OPTIONS]]`, e.g. `de`,
+/// `fr:azerty` or `us:intl:compose:ralt`.
+pub fn parse_keymap_spec(spec: &str) -> (&str, &str, Option<String>) {
+    let mut parts = spec.splitn(3, ':');
+    let layout = parts.next().unwrap_or_default();
+    let variant = parts.next().unwrap_or_default();
+    let options = parts.next().map(str::to_string);
+    (layout, variant, options)
+}
+
+/// Parses a `--key-repeat` value: `RATE:DELAY`, in repeats per second and
+/// milliseconds, e.g. `30:300`.
+pub fn parse_key_repeat_spec(spec: &str) -> Result<KeyRepeat, String> {
+    let (rate, delay) = spec
+        .split_once(':')
+        .ok_or_else(|| format!("Invalid key repeat '{}', expected RATE:DELAY", spec))?;
+    let rate = rate
+        .parse()
+        .map_err(|_| format!("Invalid repeat rate '{}'", rate))?;
+    let delay = delay
+        .parse()
+        .map_err(|_| format!("Invalid repeat delay '{}'", delay))?;
+    KeyRepeat::new(rate, delay)
+}
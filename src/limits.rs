@@ -0,0 +1,70 @@
+use std::{os::unix::process::ExitStatusExt, process::ExitStatus};
+
+/// Seconds of CPU time between SIGXCPU and SIGKILL, for apps that ignore the
+/// former.
+const CPU_GRACE_SECONDS: u64 = 1;
+
+/// Highest oom_score_adj, making an app the OOM killer's first choice.
+const OOM_SCORE_ADJ: &[u8] = b"1000";
+
+/// Caps on what a launched app may use, set with setrlimit before it starts.
+/// The limits are inherited by everything it spawns.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ResourceLimits {
+    /// Address space in bytes (RLIMIT_AS). The app is also put first in line
+    /// for the OOM killer, ahead of the compositor
+    pub memory: Option<u64>,
+    /// CPU time in seconds (RLIMIT_CPU)
+    pub cpu_seconds: Option<u64>,
+    /// Processes and threads, counted across the whole user (RLIMIT_NPROC)
+    pub max_processes: Option<u64>,
+}
+
+impl ResourceLimits {
+    /// Applies the limits to the calling process. Runs between fork and exec,
+    /// so it only makes async-signal-safe calls.
+    pub fn apply(&self) -> std::io::Result<()> {
+        let limits = [
+            (libc::RLIMIT_AS, self.memory, 0),
+            (libc::RLIMIT_CPU, self.cpu_seconds, CPU_GRACE_SECONDS),
+            (libc::RLIMIT_NPROC, self.max_processes, 0),
+        ];
+        for (resource, limit, grace) in limits {
+            let Some(limit) = limit else {
+                continue;
+            };
+            let rlimit = libc::rlimit {
+                rlim_cur: limit as libc::rlim_t,
+                rlim_max: limit.saturating_add(grace) as libc::rlim_t,
+            };
+            if unsafe { libc::setrlimit(resource, &rlimit) } != 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+        }
+
+        if self.memory.is_some() {
+            // Best effort; the address space limit applies either way
+            unsafe {
+                let path = b"/proc/self/oom_score_adj\0";
+                let fd = libc::open(path.as_ptr() as *const libc::c_char, libc::O_WRONLY);
+                if fd >= 0 {
+                    libc::write(fd, OOM_SCORE_ADJ.as_ptr() as *const _, OOM_SCORE_ADJ.len());
+                    libc::close(fd);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The limit that ended a process with `status`, if it looks like one did:
+    /// "cpu" for SIGXCPU, or the limit that applies when the kernel sent
+    /// SIGKILL and mcpvil didn't.
+    pub fn exceeded(&self, status: ExitStatus, killed: bool) -> Option<&'static str> {
+        match status.signal()? {
+            libc::SIGXCPU => Some("cpu"),
+            libc::SIGKILL if !killed && self.memory.is_some() => Some("memory"),
+            libc::SIGKILL if !killed && self.cpu_seconds.is_some() => Some("cpu"),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,56 @@
+use crate::Smallvil;
+
+pub type StepResponse = tokio::sync::oneshot::Sender<Result<u64, String>>;
+
+/// Frame stepping for `--lockstep`: outputs are only rendered, and clients
+/// only get frame callbacks, when advance_frames asks for it. Captures still
+/// render the current scene in between, without advancing clients.
+#[derive(Default)]
+pub struct Lockstep {
+    /// Frames asked for that haven't been rendered yet
+    pub steps: u64,
+    /// Frames stepped so far
+    pub frames: u64,
+    /// advance_frames calls waiting for a frame number
+    waiters: Vec<(u64, StepResponse)>,
+}
+
+impl Smallvil {
+    /// Renders `count` more frames, replying with the last one's number once
+    /// it has been rendered.
+    pub fn advance_frames(&mut self, count: u64, response_tx: StepResponse) {
+        let Some(lockstep) = self.lockstep.as_mut() else {
+            let _ = response_tx.send(Err("mcpvil is not running with --lockstep".to_string()));
+            return;
+        };
+        lockstep.steps += count;
+        let target = lockstep.frames + lockstep.steps;
+        lockstep.waiters.push((target, response_tx));
+        self.request_frame();
+    }
+
+    /// Counts a stepped frame once it has been rendered and answers the
+    /// advance_frames calls it completes.
+    pub fn finish_step(&mut self) {
+        let Some(lockstep) = self.lockstep.as_mut() else {
+            return;
+        };
+        if lockstep.steps == 0 {
+            return;
+        }
+        lockstep.steps -= 1;
+        lockstep.frames += 1;
+
+        let frames = lockstep.frames;
+        let (done, pending) = std::mem::take(&mut lockstep.waiters)
+            .into_iter()
+            .partition(|(target, _)| *target <= frames);
+        lockstep.waiters = pending;
+        for (_, response_tx) in done {
+            let _ = response_tx.send(Ok(frames));
+        }
+        if lockstep.steps > 0 {
+            self.request_frame();
+        }
+    }
+}
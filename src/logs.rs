@@ -0,0 +1,249 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt::Write,
+    sync::{Arc, Mutex},
+};
+
+use rmcp::{
+    model::{LoggingLevel, LoggingMessageNotificationParam},
+    schemars::{self, JsonSchema},
+    Peer, RoleServer,
+};
+use serde::Serialize;
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
+use tracing::{
+    field::{Field, Visit},
+    Level, Subscriber,
+};
+use tracing_subscriber::layer::{Context, Layer};
+
+/// URI of the compositor log resource.
+pub const LOGS_URI: &str = "mcpvil://logs";
+
+/// Log records kept; older ones are dropped.
+const HISTORY_LIMIT: usize = 2000;
+
+/// Logger name of compositor log records streamed to clients.
+const LOGGER: &str = "compositor";
+
+/// A tracing event from the compositor, as returned by get_logs.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct LogRecord {
+    pub id: u64,
+    /// When it was logged, in milliseconds since the Unix epoch
+    pub timestamp_ms: u64,
+    /// "error", "warn", "info", "debug" or "trace"
+    pub level: &'static str,
+    /// Module path or explicit target, e.g. "mcpvil::process" or "audit"
+    pub target: String,
+    /// The message, followed by the event's other fields as `key=value`
+    pub message: String,
+}
+
+impl LogRecord {
+    /// 0 for errors up to 4 for trace, so lower is more severe.
+    pub fn severity(&self) -> u8 {
+        severity(self.level).unwrap_or(4)
+    }
+
+    /// One line in the format of the log resource.
+    fn line(&self) -> String {
+        format!(
+            "{} {:>5} {}: {}",
+            self.timestamp_ms,
+            self.level.to_ascii_uppercase(),
+            self.target,
+            self.message
+        )
+    }
+}
+
+/// The severity of a level name, as for LogRecord::severity. MCP's
+/// "warning" is accepted for "warn".
+pub fn severity(level: &str) -> Option<u8> {
+    match level.to_ascii_lowercase().as_str() {
+        "error" => Some(0),
+        "warn" | "warning" => Some(1),
+        "info" => Some(2),
+        "debug" => Some(3),
+        "trace" => Some(4),
+        _ => None,
+    }
+}
+
+/// Recent log records, oldest first.
+#[derive(Debug, Default)]
+pub struct LogBuffer {
+    records: VecDeque<LogRecord>,
+    last_id: u64,
+}
+
+pub type Logs = Arc<Mutex<LogBuffer>>;
+
+impl LogBuffer {
+    fn push(&mut self, mut record: LogRecord) -> LogRecord {
+        self.last_id += 1;
+        record.id = self.last_id;
+        if self.records.len() == HISTORY_LIMIT {
+            self.records.pop_front();
+        }
+        self.records.push_back(record.clone());
+        record
+    }
+
+    /// Records newer than `since_id` at `max_severity` or more severe that
+    /// `keep` accepts.
+    pub fn list(
+        &self,
+        since_id: u64,
+        max_severity: u8,
+        keep: impl Fn(&LogRecord) -> bool,
+    ) -> Vec<LogRecord> {
+        self.records
+            .iter()
+            .filter(|r| r.id > since_id && r.severity() <= max_severity && keep(r))
+            .cloned()
+            .collect()
+    }
+
+    /// Id of the newest record, for polling with get_logs.
+    pub fn last_id(&self) -> u64 {
+        self.last_id
+    }
+
+    /// The buffer as text, one record per line, for the log resource.
+    pub fn text(&self) -> String {
+        self.records.iter().fold(String::new(), |mut text, record| {
+            text.push_str(&record.line());
+            text.push('\n');
+            text
+        })
+    }
+}
+
+/// Copies every tracing event that passes the global filter into the
+/// buffer, and hands it on for clients streaming the logs.
+pub struct LogLayer {
+    logs: Logs,
+    live_tx: UnboundedSender<LogRecord>,
+}
+
+impl LogLayer {
+    pub fn new(logs: Logs, live_tx: UnboundedSender<LogRecord>) -> Self {
+        Self { logs, live_tx }
+    }
+}
+
+impl<S: Subscriber> Layer<S> for LogLayer {
+    fn on_event(&self, event: &tracing::Event<'_>, _ctx: Context<'_, S>) {
+        let metadata = event.metadata();
+        let mut visitor = MessageVisitor::default();
+        event.record(&mut visitor);
+        let timestamp_ms = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or_default();
+        let record = LogRecord {
+            id: 0,
+            timestamp_ms,
+            level: level_name(metadata.level()),
+            target: metadata.target().to_string(),
+            message: visitor.message + &visitor.fields,
+        };
+        let record = self.logs.lock().unwrap().push(record);
+        // Sending a notification logs in rmcp, which would feed back into the stream
+        if !record.target.starts_with("rmcp") {
+            let _ = self.live_tx.send(record);
+        }
+    }
+}
+
+fn level_name(level: &Level) -> &'static str {
+    match *level {
+        Level::ERROR => "error",
+        Level::WARN => "warn",
+        Level::INFO => "info",
+        Level::DEBUG => "debug",
+        Level::TRACE => "trace",
+    }
+}
+
+/// Collects an event's message and its other fields as ` key=value` pairs,
+/// as the stderr output shows them.
+#[derive(Default)]
+struct MessageVisitor {
+    message: String,
+    fields: String,
+}
+
+impl Visit for MessageVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.message.push_str(value);
+        } else {
+            let _ = write!(self.fields, " {}={}", field.name(), value);
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "message" {
+            let _ = write!(self.message, "{:?}", value);
+        } else {
+            let _ = write!(self.fields, " {}={:?}", field.name(), value);
+        }
+    }
+}
+
+/// Clients streaming the logs, by connection id, with the least severe level
+/// each asked for through logging/setLevel.
+pub type LogSubscribers = Arc<Mutex<HashMap<String, (Peer<RoleServer>, u8)>>>;
+
+/// The severity of an MCP logging level. The levels MCP has beyond syslog's
+/// map onto the closest tracing level.
+pub fn logging_level_severity(level: LoggingLevel) -> u8 {
+    match level {
+        LoggingLevel::Emergency
+        | LoggingLevel::Alert
+        | LoggingLevel::Critical
+        | LoggingLevel::Error => 0,
+        LoggingLevel::Warning => 1,
+        LoggingLevel::Notice | LoggingLevel::Info => 2,
+        LoggingLevel::Debug => 3,
+    }
+}
+
+/// Sends new log records to the clients that asked for them with
+/// logging/setLevel, as `compositor` log notifications.
+pub async fn forward_logs(subscribers: LogSubscribers, mut records: UnboundedReceiver<LogRecord>) {
+    while let Some(record) = records.recv().await {
+        let peers: Vec<Peer<RoleServer>> = subscribers
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|(_, max_severity)| record.severity() <= *max_severity)
+            .map(|(peer, _)| peer.clone())
+            .collect();
+        if peers.is_empty() {
+            continue;
+        }
+        let level = match record.severity() {
+            0 => LoggingLevel::Error,
+            1 => LoggingLevel::Warning,
+            2 => LoggingLevel::Info,
+            _ => LoggingLevel::Debug,
+        };
+        let notification = LoggingMessageNotificationParam {
+            level,
+            logger: Some(LOGGER.to_string()),
+            data: serde_json::to_value(&record).unwrap_or_default(),
+        };
+        for peer in peers {
+            // Not logged: a failure here would be streamed again
+            let _ = peer.notify_logging_message(notification.clone()).await;
+        }
+        subscribers
+            .lock()
+            .unwrap()
+            .retain(|_, (peer, _)| !peer.is_transport_closed());
+    }
+}
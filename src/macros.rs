@@ -0,0 +1,193 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use smithay::{
+    backend::input::{Axis, AxisSource, ButtonState, KeyState},
+    input::keyboard::Keycode,
+    reexports::calloop::timer::{TimeoutAction, Timer},
+    utils::{Logical, Point, SERIAL_COUNTER},
+};
+
+use crate::Smallvil;
+
+/// An input event captured into a macro, real or synthetic.
+#[derive(Debug, Clone, Copy)]
+pub enum MacroEvent {
+    Motion(Point<f64, Logical>),
+    Button {
+        button: u32,
+        pressed: bool,
+    },
+    Key {
+        keycode: Keycode,
+        pressed: bool,
+    },
+    Scroll {
+        horizontal: f64,
+        vertical: f64,
+        source: AxisSource,
+    },
+}
+
+/// Events with their time since the recording started.
+pub type InputMacro = Vec<(Duration, MacroEvent)>;
+
+/// A recording in progress, saved under `session` when stopped.
+pub struct MacroRecording {
+    pub session: String,
+    pub started: Instant,
+    pub events: InputMacro,
+}
+
+/// A saved session, as reported by stop_input_recording.
+#[derive(Debug, Clone)]
+pub struct MacroSummary {
+    pub session: String,
+    pub events: usize,
+    pub duration: Duration,
+}
+
+impl Smallvil {
+    /// Adds `event` to the running macro recording, if there is one.
+    pub fn record_input(&mut self, event: MacroEvent) {
+        if let Some(recording) = &mut self.macro_recording {
+            recording.events.push((recording.started.elapsed(), event));
+        }
+    }
+
+    pub fn start_macro_recording(&mut self, session: String) -> Result<(), String> {
+        if let Some(recording) = &self.macro_recording {
+            return Err(format!(
+                "Already recording input into session '{}'",
+                recording.session
+            ));
+        }
+        self.macro_recording = Some(MacroRecording {
+            session,
+            started: Instant::now(),
+            events: Vec::new(),
+        });
+        Ok(())
+    }
+
+    /// Stops recording and saves the session, replacing any earlier one of the
+    /// same name.
+    pub fn stop_macro_recording(&mut self) -> Result<MacroSummary, String> {
+        let recording = self
+            .macro_recording
+            .take()
+            .ok_or_else(|| "No input recording is running".to_string())?;
+        let summary = MacroSummary {
+            session: recording.session.clone(),
+            events: recording.events.len(),
+            duration: recording.started.elapsed(),
+        };
+        self.input_macros
+            .insert(recording.session, recording.events);
+        Ok(summary)
+    }
+
+    /// Plays a recorded session back from a timer, `speed` times as fast as it
+    /// was recorded, and replies with the number of events once done.
+    pub fn replay_macro(
+        &mut self,
+        session: &str,
+        speed: f64,
+        response_tx: tokio::sync::oneshot::Sender<Result<usize, String>>,
+    ) {
+        let Some(events) = self.input_macros.get(session) else {
+            let _ = response_tx.send(Err(format!("No input session named '{}'", session)));
+            return;
+        };
+        let mut events: VecDeque<_> = events.iter().copied().collect();
+        let count = events.len();
+        let started = Instant::now();
+        let mut response_tx = Some(response_tx);
+
+        let timer = self
+            .loop_handle
+            .insert_source(Timer::immediate(), move |_, _, data| {
+                if response_tx.as_ref().is_some_and(|tx| tx.is_closed()) {
+                    // The call was cancelled or timed out; stop without leaving anything held
+                    data.state.release_held(&events);
+                    return TimeoutAction::Drop;
+                }
+
+                let elapsed = started.elapsed().mul_f64(speed);
+                while events.front().is_some_and(|(at, _)| *at <= elapsed) {
+                    let (_, event) = events.pop_front().unwrap();
+                    data.state.replay_event(event);
+                }
+
+                match events.front() {
+                    Some((at, _)) => TimeoutAction::ToInstant(started + at.div_f64(speed)),
+                    None => {
+                        if let Some(response_tx) = response_tx.take() {
+                            let _ = response_tx.send(Ok(count));
+                        }
+                        TimeoutAction::Drop
+                    }
+                }
+            });
+        if let Err(e) = timer {
+            tracing::error!("Failed to schedule input replay: {}", e);
+        }
+    }
+
+    /// Replays the releases in `rest`, what is left of a stopped replay, of
+    /// keys and buttons pressed before it stopped.
+    fn release_held(&mut self, rest: &VecDeque<(Duration, MacroEvent)>) {
+        let mut pressed_later = Vec::new();
+        for &(_, event) in rest {
+            let (input, pressed) = match event {
+                MacroEvent::Button { button, pressed } => ((false, button), pressed),
+                MacroEvent::Key { keycode, pressed } => ((true, keycode.raw()), pressed),
+                _ => continue,
+            };
+            if pressed {
+                pressed_later.push(input);
+            } else if !pressed_later.contains(&input) {
+                self.replay_event(event);
+            }
+        }
+    }
+
+    fn replay_event(&mut self, event: MacroEvent) {
+        match event {
+            MacroEvent::Motion(location) => self.synthetic_motion(location),
+            MacroEvent::Button { button, pressed } => {
+                let pointer = self.seat.get_pointer().unwrap();
+                if pressed && !pointer.is_grabbed() {
+                    self.focus_at(pointer.current_location(), SERIAL_COUNTER.next_serial());
+                }
+                let state = if pressed {
+                    ButtonState::Pressed
+                } else {
+                    ButtonState::Released
+                };
+                self.synthetic_button(button, state);
+            }
+            MacroEvent::Key { keycode, pressed } => {
+                let state = if pressed {
+                    KeyState::Pressed
+                } else {
+                    KeyState::Released
+                };
+                self.synthetic_key(keycode, state);
+            }
+            MacroEvent::Scroll {
+                horizontal,
+                vertical,
+                source,
+            } => {
+                for (axis, amount) in [(Axis::Horizontal, horizontal), (Axis::Vertical, vertical)] {
+                    if amount != 0.0 {
+                        self.synthetic_scroll(None, axis, amount, None, source);
+                    }
+                }
+            }
+        }
+    }
+}
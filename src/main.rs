@@ -2,32 +2,152 @@
 
 mod handlers;
 
+mod accessibility;
+mod annotate;
+mod background;
+mod cancel;
+mod capture;
+mod clipboard;
+mod command;
+mod compare;
+mod constraints;
+mod contact_sheet;
+mod cursor;
+mod dbus;
+mod decoration;
+mod desktop;
+#[cfg(feature = "drm")]
+mod drm;
+mod dump;
+mod errors;
+mod events;
+mod files;
+mod flatpak;
+mod frame_history;
+mod gesture;
 mod grabs;
+mod headless;
+mod http;
+mod idle;
 mod input;
+mod input_overlay;
+mod keybindings;
+mod keymap;
+mod limits;
+mod lockstep;
+mod logs;
+mod macros;
+mod matching;
+mod metrics;
+mod notifications;
+mod ocr;
+mod pacing;
+mod pixel;
+mod portal;
+mod presentation;
+mod process;
+mod progress;
+mod prompts;
+mod protocol_trace;
+mod recording;
+mod render;
+mod resources;
+mod sandbox;
+mod scale;
+mod scene;
+mod screencast;
+mod screencopy;
+mod screenshot;
+mod sequence;
+mod stability;
 mod state;
+mod stats;
+mod structured;
+mod tablet;
+mod telemetry;
+mod text;
+mod transform;
 mod winit;
+#[cfg(feature = "x11")]
+mod x11;
 
+use base64::Engine;
 use rmcp::{
-    handler::server::{router::tool::ToolRouter, wrapper::Parameters},
-    model::{CallToolResult, Content, ServerCapabilities, ServerInfo},
+    handler::server::{router::tool::ToolRouter, tool::ToolCallContext, wrapper::Parameters},
+    model::{
+        AnnotateAble, CallToolRequestParam, CallToolResult, Content, GetPromptRequestParam,
+        GetPromptResult, ListPromptsResult, ListResourcesResult, ListToolsResult,
+        PaginatedRequestParam, RawResource, ReadResourceRequestParam, ReadResourceResult,
+        ResourceContents, ServerCapabilities, ServerInfo, SetLevelRequestParam,
+        SubscribeRequestParam, UnsubscribeRequestParam,
+    },
     schemars,
-    tool,
-    tool_handler,
-    tool_router,
-    transport::stdio,
-    ErrorData as McpError,
-    ServerHandler,
-    ServiceExt,
+    service::{NotificationContext, RequestContext},
+    tool, tool_router,
+    transport::{
+        stdio,
+        streamable_http_server::{
+            session::local::LocalSessionManager, StreamableHttpServerConfig, StreamableHttpService,
+        },
+    },
+    ErrorData as McpError, Peer, RoleServer, ServerHandler, ServiceExt,
 };
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-
+use annotate::Annotation;
+use cancel::ToolCall;
+use clipboard::{ClipboardData, ClipboardResponse};
+use command::RunOptions;
+use desktop::DesktopEntry;
+use dump::StateDump;
+use errors::{ErrorKind, ToolError};
+use events::{Subscribers, WindowWait};
+use files::SessionDir;
+use frame_history::{FrameHistory, RecentFrame};
+use handlers::{CloseOutcome, WindowMode};
+use idle::IdleResponse;
+use input::{InputState, PointerTarget};
+use input_overlay::InputOverlay;
+use keybindings::Keybindings;
+use keymap::KeyRepeat;
+use limits::ResourceLimits;
+use lockstep::{Lockstep, StepResponse};
+use logs::{LogLayer, LogSubscribers, Logs, LOGS_URI};
+use metrics::ToolMetrics;
+use notifications::Notifications;
+use portal::{FileChooserResponses, StagedResponse};
+use presentation::FrameTimingInfo;
+use process::{LaunchOptions, LaunchResponse, LaunchedApp, ProcessInfo};
+use progress::Progress;
+use protocol_trace::{ProtocolTrace, PROTOCOL_TRACE_URI};
+use recording::{
+    Recording, RecordingFormat, RecordingOptions, RecordingResponse, RecordingSummary,
+};
+use render::RendererKind;
+use resources::SharedResources;
+use scene::{SceneReceiver, SceneSubscribers, SCENE_URI};
+use screencast::ScreencastResponse;
+use screenshot::{CaptureMode, ImageFormat, Screenshot, ScreenshotOptions, ScreenshotResponse};
 use smithay::reexports::{
     calloop::EventLoop,
-    wayland_server::{Display, DisplayHandle},
+    wayland_server::{protocol::wl_data_device_manager::DndAction, Display, DisplayHandle},
 };
+use smithay::utils::Transform;
+use smithay::wayland::selection::SelectionTarget;
+use stability::StabilityResponse;
 pub use state::Smallvil;
+use state::{Geometry, OutputInfo, WindowGeometry, WindowInfo, WindowLabel};
+use stats::StatsInfo;
+use structured::{
+    AppOutput, IdleResult, ImageMatches, InstalledAppList, LaunchResult, LogList, NotificationList,
+    OutputList, ProcessList, RecentFrameInfo, RecentFrameList, RecordingResult, RestartResult,
+    ScreenshotResult, SelectionContent, SignalResult, SkippedWindow, StabilityResult,
+    WindowCaptures, WindowList,
+};
+use telemetry::CommandSender;
+use tracing::Instrument;
+use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
 pub struct CalloopData {
     state: Smallvil,
@@ -38,12 +158,244 @@ pub struct CalloopData {
 pub struct LaunchAppRequest {
     command: String,
     args: Vec<String>,
+    /// Extra environment variables, e.g. {"GDK_BACKEND": "wayland"}
+    env: Option<std::collections::HashMap<String, String>>,
+    /// Working directory to start the app in
+    cwd: Option<String>,
+    /// Text to write to the app's stdin
+    stdin: Option<String>,
+    /// Run the app in a bubblewrap (bwrap) sandbox: read-only system directories, an empty
+    /// home, private /tmp, no network, and only the compositor's sockets shared (default:
+    /// false). `cwd` is shared read-write
+    sandbox: Option<bool>,
+    /// Most memory the app may map, in MiB (RLIMIT_AS). It is also made the first choice of
+    /// the OOM killer
+    memory_limit_mb: Option<u64>,
+    /// Most CPU time the app may use, in seconds (RLIMIT_CPU)
+    cpu_limit_seconds: Option<u64>,
+    /// Most processes and threads (RLIMIT_NPROC). Counted across the whole user, so leave
+    /// room for everything else it runs
+    max_processes: Option<u64>,
+    /// Only return once the app has mapped its first window (default: false)
+    wait_for_window: Option<bool>,
+    /// How long to wait for the window, in milliseconds (default: 10000)
+    timeout_ms: Option<u64>,
 }
 
 #[derive(Serialize, Deserialize, JsonSchema)]
 pub struct ScreenshotRequest {
-    /// File path to save the screenshot to (PNG format)
-    filename: String,
+    /// File path to save the screenshot to, encoded in `format`
+    filename: Option<String>,
+    /// Return the image inline as image content (default: true when no filename is given)
+    inline: Option<bool>,
+    /// Image format: "png" (default), "jpeg", "webp" or "qoi"
+    format: Option<String>,
+    /// 1-100. JPEG quality (default 80), or PNG compression where higher is faster
+    /// and larger. Ignored for webp (lossless) and qoi
+    quality: Option<u8>,
+    /// Draw the mouse cursor into the image (default: false)
+    include_cursor: Option<bool>,
+    /// What to capture: "full_output" (default), "focused_window", "window_id" or "region"
+    mode: Option<String>,
+    /// Window to capture in "window_id" mode, as returned by list_windows
+    window_id: Option<u64>,
+    /// Output to capture in "full_output" and "region" modes (default: the primary output)
+    output: Option<String>,
+    /// Left edge of the region to capture, relative to the output
+    x: Option<i32>,
+    /// Top edge of the region to capture, relative to the output
+    y: Option<i32>,
+    /// Width of the region to capture
+    width: Option<i32>,
+    /// Height of the region to capture
+    height: Option<i32>,
+    /// Callouts drawn onto the image before it is saved, in its pixels, each with a
+    /// "type" of rect, label, arrow or window
+    annotations: Option<Vec<AnnotationArg>>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AnnotationArg {
+    /// Outlines a rectangle, with an optional label above it
+    Rect {
+        x: i32,
+        y: i32,
+        width: i32,
+        height: i32,
+        /// #RRGGBB or #RRGGBBAA (default: "#ff0000")
+        color: Option<String>,
+        /// Line width in pixels (default: 3)
+        thickness: Option<u32>,
+        label: Option<String>,
+    },
+    /// Text in a box, with its top left corner at x, y
+    Label {
+        x: i32,
+        y: i32,
+        text: String,
+        /// Color of the box; the text is white (default: "#ff0000")
+        color: Option<String>,
+    },
+    /// An arrow pointing from one point to another
+    Arrow {
+        from_x: i32,
+        from_y: i32,
+        to_x: i32,
+        to_y: i32,
+        /// #RRGGBB or #RRGGBBAA (default: "#ff0000")
+        color: Option<String>,
+        /// Line width in pixels (default: 3)
+        thickness: Option<u32>,
+    },
+    /// Outlines a window by id, as returned by list_windows
+    Window {
+        window_id: u64,
+        /// #RRGGBB or #RRGGBBAA (default: "#ff0000")
+        color: Option<String>,
+        /// Line width in pixels (default: 3)
+        thickness: Option<u32>,
+        label: Option<String>,
+    },
+}
+
+impl AnnotationArg {
+    fn annotation(&self) -> Result<Annotation, String> {
+        let thickness = |thickness: Option<u32>| {
+            thickness
+                .unwrap_or(annotate::DEFAULT_THICKNESS)
+                .clamp(1, 100)
+        };
+        Ok(match self {
+            AnnotationArg::Rect {
+                x,
+                y,
+                width,
+                height,
+                color,
+                thickness: t,
+                label,
+            } => Annotation::Rect {
+                rect: Geometry {
+                    x: *x,
+                    y: *y,
+                    width: *width,
+                    height: *height,
+                },
+                color: annotate::parse_color(color.as_deref())?,
+                thickness: thickness(*t),
+                label: label.clone(),
+            },
+            AnnotationArg::Label { x, y, text, color } => Annotation::Label {
+                x: *x,
+                y: *y,
+                text: text.clone(),
+                color: annotate::parse_color(color.as_deref())?,
+            },
+            AnnotationArg::Arrow {
+                from_x,
+                from_y,
+                to_x,
+                to_y,
+                color,
+                thickness: t,
+            } => Annotation::Arrow {
+                from: (*from_x, *from_y),
+                to: (*to_x, *to_y),
+                color: annotate::parse_color(color.as_deref())?,
+                thickness: thickness(*t),
+            },
+            AnnotationArg::Window {
+                window_id,
+                color,
+                thickness: t,
+                label,
+            } => Annotation::Window {
+                id: *window_id,
+                color: annotate::parse_color(color.as_deref())?,
+                thickness: thickness(*t),
+                label: label.clone(),
+            },
+        })
+    }
+}
+
+/// Converts the annotations of a screenshot request.
+fn parse_annotations(annotations: Option<&[AnnotationArg]>) -> Result<Vec<Annotation>, String> {
+    annotations
+        .unwrap_or_default()
+        .iter()
+        .map(AnnotationArg::annotation)
+        .collect()
+}
+
+/// The requested capture region, if all four of x, y, width and height are given.
+fn parse_region(
+    x: Option<i32>,
+    y: Option<i32>,
+    width: Option<i32>,
+    height: Option<i32>,
+) -> Result<Option<Geometry>, String> {
+    match (x, y, width, height) {
+        (None, None, None, None) => Ok(None),
+        (Some(x), Some(y), Some(width), Some(height)) if width > 0 && height > 0 => {
+            Ok(Some(Geometry {
+                x,
+                y,
+                width,
+                height,
+            }))
+        }
+        (Some(_), Some(_), Some(_), Some(_)) => {
+            Err("Region width and height must be positive".to_string())
+        }
+        _ => Err("Specify all of x, y, width and height, or none of them".to_string()),
+    }
+}
+
+impl ScreenshotRequest {
+    fn region(&self) -> Result<Option<Geometry>, String> {
+        parse_region(self.x, self.y, self.width, self.height)
+    }
+
+    fn capture_mode(&self) -> Result<CaptureMode, String> {
+        match (self.mode.as_deref(), self.region()?) {
+            (None, Some(region)) | (Some("region"), Some(region)) => {
+                Ok(CaptureMode::Region(region))
+            }
+            (Some("region"), None) => {
+                Err("Region mode requires x, y, width and height".to_string())
+            }
+            (None, None) | (Some("full_output"), _) => Ok(CaptureMode::FullOutput),
+            (Some("focused_window"), _) => Ok(CaptureMode::FocusedWindow),
+            (Some("window_id"), _) => self
+                .window_id
+                .map(CaptureMode::Window)
+                .ok_or_else(|| "window_id mode requires window_id".to_string()),
+            (Some(other), _) => Err(format!(
+                "Unknown mode '{}'. Use 'full_output', 'focused_window', 'window_id', or 'region'.",
+                other
+            )),
+        }
+    }
+
+    fn options(&self) -> Result<ScreenshotOptions, String> {
+        Ok(ScreenshotOptions {
+            filename: self.filename.clone(),
+            inline: self.inline.unwrap_or(self.filename.is_none()),
+            mode: self.capture_mode()?,
+            output: self.output.clone(),
+            format: self
+                .format
+                .as_deref()
+                .map_or(Ok(ImageFormat::default()), ImageFormat::from_name)?,
+            quality: self.quality,
+            include_cursor: self.include_cursor.unwrap_or(false),
+            annotations: parse_annotations(self.annotations.as_deref())?,
+            requested_at: None,
+            span: None,
+        })
+    }
 }
 
 #[derive(Serialize, Deserialize, JsonSchema)]
@@ -81,270 +433,4643 @@ pub struct KeyPressRequest {
 
 #[derive(Serialize, Deserialize, JsonSchema)]
 pub struct ScrollRequest {
-    /// X coordinate to scroll at
-    x: f64,
-    /// Y coordinate to scroll at
-    y: f64,
+    /// X coordinate to scroll at (default: current pointer position)
+    x: Option<f64>,
+    /// Y coordinate to scroll at (default: current pointer position)
+    y: Option<f64>,
     /// Scroll direction: "up", "down", "left", or "right"
     direction: String,
-    /// Scroll amount in pixels (default: 15.0)
+    /// Scroll amount in pixels (default: 15.0 per step)
     amount: Option<f64>,
+    /// Number of discrete wheel steps (clicks), at most 1000. Omit for continuous scrolling.
+    steps: Option<u32>,
+    /// Axis source: "wheel" (default), "finger", "continuous", or "wheel_tilt"
+    source: Option<String>,
 }
 
-pub enum McpCommand {
-    LaunchApp {
-        command: String,
-        args: Vec<String>,
-        response_tx: tokio::sync::oneshot::Sender<Result<u32, String>>,
-    },
-    Screenshot {
-        filename: String,
-        response_tx: tokio::sync::oneshot::Sender<Result<String, String>>,
-    },
-    CaptureScreenshot {
-        response_tx: tokio::sync::oneshot::Sender<Result<(String, u32, u32), String>>,
-    },
-    CloseApp {
-        pid: u32,
-        response_tx: tokio::sync::oneshot::Sender<Result<(), String>>,
-    },
-    MouseMove {
-        x: f64,
-        y: f64,
-        response_tx: tokio::sync::oneshot::Sender<Result<(), String>>,
-    },
-    MouseClick {
-        x: f64,
-        y: f64,
-        button: u32,
-        response_tx: tokio::sync::oneshot::Sender<Result<(), String>>,
-    },
-    KeyPress {
-        key: u32,
-        response_tx: tokio::sync::oneshot::Sender<Result<(), String>>,
-    },
-    Scroll {
-        x: f64,
-        y: f64,
-        axis: smithay::backend::input::Axis,
-        amount: f64,
-        response_tx: tokio::sync::oneshot::Sender<Result<(), String>>,
-    },
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct TypeTextRequest {
+    /// UTF-8 text to type into the focused window
+    text: String,
 }
 
-impl std::fmt::Debug for McpCommand {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            McpCommand::LaunchApp { command, args, .. } => f
-                .debug_struct("LaunchApp")
-                .field("command", command)
-                .field("args", args)
-                .finish(),
-            McpCommand::Screenshot { filename, .. } => f
-                .debug_struct("Screenshot")
-                .field("filename", filename)
-                .finish(),
-            McpCommand::CaptureScreenshot { .. } => f
-                .debug_struct("CaptureScreenshot")
-                .finish(),
-            McpCommand::CloseApp { pid, .. } => f
-                .debug_struct("CloseApp")
-                .field("pid", pid)
-                .finish(),
-            McpCommand::MouseMove { x, y, .. } => f
-                .debug_struct("MouseMove")
-                .field("x", x)
-                .field("y", y)
-                .finish(),
-            McpCommand::MouseClick { x, y, button, .. } => f
-                .debug_struct("MouseClick")
-                .field("x", x)
-                .field("y", y)
-                .field("button", button)
-                .finish(),
-            McpCommand::KeyPress { key, .. } => f
-                .debug_struct("KeyPress")
-                .field("key", key)
-                .finish(),
-            McpCommand::Scroll { x, y, axis, amount, .. } => f
-                .debug_struct("Scroll")
-                .field("x", x)
-                .field("y", y)
-                .field("axis", axis)
-                .field("amount", amount)
-                .finish(),
-        }
-    }
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct PressKeyRequest {
+    /// Key name with optional modifiers joined by '+', e.g. "Return", "ctrl+shift+t" or "alt+F4".
+    /// Key names are XKB keysym names (case-insensitive) or single characters.
+    keys: String,
 }
 
-#[derive(Clone, Debug)]
-pub struct MCPvilServer {
-    tool_router: ToolRouter<Self>,
-    command_tx: smithay::reexports::calloop::channel::Sender<McpCommand>,
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct ClickRequest {
+    /// X coordinate to click at
+    x: f64,
+    /// Y coordinate to click at
+    y: f64,
+    /// Mouse button: "left", "right", or "middle" (default: "left")
+    button: Option<String>,
+    /// Number of clicks, e.g. 2 for a double-click (default: 1)
+    count: Option<u32>,
 }
 
-#[tool_router]
-impl MCPvilServer {
-    fn new(command_tx: smithay::reexports::calloop::channel::Sender<McpCommand>) -> Self {
-        Self {
-            tool_router: Self::tool_router(),
-            command_tx,
-        }
-    }
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct MovePointerRequest {
+    /// Target X coordinate, or X delta in relative mode
+    x: f64,
+    /// Target Y coordinate, or Y delta in relative mode
+    y: f64,
+    /// "absolute" (default) or "relative"
+    mode: Option<String>,
+}
 
-    #[tool(description = "Launches an application in the compositor")]
-    async fn launch_app(
-        &self,
-        params: Parameters<LaunchAppRequest>,
-    ) -> Result<CallToolResult, McpError> {
-        let command = params.0.command.clone();
-        let args = params.0.args.clone();
-        let (response_tx, response_rx) = tokio::sync::oneshot::channel();
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct Waypoint {
+    x: f64,
+    y: f64,
+}
 
-        self.command_tx
-            .send(McpCommand::LaunchApp {
-                command: command.clone(),
-                args: args.clone(),
-                response_tx,
-            })
-            .map_err(|e| {
-                McpError::internal_error(format!("Failed to send command: {}", e), None)
-            })?;
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct DragRequest {
+    /// X coordinate to press at
+    start_x: f64,
+    /// Y coordinate to press at
+    start_y: f64,
+    /// X coordinate to release at
+    end_x: f64,
+    /// Y coordinate to release at
+    end_y: f64,
+    /// Mouse button: "left", "right", or "middle" (default: "left")
+    button: Option<String>,
+    /// Intermediate points to pass through between start and end
+    waypoints: Option<Vec<Waypoint>>,
+    /// Duration of the movement in milliseconds (default: 300)
+    duration_ms: Option<u64>,
+}
 
-        let result = response_rx.await.map_err(|_| {
-            McpError::internal_error("Event loop dropped response channel".to_string(), None)
-        })?;
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct ListWindowsRequest {
+    /// Only list windows overlapping this output
+    output: Option<String>,
+}
 
-        match result {
-            Ok(pid) => Ok(CallToolResult::success(vec![Content::text(format!(
-                "Launched {} (pid {}) with args {:?}",
-                command, pid, args
-            ))])),
-            Err(e) => Ok(CallToolResult::success(vec![Content::text(format!(
-                "Failed to launch {}: {}",
-                command, e
-            ))])),
-        }
-    }
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct FocusWindowRequest {
+    /// Window id, as returned by list_windows
+    id: u64,
+}
 
-    #[tool(description = "Takes a screenshot of the compositor output and saves it as a PNG file")]
-    async fn screenshot(
-        &self,
-        params: Parameters<ScreenshotRequest>,
-    ) -> Result<CallToolResult, McpError> {
-        let filename = params.0.filename.clone();
-        let (response_tx, response_rx) = tokio::sync::oneshot::channel();
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct CloseWindowRequest {
+    /// Window id, as returned by list_windows
+    id: u64,
+    /// How long to wait for the window to close, in milliseconds (default: 5000)
+    timeout_ms: Option<u64>,
+    /// Kill the owning process if the window has not closed by the timeout (default: false)
+    force: Option<bool>,
+}
 
-        self.command_tx
-            .send(McpCommand::Screenshot {
-                filename: filename.clone(),
-                response_tx,
-            })
-            .map_err(|e| {
-                McpError::internal_error(format!("Failed to send command: {}", e), None)
-            })?;
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct MoveWindowRequest {
+    /// Window id, as returned by list_windows
+    id: u64,
+    /// New X coordinate of the window's top-left corner
+    x: i32,
+    /// New Y coordinate of the window's top-left corner
+    y: i32,
+}
 
-        let result = response_rx.await.map_err(|_| {
-            McpError::internal_error("Event loop dropped response channel".to_string(), None)
-        })?;
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct ResizeWindowRequest {
+    /// Window id, as returned by list_windows
+    id: u64,
+    /// Requested width in logical pixels
+    width: i32,
+    /// Requested height in logical pixels
+    height: i32,
+}
 
-        match result {
-            Ok(msg) => Ok(CallToolResult::success(vec![Content::text(msg)])),
-            Err(e) => Ok(CallToolResult::success(vec![Content::text(format!(
-                "Failed to take screenshot: {}",
-                e
-            ))])),
-        }
-    }
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct WindowIdRequest {
+    /// Window id, as returned by list_windows
+    id: u64,
+}
 
-    #[tool(description = "Closes/kills an application by its PID (as returned by launch_app)")]
-    async fn close_app(
-        &self,
-        params: Parameters<CloseAppRequest>,
-    ) -> Result<CallToolResult, McpError> {
-        let pid = params.0.pid;
-        let (response_tx, response_rx) = tokio::sync::oneshot::channel();
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct ScreenshotWindowRequest {
+    /// Window id, as returned by list_windows
+    id: u64,
+    /// File path to save the screenshot to, encoded in `format`
+    filename: Option<String>,
+    /// Return the image inline as image content (default: true when no filename is given)
+    inline: Option<bool>,
+    /// Image format: "png" (default), "jpeg", "webp" or "qoi"
+    format: Option<String>,
+    /// 1-100. JPEG quality (default 80), or PNG compression where higher is faster
+    /// and larger. Ignored for webp (lossless) and qoi
+    quality: Option<u8>,
+    /// Draw the mouse cursor into the image (default: false)
+    include_cursor: Option<bool>,
+    /// Callouts drawn onto the image before it is saved, in its pixels, each with a
+    /// "type" of rect, label, arrow or window
+    annotations: Option<Vec<AnnotationArg>>,
+}
 
-        self.command_tx
-            .send(McpCommand::CloseApp { pid, response_tx })
-            .map_err(|e| {
-                McpError::internal_error(format!("Failed to send command: {}", e), None)
-            })?;
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct StartRecordingRequest {
+    /// File path to write the video to (default: recording-<unix time>.webm, or .mp4
+    /// when built with H.264)
+    path: Option<String>,
+    /// Frames per second to record at (default: 30)
+    fps: Option<u32>,
+    /// Show injected key presses and clicks on the output while recording, so
+    /// they can be seen in the video (default: false)
+    show_input: Option<bool>,
+}
 
-        let result = response_rx.await.map_err(|_| {
-            McpError::internal_error("Event loop dropped response channel".to_string(), None)
-        })?;
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct StopRecordingRequest {}
 
-        match result {
-            Ok(()) => Ok(CallToolResult::success(vec![Content::text(format!(
-                "Sent SIGTERM to process {}",
-                pid
-            ))])),
-            Err(e) => Ok(CallToolResult::success(vec![Content::text(format!(
-                "Failed to close process {}: {}",
-                pid, e
-            ))])),
-        }
-    }
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct CaptureGifRequest {
+    /// File path to write the GIF to (default: recording-<unix time>.gif)
+    path: Option<String>,
+    /// Frames per second to capture at (default: 10)
+    fps: Option<u32>,
+    /// Seconds to capture for. Without it, capture runs until stop_recording is called
+    duration: Option<f64>,
+    /// Show injected key presses and clicks on the output while capturing (default: false)
+    show_input: Option<bool>,
+}
 
-    #[tool(description = "Moves the mouse pointer to the specified coordinates")]
-    async fn mouse_move(
-        &self,
-        params: Parameters<MouseMoveRequest>,
-    ) -> Result<CallToolResult, McpError> {
-        let (response_tx, response_rx) = tokio::sync::oneshot::channel();
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct ListOutputsRequest {}
 
-        self.command_tx
-            .send(McpCommand::MouseMove {
-                x: params.0.x,
-                y: params.0.y,
-                response_tx,
-            })
-            .map_err(|e| {
-                McpError::internal_error(format!("Failed to send command: {}", e), None)
-            })?;
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct SetOutputModeRequest {
+    /// Output to change, as returned by list_outputs (default: the primary output)
+    output: Option<String>,
+    /// New width in pixels
+    width: i32,
+    /// New height in pixels
+    height: i32,
+    /// New refresh rate in Hz (default: 60)
+    refresh: Option<f64>,
+}
 
-        let result = response_rx.await.map_err(|_| {
-            McpError::internal_error("Event loop dropped response channel".to_string(), None)
-        })?;
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct SetSelectionRequest {
+    /// Text to put on the selection, offered as text/plain
+    text: Option<String>,
+    /// Base64-encoded PNG to put on the selection, offered as image/png
+    image_base64: Option<String>,
+}
 
-        match result {
-            Ok(()) => Ok(CallToolResult::success(vec![Content::text(format!(
-                "Mouse moved to ({}, {})",
-                params.0.x, params.0.y
-            ))])),
-            Err(e) => Ok(CallToolResult::success(vec![Content::text(format!(
-                "Failed to move mouse: {}",
-                e
-            ))])),
-        }
-    }
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct GetSelectionRequest {
+    /// MIME type to read (default: text if offered, else image/png, else the first offered type)
+    mime_type: Option<String>,
+}
 
-    #[tool(description = "Clicks a mouse button at the specified coordinates")]
-    async fn mouse_click(
-        &self,
-        params: Parameters<MouseClickRequest>,
-    ) -> Result<CallToolResult, McpError> {
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct DndRequest {
+    /// X coordinate to start the drag at
+    start_x: f64,
+    /// Y coordinate to start the drag at
+    start_y: f64,
+    /// X coordinate to drop at
+    end_x: f64,
+    /// Y coordinate to drop at
+    end_y: f64,
+    /// Intermediate points to pass through between start and end
+    waypoints: Option<Vec<Waypoint>>,
+    /// Duration of the movement in milliseconds (default: 300)
+    duration_ms: Option<u64>,
+    /// Text to drag, offered as text/plain
+    text: Option<String>,
+    /// Base64-encoded PNG to drag, offered as image/png
+    image_base64: Option<String>,
+    /// Offer the payload under these MIME types instead, e.g. ["text/uri-list"] for file drops
+    mime_types: Option<Vec<String>>,
+    /// Drag action to offer: "copy", "move", or "ask" (default: "copy")
+    action: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct KillAppRequest {
+    /// The process ID (PID) of the application, as returned by launch_app
+    pid: u32,
+    /// Signal to send, by name ("TERM", "SIGKILL", ...) or number (default: "TERM")
+    signal: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct ListProcessesRequest {}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct GetAppOutputRequest {
+    /// The process ID (PID) of the application, as returned by launch_app
+    pid: u32,
+    /// Number of most recent lines to return (default: 100)
+    tail_lines: Option<usize>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct WaitForWindowRequest {
+    /// Regex the window's app_id must match
+    app_id: Option<String>,
+    /// Regex the window's title must match
+    title: Option<String>,
+    /// How long to wait, in milliseconds (default: 10000)
+    timeout_ms: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct WaitForIdleRequest {
+    /// How long nothing may commit or redraw for the screen to count as idle, in milliseconds (default: 500)
+    quiet_ms: Option<u64>,
+    /// How long to wait in total, in milliseconds (default: 10000)
+    timeout_ms: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct GetWindowGeometryRequest {
+    /// Window id, as returned by list_windows
+    id: u64,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct GetInputStateRequest {}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct SetKeyboardLayoutRequest {
+    /// XKB layout, e.g. "de", "fr" or "us,ru"
+    layout: String,
+    /// XKB variant, e.g. "nodeadkeys" or "azerty"
+    variant: Option<String>,
+    /// XKB options, e.g. "compose:ralt"
+    options: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct TouchTapRequest {
+    /// X coordinate in the compositor space
+    x: f64,
+    /// Y coordinate in the compositor space
+    y: f64,
+    /// How long the finger stays down in milliseconds; use ~800 for a long press (default: 50)
+    duration_ms: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct TouchSwipeRequest {
+    /// X coordinate to touch down at
+    start_x: f64,
+    /// Y coordinate to touch down at
+    start_y: f64,
+    /// X coordinate to lift the finger at
+    end_x: f64,
+    /// Y coordinate to lift the finger at
+    end_y: f64,
+    /// Intermediate points to pass through between start and end
+    waypoints: Option<Vec<Waypoint>>,
+    /// Duration of the movement in milliseconds (default: 300)
+    duration_ms: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct GestureRequest {
+    /// Gesture type: "swipe" or "pinch"
+    kind: String,
+    /// X coordinate to perform the gesture at
+    x: f64,
+    /// Y coordinate to perform the gesture at
+    y: f64,
+    /// Number of fingers (default: 3 for swipe, 2 for pinch)
+    fingers: Option<u32>,
+    /// Horizontal distance the fingers swipe (default: 0)
+    dx: Option<f64>,
+    /// Vertical distance the fingers swipe (default: 0)
+    dy: Option<f64>,
+    /// Final pinch scale relative to the start; above 1.0 zooms in, below zooms out (default: 1.0)
+    scale: Option<f64>,
+    /// Pinch rotation in degrees clockwise (default: 0)
+    rotation: Option<f64>,
+    /// Duration of the gesture in milliseconds (default: 300)
+    duration_ms: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct PenPointArg {
+    x: f64,
+    y: f64,
+    /// Pen pressure from 0.0 to 1.0 (default: 0.5)
+    pressure: Option<f64>,
+    /// Tilt in degrees along the x axis, from -90 to 90 (default: 0)
+    tilt_x: Option<f64>,
+    /// Tilt in degrees along the y axis, from -90 to 90 (default: 0)
+    tilt_y: Option<f64>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct PenStrokeRequest {
+    /// Points the pen passes through; it touches down at the first and lifts at the last
+    points: Vec<PenPointArg>,
+    /// Duration of the stroke in milliseconds (default: 300)
+    duration_ms: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum InputStepArg {
+    /// Moves the pointer to compositor coordinates
+    Move { x: f64, y: f64 },
+    /// Clicks at compositor coordinates
+    Click {
+        x: f64,
+        y: f64,
+        /// Mouse button: "left", "right", or "middle" (default: "left")
+        button: Option<String>,
+        /// Number of clicks (default: 1)
+        count: Option<u32>,
+    },
+    /// Presses a key combination, e.g. "ctrl+s"
+    Key { keys: String },
+    /// Types UTF-8 text
+    Type { text: String },
+    /// Waits before the next step
+    Wait { ms: u64 },
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct InputSequenceRequest {
+    /// Steps to run in order, each with an "action" of move, click, key, type or wait
+    steps: Vec<InputStepArg>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct StartInputRecordingRequest {
+    /// Name to save the recorded input under; an existing session of this name is replaced
+    session: String,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct StopInputRecordingRequest {}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct ReplayInputRequest {
+    /// Session name given to start_input_recording
+    session: String,
+    /// Playback speed, e.g. 2.0 for twice as fast (default: 1.0)
+    speed: Option<f64>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct SetOutputScaleRequest {
+    /// Output to change, as returned by list_outputs (default: the primary output)
+    output: Option<String>,
+    /// Scale factor, e.g. 1.25, 1.5 or 2; fractional values are rounded to 1/120
+    scale: f64,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct GetFrameTimingRequest {
+    /// Output to report on, as returned by list_outputs (default: all outputs)
+    output: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct SendRelativeMotionRequest {
+    /// Horizontal motion in logical pixels
+    dx: f64,
+    /// Vertical motion in logical pixels
+    dy: f64,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct StartScreencastRequest {
+    /// Output to cast, as returned by list_outputs (default: the primary output)
+    output: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct StopScreencastRequest {
+    /// PipeWire node id returned by start_screencast
+    node_id: u32,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct DumpStateRequest {}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct ReadTextRequest {
+    /// Window to read, as returned by list_windows. Boxes are then relative to the window
+    window_id: Option<u64>,
+    /// Output to read when no window is given (default: the primary output)
+    output: Option<String>,
+    /// Left edge of the region to read, relative to the output
+    x: Option<i32>,
+    /// Top edge of the region to read, relative to the output
+    y: Option<i32>,
+    /// Width of the region to read
+    width: Option<i32>,
+    /// Height of the region to read
+    height: Option<i32>,
+    /// Tesseract language code, e.g. "eng" (default) or "deu+eng"
+    language: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct CompareScreenshotRequest {
+    /// Path of the golden image to compare against
+    baseline_path: String,
+    /// Fraction of pixels (0.0-1.0) allowed to differ before the comparison fails (default 0.0)
+    threshold: Option<f64>,
+    /// 0-255. Per-channel difference a pixel may have and still match (default 8)
+    tolerance: Option<u8>,
+    /// Where to save the diff image (default: next to the baseline, as <name>.diff.png)
+    diff_path: Option<String>,
+    /// Window to capture on its own instead of the output, as returned by list_windows
+    window_id: Option<u64>,
+    /// Output to capture (default: the primary output)
+    output: Option<String>,
+    /// Left edge of the region to compare, relative to the output
+    x: Option<i32>,
+    /// Top edge of the region to compare, relative to the output
+    y: Option<i32>,
+    /// Width of the region to compare
+    width: Option<i32>,
+    /// Height of the region to compare
+    height: Option<i32>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct WaitForVisualStabilityRequest {
+    /// How long consecutive frames must stay unchanged, in milliseconds (default: 500)
+    stable_ms: Option<u64>,
+    /// How long to wait in total, in milliseconds (default: 10000)
+    timeout_ms: Option<u64>,
+    /// Fraction of the frame (0.0-1.0) that may change between frames and still count as
+    /// stable (default: 0.0)
+    threshold: Option<f64>,
+    /// Output to watch (default: the primary output)
+    output: Option<String>,
+    /// Left edge of the region to watch, relative to the output
+    x: Option<i32>,
+    /// Top edge of the region to watch, relative to the output
+    y: Option<i32>,
+    /// Width of the region to watch
+    width: Option<i32>,
+    /// Height of the region to watch
+    height: Option<i32>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct GetPixelRequest {
+    /// X coordinate of the pixel, relative to the output
+    x: i32,
+    /// Y coordinate of the pixel, relative to the output
+    y: i32,
+    /// Average over the square of pixels within this distance of (x, y) (default: 0,
+    /// a single pixel)
+    radius: Option<u32>,
+    /// Output to read (default: the primary output)
+    output: Option<String>,
+    /// Color to check against, as "#RRGGBB" or "#RRGGBBAA"
+    expected: Option<String>,
+    /// 0-255. Largest per-channel difference that still matches `expected` (default 0)
+    tolerance: Option<u8>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct FindImageRequest {
+    /// Path of the image to look for
+    template_path: Option<String>,
+    /// The image to look for as a base64-encoded PNG, instead of template_path
+    template_png: Option<String>,
+    /// Lowest normalized cross-correlation, 0.0-1.0, that counts as a match (default: 0.9)
+    confidence: Option<f64>,
+    /// Most matches to return, best first (default: 1)
+    max_results: Option<usize>,
+    /// Output to search (default: the primary output)
+    output: Option<String>,
+    /// Left edge of the region to search, relative to the output
+    x: Option<i32>,
+    /// Top edge of the region to search, relative to the output
+    y: Option<i32>,
+    /// Width of the region to search
+    width: Option<i32>,
+    /// Height of the region to search
+    height: Option<i32>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct QueryAccessibilityTreeRequest {
+    /// Window id, as returned by list_windows
+    window_id: u64,
+    /// How many levels below the window to read (default: 8)
+    max_depth: Option<usize>,
+    /// Include elements that aren't currently showing (default: false)
+    include_hidden: Option<bool>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct ClickElementRequest {
+    /// Window id, as returned by list_windows
+    window_id: u64,
+    /// Element id from query_accessibility_tree
+    element_id: Option<String>,
+    /// AT-SPI role name to match, e.g. "push button", "menu item" or "check box"
+    role: Option<String>,
+    /// Regular expression matched against the element's accessible name
+    name: Option<String>,
+    /// Which of several matching elements to use, in tree order (default: 0)
+    index: Option<usize>,
+    /// "action" (default) performs the element's default action over AT-SPI, falling back to a
+    /// click when it has none; "click" always clicks the center of its bounds
+    method: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct AdvanceFramesRequest {
+    /// Number of frames to render (default: 1)
+    count: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct SetFrameRateRequest {
+    /// Most frames to render per second, from 0.1 to 1000; 0 removes the limit, rendering
+    /// at the output's refresh rate
+    fps: f64,
+    /// Stop rendering after a frame that changed nothing, until a client commits, input
+    /// arrives or a capture is requested (default: unchanged)
+    idle_when_no_damage: Option<bool>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct HideCursorRequest {
+    /// Hide the cursor, or show it again when false (default: true)
+    hidden: Option<bool>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct SetOutputTransformRequest {
+    /// Output to change, as returned by list_outputs (default: the primary output)
+    output: Option<String>,
+    /// "normal", "90", "180", "270", "flipped", "flipped-90", "flipped-180" or "flipped-270";
+    /// rotations are counter-clockwise
+    transform: String,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct SetBackgroundRequest {
+    /// Color behind all windows as #RRGGBB or #RRGGBBAA. On its own it also removes the
+    /// wallpaper
+    color: Option<String>,
+    /// Image to stretch over every output as a wallpaper, e.g. a PNG
+    image_path: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct RestartAppRequest {
+    /// Pid of an app started by launch_app, running or not
+    pid: u32,
+    /// Only return once the new process has mapped its first window (default: false)
+    wait_for_window: Option<bool>,
+    /// How long to wait for the window, in milliseconds (default: 10000)
+    timeout_ms: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct RunCommandRequest {
+    /// Shell command line, run with `sh -c`, e.g. "gsettings set org.gnome.desktop.interface
+    /// color-scheme prefer-dark" or "ls ~/Downloads"
+    command: String,
+    /// Extra environment variables
+    env: Option<std::collections::HashMap<String, String>>,
+    /// Working directory to run the command in
+    cwd: Option<String>,
+    /// Text to write to the command's stdin
+    stdin: Option<String>,
+    /// Kill the command if it is still running after this many milliseconds (default: 30000)
+    timeout_ms: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct WriteFileRequest {
+    /// Path relative to the session directory, e.g. "fixtures/report.odt"; missing parent
+    /// directories are created
+    path: String,
+    /// File contents
+    content: String,
+    /// How content is encoded: "text" (default) or "base64" for binary files
+    encoding: Option<String>,
+    /// Append to the file instead of replacing it (default: false)
+    append: Option<bool>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct ReadFileRequest {
+    /// Path relative to the session directory
+    path: String,
+    /// How to return the contents: "text" (default) or "base64" for binary files
+    encoding: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct LaunchDesktopAppRequest {
+    /// Desktop file id, with or without ".desktop", e.g. "org.gnome.TextEditor" or "firefox"
+    desktop_id: String,
+    /// One of the entry's actions to run instead of its main command, e.g. "new-window"
+    action: Option<String>,
+    /// Files or URLs for the app to open, passed where its Exec line has %f, %F, %u or %U.
+    /// Relative paths are taken from the session directory
+    files: Option<Vec<String>>,
+    /// Extra environment variables
+    env: Option<std::collections::HashMap<String, String>>,
+    /// Only return once the app has mapped its first window (default: false)
+    wait_for_window: Option<bool>,
+    /// How long to wait for the window, in milliseconds (default: 10000)
+    timeout_ms: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct ListInstalledAppsRequest {
+    /// Only apps whose id, name, comment or categories contain this text (case-insensitive)
+    query: Option<String>,
+    /// Only apps in this freedesktop category, e.g. "Graphics", "Office" or "TextEditor"
+    category: Option<String>,
+    /// Include entries marked NoDisplay, such as helpers and file handlers (default: false)
+    include_hidden: Option<bool>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct LaunchFlatpakRequest {
+    /// Flatpak application id, e.g. "org.gnome.TextEditor"
+    app_id: String,
+    /// Arguments for the app
+    args: Option<Vec<String>>,
+    /// Run this command from the app instead of its default one
+    command: Option<String>,
+    /// Extra environment variables, passed into the Flatpak sandbox
+    env: Option<std::collections::HashMap<String, String>>,
+    /// Only return once the app has mapped its first window (default: false)
+    wait_for_window: Option<bool>,
+    /// How long to wait for the window, in milliseconds (default: 10000)
+    timeout_ms: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct GetNotificationsRequest {
+    /// Only notifications with a higher id, e.g. the highest id seen so far
+    since_id: Option<u32>,
+    /// Only notifications from this app name (case-insensitive)
+    app_name: Option<String>,
+    /// Include notifications that were closed or dismissed (default: true)
+    include_closed: Option<bool>,
+    /// Wait up to this many milliseconds for a matching notification if there is none yet
+    /// (default: 0)
+    wait_ms: Option<u64>,
+    /// Forget all notifications after returning them (default: false)
+    clear: Option<bool>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct InvokeNotificationActionRequest {
+    /// Notification id, from get_notifications
+    id: u32,
+    /// Action key, from the notification's actions (default: "default", clicking the
+    /// notification itself)
+    action: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct StageFileChooserResponseRequest {
+    /// Files the dialog returns, e.g. ["fixtures/report.odt"]; where to save for save dialogs,
+    /// or a single folder for dialogs saving several files. Relative paths are taken from the
+    /// session directory
+    paths: Option<Vec<String>>,
+    /// Cancel the dialog instead (default: false)
+    cancel: Option<bool>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct SetKeyRepeatRequest {
+    /// Repeats per second; 0 disables repeat (default: unchanged)
+    rate: Option<i32>,
+    /// Milliseconds a key is held before it starts repeating (default: unchanged)
+    delay_ms: Option<i32>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct HoldKeyRequest {
+    /// Key name with optional modifiers joined by '+', as for press_key, e.g. "Right" or
+    /// "shift+Down"
+    keys: String,
+    /// How long to hold it down in milliseconds (default: 1000)
+    duration_ms: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct GetLogsRequest {
+    /// Least severe level to return: "error", "warn", "info", "debug" or "trace" (default:
+    /// "info"). Only what passes RUST_LOG (default: info) is kept
+    level: Option<String>,
+    /// Only records with a larger id, e.g. last_id from a previous call (default: 0)
+    since: Option<u64>,
+    /// Regex the record's target or message must match, e.g. "process|xwayland"
+    filter: Option<String>,
+    /// Return at most this many of the newest matching records (default: 200)
+    limit: Option<usize>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct SetProtocolTraceRequest {
+    /// Trace the client that owns this window
+    window_id: Option<u64>,
+    /// Trace every Wayland connection of this process (when window_id is not given)
+    pid: Option<u32>,
+    /// Turn tracing on (true) or off (false)
+    enabled: bool,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct GetStatsRequest {}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct GetRecentFramesRequest {
+    /// How far back to go, in seconds (default: the whole history)
+    seconds: Option<f64>,
+    /// Most frames to return, evenly spaced over the period and always including
+    /// the newest (default: 10)
+    max_frames: Option<u32>,
+    /// Image format: "jpeg" (default), "png", "webp" or "qoi"
+    format: Option<String>,
+    /// 1-100. JPEG quality (default 80), or PNG compression where higher is faster
+    /// and larger. Ignored for webp (lossless) and qoi
+    quality: Option<u8>,
+    /// Directory to save the frames in as frame-<uptime ms>.<ext> instead of
+    /// returning them inline
+    directory: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct ScreenshotAllWindowsRequest {
+    /// "sheet" (default) for one tiled image with each window's id and title
+    /// above it, or "thumbnails" for an image per window
+    layout: Option<String>,
+    /// Largest width or height of each thumbnail, in pixels (default: 320)
+    thumbnail_size: Option<u32>,
+    /// Thumbnails per row of the sheet (default: as square a grid as possible)
+    columns: Option<u32>,
+    /// Only windows on this output (default: all windows)
+    output: Option<String>,
+    /// Image format: "png" (default), "jpeg", "webp" or "qoi"
+    format: Option<String>,
+    /// 1-100. JPEG quality (default 80), or PNG compression where higher is faster
+    /// and larger. Ignored for webp (lossless) and qoi
+    quality: Option<u8>,
+}
+
+pub enum McpCommand {
+    LaunchApp {
+        options: LaunchOptions,
+        wait_for_window: Option<std::time::Duration>,
+        response_tx: LaunchResponse,
+    },
+    Screenshot {
+        options: ScreenshotOptions,
+        response_tx: ScreenshotResponse,
+    },
+    CloseApp {
+        pid: u32,
+        response_tx: tokio::sync::oneshot::Sender<Result<(), ToolError>>,
+    },
+    MouseMove {
+        x: f64,
+        y: f64,
+        relative: bool,
+        response_tx: tokio::sync::oneshot::Sender<Result<PointerTarget, String>>,
+    },
+    MouseClick {
+        x: f64,
+        y: f64,
+        button: u32,
+        count: u32,
+        response_tx: tokio::sync::oneshot::Sender<Result<Option<WindowLabel>, String>>,
+    },
+    KeyPress {
+        key: u32,
+        response_tx: tokio::sync::oneshot::Sender<Result<(), String>>,
+    },
+    Scroll {
+        position: Option<(f64, f64)>,
+        axis: smithay::backend::input::Axis,
+        amount: f64,
+        steps: Option<i32>,
+        source: smithay::backend::input::AxisSource,
+        response_tx: tokio::sync::oneshot::Sender<Result<PointerTarget, String>>,
+    },
+    TypeText {
+        text: String,
+        response_tx: tokio::sync::oneshot::Sender<Result<usize, String>>,
+    },
+    PressKeyCombo {
+        keys: String,
+        response_tx: tokio::sync::oneshot::Sender<Result<(), String>>,
+    },
+    ListWindows {
+        output: Option<String>,
+        response_tx: tokio::sync::oneshot::Sender<Result<Vec<WindowInfo>, String>>,
+    },
+    Drag {
+        path: Vec<(f64, f64)>,
+        button: u32,
+        duration: std::time::Duration,
+        response_tx: tokio::sync::oneshot::Sender<Result<PointerTarget, String>>,
+    },
+    FocusWindow {
+        id: u64,
+        response_tx: tokio::sync::oneshot::Sender<Result<WindowLabel, ToolError>>,
+    },
+    CloseWindow {
+        id: u64,
+        timeout: std::time::Duration,
+        force: bool,
+        response_tx: handlers::CloseResponse,
+    },
+    MoveWindow {
+        id: u64,
+        x: i32,
+        y: i32,
+        response_tx: tokio::sync::oneshot::Sender<Result<WindowInfo, ToolError>>,
+    },
+    ResizeWindow {
+        id: u64,
+        width: i32,
+        height: i32,
+        response_tx: tokio::sync::oneshot::Sender<Result<WindowInfo, ToolError>>,
+    },
+    SetWindowMode {
+        id: u64,
+        mode: WindowMode,
+        response_tx: tokio::sync::oneshot::Sender<Result<WindowInfo, ToolError>>,
+    },
+    StartRecording {
+        options: RecordingOptions,
+        response_tx: tokio::sync::oneshot::Sender<Result<String, String>>,
+    },
+    StopRecording {
+        response_tx: RecordingResponse,
+    },
+    ListOutputs {
+        response_tx: tokio::sync::oneshot::Sender<Result<Vec<OutputInfo>, String>>,
+    },
+    SetOutputMode {
+        output: Option<String>,
+        mode: smithay::output::Mode,
+        response_tx: tokio::sync::oneshot::Sender<Result<OutputInfo, String>>,
+    },
+    SetSelection {
+        target: SelectionTarget,
+        mime_types: Vec<String>,
+        data: Vec<u8>,
+        response_tx: tokio::sync::oneshot::Sender<Result<(), String>>,
+    },
+    GetSelection {
+        target: SelectionTarget,
+        mime_type: Option<String>,
+        response_tx: ClipboardResponse,
+    },
+    Dnd {
+        path: Vec<(f64, f64)>,
+        duration: std::time::Duration,
+        mime_types: Vec<String>,
+        actions: DndAction,
+        data: Vec<u8>,
+        response_tx: tokio::sync::oneshot::Sender<Result<PointerTarget, String>>,
+        outcome_tx: clipboard::DndResponse,
+    },
+    KillApp {
+        pid: u32,
+        signal: i32,
+        response_tx: tokio::sync::oneshot::Sender<Result<(), ToolError>>,
+    },
+    ListProcesses {
+        response_tx: tokio::sync::oneshot::Sender<Result<Vec<ProcessInfo>, String>>,
+    },
+    GetAppOutput {
+        pid: u32,
+        tail_lines: usize,
+        response_tx: tokio::sync::oneshot::Sender<Result<String, ToolError>>,
+    },
+    WaitForWindow {
+        wait: WindowWait,
+        timeout: std::time::Duration,
+    },
+    WaitForIdle {
+        quiet: std::time::Duration,
+        timeout: std::time::Duration,
+        response_tx: IdleResponse,
+    },
+    GetWindowGeometry {
+        id: u64,
+        response_tx: tokio::sync::oneshot::Sender<Result<WindowGeometry, ToolError>>,
+    },
+    GetInputState {
+        response_tx: tokio::sync::oneshot::Sender<Result<InputState, String>>,
+    },
+    SetKeyboardLayout {
+        layout: String,
+        variant: String,
+        options: Option<String>,
+        response_tx: tokio::sync::oneshot::Sender<Result<(), String>>,
+    },
+    Touch {
+        path: Vec<(f64, f64)>,
+        duration: std::time::Duration,
+        response_tx: tokio::sync::oneshot::Sender<Result<PointerTarget, String>>,
+    },
+    Gesture {
+        x: f64,
+        y: f64,
+        kind: gesture::GestureKind,
+        duration: std::time::Duration,
+        response_tx: tokio::sync::oneshot::Sender<Result<PointerTarget, String>>,
+    },
+    PenStroke {
+        points: Vec<tablet::PenPoint>,
+        duration: std::time::Duration,
+        response_tx: tokio::sync::oneshot::Sender<Result<PointerTarget, String>>,
+    },
+    InputSequence {
+        steps: Vec<sequence::InputStep>,
+        response_tx: tokio::sync::oneshot::Sender<Result<usize, String>>,
+        progress_tx: tokio::sync::mpsc::UnboundedSender<usize>,
+    },
+    StartInputRecording {
+        session: String,
+        response_tx: tokio::sync::oneshot::Sender<Result<(), String>>,
+    },
+    StopInputRecording {
+        response_tx: tokio::sync::oneshot::Sender<Result<macros::MacroSummary, String>>,
+    },
+    ReplayInput {
+        session: String,
+        speed: f64,
+        response_tx: tokio::sync::oneshot::Sender<Result<usize, String>>,
+    },
+    SetOutputScale {
+        output: Option<String>,
+        scale: f64,
+        response_tx: tokio::sync::oneshot::Sender<Result<OutputInfo, String>>,
+    },
+    GetFrameTiming {
+        output: Option<String>,
+        response_tx: tokio::sync::oneshot::Sender<Result<Vec<FrameTimingInfo>, String>>,
+    },
+    RelativeMotion {
+        delta: (f64, f64),
+        response_tx: tokio::sync::oneshot::Sender<Result<PointerTarget, String>>,
+    },
+    StartScreencast {
+        output: Option<String>,
+        response_tx: ScreencastResponse,
+    },
+    StopScreencast {
+        node_id: u32,
+        response_tx: tokio::sync::oneshot::Sender<Result<(), String>>,
+    },
+    DumpState {
+        response_tx: tokio::sync::oneshot::Sender<Result<StateDump, String>>,
+    },
+    WaitForVisualStability {
+        output: Option<String>,
+        region: Option<Geometry>,
+        threshold: f64,
+        stable_for: std::time::Duration,
+        timeout: std::time::Duration,
+        response_tx: StabilityResponse,
+    },
+    AdvanceFrames {
+        count: u64,
+        response_tx: StepResponse,
+    },
+    SetFrameRate {
+        fps: Option<f64>,
+        idle_when_no_damage: Option<bool>,
+        response_tx: tokio::sync::oneshot::Sender<Result<(), String>>,
+    },
+    HideCursor {
+        hidden: bool,
+        response_tx: tokio::sync::oneshot::Sender<Result<(), String>>,
+    },
+    SetOutputTransform {
+        output: Option<String>,
+        transform: Transform,
+        response_tx: tokio::sync::oneshot::Sender<Result<OutputInfo, String>>,
+    },
+    SetBackground {
+        color: Option<pixel::Color>,
+        image_path: Option<String>,
+        response_tx: tokio::sync::oneshot::Sender<Result<(), String>>,
+    },
+    RestartApp {
+        pid: u32,
+        wait_for_window: Option<std::time::Duration>,
+        response_tx: LaunchResponse,
+    },
+    SetKeyRepeat {
+        rate: Option<i32>,
+        delay_ms: Option<i32>,
+        response_tx: tokio::sync::oneshot::Sender<Result<KeyRepeat, String>>,
+    },
+    HoldKey {
+        keys: String,
+        duration: std::time::Duration,
+        response_tx: tokio::sync::oneshot::Sender<Result<u64, String>>,
+    },
+    SetProtocolTrace {
+        pid: Option<u32>,
+        window_id: Option<u64>,
+        enabled: bool,
+        response_tx: tokio::sync::oneshot::Sender<Result<Vec<String>, String>>,
+    },
+    GetStats {
+        response_tx: tokio::sync::oneshot::Sender<StatsInfo>,
+    },
+    GetRecentFrames {
+        seconds: Option<f64>,
+        max_frames: usize,
+        response_tx: tokio::sync::oneshot::Sender<Result<Vec<RecentFrame>, String>>,
+    },
+}
+
+impl std::fmt::Debug for McpCommand {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            McpCommand::LaunchApp {
+                options,
+                wait_for_window,
+                ..
+            } => f
+                .debug_struct("LaunchApp")
+                .field("options", options)
+                .field("wait_for_window", wait_for_window)
+                .finish(),
+            McpCommand::Screenshot { options, .. } => f
+                .debug_struct("Screenshot")
+                .field("options", options)
+                .finish(),
+            McpCommand::CloseApp { pid, .. } => {
+                f.debug_struct("CloseApp").field("pid", pid).finish()
+            }
+            McpCommand::MouseMove { x, y, relative, .. } => f
+                .debug_struct("MouseMove")
+                .field("x", x)
+                .field("y", y)
+                .field("relative", relative)
+                .finish(),
+            McpCommand::MouseClick {
+                x,
+                y,
+                button,
+                count,
+                ..
+            } => f
+                .debug_struct("MouseClick")
+                .field("x", x)
+                .field("y", y)
+                .field("button", button)
+                .field("count", count)
+                .finish(),
+            McpCommand::KeyPress { key, .. } => {
+                f.debug_struct("KeyPress").field("key", key).finish()
+            }
+            McpCommand::Scroll {
+                position,
+                axis,
+                amount,
+                steps,
+                source,
+                ..
+            } => f
+                .debug_struct("Scroll")
+                .field("position", position)
+                .field("axis", axis)
+                .field("amount", amount)
+                .field("steps", steps)
+                .field("source", source)
+                .finish(),
+            McpCommand::TypeText { text, .. } => {
+                f.debug_struct("TypeText").field("text", text).finish()
+            }
+            McpCommand::PressKeyCombo { keys, .. } => {
+                f.debug_struct("PressKeyCombo").field("keys", keys).finish()
+            }
+            McpCommand::ListWindows { output, .. } => f
+                .debug_struct("ListWindows")
+                .field("output", output)
+                .finish(),
+            McpCommand::Drag {
+                path,
+                button,
+                duration,
+                ..
+            } => f
+                .debug_struct("Drag")
+                .field("path", path)
+                .field("button", button)
+                .field("duration", duration)
+                .finish(),
+            McpCommand::FocusWindow { id, .. } => {
+                f.debug_struct("FocusWindow").field("id", id).finish()
+            }
+            McpCommand::CloseWindow {
+                id, timeout, force, ..
+            } => f
+                .debug_struct("CloseWindow")
+                .field("id", id)
+                .field("timeout", timeout)
+                .field("force", force)
+                .finish(),
+            McpCommand::MoveWindow { id, x, y, .. } => f
+                .debug_struct("MoveWindow")
+                .field("id", id)
+                .field("x", x)
+                .field("y", y)
+                .finish(),
+            McpCommand::ResizeWindow {
+                id, width, height, ..
+            } => f
+                .debug_struct("ResizeWindow")
+                .field("id", id)
+                .field("width", width)
+                .field("height", height)
+                .finish(),
+            McpCommand::SetWindowMode { id, mode, .. } => f
+                .debug_struct("SetWindowMode")
+                .field("id", id)
+                .field("mode", mode)
+                .finish(),
+            McpCommand::StartRecording { options, .. } => f
+                .debug_struct("StartRecording")
+                .field("options", options)
+                .finish(),
+            McpCommand::StopRecording { .. } => f.debug_struct("StopRecording").finish(),
+            McpCommand::ListOutputs { .. } => f.debug_struct("ListOutputs").finish(),
+            McpCommand::SetOutputMode { output, mode, .. } => f
+                .debug_struct("SetOutputMode")
+                .field("output", output)
+                .field("mode", mode)
+                .finish(),
+            McpCommand::SetSelection {
+                target,
+                mime_types,
+                data,
+                ..
+            } => f
+                .debug_struct("SetSelection")
+                .field("target", target)
+                .field("mime_types", mime_types)
+                .field("len", &data.len())
+                .finish(),
+            McpCommand::GetSelection {
+                target, mime_type, ..
+            } => f
+                .debug_struct("GetSelection")
+                .field("target", target)
+                .field("mime_type", mime_type)
+                .finish(),
+            McpCommand::Dnd {
+                path,
+                duration,
+                mime_types,
+                actions,
+                data,
+                ..
+            } => f
+                .debug_struct("Dnd")
+                .field("path", path)
+                .field("duration", duration)
+                .field("mime_types", mime_types)
+                .field("actions", actions)
+                .field("data_len", &data.len())
+                .finish(),
+            McpCommand::KillApp { pid, signal, .. } => f
+                .debug_struct("KillApp")
+                .field("pid", pid)
+                .field("signal", signal)
+                .finish(),
+            McpCommand::ListProcesses { .. } => f.debug_struct("ListProcesses").finish(),
+            McpCommand::GetAppOutput {
+                pid, tail_lines, ..
+            } => f
+                .debug_struct("GetAppOutput")
+                .field("pid", pid)
+                .field("tail_lines", tail_lines)
+                .finish(),
+            McpCommand::WaitForWindow { wait, timeout } => f
+                .debug_struct("WaitForWindow")
+                .field("app_id", &wait.app_id)
+                .field("title", &wait.title)
+                .field("timeout", timeout)
+                .finish(),
+            McpCommand::WaitForIdle { quiet, timeout, .. } => f
+                .debug_struct("WaitForIdle")
+                .field("quiet", quiet)
+                .field("timeout", timeout)
+                .finish(),
+            McpCommand::GetWindowGeometry { id, .. } => {
+                f.debug_struct("GetWindowGeometry").field("id", id).finish()
+            }
+            McpCommand::GetInputState { .. } => f.debug_struct("GetInputState").finish(),
+            McpCommand::SetKeyboardLayout {
+                layout,
+                variant,
+                options,
+                ..
+            } => f
+                .debug_struct("SetKeyboardLayout")
+                .field("layout", layout)
+                .field("variant", variant)
+                .field("options", options)
+                .finish(),
+            McpCommand::Touch { path, duration, .. } => f
+                .debug_struct("Touch")
+                .field("path", path)
+                .field("duration", duration)
+                .finish(),
+            McpCommand::Gesture {
+                x,
+                y,
+                kind,
+                duration,
+                ..
+            } => f
+                .debug_struct("Gesture")
+                .field("x", x)
+                .field("y", y)
+                .field("kind", kind)
+                .field("duration", duration)
+                .finish(),
+            McpCommand::PenStroke {
+                points, duration, ..
+            } => f
+                .debug_struct("PenStroke")
+                .field("points", points)
+                .field("duration", duration)
+                .finish(),
+            McpCommand::InputSequence { steps, .. } => f
+                .debug_struct("InputSequence")
+                .field("steps", steps)
+                .finish(),
+            McpCommand::StartInputRecording { session, .. } => f
+                .debug_struct("StartInputRecording")
+                .field("session", session)
+                .finish(),
+            McpCommand::StopInputRecording { .. } => f.debug_struct("StopInputRecording").finish(),
+            McpCommand::ReplayInput { session, speed, .. } => f
+                .debug_struct("ReplayInput")
+                .field("session", session)
+                .field("speed", speed)
+                .finish(),
+            McpCommand::SetOutputScale { output, scale, .. } => f
+                .debug_struct("SetOutputScale")
+                .field("output", output)
+                .field("scale", scale)
+                .finish(),
+            McpCommand::GetFrameTiming { output, .. } => f
+                .debug_struct("GetFrameTiming")
+                .field("output", output)
+                .finish(),
+            McpCommand::RelativeMotion { delta, .. } => f
+                .debug_struct("RelativeMotion")
+                .field("delta", delta)
+                .finish(),
+            McpCommand::StartScreencast { output, .. } => f
+                .debug_struct("StartScreencast")
+                .field("output", output)
+                .finish(),
+            McpCommand::StopScreencast { node_id, .. } => f
+                .debug_struct("StopScreencast")
+                .field("node_id", node_id)
+                .finish(),
+            McpCommand::DumpState { .. } => f.debug_struct("DumpState").finish(),
+            McpCommand::WaitForVisualStability {
+                output,
+                region,
+                threshold,
+                stable_for,
+                timeout,
+                ..
+            } => f
+                .debug_struct("WaitForVisualStability")
+                .field("output", output)
+                .field("region", region)
+                .field("threshold", threshold)
+                .field("stable_for", stable_for)
+                .field("timeout", timeout)
+                .finish(),
+            McpCommand::AdvanceFrames { count, .. } => f
+                .debug_struct("AdvanceFrames")
+                .field("count", count)
+                .finish(),
+            McpCommand::SetFrameRate {
+                fps,
+                idle_when_no_damage,
+                ..
+            } => f
+                .debug_struct("SetFrameRate")
+                .field("fps", fps)
+                .field("idle_when_no_damage", idle_when_no_damage)
+                .finish(),
+            McpCommand::HideCursor { hidden, .. } => f
+                .debug_struct("HideCursor")
+                .field("hidden", hidden)
+                .finish(),
+            McpCommand::SetOutputTransform {
+                output, transform, ..
+            } => f
+                .debug_struct("SetOutputTransform")
+                .field("output", output)
+                .field("transform", transform)
+                .finish(),
+            McpCommand::SetBackground {
+                color, image_path, ..
+            } => f
+                .debug_struct("SetBackground")
+                .field("color", color)
+                .field("image_path", image_path)
+                .finish(),
+            McpCommand::RestartApp {
+                pid,
+                wait_for_window,
+                ..
+            } => f
+                .debug_struct("RestartApp")
+                .field("pid", pid)
+                .field("wait_for_window", wait_for_window)
+                .finish(),
+            McpCommand::SetKeyRepeat { rate, delay_ms, .. } => f
+                .debug_struct("SetKeyRepeat")
+                .field("rate", rate)
+                .field("delay_ms", delay_ms)
+                .finish(),
+            McpCommand::HoldKey { keys, duration, .. } => f
+                .debug_struct("HoldKey")
+                .field("keys", keys)
+                .field("duration", duration)
+                .finish(),
+            McpCommand::SetProtocolTrace {
+                pid,
+                window_id,
+                enabled,
+                ..
+            } => f
+                .debug_struct("SetProtocolTrace")
+                .field("pid", pid)
+                .field("window_id", window_id)
+                .field("enabled", enabled)
+                .finish(),
+            McpCommand::GetStats { .. } => f.debug_struct("GetStats").finish(),
+            McpCommand::GetRecentFrames {
+                seconds,
+                max_frames,
+                ..
+            } => f
+                .debug_struct("GetRecentFrames")
+                .field("seconds", seconds)
+                .field("max_frames", max_frames)
+                .finish(),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct MCPvilServer {
+    tool_router: ToolRouter<Self>,
+    command_tx: CommandSender,
+    subscribers: Subscribers,
+    /// Which connection this instance serves, e.g. "stdio" or "http-3"
+    client_id: String,
+    resources: SharedResources,
+    scene: SceneReceiver,
+    scene_subscribers: SceneSubscribers,
+    session_dir: SessionDir,
+    notifications: Notifications,
+    file_chooser: FileChooserResponses,
+    logs: Logs,
+    log_subscribers: LogSubscribers,
+    protocol_trace: Option<ProtocolTrace>,
+    tool_metrics: ToolMetrics,
+    /// How long a tool call may take besides the time it asks for (--command-timeout)
+    command_timeout: std::time::Duration,
+}
+
+#[tool_router]
+impl MCPvilServer {
+    fn new(
+        command_tx: CommandSender,
+        subscribers: Subscribers,
+        scene: SceneReceiver,
+        scene_subscribers: SceneSubscribers,
+        session_dir: SessionDir,
+        logs: Logs,
+        protocol_trace: Option<ProtocolTrace>,
+    ) -> Self {
+        Self {
+            tool_router: Self::tool_router(),
+            command_tx,
+            subscribers,
+            client_id: String::from("stdio"),
+            resources: SharedResources::default(),
+            scene,
+            scene_subscribers,
+            session_dir,
+            notifications: Notifications::default(),
+            file_chooser: FileChooserResponses::default(),
+            logs,
+            log_subscribers: LogSubscribers::default(),
+            protocol_trace,
+            tool_metrics: ToolMetrics::default(),
+            command_timeout: cancel::DEFAULT_COMMAND_TIMEOUT,
+        }
+    }
+
+    /// A copy serving another connection. Every client shares the command
+    /// channel into the event loop.
+    fn for_client(&self, client_id: String) -> Self {
+        Self {
+            client_id,
+            ..self.clone()
+        }
+    }
+
+    /// The connection id, with the name the client introduced itself with.
+    fn client_label(&self, peer: &Peer<RoleServer>) -> String {
+        match peer.peer_info() {
+            Some(info) => format!("{} ({})", self.client_id, info.client_info.name),
+            None => self.client_id.clone(),
+        }
+    }
+
+    #[tool(
+        description = "Launches an application in the compositor, optionally waiting until its first window has mapped"
+    )]
+    async fn launch_app(
+        &self,
+        params: Parameters<LaunchAppRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        let command = params.0.command.clone();
+        let args = params.0.args.clone();
+        let options = LaunchOptions {
+            command: command.clone(),
+            args: args.clone(),
+            env: params.0.env.clone().unwrap_or_default(),
+            cwd: params.0.cwd.clone(),
+            stdin: params.0.stdin.clone(),
+            sandbox: params.0.sandbox.unwrap_or(false),
+            limits: ResourceLimits {
+                memory: params
+                    .0
+                    .memory_limit_mb
+                    .map(|mb| mb.saturating_mul(1024 * 1024)),
+                cpu_seconds: params.0.cpu_limit_seconds,
+                max_processes: params.0.max_processes,
+            },
+            desktop: None,
+        };
+        let wait_for_window = params
+            .0
+            .wait_for_window
+            .unwrap_or(false)
+            .then(|| std::time::Duration::from_millis(params.0.timeout_ms.unwrap_or(10000)));
+        let (response_tx, response_rx) = tokio::sync::oneshot::channel();
+
+        self.command_tx
+            .send(McpCommand::LaunchApp {
+                options,
+                wait_for_window,
+                response_tx,
+            })
+            .map_err(|e| {
+                McpError::internal_error(format!("Failed to send command: {}", e), None)
+            })?;
+
+        let result = response_rx.await.map_err(|_| {
+            McpError::internal_error("Event loop dropped response channel".to_string(), None)
+        })?;
+
+        match result {
+            Ok(LaunchedApp { pid, window }) => {
+                let text = match &window {
+                    Some(window) => format!(
+                        "Launched {} (pid {}) with args {:?}; mapped window {}",
+                        command, pid, args, window
+                    ),
+                    None => format!("Launched {} (pid {}) with args {:?}", command, pid, args),
+                };
+                structured::success(
+                    vec![Content::text(text)],
+                    &LaunchResult {
+                        pid,
+                        command,
+                        args,
+                        window,
+                    },
+                )
+            }
+            Err(e) => Ok(e
+                .context(format!("Failed to launch {}", command))
+                .into_result()),
+        }
+    }
+
+    #[tool(
+        description = "Takes a screenshot of an output (the primary one by default), the focused window, a window by id, or a region, saving it to a file and/or returning it inline as PNG, JPEG, WebP or QOI"
+    )]
+    async fn screenshot(
+        &self,
+        params: Parameters<ScreenshotRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        let options = match params.0.options() {
+            Ok(options) => options,
+            Err(e) => {
+                return Err(McpError::invalid_params(
+                    format!("Invalid screenshot request: {}", e),
+                    None,
+                ));
+            }
+        };
+
+        self.take_screenshot(options).await
+    }
+
+    #[tool(description = "Closes/kills an application by its PID (as returned by launch_app)")]
+    async fn close_app(
+        &self,
+        params: Parameters<CloseAppRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        let pid = params.0.pid;
+        let (response_tx, response_rx) = tokio::sync::oneshot::channel();
+
+        self.command_tx
+            .send(McpCommand::CloseApp { pid, response_tx })
+            .map_err(|e| {
+                McpError::internal_error(format!("Failed to send command: {}", e), None)
+            })?;
+
+        let result = response_rx.await.map_err(|_| {
+            McpError::internal_error("Event loop dropped response channel".to_string(), None)
+        })?;
+
+        match result {
+            Ok(()) => Ok(CallToolResult::success(vec![Content::text(format!(
+                "Sent SIGTERM to process {}",
+                pid
+            ))])),
+            Err(e) => Ok(e
+                .context(format!("Failed to close process {}", pid))
+                .into_result()),
+        }
+    }
+
+    #[tool(description = "Moves the mouse pointer to the specified coordinates")]
+    async fn mouse_move(
+        &self,
+        params: Parameters<MouseMoveRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        let (response_tx, response_rx) = tokio::sync::oneshot::channel();
+
+        self.command_tx
+            .send(McpCommand::MouseMove {
+                x: params.0.x,
+                y: params.0.y,
+                relative: false,
+                response_tx,
+            })
+            .map_err(|e| {
+                McpError::internal_error(format!("Failed to send command: {}", e), None)
+            })?;
+
+        let result = response_rx.await.map_err(|_| {
+            McpError::internal_error("Event loop dropped response channel".to_string(), None)
+        })?;
+
+        match result {
+            Ok(_) => Ok(CallToolResult::success(vec![Content::text(format!(
+                "Mouse moved to ({}, {})",
+                params.0.x, params.0.y
+            ))])),
+            Err(e) => Ok(ToolError::new(ErrorKind::Other, e)
+                .context("Failed to move mouse")
+                .into_result()),
+        }
+    }
+
+    #[tool(description = "Clicks a mouse button at the specified coordinates")]
+    async fn mouse_click(
+        &self,
+        params: Parameters<MouseClickRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        let button_name = params.0.button.as_deref().unwrap_or("left");
+        let Some(button_code) = button_code(button_name) else {
+            return Err(McpError::invalid_params(
+                format!(
+                    "Unknown button '{}'. Use 'left', 'right', or 'middle'.",
+                    button_name
+                ),
+                None,
+            ));
+        };
+
+        let (response_tx, response_rx) = tokio::sync::oneshot::channel();
+
+        self.command_tx
+            .send(McpCommand::MouseClick {
+                x: params.0.x,
+                y: params.0.y,
+                button: button_code,
+                count: 1,
+                response_tx,
+            })
+            .map_err(|e| {
+                McpError::internal_error(format!("Failed to send command: {}", e), None)
+            })?;
+
+        let result = response_rx.await.map_err(|_| {
+            McpError::internal_error("Event loop dropped response channel".to_string(), None)
+        })?;
+
+        match result {
+            Ok(_) => Ok(CallToolResult::success(vec![Content::text(format!(
+                "Clicked {} at ({}, {})",
+                button_name, params.0.x, params.0.y
+            ))])),
+            Err(e) => Ok(ToolError::new(ErrorKind::Other, e)
+                .context("Failed to click")
+                .into_result()),
+        }
+    }
+
+    #[tool(description = "Simulates a key press and release using a Linux evdev keycode")]
+    async fn key_press(
+        &self,
+        params: Parameters<KeyPressRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        let (response_tx, response_rx) = tokio::sync::oneshot::channel();
+
+        self.command_tx
+            .send(McpCommand::KeyPress {
+                key: params.0.key,
+                response_tx,
+            })
+            .map_err(|e| {
+                McpError::internal_error(format!("Failed to send command: {}", e), None)
+            })?;
+
+        let result = response_rx.await.map_err(|_| {
+            McpError::internal_error("Event loop dropped response channel".to_string(), None)
+        })?;
+
+        match result {
+            Ok(()) => Ok(CallToolResult::success(vec![Content::text(format!(
+                "Key {} pressed and released",
+                params.0.key
+            ))])),
+            Err(e) => Ok(ToolError::new(ErrorKind::Other, e)
+                .context("Failed to press key")
+                .into_result()),
+        }
+    }
+
+    #[tool(description = "Scrolls at the specified coordinates in the given direction")]
+    async fn scroll(&self, params: Parameters<ScrollRequest>) -> Result<CallToolResult, McpError> {
+        use smithay::backend::input::{Axis, AxisSource};
+
+        let (axis, sign) = match params.0.direction.as_str() {
+            "up" => (Axis::Vertical, -1),
+            "down" => (Axis::Vertical, 1),
+            "left" => (Axis::Horizontal, -1),
+            "right" => (Axis::Horizontal, 1),
+            other => {
+                return Err(McpError::invalid_params(
+                    format!(
+                        "Unknown direction '{}'. Use 'up', 'down', 'left', or 'right'.",
+                        other
+                    ),
+                    None,
+                ));
+            }
+        };
+
+        let source = match params.0.source.as_deref().unwrap_or("wheel") {
+            "wheel" => AxisSource::Wheel,
+            "finger" => AxisSource::Finger,
+            "continuous" => AxisSource::Continuous,
+            "wheel_tilt" => AxisSource::WheelTilt,
+            other => {
+                return Err(McpError::invalid_params(
+                    format!(
+                        "Unknown source '{}'. Use 'wheel', 'finger', 'continuous', or 'wheel_tilt'.",
+                        other
+                    ),
+                    None,
+                ));
+            }
+        };
+
+        let position = match (params.0.x, params.0.y) {
+            (Some(x), Some(y)) => Some((x, y)),
+            (None, None) => None,
+            _ => {
+                return Err(McpError::invalid_params(
+                    "Specify both x and y, or neither to scroll at the current pointer position.",
+                    None,
+                ));
+            }
+        };
+
+        if params
+            .0
+            .steps
+            .is_some_and(|steps| steps > input::MAX_SCROLL_STEPS)
+        {
+            return Err(McpError::invalid_params(
+                format!(
+                    "Too many steps, at most {} are allowed",
+                    input::MAX_SCROLL_STEPS
+                ),
+                None,
+            ));
+        }
+        let steps = params.0.steps.map(|steps| sign * steps as i32);
+        let amount = sign as f64
+            * params
+                .0
+                .amount
+                .unwrap_or(15.0 * params.0.steps.unwrap_or(1) as f64);
+
+        let (response_tx, response_rx) = tokio::sync::oneshot::channel();
+
+        self.command_tx
+            .send(McpCommand::Scroll {
+                position,
+                axis,
+                amount,
+                steps,
+                source,
+                response_tx,
+            })
+            .map_err(|e| {
+                McpError::internal_error(format!("Failed to send command: {}", e), None)
+            })?;
+
+        let result = response_rx.await.map_err(|_| {
+            McpError::internal_error("Event loop dropped response channel".to_string(), None)
+        })?;
+
+        match result {
+            Ok(target) => Ok(CallToolResult::success(vec![Content::text(format!(
+                "Scrolled {} at {}",
+                params.0.direction, target
+            ))])),
+            Err(e) => Ok(ToolError::new(ErrorKind::Other, e)
+                .context("Failed to scroll")
+                .into_result()),
+        }
+    }
+
+    #[tool(description = "Types UTF-8 text into the focused window as synthetic key events")]
+    async fn type_text(
+        &self,
+        params: Parameters<TypeTextRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        let (response_tx, response_rx) = tokio::sync::oneshot::channel();
+
+        self.command_tx
+            .send(McpCommand::TypeText {
+                text: params.0.text.clone(),
+                response_tx,
+            })
+            .map_err(|e| {
+                McpError::internal_error(format!("Failed to send command: {}", e), None)
+            })?;
+
+        let result = response_rx.await.map_err(|_| {
+            McpError::internal_error("Event loop dropped response channel".to_string(), None)
+        })?;
+
+        match result {
+            Ok(count) => Ok(CallToolResult::success(vec![Content::text(format!(
+                "Typed {} characters",
+                count
+            ))])),
+            Err(e) => Ok(ToolError::new(ErrorKind::Other, e)
+                .context("Failed to type text")
+                .into_result()),
+        }
+    }
+
+    #[tool(
+        description = "Presses and releases a key by name, optionally with modifiers (e.g. \"ctrl+c\", \"alt+F4\")"
+    )]
+    async fn press_key(
+        &self,
+        params: Parameters<PressKeyRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        let (response_tx, response_rx) = tokio::sync::oneshot::channel();
+
+        self.command_tx
+            .send(McpCommand::PressKeyCombo {
+                keys: params.0.keys.clone(),
+                response_tx,
+            })
+            .map_err(|e| {
+                McpError::internal_error(format!("Failed to send command: {}", e), None)
+            })?;
+
+        let result = response_rx.await.map_err(|_| {
+            McpError::internal_error("Event loop dropped response channel".to_string(), None)
+        })?;
+
+        match result {
+            Ok(()) => Ok(CallToolResult::success(vec![Content::text(format!(
+                "Pressed {}",
+                params.0.keys
+            ))])),
+            Err(e) => Ok(ToolError::new(ErrorKind::Other, e)
+                .context(format!("Failed to press {}", params.0.keys))
+                .into_result()),
+        }
+    }
+
+    #[tool(
+        description = "Clicks at compositor coordinates and reports which window received the click"
+    )]
+    async fn click(&self, params: Parameters<ClickRequest>) -> Result<CallToolResult, McpError> {
+        let button_name = params.0.button.as_deref().unwrap_or("left");
+        let Some(button_code) = button_code(button_name) else {
+            return Err(McpError::invalid_params(
+                format!(
+                    "Unknown button '{}'. Use 'left', 'right', or 'middle'.",
+                    button_name
+                ),
+                None,
+            ));
+        };
+        let count = params.0.count.unwrap_or(1);
+
+        let (response_tx, response_rx) = tokio::sync::oneshot::channel();
+
+        self.command_tx
+            .send(McpCommand::MouseClick {
+                x: params.0.x,
+                y: params.0.y,
+                button: button_code,
+                count,
+                response_tx,
+            })
+            .map_err(|e| {
+                McpError::internal_error(format!("Failed to send command: {}", e), None)
+            })?;
+
+        let result = response_rx.await.map_err(|_| {
+            McpError::internal_error("Event loop dropped response channel".to_string(), None)
+        })?;
+
+        match result {
+            Ok(Some(window)) => Ok(CallToolResult::success(vec![Content::text(format!(
+                "Clicked {} x{} at ({}, {}) on window {}",
+                button_name, count, params.0.x, params.0.y, window
+            ))])),
+            Ok(None) => Ok(CallToolResult::success(vec![Content::text(format!(
+                "Clicked {} x{} at ({}, {}) on the background (no window)",
+                button_name, count, params.0.x, params.0.y
+            ))])),
+            Err(e) => Ok(ToolError::new(ErrorKind::Other, e)
+                .context("Failed to click")
+                .into_result()),
+        }
+    }
+
+    #[tool(
+        description = "Moves the pointer to absolute coordinates or by a relative delta and reports the surface under the cursor"
+    )]
+    async fn move_pointer(
+        &self,
+        params: Parameters<MovePointerRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        let relative = match params.0.mode.as_deref().unwrap_or("absolute") {
+            "absolute" => false,
+            "relative" => true,
+            other => {
+                return Err(McpError::invalid_params(
+                    format!("Unknown mode '{}'. Use 'absolute' or 'relative'.", other),
+                    None,
+                ));
+            }
+        };
+
+        let (response_tx, response_rx) = tokio::sync::oneshot::channel();
+
+        self.command_tx
+            .send(McpCommand::MouseMove {
+                x: params.0.x,
+                y: params.0.y,
+                relative,
+                response_tx,
+            })
+            .map_err(|e| {
+                McpError::internal_error(format!("Failed to send command: {}", e), None)
+            })?;
+
+        let result = response_rx.await.map_err(|_| {
+            McpError::internal_error("Event loop dropped response channel".to_string(), None)
+        })?;
+
+        match result {
+            Ok(target) => Ok(CallToolResult::success(vec![Content::text(format!(
+                "Pointer at {}",
+                target
+            ))])),
+            Err(e) => Ok(ToolError::new(ErrorKind::Other, e)
+                .context("Failed to move pointer")
+                .into_result()),
+        }
+    }
+
+    #[tool(
+        description = "Performs a press-move-release drag gesture from a start point to an end point, optionally via waypoints"
+    )]
+    async fn drag(&self, params: Parameters<DragRequest>) -> Result<CallToolResult, McpError> {
         let button_name = params.0.button.as_deref().unwrap_or("left");
-        let button_code: u32 = match button_name {
-            "left" => 0x110,   // BTN_LEFT
-            "right" => 0x111,  // BTN_RIGHT
-            "middle" => 0x112, // BTN_MIDDLE
+        let Some(button_code) = button_code(button_name) else {
+            return Err(McpError::invalid_params(
+                format!(
+                    "Unknown button '{}'. Use 'left', 'right', or 'middle'.",
+                    button_name
+                ),
+                None,
+            ));
+        };
+
+        let mut path = vec![(params.0.start_x, params.0.start_y)];
+        path.extend(
+            params
+                .0
+                .waypoints
+                .iter()
+                .flatten()
+                .map(|point| (point.x, point.y)),
+        );
+        path.push((params.0.end_x, params.0.end_y));
+        let duration = std::time::Duration::from_millis(params.0.duration_ms.unwrap_or(300));
+
+        let (response_tx, response_rx) = tokio::sync::oneshot::channel();
+
+        self.command_tx
+            .send(McpCommand::Drag {
+                path,
+                button: button_code,
+                duration,
+                response_tx,
+            })
+            .map_err(|e| {
+                McpError::internal_error(format!("Failed to send command: {}", e), None)
+            })?;
+
+        let result = response_rx.await.map_err(|_| {
+            McpError::internal_error("Event loop dropped response channel".to_string(), None)
+        })?;
+
+        match result {
+            Ok(target) => Ok(CallToolResult::success(vec![Content::text(format!(
+                "Dragged {} from ({}, {}), released at {}",
+                button_name, params.0.start_x, params.0.start_y, target
+            ))])),
+            Err(e) => Ok(ToolError::new(ErrorKind::Other, e)
+                .context("Failed to drag")
+                .into_result()),
+        }
+    }
+
+    #[tool(
+        description = "Lists mapped windows as JSON (id, app_id, title, geometry, state, pid, outputs), bottom to top in stacking order, optionally only those on one output"
+    )]
+    async fn list_windows(
+        &self,
+        params: Parameters<ListWindowsRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        let (response_tx, response_rx) = tokio::sync::oneshot::channel();
+
+        self.command_tx
+            .send(McpCommand::ListWindows {
+                output: params.0.output.clone(),
+                response_tx,
+            })
+            .map_err(|e| {
+                McpError::internal_error(format!("Failed to send command: {}", e), None)
+            })?;
+
+        let result = response_rx.await.map_err(|_| {
+            McpError::internal_error("Event loop dropped response channel".to_string(), None)
+        })?;
+
+        match result {
+            Ok(windows) => structured::json(&WindowList { windows }),
+            Err(e) => Ok(CallToolResult::error(vec![Content::text(format!(
+                "Failed to list windows: {}",
+                e
+            ))])),
+        }
+    }
+
+    #[tool(description = "Captures a screenshot of the compositor output and returns it as a base64-encoded PNG image")]
+    async fn capture_screenshot(
+        &self,
+        #[allow(unused_variables)] params: Parameters<CaptureScreenshotRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        self.take_screenshot(ScreenshotOptions {
+            inline: true,
+            ..Default::default()
+        })
+        .await
+    }
+
+    async fn take_screenshot(
+        &self,
+        options: ScreenshotOptions,
+    ) -> Result<CallToolResult, McpError> {
+        match self.capture(options).await? {
+            Ok(screenshot) => {
+                let uri = self.resources.lock().unwrap().add_bytes(
+                    screenshot.format.extension(),
+                    screenshot.mime_type,
+                    screenshot.data,
+                );
+                let mut content = Vec::new();
+                if let Some(data) = screenshot.base64_data {
+                    content.push(Content::image(data, screenshot.mime_type));
+                }
+                content.push(Content::text(match &screenshot.path {
+                    Some(path) => format!(
+                        "Screenshot saved to {} ({}x{}, {}, {} bytes), available as {}",
+                        path,
+                        screenshot.width,
+                        screenshot.height,
+                        screenshot.mime_type,
+                        screenshot.size_bytes,
+                        uri
+                    ),
+                    None => format!(
+                        "Screenshot captured ({}x{}, {}, {} bytes), available as {}",
+                        screenshot.width,
+                        screenshot.height,
+                        screenshot.mime_type,
+                        screenshot.size_bytes,
+                        uri
+                    ),
+                }));
+                structured::success(
+                    content,
+                    &ScreenshotResult {
+                        width: screenshot.width,
+                        height: screenshot.height,
+                        mime_type: screenshot.mime_type.to_string(),
+                        size_bytes: screenshot.size_bytes,
+                        path: screenshot.path,
+                        uri,
+                    },
+                )
+            }
+            Err(e) => Ok(e.context("Failed to take screenshot").into_result()),
+        }
+    }
+
+    #[tool(description = "Gives keyboard focus to a window by id, raising and activating it")]
+    async fn focus_window(
+        &self,
+        params: Parameters<FocusWindowRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        let (response_tx, response_rx) = tokio::sync::oneshot::channel();
+
+        self.command_tx
+            .send(McpCommand::FocusWindow {
+                id: params.0.id,
+                response_tx,
+            })
+            .map_err(|e| {
+                McpError::internal_error(format!("Failed to send command: {}", e), None)
+            })?;
+
+        let result = response_rx.await.map_err(|_| {
+            McpError::internal_error("Event loop dropped response channel".to_string(), None)
+        })?;
+
+        match result {
+            Ok(window) => structured::success(
+                vec![Content::text(format!("Focused window {}", window))],
+                &window,
+            ),
+            Err(e) => Ok(e
+                .context(format!("Failed to focus window {}", params.0.id))
+                .into_result()),
+        }
+    }
+
+    #[tool(
+        description = "Asks a window to close by id, optionally killing its process if it does not close within the timeout"
+    )]
+    async fn close_window(
+        &self,
+        params: Parameters<CloseWindowRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        let (response_tx, response_rx) = tokio::sync::oneshot::channel();
+
+        self.command_tx
+            .send(McpCommand::CloseWindow {
+                id: params.0.id,
+                timeout: std::time::Duration::from_millis(params.0.timeout_ms.unwrap_or(5000)),
+                force: params.0.force.unwrap_or(false),
+                response_tx,
+            })
+            .map_err(|e| {
+                McpError::internal_error(format!("Failed to send command: {}", e), None)
+            })?;
+
+        let result = response_rx.await.map_err(|_| {
+            McpError::internal_error("Event loop dropped response channel".to_string(), None)
+        })?;
+
+        match result {
+            Ok(CloseOutcome::Graceful) => Ok(CallToolResult::success(vec![Content::text(
+                format!("Window {} closed gracefully", params.0.id),
+            )])),
+            Ok(CloseOutcome::Forced) => Ok(CallToolResult::success(vec![Content::text(format!(
+                "Window {} did not close in time; killed its process",
+                params.0.id
+            ))])),
+            Err(e) => Ok(e
+                .context(format!("Failed to close window {}", params.0.id))
+                .into_result()),
+        }
+    }
+
+    #[tool(
+        description = "Moves a window by id so its top-left corner is at the given compositor coordinates"
+    )]
+    async fn move_window(
+        &self,
+        params: Parameters<MoveWindowRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        let (response_tx, response_rx) = tokio::sync::oneshot::channel();
+
+        self.command_tx
+            .send(McpCommand::MoveWindow {
+                id: params.0.id,
+                x: params.0.x,
+                y: params.0.y,
+                response_tx,
+            })
+            .map_err(|e| {
+                McpError::internal_error(format!("Failed to send command: {}", e), None)
+            })?;
+
+        let result = response_rx.await.map_err(|_| {
+            McpError::internal_error("Event loop dropped response channel".to_string(), None)
+        })?;
+
+        match result {
+            Ok(window) => structured::success(
+                vec![Content::text(format!(
+                    "Moved window {} to ({}, {}), geometry {:?}",
+                    window.id,
+                    params.0.x,
+                    params.0.y,
+                    window.geometry.map(|g| g.bounds)
+                ))],
+                &window,
+            ),
+            Err(e) => Ok(e
+                .context(format!("Failed to move window {}", params.0.id))
+                .into_result()),
+        }
+    }
+
+    #[tool(
+        description = "Asks a window (by id) to resize to the given size; the client applies it on its next commit"
+    )]
+    async fn resize_window(
+        &self,
+        params: Parameters<ResizeWindowRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        if params.0.width <= 0 || params.0.height <= 0 {
+            return Err(McpError::invalid_params(
+                format!("Invalid size {}x{}", params.0.width, params.0.height),
+                None,
+            ));
+        }
+
+        let (response_tx, response_rx) = tokio::sync::oneshot::channel();
+
+        self.command_tx
+            .send(McpCommand::ResizeWindow {
+                id: params.0.id,
+                width: params.0.width,
+                height: params.0.height,
+                response_tx,
+            })
+            .map_err(|e| {
+                McpError::internal_error(format!("Failed to send command: {}", e), None)
+            })?;
+
+        let result = response_rx.await.map_err(|_| {
+            McpError::internal_error("Event loop dropped response channel".to_string(), None)
+        })?;
+
+        match result {
+            Ok(window) => structured::success(
+                vec![Content::text(format!(
+                    "Requested {}x{} for window {} (current geometry {:?})",
+                    params.0.width,
+                    params.0.height,
+                    window.id,
+                    window.geometry.map(|g| g.bounds)
+                ))],
+                &window,
+            ),
+            Err(e) => Ok(e
+                .context(format!("Failed to resize window {}", params.0.id))
+                .into_result()),
+        }
+    }
+
+    #[tool(description = "Maximizes a window by id to fill the output")]
+    async fn maximize_window(
+        &self,
+        params: Parameters<WindowIdRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        self.set_window_mode(params.0.id, WindowMode::Maximized)
+            .await
+    }
+
+    #[tool(description = "Makes a window fullscreen by id")]
+    async fn fullscreen_window(
+        &self,
+        params: Parameters<WindowIdRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        self.set_window_mode(params.0.id, WindowMode::Fullscreen)
+            .await
+    }
+
+    #[tool(
+        description = "Restores a maximized or fullscreen window by id to its previous geometry"
+    )]
+    async fn restore_window(
+        &self,
+        params: Parameters<WindowIdRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        self.set_window_mode(params.0.id, WindowMode::Normal).await
+    }
+
+    async fn set_window_mode(&self, id: u64, mode: WindowMode) -> Result<CallToolResult, McpError> {
+        let (response_tx, response_rx) = tokio::sync::oneshot::channel();
+
+        self.command_tx
+            .send(McpCommand::SetWindowMode {
+                id,
+                mode,
+                response_tx,
+            })
+            .map_err(|e| {
+                McpError::internal_error(format!("Failed to send command: {}", e), None)
+            })?;
+
+        let result = response_rx.await.map_err(|_| {
+            McpError::internal_error("Event loop dropped response channel".to_string(), None)
+        })?;
+
+        match result {
+            Ok(window) => structured::success(
+                vec![Content::text(format!(
+                    "Window {} is now {:?}, geometry {:?}",
+                    window.id,
+                    mode,
+                    window.geometry.map(|g| g.bounds)
+                ))],
+                &window,
+            ),
+            Err(e) => Ok(e
+                .context(format!("Failed to change window {}", id))
+                .into_result()),
+        }
+    }
+
+    #[tool(
+        description = "Takes a screenshot of a single window by id, rendered on its own so overlapping windows are excluded"
+    )]
+    async fn screenshot_window(
+        &self,
+        params: Parameters<ScreenshotWindowRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        let format = match params.0.format.as_deref().map(ImageFormat::from_name) {
+            Some(Ok(format)) => format,
+            None => ImageFormat::default(),
+            Some(Err(e)) => {
+                return Err(McpError::invalid_params(
+                    format!("Invalid screenshot request: {}", e),
+                    None,
+                ));
+            }
+        };
+        let annotations = match parse_annotations(params.0.annotations.as_deref()) {
+            Ok(annotations) => annotations,
+            Err(e) => {
+                return Err(McpError::invalid_params(
+                    format!("Invalid screenshot request: {}", e),
+                    None,
+                ));
+            }
+        };
+        let inline = params.0.inline.unwrap_or(params.0.filename.is_none());
+        let options = ScreenshotOptions {
+            filename: params.0.filename.clone(),
+            inline,
+            mode: CaptureMode::Window(params.0.id),
+            output: None,
+            format,
+            quality: params.0.quality,
+            include_cursor: params.0.include_cursor.unwrap_or(false),
+            annotations,
+            requested_at: None,
+            span: None,
+        };
+
+        self.take_screenshot(options).await
+    }
+
+    #[tool(
+        description = "Starts recording the compositor output to a video file (WebM/VP9, or MP4/H.264 when built with the h264 feature) using ffmpeg"
+    )]
+    async fn start_recording(
+        &self,
+        params: Parameters<StartRecordingRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        let options = RecordingOptions {
+            path: params
+                .0
+                .path
+                .clone()
+                .unwrap_or_else(|| default_recording_path(recording::DEFAULT_EXTENSION)),
+            fps: params.0.fps.unwrap_or(30),
+            format: RecordingFormat::Video,
+            show_input: params.0.show_input.unwrap_or(false),
+        };
+
+        match self.send_start_recording(options).await? {
+            Ok(path) => Ok(CallToolResult::success(vec![Content::text(format!(
+                "Recording to {}",
+                path
+            ))])),
+            Err(e) => Ok(ToolError::new(ErrorKind::CaptureFailed, e)
+                .context("Failed to start recording")
+                .into_result()),
+        }
+    }
+
+    #[tool(
+        description = "Stops the current recording or GIF capture and returns the saved path and duration"
+    )]
+    async fn stop_recording(
+        &self,
+        #[allow(unused_variables)] params: Parameters<StopRecordingRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        match self.send_stop_recording().await? {
+            Ok(summary) => self.recording_saved("Recording", summary),
+            Err(e) => Ok(ToolError::new(ErrorKind::CaptureFailed, e)
+                .context("Failed to stop recording")
+                .into_result()),
+        }
+    }
+
+    #[tool(
+        description = "Captures the output as an animated GIF, for `duration` seconds or until stop_recording is called"
+    )]
+    async fn capture_gif(
+        &self,
+        params: Parameters<CaptureGifRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        let options = RecordingOptions {
+            path: params
+                .0
+                .path
+                .clone()
+                .unwrap_or_else(|| default_recording_path("gif")),
+            fps: params.0.fps.unwrap_or(recording::DEFAULT_GIF_FPS),
+            format: RecordingFormat::Gif,
+            show_input: params.0.show_input.unwrap_or(false),
+        };
+
+        let path = match self.send_start_recording(options).await? {
+            Ok(path) => path,
+            Err(e) => {
+                return Ok(ToolError::new(ErrorKind::CaptureFailed, e)
+                    .context("Failed to start GIF capture")
+                    .into_result());
+            }
+        };
+
+        let Some(duration) = params.0.duration else {
+            return structured::success(
+                vec![Content::text(format!(
+                    "Capturing GIF to {}, call stop_recording to finish",
+                    path
+                ))],
+                &RecordingResult {
+                    path,
+                    uri: None,
+                    duration_secs: None,
+                    frames: None,
+                    dropped: None,
+                },
+            );
+        };
+        let duration = std::time::Duration::from_secs_f64(duration.max(0.0));
+        let capture = tokio::time::sleep(duration);
+        progress::timed(capture, Some(duration), "Capturing GIF").await;
+
+        match self.send_stop_recording().await? {
+            Ok(summary) => self.recording_saved("GIF", summary),
+            Err(e) => Ok(ToolError::new(ErrorKind::CaptureFailed, e)
+                .context("Failed to finish GIF capture")
+                .into_result()),
+        }
+    }
+
+    /// Captures a screenshot after the next frame, for tools that analyse it.
+    async fn capture(
+        &self,
+        options: ScreenshotOptions,
+    ) -> Result<Result<Screenshot, ToolError>, McpError> {
+        let (response_tx, response_rx) = tokio::sync::oneshot::channel();
+
+        self.command_tx
+            .send(McpCommand::Screenshot {
+                options,
+                response_tx,
+            })
+            .map_err(|e| {
+                McpError::internal_error(format!("Failed to send command: {}", e), None)
+            })?;
+
+        response_rx.await.map_err(|_| {
+            McpError::internal_error("Event loop dropped response channel".to_string(), None)
+        })
+    }
+
+    async fn send_start_recording(
+        &self,
+        options: RecordingOptions,
+    ) -> Result<Result<String, String>, McpError> {
+        let (response_tx, response_rx) = tokio::sync::oneshot::channel();
+
+        self.command_tx
+            .send(McpCommand::StartRecording {
+                options,
+                response_tx,
+            })
+            .map_err(|e| {
+                McpError::internal_error(format!("Failed to send command: {}", e), None)
+            })?;
+
+        response_rx.await.map_err(|_| {
+            McpError::internal_error("Event loop dropped response channel".to_string(), None)
+        })
+    }
+
+    async fn send_stop_recording(&self) -> Result<Result<RecordingSummary, String>, McpError> {
+        let (response_tx, response_rx) = tokio::sync::oneshot::channel();
+
+        self.command_tx
+            .send(McpCommand::StopRecording { response_tx })
+            .map_err(|e| {
+                McpError::internal_error(format!("Failed to send command: {}", e), None)
+            })?;
+
+        response_rx.await.map_err(|_| {
+            McpError::internal_error("Event loop dropped response channel".to_string(), None)
+        })
+    }
+
+    /// The result of a finished recording, whose file is added as a resource.
+    fn recording_saved(
+        &self,
+        what: &str,
+        summary: RecordingSummary,
+    ) -> Result<CallToolResult, McpError> {
+        let uri = self.resources.lock().unwrap().add_file(&summary.path);
+        let duration_secs = summary.duration.as_secs_f64();
+        structured::success(
+            vec![Content::text(format!(
+                "{} saved to {} ({:.1}s, {} frames, {} dropped), available as {}",
+                what, summary.path, duration_secs, summary.frames, summary.dropped, uri
+            ))],
+            &RecordingResult {
+                path: summary.path,
+                uri: Some(uri),
+                duration_secs: Some(duration_secs),
+                frames: Some(summary.frames),
+                dropped: Some(summary.dropped),
+            },
+        )
+    }
+
+    #[tool(description = "Lists outputs as JSON (name, geometry, mode, primary), left to right")]
+    async fn list_outputs(
+        &self,
+        #[allow(unused_variables)] params: Parameters<ListOutputsRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        let (response_tx, response_rx) = tokio::sync::oneshot::channel();
+
+        self.command_tx
+            .send(McpCommand::ListOutputs { response_tx })
+            .map_err(|e| {
+                McpError::internal_error(format!("Failed to send command: {}", e), None)
+            })?;
+
+        let result = response_rx.await.map_err(|_| {
+            McpError::internal_error("Event loop dropped response channel".to_string(), None)
+        })?;
+
+        match result {
+            Ok(outputs) => structured::json(&OutputList { outputs }),
+            Err(e) => Ok(CallToolResult::error(vec![Content::text(format!(
+                "Failed to list outputs: {}",
+                e
+            ))])),
+        }
+    }
+
+    #[tool(
+        description = "Changes an output's resolution and refresh rate at runtime, e.g. to switch between phone, tablet and desktop sizes"
+    )]
+    async fn set_output_mode(
+        &self,
+        params: Parameters<SetOutputModeRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        let SetOutputModeRequest {
+            output,
+            width,
+            height,
+            refresh,
+        } = params.0;
+        let refresh = refresh.unwrap_or(60.0);
+        if width <= 0 || height <= 0 || refresh <= 0.0 {
+            return Err(McpError::invalid_params(
+                "Width, height and refresh must be positive",
+                None,
+            ));
+        }
+        let mode = smithay::output::Mode {
+            size: (width, height).into(),
+            refresh: (refresh * 1000.0).round() as i32,
+        };
+        let (response_tx, response_rx) = tokio::sync::oneshot::channel();
+
+        self.command_tx
+            .send(McpCommand::SetOutputMode {
+                output,
+                mode,
+                response_tx,
+            })
+            .map_err(|e| {
+                McpError::internal_error(format!("Failed to send command: {}", e), None)
+            })?;
+
+        let result = response_rx.await.map_err(|_| {
+            McpError::internal_error("Event loop dropped response channel".to_string(), None)
+        })?;
+
+        match result {
+            Ok(info) => Ok(CallToolResult::success(vec![Content::text(format!(
+                "Output {} is now {}x{} @ {:.3} Hz, geometry {:?}",
+                info.name,
+                info.width,
+                info.height,
+                info.refresh as f64 / 1000.0,
+                info.geometry
+            ))])),
+            Err(e) => Ok(ToolError::new(ErrorKind::Other, e)
+                .context("Failed to set output mode")
+                .into_result()),
+        }
+    }
+
+    #[tool(description = "Puts text or a PNG image on the clipboard, so apps can paste it")]
+    async fn set_clipboard(
+        &self,
+        params: Parameters<SetSelectionRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        self.set_selection(SelectionTarget::Clipboard, params.0)
+            .await
+    }
+
+    #[tool(description = "Reads the clipboard, returning text as text and image/png as an image")]
+    async fn get_clipboard(
+        &self,
+        params: Parameters<GetSelectionRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        self.get_selection(SelectionTarget::Clipboard, params.0.mime_type)
+            .await
+    }
+
+    #[tool(
+        description = "Sets the primary selection (what middle-click pastes) to text or a PNG image"
+    )]
+    async fn set_primary_selection(
+        &self,
+        params: Parameters<SetSelectionRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        self.set_selection(SelectionTarget::Primary, params.0).await
+    }
+
+    #[tool(description = "Reads the primary selection, i.e. the most recently selected text")]
+    async fn get_primary_selection(
+        &self,
+        params: Parameters<GetSelectionRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        self.get_selection(SelectionTarget::Primary, params.0.mime_type)
+            .await
+    }
+
+    async fn set_selection(
+        &self,
+        target: SelectionTarget,
+        params: SetSelectionRequest,
+    ) -> Result<CallToolResult, McpError> {
+        let name = selection_name(target);
+        let (mime_types, data) =
+            match selection_payload(params.text.as_deref(), params.image_base64.as_deref()) {
+                Ok(payload) => payload,
+                Err(e) => return Err(McpError::invalid_params(e, None)),
+            };
+        let len = data.len();
+        let (response_tx, response_rx) = tokio::sync::oneshot::channel();
+
+        self.command_tx
+            .send(McpCommand::SetSelection {
+                target,
+                mime_types,
+                data,
+                response_tx,
+            })
+            .map_err(|e| {
+                McpError::internal_error(format!("Failed to send command: {}", e), None)
+            })?;
+
+        let result = response_rx.await.map_err(|_| {
+            McpError::internal_error("Event loop dropped response channel".to_string(), None)
+        })?;
+
+        match result {
+            Ok(()) => Ok(CallToolResult::success(vec![Content::text(format!(
+                "Set {} ({} bytes)",
+                name, len
+            ))])),
+            Err(e) => Ok(ToolError::new(ErrorKind::Other, e)
+                .context(format!("Failed to set {}", name))
+                .into_result()),
+        }
+    }
+
+    async fn get_selection(
+        &self,
+        target: SelectionTarget,
+        mime_type: Option<String>,
+    ) -> Result<CallToolResult, McpError> {
+        let name = selection_name(target);
+        let (response_tx, response_rx) = tokio::sync::oneshot::channel();
+
+        self.command_tx
+            .send(McpCommand::GetSelection {
+                target,
+                mime_type,
+                response_tx,
+            })
+            .map_err(|e| {
+                McpError::internal_error(format!("Failed to send command: {}", e), None)
+            })?;
+
+        // The owning client writes the data itself and may never do so
+        let result = match tokio::time::timeout(std::time::Duration::from_secs(5), response_rx)
+            .await
+        {
+            Ok(result) => result.map_err(|_| {
+                McpError::internal_error("Event loop dropped response channel".to_string(), None)
+            })?,
+            Err(_) => Err(format!("Timed out waiting for the {} owner", name)),
+        };
+
+        match result {
+            Ok(ClipboardData { mime_type, data }) if mime_type.starts_with("image/") => {
+                let encoded = base64::engine::general_purpose::STANDARD.encode(&data);
+                structured::success(
+                    vec![Content::image(encoded.clone(), mime_type.clone())],
+                    &SelectionContent {
+                        mime_type,
+                        size_bytes: data.len(),
+                        text: None,
+                        base64: Some(encoded),
+                    },
+                )
+            }
+            Ok(ClipboardData { mime_type, data }) => {
+                let size_bytes = data.len();
+                match String::from_utf8(data) {
+                    Ok(text) => structured::success(
+                        vec![Content::text(text.clone())],
+                        &SelectionContent {
+                            mime_type,
+                            size_bytes,
+                            text: Some(text),
+                            base64: None,
+                        },
+                    ),
+                    Err(e) => {
+                        let encoded =
+                            base64::engine::general_purpose::STANDARD.encode(e.as_bytes());
+                        structured::success(
+                            vec![Content::text(format!(
+                                "The {} {} data ({} bytes) is not UTF-8, base64: {}",
+                                name, mime_type, size_bytes, encoded
+                            ))],
+                            &SelectionContent {
+                                mime_type,
+                                size_bytes,
+                                text: None,
+                                base64: Some(encoded),
+                            },
+                        )
+                    }
+                }
+            }
+            Err(e) => Ok(ToolError::new(ErrorKind::Other, e)
+                .context(format!("Failed to read {}", name))
+                .into_result()),
+        }
+    }
+}
+
+fn selection_name(target: SelectionTarget) -> &'static str {
+    match target {
+        SelectionTarget::Clipboard => "clipboard",
+        SelectionTarget::Primary => "primary selection",
+    }
+}
+
+/// MIME types and bytes for a text or base64 PNG payload; exactly one must be given.
+fn selection_payload(
+    text: Option<&str>,
+    image_base64: Option<&str>,
+) -> Result<(Vec<String>, Vec<u8>), String> {
+    match (text, image_base64) {
+        (Some(text), None) => Ok((
+            clipboard::TEXT_MIME_TYPES
+                .iter()
+                .map(|mime_type| mime_type.to_string())
+                .collect(),
+            text.as_bytes().to_vec(),
+        )),
+        (None, Some(image)) => base64::engine::general_purpose::STANDARD
+            .decode(image)
+            .map(|data| (vec![clipboard::PNG_MIME_TYPE.to_string()], data))
+            .map_err(|e| format!("Invalid image_base64: {}", e)),
+        _ => Err("Specify exactly one of text or image_base64".to_string()),
+    }
+
+    #[tool(
+        description = "Drags text or an image from the compositor into a window: starts a drag-and-drop at the start point, carries it along the path and drops it at the end point, reporting what the target accepted"
+    )]
+    async fn dnd(&self, params: Parameters<DndRequest>) -> Result<CallToolResult, McpError> {
+        let actions = match params.0.action.as_deref().unwrap_or("copy") {
+            "copy" => DndAction::Copy,
+            "move" => DndAction::Move,
+            "ask" => DndAction::Ask,
+            other => {
+                return Err(McpError::invalid_params(
+                    format!("Unknown action '{}'. Use 'copy', 'move', or 'ask'.", other),
+                    None,
+                ));
+            }
+        };
+        let (mut mime_types, data) =
+            match selection_payload(params.0.text.as_deref(), params.0.image_base64.as_deref()) {
+                Ok(payload) => payload,
+                Err(e) => return Err(McpError::invalid_params(e, None)),
+            };
+        if let Some(custom) = params.0.mime_types.clone().filter(|m| !m.is_empty()) {
+            mime_types = custom;
+        }
+
+        let mut path = vec![(params.0.start_x, params.0.start_y)];
+        path.extend(
+            params
+                .0
+                .waypoints
+                .iter()
+                .flatten()
+                .map(|point| (point.x, point.y)),
+        );
+        path.push((params.0.end_x, params.0.end_y));
+        let duration = std::time::Duration::from_millis(params.0.duration_ms.unwrap_or(300));
+
+        let (response_tx, response_rx) = tokio::sync::oneshot::channel();
+        let (outcome_tx, outcome_rx) = tokio::sync::oneshot::channel();
+
+        self.command_tx
+            .send(McpCommand::Dnd {
+                path,
+                duration,
+                mime_types,
+                actions,
+                data,
+                response_tx,
+                outcome_tx,
+            })
+            .map_err(|e| {
+                McpError::internal_error(format!("Failed to send command: {}", e), None)
+            })?;
+
+        let result = response_rx.await.map_err(|_| {
+            McpError::internal_error("Event loop dropped response channel".to_string(), None)
+        })?;
+        let target = match result {
+            Ok(target) => target,
+            Err(e) => {
+                return Ok(ToolError::new(ErrorKind::Other, e)
+                    .context("Failed to drag and drop")
+                    .into_result());
+            }
+        };
+
+        // The target reads the data and finishes the drop at its own pace
+        let outcome = match tokio::time::timeout(std::time::Duration::from_secs(5), outcome_rx)
+            .await
+        {
+            Ok(outcome) => outcome.map_err(|_| {
+                McpError::internal_error("Event loop dropped response channel".to_string(), None)
+            })?,
+            Err(_) => {
+                return Ok(CallToolResult::success(vec![Content::text(format!(
+                    "Dropped at {}, but the target did not finish the drop within 5 seconds",
+                    target
+                ))]));
+            }
+        };
+
+        let accepted = outcome.accepted.as_deref().unwrap_or("nothing");
+        if outcome.completed {
+            Ok(CallToolResult::success(vec![Content::text(format!(
+                "Dropped at {}: target accepted {} with action {:?} and read [{}]",
+                target,
+                accepted,
+                outcome.action.unwrap_or(DndAction::empty()),
+                outcome.transferred.join(", ")
+            ))]))
+        } else {
+            Ok(CallToolResult::success(vec![Content::text(format!(
+                "Drop at {} was cancelled (target accepted {})",
+                target, accepted
+            ))]))
+        }
+    }
+
+    #[tool(
+        description = "Sends a signal (SIGTERM by default) to an application started by launch_app, e.g. SIGKILL to stop a hung app"
+    )]
+    async fn kill_app(
+        &self,
+        params: Parameters<KillAppRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        let pid = params.0.pid;
+        let signal_name = params.0.signal.as_deref().unwrap_or("TERM");
+        let Some(signal) = process::parse_signal(signal_name) else {
+            return Err(McpError::invalid_params(
+                format!("Unknown signal '{}'", signal_name),
+                None,
+            ));
+        };
+        let (response_tx, response_rx) = tokio::sync::oneshot::channel();
+
+        self.command_tx
+            .send(McpCommand::KillApp {
+                pid,
+                signal,
+                response_tx,
+            })
+            .map_err(|e| {
+                McpError::internal_error(format!("Failed to send command: {}", e), None)
+            })?;
+
+        let result = response_rx.await.map_err(|_| {
+            McpError::internal_error("Event loop dropped response channel".to_string(), None)
+        })?;
+
+        match result {
+            Ok(()) => structured::success(
+                vec![Content::text(format!(
+                    "Sent signal {} to process {}",
+                    signal_name, pid
+                ))],
+                &SignalResult {
+                    pid,
+                    signal: signal_name.to_string(),
+                },
+            ),
+            Err(e) => Ok(e
+                .context(format!("Failed to signal process {}", pid))
+                .into_result()),
+        }
+    }
+
+    #[tool(
+        description = "Lists apps started by launch_app as JSON (pid, command, args, running, exit_code, signal, windows), with the ids of the windows each one owns"
+    )]
+    async fn list_processes(
+        &self,
+        #[allow(unused_variables)] params: Parameters<ListProcessesRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        let (response_tx, response_rx) = tokio::sync::oneshot::channel();
+
+        self.command_tx
+            .send(McpCommand::ListProcesses { response_tx })
+            .map_err(|e| {
+                McpError::internal_error(format!("Failed to send command: {}", e), None)
+            })?;
+
+        let result = response_rx.await.map_err(|_| {
+            McpError::internal_error("Event loop dropped response channel".to_string(), None)
+        })?;
+
+        match result {
+            Ok(processes) => structured::json(&ProcessList { processes }),
+            Err(e) => Ok(CallToolResult::error(vec![Content::text(format!(
+                "Failed to list processes: {}",
+                e
+            ))])),
+        }
+    }
+
+    #[tool(
+        description = "Returns the most recent stdout/stderr output of an application started by launch_app"
+    )]
+    async fn get_app_output(
+        &self,
+        params: Parameters<GetAppOutputRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        let pid = params.0.pid;
+        let (response_tx, response_rx) = tokio::sync::oneshot::channel();
+
+        self.command_tx
+            .send(McpCommand::GetAppOutput {
+                pid,
+                tail_lines: params.0.tail_lines.unwrap_or(100),
+                response_tx,
+            })
+            .map_err(|e| {
+                McpError::internal_error(format!("Failed to send command: {}", e), None)
+            })?;
+
+        let result = response_rx.await.map_err(|_| {
+            McpError::internal_error("Event loop dropped response channel".to_string(), None)
+        })?;
+
+        match result {
+            Ok(output) => {
+                let text = if output.is_empty() {
+                    format!("Process {} has not written any output", pid)
+                } else {
+                    output.clone()
+                };
+                structured::success(vec![Content::text(text)], &AppOutput { pid, output })
+            }
+            Err(e) => Ok(e
+                .context(format!("Failed to get output of process {}", pid))
+                .into_result()),
+        }
+    }
+
+    #[tool(
+        description = "Waits until a window whose app_id and/or title match the given regexes is mapped, returning its id and geometry as JSON"
+    )]
+    async fn wait_for_window(
+        &self,
+        params: Parameters<WaitForWindowRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        if params.0.app_id.is_none() && params.0.title.is_none() {
+            return Err(McpError::invalid_params(
+                "Specify app_id, title or both",
+                None,
+            ));
+        }
+        let compile =
+            |pattern: &Option<String>| pattern.as_deref().map(regex::Regex::new).transpose();
+        let (app_id, title) = match (compile(&params.0.app_id), compile(&params.0.title)) {
+            (Ok(app_id), Ok(title)) => (app_id, title),
+            (Err(e), _) | (_, Err(e)) => {
+                return Err(McpError::invalid_params(
+                    format!("Invalid pattern: {}", e),
+                    None,
+                ));
+            }
+        };
+        let timeout = std::time::Duration::from_millis(params.0.timeout_ms.unwrap_or(10000));
+        let (response_tx, response_rx) = tokio::sync::oneshot::channel();
+
+        self.command_tx
+            .send(McpCommand::WaitForWindow {
+                wait: WindowWait {
+                    app_id,
+                    title,
+                    response_tx,
+                },
+                timeout,
+            })
+            .map_err(|e| {
+                McpError::internal_error(format!("Failed to send command: {}", e), None)
+            })?;
+
+        let result = progress::timed(response_rx, Some(timeout), "Waiting for a window")
+            .await
+            .map_err(|_| {
+                McpError::internal_error("Event loop dropped response channel".to_string(), None)
+            })?;
+
+        match result {
+            Ok(window) => structured::json(&window),
+            Err(e) => Ok(e.context("Failed to wait for window").into_result()),
+        }
+    }
+
+    #[tool(
+        description = "Waits until no client has committed and nothing has been redrawn for a quiet period, e.g. before taking a screenshot after an animation"
+    )]
+    async fn wait_for_idle(
+        &self,
+        params: Parameters<WaitForIdleRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        let quiet = std::time::Duration::from_millis(params.0.quiet_ms.unwrap_or(500));
+        let timeout = std::time::Duration::from_millis(params.0.timeout_ms.unwrap_or(10000));
+        let (response_tx, response_rx) = tokio::sync::oneshot::channel();
+
+        self.command_tx
+            .send(McpCommand::WaitForIdle {
+                quiet,
+                timeout,
+                response_tx,
+            })
+            .map_err(|e| {
+                McpError::internal_error(format!("Failed to send command: {}", e), None)
+            })?;
+
+        let result = progress::timed(response_rx, Some(timeout), "Waiting for idle")
+            .await
+            .map_err(|_| {
+                McpError::internal_error("Event loop dropped response channel".to_string(), None)
+            })?;
+
+        match result {
+            Ok(waited) => structured::success(
+                vec![Content::text(format!(
+                    "Idle for {} ms after waiting {} ms",
+                    quiet.as_millis(),
+                    waited.as_millis()
+                ))],
+                &IdleResult {
+                    quiet_ms: quiet.as_millis() as u64,
+                    waited_ms: waited.as_millis() as u64,
+                },
+            ),
+            Err(e) => Ok(e.context("Failed to wait for idle").into_result()),
+        }
+    }
+
+    #[tool(
+        description = "Returns a window's bounds, surface origin, xdg geometry, buffer size and scale as JSON, for computing exact click coordinates"
+    )]
+    async fn get_window_geometry(
+        &self,
+        params: Parameters<GetWindowGeometryRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        let (response_tx, response_rx) = tokio::sync::oneshot::channel();
+
+        self.command_tx
+            .send(McpCommand::GetWindowGeometry {
+                id: params.0.id,
+                response_tx,
+            })
+            .map_err(|e| {
+                McpError::internal_error(format!("Failed to send command: {}", e), None)
+            })?;
+
+        let result = response_rx.await.map_err(|_| {
+            McpError::internal_error("Event loop dropped response channel".to_string(), None)
+        })?;
+
+        match result {
+            Ok(geometry) => structured::json(&geometry),
+            Err(e) => Ok(e
+                .context(format!("Failed to get geometry of window {}", params.0.id))
+                .into_result()),
+        }
+    }
+
+    #[tool(
+        description = "Returns the pointer position and held buttons, the window and surface under the pointer, the keyboard focus, active modifiers and held keys as JSON"
+    )]
+    async fn get_input_state(
+        &self,
+        #[allow(unused_variables)] params: Parameters<GetInputStateRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        let (response_tx, response_rx) = tokio::sync::oneshot::channel();
+
+        self.command_tx
+            .send(McpCommand::GetInputState { response_tx })
+            .map_err(|e| {
+                McpError::internal_error(format!("Failed to send command: {}", e), None)
+            })?;
+
+        let result = response_rx.await.map_err(|_| {
+            McpError::internal_error("Event loop dropped response channel".to_string(), None)
+        })?;
+
+        match result {
+            Ok(state) => structured::json(&state),
+            Err(e) => Ok(CallToolResult::error(vec![Content::text(format!(
+                "Failed to get input state: {}",
+                e
+            ))])),
+        }
+    }
+
+    #[tool(
+        description = "Switches the keyboard to another XKB layout, so type_text and press_key produce the right characters for it (e.g. German umlauts or AZERTY)"
+    )]
+    async fn set_keyboard_layout(
+        &self,
+        params: Parameters<SetKeyboardLayoutRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        let layout = params.0.layout.clone();
+        let variant = params.0.variant.clone().unwrap_or_default();
+        let (response_tx, response_rx) = tokio::sync::oneshot::channel();
+
+        self.command_tx
+            .send(McpCommand::SetKeyboardLayout {
+                layout: layout.clone(),
+                variant: variant.clone(),
+                options: params.0.options.clone(),
+                response_tx,
+            })
+            .map_err(|e| {
+                McpError::internal_error(format!("Failed to send command: {}", e), None)
+            })?;
+
+        let result = response_rx.await.map_err(|_| {
+            McpError::internal_error("Event loop dropped response channel".to_string(), None)
+        })?;
+
+        match result {
+            Ok(()) if variant.is_empty() => Ok(CallToolResult::success(vec![Content::text(
+                format!("Keyboard layout set to {}", layout),
+            )])),
+            Ok(()) => Ok(CallToolResult::success(vec![Content::text(format!(
+                "Keyboard layout set to {} ({})",
+                layout, variant
+            ))])),
+            Err(e) => Ok(ToolError::new(ErrorKind::Other, e)
+                .context("Failed to set keyboard layout")
+                .into_result()),
+        }
+    }
+
+    #[tool(
+        description = "Taps the virtual touchscreen at the specified coordinates, optionally holding the finger down for a long press"
+    )]
+    async fn touch_tap(
+        &self,
+        params: Parameters<TouchTapRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        let point = (params.0.x, params.0.y);
+        let duration = std::time::Duration::from_millis(params.0.duration_ms.unwrap_or(50));
+
+        match self.send_touch(vec![point, point], duration).await? {
+            Ok(target) => Ok(CallToolResult::success(vec![Content::text(format!(
+                "Tapped at {}",
+                target
+            ))])),
+            Err(e) => Ok(ToolError::new(ErrorKind::Other, e)
+                .context("Failed to tap")
+                .into_result()),
+        }
+    }
+
+    #[tool(
+        description = "Swipes a finger across the virtual touchscreen from a start point to an end point, optionally via waypoints"
+    )]
+    async fn touch_swipe(
+        &self,
+        params: Parameters<TouchSwipeRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        let mut path = vec![(params.0.start_x, params.0.start_y)];
+        path.extend(
+            params
+                .0
+                .waypoints
+                .iter()
+                .flatten()
+                .map(|point| (point.x, point.y)),
+        );
+        path.push((params.0.end_x, params.0.end_y));
+        let duration = std::time::Duration::from_millis(params.0.duration_ms.unwrap_or(300));
+
+        match self.send_touch(path, duration).await? {
+            Ok(target) => Ok(CallToolResult::success(vec![Content::text(format!(
+                "Swiped from ({}, {}), lifted at {}",
+                params.0.start_x, params.0.start_y, target
+            ))])),
+            Err(e) => Ok(ToolError::new(ErrorKind::Other, e)
+                .context("Failed to swipe")
+                .into_result()),
+        }
+    }
+
+    async fn send_touch(
+        &self,
+        path: Vec<(f64, f64)>,
+        duration: std::time::Duration,
+    ) -> Result<Result<PointerTarget, String>, McpError> {
+        let (response_tx, response_rx) = tokio::sync::oneshot::channel();
+
+        self.command_tx
+            .send(McpCommand::Touch {
+                path,
+                duration,
+                response_tx,
+            })
+            .map_err(|e| {
+                McpError::internal_error(format!("Failed to send command: {}", e), None)
+            })?;
+
+        response_rx.await.map_err(|_| {
+            McpError::internal_error("Event loop dropped response channel".to_string(), None)
+        })
+    }
+
+    #[tool(
+        description = "Performs a touchpad swipe or pinch gesture (pointer-gestures protocol) at the specified coordinates"
+    )]
+    async fn gesture(
+        &self,
+        params: Parameters<GestureRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        let kind = match params.0.kind.as_str() {
+            "swipe" => gesture::GestureKind::Swipe {
+                fingers: params.0.fingers.unwrap_or(3),
+                delta: (params.0.dx.unwrap_or(0.0), params.0.dy.unwrap_or(0.0)).into(),
+            },
+            "pinch" => gesture::GestureKind::Pinch {
+                fingers: params.0.fingers.unwrap_or(2),
+                scale: params.0.scale.unwrap_or(1.0),
+                rotation: params.0.rotation.unwrap_or(0.0),
+            },
+            other => {
+                return Err(McpError::invalid_params(
+                    format!("Unknown gesture '{}'. Use 'swipe' or 'pinch'.", other),
+                    None,
+                ));
+            }
+        };
+        let duration = std::time::Duration::from_millis(params.0.duration_ms.unwrap_or(300));
+
+        let (response_tx, response_rx) = tokio::sync::oneshot::channel();
+
+        self.command_tx
+            .send(McpCommand::Gesture {
+                x: params.0.x,
+                y: params.0.y,
+                kind,
+                duration,
+                response_tx,
+            })
+            .map_err(|e| {
+                McpError::internal_error(format!("Failed to send command: {}", e), None)
+            })?;
+
+        let result = response_rx.await.map_err(|_| {
+            McpError::internal_error("Event loop dropped response channel".to_string(), None)
+        })?;
+
+        match result {
+            Ok(target) => Ok(CallToolResult::success(vec![Content::text(format!(
+                "Performed {} gesture at {}",
+                params.0.kind, target
+            ))])),
+            Err(e) => Ok(ToolError::new(ErrorKind::Other, e)
+                .context("Failed to perform gesture")
+                .into_result()),
+        }
+    }
+
+    #[tool(
+        description = "Draws a stroke with a virtual tablet pen through a list of points, with per-point pressure and tilt"
+    )]
+    async fn pen_stroke(
+        &self,
+        params: Parameters<PenStrokeRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        if params.0.points.is_empty() {
+            return Err(McpError::invalid_params(
+                "A pen stroke needs at least one point",
+                None,
+            ));
+        }
+
+        let points = params
+            .0
+            .points
+            .iter()
+            .map(|point| tablet::PenPoint {
+                location: (point.x, point.y).into(),
+                pressure: point.pressure.unwrap_or(0.5).clamp(0.0, 1.0),
+                tilt: (point.tilt_x.unwrap_or(0.0), point.tilt_y.unwrap_or(0.0)),
+            })
+            .collect();
+        let duration = std::time::Duration::from_millis(params.0.duration_ms.unwrap_or(300));
+
+        let (response_tx, response_rx) = tokio::sync::oneshot::channel();
+
+        self.command_tx
+            .send(McpCommand::PenStroke {
+                points,
+                duration,
+                response_tx,
+            })
+            .map_err(|e| {
+                McpError::internal_error(format!("Failed to send command: {}", e), None)
+            })?;
+
+        let result = response_rx.await.map_err(|_| {
+            McpError::internal_error("Event loop dropped response channel".to_string(), None)
+        })?;
+
+        match result {
+            Ok(target) => Ok(CallToolResult::success(vec![Content::text(format!(
+                "Drew a stroke of {} points, lifted at {}",
+                params.0.points.len(),
+                target
+            ))])),
+            Err(e) => Ok(ToolError::new(ErrorKind::Other, e)
+                .context("Failed to draw stroke")
+                .into_result()),
+        }
+    }
+
+    #[tool(
+        description = "Runs a sequence of pointer moves, clicks, key presses, typing and waits in one call, with exact delays between steps"
+    )]
+    async fn input_sequence(
+        &self,
+        params: Parameters<InputSequenceRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        let mut steps = Vec::with_capacity(params.0.steps.len());
+        for step in params.0.steps {
+            steps.push(match step {
+                InputStepArg::Move { x, y } => sequence::InputStep::Move((x, y).into()),
+                InputStepArg::Click {
+                    x,
+                    y,
+                    button,
+                    count,
+                } => {
+                    let button_name = button.as_deref().unwrap_or("left");
+                    let Some(button) = button_code(button_name) else {
+                        return Err(McpError::invalid_params(
+                            format!(
+                                "Unknown button '{}'. Use 'left', 'right', or 'middle'.",
+                                button_name
+                            ),
+                            None,
+                        ));
+                    };
+                    sequence::InputStep::Click {
+                        location: (x, y).into(),
+                        button,
+                        count: count.unwrap_or(1),
+                    }
+                }
+                InputStepArg::Key { keys } => sequence::InputStep::Key(keys),
+                InputStepArg::Type { text } => sequence::InputStep::Type(text),
+                InputStepArg::Wait { ms } => {
+                    sequence::InputStep::Wait(std::time::Duration::from_millis(ms))
+                }
+            });
+        }
+
+        let total = steps.len();
+        let (response_tx, response_rx) = tokio::sync::oneshot::channel();
+        let (progress_tx, progress_rx) = tokio::sync::mpsc::unbounded_channel();
+
+        self.command_tx
+            .send(McpCommand::InputSequence {
+                steps,
+                response_tx,
+                progress_tx,
+            })
+            .map_err(|e| {
+                McpError::internal_error(format!("Failed to send command: {}", e), None)
+            })?;
+
+        let result = progress::stepped(response_rx, progress_rx, total)
+            .await
+            .map_err(|_| {
+                McpError::internal_error("Event loop dropped response channel".to_string(), None)
+            })?;
+
+        match result {
+            Ok(count) => Ok(CallToolResult::success(vec![Content::text(format!(
+                "Ran {} steps",
+                count
+            ))])),
+            Err(e) => Ok(ToolError::new(ErrorKind::Other, e)
+                .context("Input sequence stopped")
+                .into_result()),
+        }
+    }
+
+    #[tool(
+        description = "Starts recording real and synthetic input events (pointer, buttons, keys, scrolling) with timestamps into a named session"
+    )]
+    async fn start_input_recording(
+        &self,
+        params: Parameters<StartInputRecordingRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        let (response_tx, response_rx) = tokio::sync::oneshot::channel();
+
+        self.command_tx
+            .send(McpCommand::StartInputRecording {
+                session: params.0.session.clone(),
+                response_tx,
+            })
+            .map_err(|e| {
+                McpError::internal_error(format!("Failed to send command: {}", e), None)
+            })?;
+
+        let result = response_rx.await.map_err(|_| {
+            McpError::internal_error("Event loop dropped response channel".to_string(), None)
+        })?;
+
+        match result {
+            Ok(()) => Ok(CallToolResult::success(vec![Content::text(format!(
+                "Recording input into session '{}'",
+                params.0.session
+            ))])),
+            Err(e) => Ok(ToolError::new(ErrorKind::Other, e)
+                .context("Failed to start input recording")
+                .into_result()),
+        }
+    }
+
+    #[tool(description = "Stops the input recording and saves it for replay_input")]
+    async fn stop_input_recording(
+        &self,
+        #[allow(unused_variables)] params: Parameters<StopInputRecordingRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        let (response_tx, response_rx) = tokio::sync::oneshot::channel();
+
+        self.command_tx
+            .send(McpCommand::StopInputRecording { response_tx })
+            .map_err(|e| {
+                McpError::internal_error(format!("Failed to send command: {}", e), None)
+            })?;
+
+        let result = response_rx.await.map_err(|_| {
+            McpError::internal_error("Event loop dropped response channel".to_string(), None)
+        })?;
+
+        match result {
+            Ok(summary) => Ok(CallToolResult::success(vec![Content::text(format!(
+                "Saved {} events over {:.1}s as session '{}'",
+                summary.events,
+                summary.duration.as_secs_f64(),
+                summary.session
+            ))])),
+            Err(e) => Ok(ToolError::new(ErrorKind::Other, e)
+                .context("Failed to stop input recording")
+                .into_result()),
+        }
+    }
+
+    #[tool(
+        description = "Replays a recorded input session with its original timing, optionally faster or slower"
+    )]
+    async fn replay_input(
+        &self,
+        params: Parameters<ReplayInputRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        let speed = params.0.speed.unwrap_or(1.0);
+        if !(speed > 0.0) {
+            return Err(McpError::invalid_params(
+                "Speed must be greater than 0",
+                None,
+            ));
+        }
+
+        let (response_tx, response_rx) = tokio::sync::oneshot::channel();
+
+        self.command_tx
+            .send(McpCommand::ReplayInput {
+                session: params.0.session.clone(),
+                speed,
+                response_tx,
+            })
+            .map_err(|e| {
+                McpError::internal_error(format!("Failed to send command: {}", e), None)
+            })?;
+
+        let result = progress::timed(response_rx, None, "Replaying input")
+            .await
+            .map_err(|_| {
+                McpError::internal_error("Event loop dropped response channel".to_string(), None)
+            })?;
+
+        match result {
+            Ok(count) => Ok(CallToolResult::success(vec![Content::text(format!(
+                "Replayed {} events from session '{}'",
+                count, params.0.session
+            ))])),
+            Err(e) => Ok(ToolError::new(ErrorKind::Other, e)
+                .context("Failed to replay input")
+                .into_result()),
+        }
+    }
+
+    #[tool(
+        description = "Changes an output's scale factor, e.g. 1.25, 1.5 or 2, to test how apps render on HiDPI screens. Clients are told the fractional scale through wp_fractional_scale and the rounded-up one through wl_output"
+    )]
+    async fn set_output_scale(
+        &self,
+        params: Parameters<SetOutputScaleRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        let SetOutputScaleRequest { output, scale } = params.0;
+        if !(scale::MIN_SCALE..=scale::MAX_SCALE).contains(&scale) {
+            return Err(McpError::invalid_params(
+                format!(
+                    "Scale must be between {} and {}",
+                    scale::MIN_SCALE,
+                    scale::MAX_SCALE
+                ),
+                None,
+            ));
+        }
+        let (response_tx, response_rx) = tokio::sync::oneshot::channel();
+
+        self.command_tx
+            .send(McpCommand::SetOutputScale {
+                output,
+                scale,
+                response_tx,
+            })
+            .map_err(|e| {
+                McpError::internal_error(format!("Failed to send command: {}", e), None)
+            })?;
+
+        let result = response_rx.await.map_err(|_| {
+            McpError::internal_error("Event loop dropped response channel".to_string(), None)
+        })?;
+
+        match result {
+            Ok(info) => Ok(CallToolResult::success(vec![Content::text(format!(
+                "Output {} is now at scale {}, geometry {:?}",
+                info.name, info.scale, info.geometry
+            ))])),
+            Err(e) => Ok(ToolError::new(ErrorKind::Other, e)
+                .context("Failed to set output scale")
+                .into_result()),
+        }
+    }
+
+    #[tool(
+        description = "Reports presentation statistics per output: frames presented, configured refresh rate, measured frame rate and recent frame intervals, as also sent to clients through wp_presentation"
+    )]
+    async fn get_frame_timing(
+        &self,
+        params: Parameters<GetFrameTimingRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        let (response_tx, response_rx) = tokio::sync::oneshot::channel();
+
+        self.command_tx
+            .send(McpCommand::GetFrameTiming {
+                output: params.0.output,
+                response_tx,
+            })
+            .map_err(|e| {
+                McpError::internal_error(format!("Failed to send command: {}", e), None)
+            })?;
+
+        let result = response_rx.await.map_err(|_| {
+            McpError::internal_error("Event loop dropped response channel".to_string(), None)
+        })?;
+
+        match result {
+            Ok(timing) => structured::json(&timing),
+            Err(e) => Ok(CallToolResult::error(vec![Content::text(format!(
+                "Failed to get frame timing: {}",
+                e
+            ))])),
+        }
+    }
+
+    #[tool(
+        description = "Sends relative mouse motion, as games and 3D apps read through zwp_relative_pointer_v1. While an app has locked the pointer it stays put and only the relative motion is delivered; a confined pointer stays inside its region"
+    )]
+    async fn send_relative_motion(
+        &self,
+        params: Parameters<SendRelativeMotionRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        let (response_tx, response_rx) = tokio::sync::oneshot::channel();
+
+        self.command_tx
+            .send(McpCommand::RelativeMotion {
+                delta: (params.0.dx, params.0.dy),
+                response_tx,
+            })
+            .map_err(|e| {
+                McpError::internal_error(format!("Failed to send command: {}", e), None)
+            })?;
+
+        let result = response_rx.await.map_err(|_| {
+            McpError::internal_error("Event loop dropped response channel".to_string(), None)
+        })?;
+
+        match result {
+            Ok(target) => Ok(CallToolResult::success(vec![Content::text(format!(
+                "Sent relative motion ({}, {}); pointer at {}",
+                params.0.dx, params.0.dy, target
+            ))])),
+            Err(e) => Ok(ToolError::new(ErrorKind::Other, e)
+                .context("Failed to send relative motion")
+                .into_result()),
+        }
+    }
+
+    #[tool(
+        description = "Starts streaming an output into a new PipeWire video node (needs the screencast feature). Returns the node id and the output's position and size as JSON; connect any PipeWire consumer to it"
+    )]
+    async fn start_screencast(
+        &self,
+        params: Parameters<StartScreencastRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        let (response_tx, response_rx) = tokio::sync::oneshot::channel();
+
+        self.command_tx
+            .send(McpCommand::StartScreencast {
+                output: params.0.output,
+                response_tx,
+            })
+            .map_err(|e| {
+                McpError::internal_error(format!("Failed to send command: {}", e), None)
+            })?;
+
+        let result = response_rx.await.map_err(|_| {
+            McpError::internal_error("Event loop dropped response channel".to_string(), None)
+        })?;
+
+        match result {
+            Ok(info) => {
+                let json = serde_json::to_string_pretty(&info).map_err(|e| {
+                    McpError::internal_error(format!("Failed to serialize screencast: {}", e), None)
+                })?;
+                Ok(CallToolResult::success(vec![Content::text(json)]))
+            }
+            Err(e) => Ok(ToolError::new(ErrorKind::CaptureFailed, e)
+                .context("Failed to start screencast")
+                .into_result()),
+        }
+    }
+
+    #[tool(
+        description = "Stops a screencast started with start_screencast or through the ScreenCast portal"
+    )]
+    async fn stop_screencast(
+        &self,
+        params: Parameters<StopScreencastRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        let (response_tx, response_rx) = tokio::sync::oneshot::channel();
+
+        self.command_tx
+            .send(McpCommand::StopScreencast {
+                node_id: params.0.node_id,
+                response_tx,
+            })
+            .map_err(|e| {
+                McpError::internal_error(format!("Failed to send command: {}", e), None)
+            })?;
+
+        let result = response_rx.await.map_err(|_| {
+            McpError::internal_error("Event loop dropped response channel".to_string(), None)
+        })?;
+
+        match result {
+            Ok(()) => Ok(CallToolResult::success(vec![Content::text(format!(
+                "Stopped screencast {}",
+                params.0.node_id
+            ))])),
+            Err(e) => Ok(ToolError::new(ErrorKind::CaptureFailed, e)
+                .context("Failed to stop screencast")
+                .into_result()),
+        }
+    }
+
+    #[tool(
+        description = "Returns a JSON snapshot of the whole compositor state: outputs, layer surfaces, windows in stacking order, popups, seat focus and pointer, tracked processes, pending grabs and requests, and frame timing. Useful for debugging a failed run"
+    )]
+    async fn dump_state(
+        &self,
+        #[allow(unused_variables)] params: Parameters<DumpStateRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        let (response_tx, response_rx) = tokio::sync::oneshot::channel();
+
+        self.command_tx
+            .send(McpCommand::DumpState { response_tx })
+            .map_err(|e| {
+                McpError::internal_error(format!("Failed to send command: {}", e), None)
+            })?;
+
+        let result = response_rx.await.map_err(|_| {
+            McpError::internal_error("Event loop dropped response channel".to_string(), None)
+        })?;
+
+        match result {
+            Ok(dump) => structured::json(&dump),
+            Err(e) => Ok(ToolError::new(ErrorKind::Other, e)
+                .context("Failed to dump state")
+                .into_result()),
+        }
+    }
+
+    #[tool(
+        description = "Recognizes text on screen with OCR (built with the ocr feature). Reads a window by id, a region of an output, or a whole output, and returns the text with a bounding box and confidence per line"
+    )]
+    async fn read_text(
+        &self,
+        params: Parameters<ReadTextRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        let region = match parse_region(params.0.x, params.0.y, params.0.width, params.0.height) {
+            Ok(region) => region,
+            Err(e) => return Ok(CallToolResult::error(vec![Content::text(e)])),
+        };
+        let (mode, origin) = match (params.0.window_id, region) {
+            (Some(_), Some(_)) => {
+                return Ok(CallToolResult::error(vec![Content::text(
+                    "Specify either window_id or a region, not both",
+                )]));
+            }
+            (Some(id), None) => (CaptureMode::Window(id), (0, 0)),
+            (None, Some(region)) => (CaptureMode::Region(region), (region.x, region.y)),
+            (None, None) => (CaptureMode::FullOutput, (0, 0)),
+        };
+        let options = ScreenshotOptions {
+            mode,
+            output: params.0.output.clone(),
+            ..Default::default()
+        };
+
+        let screenshot = match self.capture(options).await? {
+            Ok(screenshot) => screenshot,
+            Err(e) => return Ok(e.into_result()),
+        };
+        let language = params
+            .0
+            .language
+            .clone()
+            .unwrap_or_else(|| "eng".to_string());
+        let recognize = tokio::task::spawn_blocking(move || {
+            ocr::recognize(&screenshot.data, origin, &language)
+        });
+        let result = progress::timed(recognize, None, "Recognizing text")
+            .await
+            .map_err(|e| McpError::internal_error(format!("OCR task failed: {}", e), None))?;
+
+        match result {
+            Ok(result) => structured::json(&result),
+            Err(e) => Ok(CallToolResult::error(vec![Content::text(e)])),
+        }
+    }
+
+    #[tool(
+        description = "Captures the output, a region of it or a window and compares it pixel by pixel against a baseline image. Saves a diff image with differing pixels in red and returns pass/fail with the fraction of pixels that differ"
+    )]
+    async fn compare_screenshot(
+        &self,
+        params: Parameters<CompareScreenshotRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        let region = match parse_region(params.0.x, params.0.y, params.0.width, params.0.height) {
+            Ok(region) => region,
+            Err(e) => return Ok(CallToolResult::error(vec![Content::text(e)])),
+        };
+        let threshold = params.0.threshold.unwrap_or(0.0);
+        if !(0.0..=1.0).contains(&threshold) {
+            return Ok(CallToolResult::error(vec![Content::text(
+                "threshold must be between 0.0 and 1.0",
+            )]));
+        }
+        let mode = match (params.0.window_id, region) {
+            (Some(_), Some(_)) => {
+                return Ok(CallToolResult::error(vec![Content::text(
+                    "Specify either window_id or a region, not both",
+                )]));
+            }
+            (Some(id), None) => CaptureMode::Window(id),
+            (None, Some(region)) => CaptureMode::Region(region),
+            (None, None) => CaptureMode::FullOutput,
+        };
+        let options = ScreenshotOptions {
+            mode,
+            output: params.0.output.clone(),
+            ..Default::default()
+        };
+
+        let screenshot = match self.capture(options).await? {
+            Ok(screenshot) => screenshot,
+            Err(e) => return Ok(e.into_result()),
+        };
+        let baseline_path = params.0.baseline_path.clone();
+        let diff_path = params
+            .0
+            .diff_path
+            .clone()
+            .unwrap_or_else(|| compare::default_diff_path(&baseline_path));
+        let tolerance = params.0.tolerance.unwrap_or(8);
+        let result = tokio::task::spawn_blocking(move || {
+            compare::compare(
+                &screenshot.data,
+                &baseline_path,
+                &diff_path,
+                tolerance,
+                threshold,
+            )
+        })
+        .await
+        .map_err(|e| McpError::internal_error(format!("Comparison task failed: {}", e), None))?;
+
+        match result {
+            Ok(mut comparison) => {
+                comparison.diff_uri = Some(
+                    self.resources
+                        .lock()
+                        .unwrap()
+                        .add_file(&comparison.diff_path),
+                );
+                structured::json(&comparison)
+            }
+            Err(e) => Ok(CallToolResult::error(vec![Content::text(e)])),
+        }
+    }
+
+    #[tool(
+        description = "Waits until the output (or a region of it) stops changing visibly: resolves once downscaled consecutive frames have differed by at most a threshold for a stable period. Unlike wait_for_idle, it ignores commits that don't change what is shown, and a threshold lets small changes like a blinking caret through"
+    )]
+    async fn wait_for_visual_stability(
+        &self,
+        params: Parameters<WaitForVisualStabilityRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        let region = match parse_region(params.0.x, params.0.y, params.0.width, params.0.height) {
+            Ok(region) => region,
+            Err(e) => return Ok(CallToolResult::error(vec![Content::text(e)])),
+        };
+        let threshold = params.0.threshold.unwrap_or(0.0);
+        if !(0.0..=1.0).contains(&threshold) {
+            return Ok(CallToolResult::error(vec![Content::text(
+                "threshold must be between 0.0 and 1.0",
+            )]));
+        }
+        let stable_for = std::time::Duration::from_millis(params.0.stable_ms.unwrap_or(500));
+        let timeout = std::time::Duration::from_millis(params.0.timeout_ms.unwrap_or(10000));
+        let (response_tx, response_rx) = tokio::sync::oneshot::channel();
+
+        self.command_tx
+            .send(McpCommand::WaitForVisualStability {
+                output: params.0.output.clone(),
+                region,
+                threshold,
+                stable_for,
+                timeout,
+                response_tx,
+            })
+            .map_err(|e| {
+                McpError::internal_error(format!("Failed to send command: {}", e), None)
+            })?;
+
+        let result = progress::timed(response_rx, Some(timeout), "Waiting for stability")
+            .await
+            .map_err(|_| {
+                McpError::internal_error("Event loop dropped response channel".to_string(), None)
+            })?;
+
+        match result {
+            Ok(stability) => structured::success(
+                vec![Content::text(format!(
+                    "Stable for {} ms after waiting {} ms ({} frames compared)",
+                    stable_for.as_millis(),
+                    stability.waited.as_millis(),
+                    stability.frames
+                ))],
+                &StabilityResult {
+                    stable_ms: stable_for.as_millis() as u64,
+                    waited_ms: stability.waited.as_millis() as u64,
+                    frames: stability.frames,
+                },
+            ),
+            Err(e) => Ok(e
+                .context("Failed to wait for visual stability")
+                .into_result()),
+        }
+    }
+
+    #[tool(
+        description = "Reads the RGBA color of a pixel, or the average of a small square around it, from the rendered output. With expected, also reports whether the color matches within a tolerance; cheaper than a screenshot for checking an indicator"
+    )]
+    async fn get_pixel(
+        &self,
+        params: Parameters<GetPixelRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        let expected = match params.0.expected.as_deref().map(pixel::Color::from_hex) {
+            Some(Err(e)) => return Ok(CallToolResult::error(vec![Content::text(e)])),
+            Some(Ok(color)) => Some(color),
+            None => None,
+        };
+        let radius = params.0.radius.unwrap_or(0).min(64) as i32;
+        let region = Geometry {
+            x: params.0.x - radius,
+            y: params.0.y - radius,
+            width: radius * 2 + 1,
+            height: radius * 2 + 1,
+        };
+        let options = ScreenshotOptions {
+            mode: CaptureMode::Region(region),
+            output: params.0.output.clone(),
+            ..Default::default()
+        };
+
+        let color = match self.capture(options).await?.and_then(|s| {
+            pixel::average(&s.data).map_err(|e| ToolError::new(ErrorKind::CaptureFailed, e))
+        }) {
+            Ok(color) => color,
+            Err(e) => return Ok(e.into_result()),
+        };
+        let difference = expected.map(|expected| color.distance(expected));
+        let tolerance = params.0.tolerance.unwrap_or(0);
+        let probe = pixel::PixelProbe {
+            x: params.0.x,
+            y: params.0.y,
+            color,
+            hex: color.to_hex(),
+            matches: difference.map(|difference| difference <= tolerance),
+            difference,
+        };
+        structured::json(&probe)
+    }
+
+    #[tool(
+        description = "Locates a reference image on the output using normalized cross-correlation and returns the matches with their bounds, center and confidence, for clicking on apps without accessibility data"
+    )]
+    async fn find_image(
+        &self,
+        params: Parameters<FindImageRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        let template = match (&params.0.template_path, &params.0.template_png) {
+            (Some(path), None) => {
+                std::fs::read(path).map_err(|e| format!("Failed to read template {}: {}", path, e))
+            }
+            (None, Some(png)) => base64::engine::general_purpose::STANDARD
+                .decode(png)
+                .map_err(|e| format!("Invalid template_png: {}", e)),
+            _ => Err("Specify exactly one of template_path or template_png".to_string()),
+        };
+        let template = match template {
+            Ok(template) => template,
+            Err(e) => return Ok(CallToolResult::error(vec![Content::text(e)])),
+        };
+        let region = match parse_region(params.0.x, params.0.y, params.0.width, params.0.height) {
+            Ok(region) => region,
+            Err(e) => return Ok(CallToolResult::error(vec![Content::text(e)])),
+        };
+        let options = ScreenshotOptions {
+            mode: region.map_or(CaptureMode::FullOutput, CaptureMode::Region),
+            output: params.0.output.clone(),
+            ..Default::default()
+        };
+
+        let screenshot = match self.capture(options).await? {
+            Ok(screenshot) => screenshot,
+            Err(e) => return Ok(e.into_result()),
+        };
+        let origin = region.map_or((0, 0), |region| (region.x, region.y));
+        let confidence = params.0.confidence.unwrap_or(0.9);
+        let max_results = params.0.max_results.unwrap_or(1).max(1);
+        let result = tokio::task::spawn_blocking(move || {
+            matching::find(&screenshot.data, &template, confidence, max_results, origin)
+        })
+        .await
+        .map_err(|e| McpError::internal_error(format!("Image search failed: {}", e), None))?;
+
+        match result {
+            Ok(matches) if matches.is_empty() => structured::success(
+                vec![Content::text(format!(
+                    "No match with confidence {} or higher",
+                    confidence
+                ))],
+                &ImageMatches { matches },
+            ),
+            Ok(matches) => structured::json(&ImageMatches { matches }),
+            Err(e) => Ok(CallToolResult::error(vec![Content::text(e)])),
+        }
+    }
+
+    /// The window's owner, title and surface origin, for AT-SPI lookups.
+    async fn window_target(
+        &self,
+        id: u64,
+    ) -> Result<Result<accessibility::WindowTarget, String>, McpError> {
+        let (response_tx, response_rx) = tokio::sync::oneshot::channel();
+
+        self.command_tx
+            .send(McpCommand::ListWindows {
+                output: None,
+                response_tx,
+            })
+            .map_err(|e| {
+                McpError::internal_error(format!("Failed to send command: {}", e), None)
+            })?;
+
+        let windows = response_rx.await.map_err(|_| {
+            McpError::internal_error("Event loop dropped response channel".to_string(), None)
+        })?;
+
+        Ok(windows.and_then(|windows| {
+            let window = windows
+                .into_iter()
+                .find(|window| window.id == id)
+                .ok_or_else(|| format!("No window with id {}", id))?;
+            let pid = window
+                .pid
+                .ok_or_else(|| format!("The owner of window {} is unknown", id))?;
+            Ok(accessibility::WindowTarget {
+                pid,
+                title: window.title,
+                origin: window
+                    .geometry
+                    .map_or((0, 0), |geometry| (geometry.surface_x, geometry.surface_y)),
+            })
+        }))
+    }
+
+    #[tool(
+        description = "Returns a window's accessibility tree over AT-SPI as JSON (built with the accessibility feature): each element's id, role, name, states, actions and bounds in compositor coordinates. Lets GTK and Qt apps be driven by their widgets rather than pixels"
+    )]
+    async fn query_accessibility_tree(
+        &self,
+        params: Parameters<QueryAccessibilityTreeRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        let window = match self.window_target(params.0.window_id).await? {
+            Ok(window) => window,
+            Err(e) => return Ok(CallToolResult::error(vec![Content::text(e)])),
+        };
+        let max_depth = params.0.max_depth.unwrap_or(8);
+        let showing_only = !params.0.include_hidden.unwrap_or(false);
+
+        match accessibility::tree(&window, max_depth, showing_only).await {
+            Ok(tree) => structured::json(&tree),
+            Err(e) => Ok(CallToolResult::error(vec![Content::text(e)])),
+        }
+    }
+
+    #[tool(
+        description = "Finds an element in a window's accessibility tree by id, or by role and/or name, and activates it: performs its default AT-SPI action (e.g. press or toggle), or clicks the center of its bounds"
+    )]
+    async fn click_element(
+        &self,
+        params: Parameters<ClickElementRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        let force_click = match params.0.method.as_deref().unwrap_or("action") {
+            "action" => false,
+            "click" => true,
             other => {
-                return Ok(CallToolResult::success(vec![Content::text(format!(
-                    "Unknown button '{}'. Use 'left', 'right', or 'middle'.",
+                return Ok(CallToolResult::error(vec![Content::text(format!(
+                    "Unknown method '{}'. Use 'action' or 'click'.",
+                    other
+                ))]));
+            }
+        };
+        let name = match params.0.name.as_deref().map(regex::Regex::new).transpose() {
+            Ok(name) => name,
+            Err(e) => {
+                return Ok(CallToolResult::error(vec![Content::text(format!(
+                    "Invalid name pattern: {}",
+                    e
+                ))]));
+            }
+        };
+        if params.0.element_id.is_none() && params.0.role.is_none() && name.is_none() {
+            return Ok(CallToolResult::error(vec![Content::text(
+                "Specify element_id, role or name",
+            )]));
+        }
+        let query = accessibility::ElementQuery {
+            id: params.0.element_id.clone(),
+            role: params.0.role.clone(),
+            name,
+            index: params.0.index.unwrap_or(0),
+        };
+
+        let window = match self.window_target(params.0.window_id).await? {
+            Ok(window) => window,
+            Err(e) => return Ok(CallToolResult::error(vec![Content::text(e)])),
+        };
+        let element = match accessibility::find(&window, &query).await {
+            Ok(element) => element,
+            Err(e) => return Ok(CallToolResult::error(vec![Content::text(e)])),
+        };
+        let label = format!("{} '{}' ({})", element.role, element.name, element.id);
+
+        if !force_click {
+            match accessibility::activate(&element.id).await {
+                Ok(Some(action)) => {
+                    return Ok(CallToolResult::success(vec![Content::text(format!(
+                        "Performed '{}' on {}",
+                        action, label
+                    ))]));
+                }
+                Ok(None) => {}
+                Err(e) => return Ok(CallToolResult::error(vec![Content::text(e)])),
+            }
+        }
+
+        let Some(bounds) = element.bounds else {
+            return Ok(CallToolResult::error(vec![Content::text(format!(
+                "{} has no actions and no bounds to click",
+                label
+            ))]));
+        };
+        let x = bounds.x as f64 + bounds.width as f64 / 2.0;
+        let y = bounds.y as f64 + bounds.height as f64 / 2.0;
+        let (response_tx, response_rx) = tokio::sync::oneshot::channel();
+
+        self.command_tx
+            .send(McpCommand::MouseClick {
+                x,
+                y,
+                button: 0x110, // BTN_LEFT
+                count: 1,
+                response_tx,
+            })
+            .map_err(|e| {
+                McpError::internal_error(format!("Failed to send command: {}", e), None)
+            })?;
+
+        let result = response_rx.await.map_err(|_| {
+            McpError::internal_error("Event loop dropped response channel".to_string(), None)
+        })?;
+
+        match result {
+            Ok(_) => Ok(CallToolResult::success(vec![Content::text(format!(
+                "Clicked {} at ({}, {})",
+                label, x, y
+            ))])),
+            Err(e) => Ok(CallToolResult::error(vec![Content::text(format!(
+                "Failed to click {}: {}",
+                label, e
+            ))])),
+        }
+    }
+
+    #[tool(
+        description = "In --lockstep mode, renders the given number of frames, sending clients a frame callback for each, and returns once the last has been rendered. Between steps nothing is rendered and animations don't advance, so screenshots are deterministic"
+    )]
+    async fn advance_frames(
+        &self,
+        params: Parameters<AdvanceFramesRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        let count = params.0.count.unwrap_or(1);
+        if count == 0 {
+            return Ok(CallToolResult::error(vec![Content::text(
+                "count must be at least 1",
+            )]));
+        }
+        let (response_tx, response_rx) = tokio::sync::oneshot::channel();
+
+        self.command_tx
+            .send(McpCommand::AdvanceFrames { count, response_tx })
+            .map_err(|e| {
+                McpError::internal_error(format!("Failed to send command: {}", e), None)
+            })?;
+
+        let result = response_rx.await.map_err(|_| {
+            McpError::internal_error("Event loop dropped response channel".to_string(), None)
+        })?;
+
+        match result {
+            Ok(frame) => Ok(CallToolResult::success(vec![Content::text(format!(
+                "Advanced {} frame(s), now at frame {}",
+                count, frame
+            ))])),
+            Err(e) => Ok(CallToolResult::error(vec![Content::text(e)])),
+        }
+    }
+
+    #[tool(
+        description = "Limits how many frames per second the compositor renders (winit and headless backends), and optionally stops rendering while nothing changes, to save CPU"
+    )]
+    async fn set_frame_rate(
+        &self,
+        params: Parameters<SetFrameRateRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        let fps = match check_fps(params.0.fps) {
+            Ok(fps) => fps,
+            Err(e) => return Ok(CallToolResult::error(vec![Content::text(e)])),
+        };
+        let (response_tx, response_rx) = tokio::sync::oneshot::channel();
+
+        self.command_tx
+            .send(McpCommand::SetFrameRate {
+                fps,
+                idle_when_no_damage: params.0.idle_when_no_damage,
+                response_tx,
+            })
+            .map_err(|e| {
+                McpError::internal_error(format!("Failed to send command: {}", e), None)
+            })?;
+
+        let result = response_rx.await.map_err(|_| {
+            McpError::internal_error("Event loop dropped response channel".to_string(), None)
+        })?;
+
+        match result {
+            Ok(()) => Ok(CallToolResult::success(vec![Content::text(match fps {
+                Some(fps) => format!("Frame rate limited to {} fps", fps),
+                None => "Frame rate limit removed".to_string(),
+            })])),
+            Err(e) => Ok(CallToolResult::error(vec![Content::text(e)])),
+        }
+    }
+
+    #[tool(
+        description = "Hides the mouse cursor from captures that include it, whatever the client asked for, for pixel-perfect baselines. Pass hidden=false to show it again"
+    )]
+    async fn hide_cursor(
+        &self,
+        params: Parameters<HideCursorRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        let hidden = params.0.hidden.unwrap_or(true);
+        let (response_tx, response_rx) = tokio::sync::oneshot::channel();
+
+        self.command_tx
+            .send(McpCommand::HideCursor {
+                hidden,
+                response_tx,
+            })
+            .map_err(|e| {
+                McpError::internal_error(format!("Failed to send command: {}", e), None)
+            })?;
+
+        let result = response_rx.await.map_err(|_| {
+            McpError::internal_error("Event loop dropped response channel".to_string(), None)
+        })?;
+
+        match result {
+            Ok(()) => Ok(CallToolResult::success(vec![Content::text(if hidden {
+                "Cursor hidden"
+            } else {
+                "Cursor shown"
+            })])),
+            Err(e) => Ok(CallToolResult::error(vec![Content::text(e)])),
+        }
+    }
+
+    #[tool(
+        description = "Rotates or flips an output, e.g. to 90 or 270 for portrait-mode testing. Clients are told the transform through wl_output, screenshots come out the right way up, and windows filling the output are resized to its new shape"
+    )]
+    async fn set_output_transform(
+        &self,
+        params: Parameters<SetOutputTransformRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        let transform = match transform::parse_transform(&params.0.transform) {
+            Ok(transform) => transform,
+            Err(e) => return Ok(CallToolResult::error(vec![Content::text(e)])),
+        };
+        let (response_tx, response_rx) = tokio::sync::oneshot::channel();
+
+        self.command_tx
+            .send(McpCommand::SetOutputTransform {
+                output: params.0.output.clone(),
+                transform,
+                response_tx,
+            })
+            .map_err(|e| {
+                McpError::internal_error(format!("Failed to send command: {}", e), None)
+            })?;
+
+        let result = response_rx.await.map_err(|_| {
+            McpError::internal_error("Event loop dropped response channel".to_string(), None)
+        })?;
+
+        match result {
+            Ok(info) => Ok(CallToolResult::success(vec![Content::text(format!(
+                "Output {} is now transformed {}, geometry {:?}",
+                info.name, info.transform, info.geometry
+            ))])),
+            Err(e) => Ok(CallToolResult::error(vec![Content::text(e)])),
+        }
+    }
+
+    #[tool(
+        description = "Sets the background color and/or a wallpaper image behind all windows. A contrasting background makes window edges easy to find in screenshots"
+    )]
+    async fn set_background(
+        &self,
+        params: Parameters<SetBackgroundRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        if params.0.color.is_none() && params.0.image_path.is_none() {
+            return Ok(CallToolResult::error(vec![Content::text(
+                "Specify a color, an image_path or both",
+            )]));
+        }
+        let color = match params
+            .0
+            .color
+            .as_deref()
+            .map(pixel::Color::from_hex)
+            .transpose()
+        {
+            Ok(color) => color,
+            Err(e) => return Ok(CallToolResult::error(vec![Content::text(e)])),
+        };
+        let (response_tx, response_rx) = tokio::sync::oneshot::channel();
+
+        self.command_tx
+            .send(McpCommand::SetBackground {
+                color,
+                image_path: params.0.image_path.clone(),
+                response_tx,
+            })
+            .map_err(|e| {
+                McpError::internal_error(format!("Failed to send command: {}", e), None)
+            })?;
+
+        let result = response_rx.await.map_err(|_| {
+            McpError::internal_error("Event loop dropped response channel".to_string(), None)
+        })?;
+
+        match result {
+            Ok(()) => Ok(CallToolResult::success(vec![Content::text(
+                "Background updated",
+            )])),
+            Err(e) => Ok(CallToolResult::error(vec![Content::text(e)])),
+        }
+    }
+
+    #[tool(
+        description = "Kills an app started by launch_app (if it is still running) and launches it again with the same command, arguments, environment, working directory and options, optionally waiting for its first window. Returns the new pid"
+    )]
+    async fn restart_app(
+        &self,
+        params: Parameters<RestartAppRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        let pid = params.0.pid;
+        let wait_for_window = params
+            .0
+            .wait_for_window
+            .unwrap_or(false)
+            .then(|| std::time::Duration::from_millis(params.0.timeout_ms.unwrap_or(10000)));
+        let (response_tx, response_rx) = tokio::sync::oneshot::channel();
+
+        self.command_tx
+            .send(McpCommand::RestartApp {
+                pid,
+                wait_for_window,
+                response_tx,
+            })
+            .map_err(|e| {
+                McpError::internal_error(format!("Failed to send command: {}", e), None)
+            })?;
+
+        let result = response_rx.await.map_err(|_| {
+            McpError::internal_error("Event loop dropped response channel".to_string(), None)
+        })?;
+
+        match result {
+            Ok(LaunchedApp {
+                pid: new_pid,
+                window,
+            }) => {
+                let mut text = format!("Restarted pid {} as pid {}", pid, new_pid);
+                if let Some(window) = &window {
+                    text.push_str(&format!("; mapped window {}", window));
+                }
+                structured::success(
+                    vec![Content::text(text)],
+                    &RestartResult {
+                        previous_pid: pid,
+                        pid: new_pid,
+                        window,
+                    },
+                )
+            }
+            Err(e) => Ok(e
+                .context(format!("Failed to restart pid {}", pid))
+                .into_result()),
+        }
+    }
+
+    #[tool(
+        description = "Runs a short non-GUI shell command (e.g. gsettings, ls, rm) to completion and returns its exit status, stdout and stderr. The command is killed at the timeout. Use launch_app for GUI apps and anything long-running"
+    )]
+    async fn run_command(
+        &self,
+        params: Parameters<RunCommandRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        let options = RunOptions {
+            command: params.0.command.clone(),
+            env: params.0.env.clone().unwrap_or_default(),
+            cwd: params.0.cwd.clone(),
+            stdin: params.0.stdin.clone(),
+            timeout: std::time::Duration::from_millis(params.0.timeout_ms.unwrap_or(30000)),
+        };
+        let result = tokio::task::spawn_blocking(move || command::run(options))
+            .await
+            .map_err(|e| McpError::internal_error(format!("Command task failed: {}", e), None))?;
+
+        match result {
+            Ok(output) => {
+                let mut result = structured::json(&output)?;
+                // A failing command is an error, with its output all the same
+                if output.exit_code != Some(0) {
+                    result.is_error = Some(true);
+                }
+                Ok(result)
+            }
+            Err(e) => Ok(CallToolResult::error(vec![Content::text(e)])),
+        }
+    }
+
+    #[tool(
+        description = "Writes a file in the session directory, e.g. a document or image for an app to open. Launched apps find the directory in $MCPVIL_SESSION_DIR. Paths are relative to it and can't leave it. Returns the file's absolute path"
+    )]
+    async fn write_file(
+        &self,
+        params: Parameters<WriteFileRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        let params = params.0;
+        let data = match params.encoding.as_deref().unwrap_or("text") {
+            "text" => params.content.into_bytes(),
+            "base64" => match base64::engine::general_purpose::STANDARD.decode(&params.content) {
+                Ok(data) => data,
+                Err(e) => {
+                    return Ok(CallToolResult::error(vec![Content::text(format!(
+                        "Invalid base64 content: {}",
+                        e
+                    ))]))
+                }
+            },
+            other => {
+                return Ok(CallToolResult::error(vec![Content::text(format!(
+                    "Unknown encoding '{}', expected text or base64",
                     other
-                ))]));
+                ))]))
+            }
+        };
+        let session_dir = self.session_dir.clone();
+        let append = params.append.unwrap_or(false);
+        let path = params.path;
+        let len = data.len();
+        let result = tokio::task::spawn_blocking(move || session_dir.write(&path, &data, append))
+            .await
+            .map_err(|e| McpError::internal_error(format!("Write task failed: {}", e), None))?;
+
+        match result {
+            Ok(full) => Ok(CallToolResult::success(vec![Content::text(format!(
+                "Wrote {} bytes to {}",
+                len,
+                full.display()
+            ))])),
+            Err(e) => Ok(CallToolResult::error(vec![Content::text(e)])),
+        }
+    }
+
+    #[tool(
+        description = "Reads a file from the session directory, e.g. one an app under test saved there. Paths are relative to it and can't leave it"
+    )]
+    async fn read_file(
+        &self,
+        params: Parameters<ReadFileRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        let encoding = params
+            .0
+            .encoding
+            .clone()
+            .unwrap_or_else(|| String::from("text"));
+        if encoding != "text" && encoding != "base64" {
+            return Ok(CallToolResult::error(vec![Content::text(format!(
+                "Unknown encoding '{}', expected text or base64",
+                encoding
+            ))]));
+        }
+        let session_dir = self.session_dir.clone();
+        let path = params.0.path.clone();
+        let result = tokio::task::spawn_blocking(move || session_dir.read(&path))
+            .await
+            .map_err(|e| McpError::internal_error(format!("Read task failed: {}", e), None))?;
+
+        match result {
+            Ok(data) if encoding == "base64" => Ok(CallToolResult::success(vec![Content::text(
+                base64::engine::general_purpose::STANDARD.encode(&data),
+            )])),
+            Ok(data) => match String::from_utf8(data) {
+                Ok(text) => Ok(CallToolResult::success(vec![Content::text(text)])),
+                Err(_) => Ok(CallToolResult::error(vec![Content::text(format!(
+                    "{} is not UTF-8 text; read it with encoding base64",
+                    params.0.path
+                ))])),
+            },
+            Err(e) => Ok(CallToolResult::error(vec![Content::text(e)])),
+        }
+    }
+
+    #[tool(
+        description = "Launches an installed application by its desktop file id (e.g. org.gnome.TextEditor), running the Exec line of its .desktop entry or of one of its actions, optionally with files to open. Its windows are listed with the entry's name, Icon and StartupWMClass"
+    )]
+    async fn launch_desktop_app(
+        &self,
+        params: Parameters<LaunchDesktopAppRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        let params = params.0;
+        let files: Vec<String> = params
+            .files
+            .unwrap_or_default()
+            .into_iter()
+            .map(|file| {
+                let is_url = file.contains("://");
+                if is_url || std::path::Path::new(&file).is_absolute() {
+                    file
+                } else {
+                    self.session_dir
+                        .root()
+                        .join(file)
+                        .to_string_lossy()
+                        .into_owned()
+                }
+            })
+            .collect();
+        let entry = match DesktopEntry::find(&params.desktop_id) {
+            Ok(entry) if entry.terminal => {
+                return Ok(CallToolResult::error(vec![Content::text(format!(
+                    "{} runs in a terminal; launch a terminal emulator with launch_app instead",
+                    entry.id
+                ))]))
             }
+            Ok(entry) => entry,
+            Err(e) => return Ok(CallToolResult::error(vec![Content::text(e)])),
         };
+        let mut argv = match entry.command_line(params.action.as_deref(), &files) {
+            Ok(argv) => argv,
+            Err(e) => return Ok(CallToolResult::error(vec![Content::text(e)])),
+        };
+        // Entries exported by Flatpak run `flatpak run`, which needs to be let through to mcpvil
+        let env = params.env.unwrap_or_default();
+        if flatpak::is_flatpak_run(&argv) {
+            flatpak::add_run_options(&mut argv, &env, Some(self.session_dir.root()));
+        }
 
+        let command = argv.remove(0);
+        let options = LaunchOptions {
+            command: command.clone(),
+            args: argv.clone(),
+            env,
+            cwd: entry.working_dir.clone(),
+            desktop: Some(entry.info()),
+            ..Default::default()
+        };
+        let wait_for_window = params
+            .wait_for_window
+            .unwrap_or(false)
+            .then(|| std::time::Duration::from_millis(params.timeout_ms.unwrap_or(10000)));
         let (response_tx, response_rx) = tokio::sync::oneshot::channel();
 
         self.command_tx
-            .send(McpCommand::MouseClick {
-                x: params.0.x,
-                y: params.0.y,
-                button: button_code,
+            .send(McpCommand::LaunchApp {
+                options,
+                wait_for_window,
                 response_tx,
             })
             .map_err(|e| {
@@ -356,27 +5081,292 @@ impl MCPvilServer {
         })?;
 
         match result {
-            Ok(()) => Ok(CallToolResult::success(vec![Content::text(format!(
-                "Clicked {} at ({}, {})",
-                button_name, params.0.x, params.0.y
+            Ok(LaunchedApp { pid, window }) => {
+                let mut text = format!(
+                    "Launched {} from {} (pid {}) with args {:?}",
+                    command,
+                    entry.path.display(),
+                    pid,
+                    argv
+                );
+                if let Some(window) = &window {
+                    text.push_str(&format!("; mapped window {}", window));
+                }
+                structured::success(
+                    vec![Content::text(text)],
+                    &LaunchResult {
+                        pid,
+                        command,
+                        args: argv,
+                        window,
+                    },
+                )
+            }
+            Err(e) => Ok(e
+                .context(format!("Failed to launch {}", entry.id))
+                .into_result()),
+        }
+    }
+
+    #[tool(
+        description = "Lists applications installed on the host from the .desktop files in the XDG application directories, with the desktop ids launch_desktop_app takes, names, comments, categories and actions"
+    )]
+    async fn list_installed_apps(
+        &self,
+        params: Parameters<ListInstalledAppsRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        let include_hidden = params.0.include_hidden.unwrap_or(false);
+        let entries = tokio::task::spawn_blocking(move || desktop::installed_apps(include_hidden))
+            .await
+            .map_err(|e| McpError::internal_error(format!("Scan task failed: {}", e), None))?;
+
+        let query = params.0.query.as_deref().map(str::to_lowercase);
+        let category = params.0.category.as_deref();
+        let apps: Vec<_> = entries
+            .iter()
+            .map(DesktopEntry::installed_app)
+            .filter(|app| {
+                category.is_none_or(|category| {
+                    app.categories
+                        .iter()
+                        .any(|candidate| candidate.eq_ignore_ascii_case(category))
+                })
+            })
+            .filter(|app| {
+                query.as_deref().is_none_or(|query| {
+                    std::iter::once(&app.desktop_id)
+                        .chain(&app.name)
+                        .chain(&app.comment)
+                        .chain(&app.categories)
+                        .any(|text| text.to_lowercase().contains(query))
+                })
+            })
+            .collect();
+
+        structured::json(&InstalledAppList { apps })
+    }
+
+    #[tool(
+        description = "Launches a Flatpak application with flatpak run, letting it through to mcpvil's Wayland socket and the session directory. Installed Flatpaks are also listed by list_installed_apps and can be started with launch_desktop_app"
+    )]
+    async fn launch_flatpak(
+        &self,
+        params: Parameters<LaunchFlatpakRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        if !flatpak::available() {
+            return Ok(CallToolResult::error(vec![Content::text(
+                "flatpak is not installed".to_string(),
+            )]));
+        }
+        let params = params.0;
+        let env = params.env.unwrap_or_default();
+        let args = params.args.unwrap_or_default();
+        let mut argv = flatpak::run_command_line(&params.app_id, params.command.as_deref(), &args);
+        flatpak::add_run_options(&mut argv, &env, Some(self.session_dir.root()));
+
+        let command = argv.remove(0);
+        let options = LaunchOptions {
+            command: command.clone(),
+            args: argv.clone(),
+            env,
+            desktop: DesktopEntry::find(&params.app_id)
+                .ok()
+                .map(|entry| entry.info()),
+            ..Default::default()
+        };
+        let wait_for_window = params
+            .wait_for_window
+            .unwrap_or(false)
+            .then(|| std::time::Duration::from_millis(params.timeout_ms.unwrap_or(10000)));
+        let (response_tx, response_rx) = tokio::sync::oneshot::channel();
+
+        self.command_tx
+            .send(McpCommand::LaunchApp {
+                options,
+                wait_for_window,
+                response_tx,
+            })
+            .map_err(|e| {
+                McpError::internal_error(format!("Failed to send command: {}", e), None)
+            })?;
+
+        let result = response_rx.await.map_err(|_| {
+            McpError::internal_error("Event loop dropped response channel".to_string(), None)
+        })?;
+
+        match result {
+            Ok(LaunchedApp { pid, window }) => {
+                let mut text = format!("Launched {} (pid {})", params.app_id, pid);
+                if let Some(window) = &window {
+                    text.push_str(&format!("; mapped window {}", window));
+                }
+                structured::success(
+                    vec![Content::text(text)],
+                    &LaunchResult {
+                        pid,
+                        command,
+                        args: argv,
+                        window,
+                    },
+                )
+            }
+            Err(e) => Ok(e
+                .context(format!("Failed to launch {}", params.app_id))
+                .into_result()),
+        }
+    }
+
+    #[tool(
+        description = "Returns the desktop notifications apps have sent (id, app name, summary, body, icon, actions, urgency), oldest first, optionally waiting for one to appear. New notifications are also pushed from the notifications logger (notifications feature)"
+    )]
+    async fn get_notifications(
+        &self,
+        params: Parameters<GetNotificationsRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        if cfg!(not(feature = "notifications")) {
+            return Ok(CallToolResult::error(vec![Content::text(
+                "mcpvil was built without the notifications feature".to_string(),
+            )]));
+        }
+        let since_id = params.0.since_id.unwrap_or(0);
+        let include_closed = params.0.include_closed.unwrap_or(true);
+        let deadline = tokio::time::Instant::now()
+            + std::time::Duration::from_millis(params.0.wait_ms.unwrap_or(0));
+        let notifications = loop {
+            let notifications: Vec<_> = self
+                .notifications
+                .lock()
+                .unwrap()
+                .list(since_id, include_closed)
+                .into_iter()
+                .filter(|n| {
+                    params
+                        .0
+                        .app_name
+                        .as_deref()
+                        .is_none_or(|app_name| n.app_name.eq_ignore_ascii_case(app_name))
+                })
+                .collect();
+            if !notifications.is_empty() || tokio::time::Instant::now() >= deadline {
+                break notifications;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        };
+        if params.0.clear.unwrap_or(false) {
+            self.notifications.lock().unwrap().clear();
+        }
+
+        structured::json(&NotificationList { notifications })
+    }
+
+    #[tool(
+        description = "Invokes an action of a desktop notification as if the user had clicked it, e.g. \"default\" to click the notification or the key of one of its buttons, then dismisses it (notifications feature)"
+    )]
+    async fn invoke_notification_action(
+        &self,
+        params: Parameters<InvokeNotificationActionRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        let action = params.0.action.as_deref().unwrap_or("default");
+        match notifications::invoke_action(&self.notifications, params.0.id, action).await {
+            Ok(notification) => Ok(CallToolResult::success(vec![Content::text(format!(
+                "Invoked '{}' on notification {} ({}: {})",
+                action, notification.id, notification.app_name, notification.summary
             ))])),
-            Err(e) => Ok(CallToolResult::success(vec![Content::text(format!(
-                "Failed to click: {}",
+            Err(e) => Ok(CallToolResult::error(vec![Content::text(e)])),
+        }
+    }
+
+    #[tool(
+        description = "Answers the next file chooser dialog an app opens through the FileChooser portal (as Flatpak apps do), or the oldest one waiting, with the given paths or by cancelling it. Opened dialogs are reported as file_chooser_opened events from the portals logger (portal feature)"
+    )]
+    async fn stage_file_chooser_response(
+        &self,
+        params: Parameters<StageFileChooserResponseRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        if cfg!(not(feature = "portal")) {
+            return Ok(CallToolResult::error(vec![Content::text(
+                "mcpvil was built without the portal feature".to_string(),
+            )]));
+        }
+        let response = if params.0.cancel.unwrap_or(false) {
+            StagedResponse::Cancel
+        } else {
+            let paths = params.0.paths.clone().unwrap_or_default();
+            if paths.is_empty() {
+                return Ok(CallToolResult::error(vec![Content::text(
+                    "Give paths, or cancel: true".to_string(),
+                )]));
+            }
+            StagedResponse::Files(
+                paths
+                    .iter()
+                    .map(|path| self.session_dir.root().join(path))
+                    .collect(),
+            )
+        };
+
+        let mut queue = self.file_chooser.lock().unwrap();
+        let text = match queue.stage(response) {
+            Some(request) => format!(
+                "Answered the {} dialog \"{}\" of {}",
+                request.kind, request.title, request.app_id
+            ),
+            None => format!(
+                "Staged for the next file chooser dialog ({} staged)",
+                queue.staged()
+            ),
+        };
+        Ok(CallToolResult::success(vec![Content::text(text)]))
+    }
+
+    #[tool(
+        description = "Sets the keyboard repeat rate and delay announced to clients, which repeat held keys themselves. Returns the new settings as JSON"
+    )]
+    async fn set_key_repeat(
+        &self,
+        params: Parameters<SetKeyRepeatRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        let (response_tx, response_rx) = tokio::sync::oneshot::channel();
+
+        self.command_tx
+            .send(McpCommand::SetKeyRepeat {
+                rate: params.0.rate,
+                delay_ms: params.0.delay_ms,
+                response_tx,
+            })
+            .map_err(|e| {
+                McpError::internal_error(format!("Failed to send command: {}", e), None)
+            })?;
+
+        let result = response_rx.await.map_err(|_| {
+            McpError::internal_error("Event loop dropped response channel".to_string(), None)
+        })?;
+
+        match result {
+            Ok(repeat) => Ok(CallToolResult::success(vec![Content::text(
+                serde_json::to_string_pretty(&repeat).unwrap(),
+            )])),
+            Err(e) => Ok(CallToolResult::error(vec![Content::text(format!(
+                "Failed to set key repeat: {}",
                 e
             ))])),
         }
     }
 
-    #[tool(description = "Simulates a key press and release using a Linux evdev keycode")]
-    async fn key_press(
+    #[tool(
+        description = "Holds a key (with optional modifiers) down for a while, then releases it. Clients repeat it at the configured rate while it is held, as with a physical key, e.g. for scrolling with arrow keys or moving in games. Replies once the key is released"
+    )]
+    async fn hold_key(
         &self,
-        params: Parameters<KeyPressRequest>,
+        params: Parameters<HoldKeyRequest>,
     ) -> Result<CallToolResult, McpError> {
+        let duration_ms = params.0.duration_ms.unwrap_or(1000);
         let (response_tx, response_rx) = tokio::sync::oneshot::channel();
 
         self.command_tx
-            .send(McpCommand::KeyPress {
-                key: params.0.key,
+            .send(McpCommand::HoldKey {
+                keys: params.0.keys.clone(),
+                duration: std::time::Duration::from_millis(duration_ms),
                 response_tx,
             })
             .map_err(|e| {
@@ -388,119 +5378,683 @@ impl MCPvilServer {
         })?;
 
         match result {
-            Ok(()) => Ok(CallToolResult::success(vec![Content::text(format!(
-                "Key {} pressed and released",
-                params.0.key
+            Ok(repeats) => Ok(CallToolResult::success(vec![Content::text(format!(
+                "Held {} for {} ms; clients repeat it about {} times",
+                params.0.keys, duration_ms, repeats
+            ))])),
+            Err(e) => Ok(CallToolResult::error(vec![Content::text(format!(
+                "Failed to hold {}: {}",
+                params.0.keys, e
+            ))])),
+        }
+    }
+
+    #[tool(
+        description = "Returns recent compositor log records (id, timestamp, level, target, message) as JSON, oldest first, with the id of the newest to poll from. Use it to find compositor-side errors behind a failed tool call. Set a logging level to also receive new records live as compositor log notifications"
+    )]
+    async fn get_logs(
+        &self,
+        params: Parameters<GetLogsRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        let level = params.0.level.as_deref().unwrap_or("info");
+        let Some(max_severity) = logs::severity(level) else {
+            return Ok(CallToolResult::error(vec![Content::text(format!(
+                "Unknown level '{}', expected error, warn, info, debug or trace",
+                level
+            ))]));
+        };
+        let filter = params.0.filter.as_deref().map(regex::Regex::new);
+        let filter = match filter.transpose() {
+            Ok(filter) => filter,
+            Err(e) => {
+                return Ok(CallToolResult::error(vec![Content::text(format!(
+                    "Invalid filter: {}",
+                    e
+                ))]))
+            }
+        };
+
+        let (mut records, last_id) = {
+            let logs = self.logs.lock().unwrap();
+            let records = logs.list(params.0.since.unwrap_or(0), max_severity, |record| {
+                filter.as_ref().is_none_or(|filter| {
+                    filter.is_match(&record.target) || filter.is_match(&record.message)
+                })
+            });
+            (records, logs.last_id())
+        };
+        let limit = params.0.limit.unwrap_or(200);
+        if records.len() > limit {
+            records.drain(..records.len() - limit);
+        }
+
+        structured::json(&LogList { records, last_id })
+    }
+
+    #[tool(
+        description = "Turns Wayland protocol tracing on or off for a client, by window id or pid: every request and event with its arguments, like WAYLAND_DEBUG, is logged to the mcpvil://protocol-trace resource. Needs mcpvil to be started with --protocol-trace"
+    )]
+    async fn set_protocol_trace(
+        &self,
+        params: Parameters<SetProtocolTraceRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        let (response_tx, response_rx) = tokio::sync::oneshot::channel();
+
+        self.command_tx
+            .send(McpCommand::SetProtocolTrace {
+                pid: params.0.pid,
+                window_id: params.0.window_id,
+                enabled: params.0.enabled,
+                response_tx,
+            })
+            .map_err(|e| {
+                McpError::internal_error(format!("Failed to send command: {}", e), None)
+            })?;
+
+        let result = response_rx.await.map_err(|_| {
+            McpError::internal_error("Event loop dropped response channel".to_string(), None)
+        })?;
+
+        match result {
+            Ok(clients) => Ok(CallToolResult::success(vec![Content::text(format!(
+                "Protocol tracing {} for {}; read {} for the trace",
+                if params.0.enabled { "on" } else { "off" },
+                clients.join(", "),
+                PROTOCOL_TRACE_URI
             ))])),
-            Err(e) => Ok(CallToolResult::success(vec![Content::text(format!(
-                "Failed to press key: {}",
+            Err(e) => Ok(CallToolResult::error(vec![Content::text(format!(
+                "Failed to set protocol tracing: {}",
                 e
             ))])),
         }
     }
 
-    #[tool(description = "Scrolls at the specified coordinates in the given direction")]
-    async fn scroll(
+    #[tool(
+        description = "Reports compositor health metrics: uptime, connected clients, windows, frames rendered, average and percentile frame times, dropped frames and screenshot latency. Useful for catching performance regressions in CI"
+    )]
+    async fn get_stats(
+        &self,
+        #[allow(unused_variables)] params: Parameters<GetStatsRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        let (response_tx, response_rx) = tokio::sync::oneshot::channel();
+
+        self.command_tx
+            .send(McpCommand::GetStats { response_tx })
+            .map_err(|e| {
+                McpError::internal_error(format!("Failed to send command: {}", e), None)
+            })?;
+
+        let stats = response_rx.await.map_err(|_| {
+            McpError::internal_error("Event loop dropped response channel".to_string(), None)
+        })?;
+
+        structured::json(&stats)
+    }
+
+    #[tool(
+        description = "Returns the frames the primary output showed in the last few seconds, kept downscaled in memory like a flight recorder (needs --frame-history). Call it after a failure to see what led up to it"
+    )]
+    async fn get_recent_frames(
+        &self,
+        params: Parameters<GetRecentFramesRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        let max_frames = params.0.max_frames.unwrap_or(10).max(1) as usize;
+        let format = match params.0.format.as_deref() {
+            Some(name) => match ImageFormat::from_name(name) {
+                Ok(format) => format,
+                Err(e) => return Ok(CallToolResult::error(vec![Content::text(e)])),
+            },
+            None => ImageFormat::Jpeg,
+        };
+        let (response_tx, response_rx) = tokio::sync::oneshot::channel();
+
+        self.command_tx
+            .send(McpCommand::GetRecentFrames {
+                seconds: params.0.seconds,
+                max_frames,
+                response_tx,
+            })
+            .map_err(|e| {
+                McpError::internal_error(format!("Failed to send command: {}", e), None)
+            })?;
+
+        let result = response_rx.await.map_err(|_| {
+            McpError::internal_error("Event loop dropped response channel".to_string(), None)
+        })?;
+        let frames = match result {
+            Ok(frames) => frames,
+            Err(e) => return Ok(CallToolResult::error(vec![Content::text(e)])),
+        };
+        if frames.is_empty() {
+            return structured::success(
+                vec![Content::text("No frames in the requested period")],
+                &RecentFrameList { frames: Vec::new() },
+            );
+        }
+
+        let quality = params.0.quality;
+        let directory = params.0.directory.clone().map(std::path::PathBuf::from);
+        let result = tokio::task::spawn_blocking(move || {
+            frame_history::encode_frames(frames, format, quality, directory.as_deref())
+        })
+        .await
+        .map_err(|e| McpError::internal_error(format!("Encoding task failed: {}", e), None))?;
+        let frames = match result {
+            Ok(frames) => frames,
+            Err(e) => return Ok(CallToolResult::error(vec![Content::text(e)])),
+        };
+
+        let mut summary = format!("{} frames, oldest first:", frames.len());
+        let mut content = Vec::new();
+        let mut infos = Vec::new();
+        for frame in frames {
+            summary.push_str(&format!(
+                "\n{:.2}s ago (uptime {} ms), {}x{}",
+                frame.age.as_secs_f64(),
+                frame.uptime_ms,
+                frame.width,
+                frame.height
+            ));
+            match &frame.path {
+                Some(path) => summary.push_str(&format!(", saved to {}", path.display())),
+                None => content.push(Content::image(
+                    base64::engine::general_purpose::STANDARD.encode(&frame.data),
+                    format.mime_type(),
+                )),
+            }
+            infos.push(RecentFrameInfo {
+                uptime_ms: frame.uptime_ms,
+                age_secs: frame.age.as_secs_f64(),
+                width: frame.width,
+                height: frame.height,
+                path: frame.path.map(|path| path.to_string_lossy().into_owned()),
+            });
+        }
+        content.insert(0, Content::text(summary));
+        structured::success(content, &RecentFrameList { frames: infos })
+    }
+
+    #[tool(
+        description = "Captures every window in one call, as a single contact sheet with each window's id and title overlaid, or as a thumbnail per window. Windows are rendered on their own, so covered ones show in full. For surveying a busy session without a screenshot per window"
+    )]
+    async fn screenshot_all_windows(
+        &self,
+        params: Parameters<ScreenshotAllWindowsRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        let sheet = match params.0.layout.as_deref() {
+            None | Some("sheet") => true,
+            Some("thumbnails") => false,
+            Some(other) => {
+                return Ok(CallToolResult::error(vec![Content::text(format!(
+                    "Unknown layout '{}'. Use 'sheet' or 'thumbnails'.",
+                    other
+                ))]));
+            }
+        };
+        let format = match params.0.format.as_deref().map(ImageFormat::from_name) {
+            Some(Ok(format)) => format,
+            Some(Err(e)) => return Ok(CallToolResult::error(vec![Content::text(e)])),
+            None => ImageFormat::default(),
+        };
+        let thumbnail_size = params.0.thumbnail_size.unwrap_or(320).clamp(16, 4096);
+
+        let (response_tx, response_rx) = tokio::sync::oneshot::channel();
+        self.command_tx
+            .send(McpCommand::ListWindows {
+                output: params.0.output.clone(),
+                response_tx,
+            })
+            .map_err(|e| {
+                McpError::internal_error(format!("Failed to send command: {}", e), None)
+            })?;
+        let windows = match response_rx.await.map_err(|_| {
+            McpError::internal_error("Event loop dropped response channel".to_string(), None)
+        })? {
+            Ok(windows) if windows.is_empty() => {
+                return structured::success(
+                    vec![Content::text("No windows")],
+                    &WindowCaptures {
+                        windows: Vec::new(),
+                        uri: None,
+                        skipped: Vec::new(),
+                    },
+                );
+            }
+            Ok(windows) => windows,
+            Err(e) => return Ok(CallToolResult::error(vec![Content::text(e)])),
+        };
+
+        // Requested together, so every window is captured in the same frame
+        let mut captures = Vec::new();
+        for window in &windows {
+            let (response_tx, response_rx) = tokio::sync::oneshot::channel();
+            let options = ScreenshotOptions {
+                mode: CaptureMode::Window(window.id),
+                format: ImageFormat::Qoi,
+                ..Default::default()
+            };
+            self.command_tx
+                .send(McpCommand::Screenshot {
+                    options,
+                    response_tx,
+                })
+                .map_err(|e| {
+                    McpError::internal_error(format!("Failed to send command: {}", e), None)
+                })?;
+            captures.push(response_rx);
+        }
+        let mut tiles = Vec::new();
+        let mut skipped = Vec::new();
+        for (window, capture) in windows.iter().zip(captures) {
+            let screenshot = capture.await.map_err(|_| {
+                McpError::internal_error("Event loop dropped response channel".to_string(), None)
+            })?;
+            match screenshot {
+                Ok(screenshot) => tiles.push((window.clone(), screenshot.data)),
+                Err(e) => skipped.push(SkippedWindow {
+                    id: window.id,
+                    message: e.to_string(),
+                }),
+            }
+        }
+        let captured: Vec<WindowInfo> = tiles.iter().map(|(window, _)| window.clone()).collect();
+
+        let columns = params.0.columns;
+        let quality = params.0.quality;
+        let result = tokio::task::spawn_blocking(move || {
+            let tiles = tiles
+                .into_iter()
+                .map(|(window, data)| {
+                    let img =
+                        image::load_from_memory_with_format(&data, image::ImageFormat::Qoi)
+                            .map_err(|e| format!("Failed to decode window {}: {}", window.id, e))?;
+                    Ok((window, img.to_rgba8()))
+                })
+                .collect::<Result<Vec<_>, String>>()?;
+            if sheet {
+                let labeled: Vec<(String, image::RgbaImage)> = tiles
+                    .into_iter()
+                    .map(|(window, img)| (contact_sheet::label(&window), img))
+                    .collect();
+                let columns =
+                    columns.unwrap_or_else(|| (labeled.len() as f64).sqrt().ceil() as u32);
+                let img = contact_sheet::contact_sheet(&labeled, thumbnail_size, columns);
+                let data =
+                    screenshot::encode(&image::DynamicImage::ImageRgba8(img), format, quality)?;
+                Ok(vec![(None, data)])
+            } else {
+                tiles
+                    .into_iter()
+                    .map(|(window, img)| {
+                        let img = contact_sheet::thumbnail(&img, thumbnail_size);
+                        let data = screenshot::encode(
+                            &image::DynamicImage::ImageRgba8(img),
+                            format,
+                            quality,
+                        )?;
+                        Ok((Some(window), data))
+                    })
+                    .collect()
+            }
+        })
+        .await
+        .map_err(|e| McpError::internal_error(format!("Encoding task failed: {}", e), None))?;
+        let images = match result {
+            Ok(images) => images,
+            Err(e) => return Ok(CallToolResult::error(vec![Content::text(e)])),
+        };
+
+        let mut content = Vec::new();
+        let mut sheet_uri = None;
+        for (window, data) in images {
+            let encoded = base64::engine::general_purpose::STANDARD.encode(&data);
+            match window {
+                Some(window) => {
+                    content.push(Content::text(contact_sheet::label(&window)));
+                    content.push(Content::image(encoded, format.mime_type()));
+                }
+                None => {
+                    let uri = self.resources.lock().unwrap().add_bytes(
+                        format.extension(),
+                        format.mime_type(),
+                        data,
+                    );
+                    content.push(Content::image(encoded, format.mime_type()));
+                    content.push(Content::text(format!("Contact sheet available as {}", uri)));
+                    sheet_uri = Some(uri);
+                }
+            }
+        }
+        if !skipped.is_empty() {
+            let skipped: Vec<String> = skipped
+                .iter()
+                .map(|window| format!("#{}: {}", window.id, window.message))
+                .collect();
+            content.push(Content::text(format!(
+                "Not captured: {}",
+                skipped.join("; ")
+            )));
+        }
+        structured::success(
+            content,
+            &WindowCaptures {
+                windows: captured,
+                uri: sheet_uri,
+                skipped,
+            },
+        )
+    }
+}
+
+/// A recording path in the working directory, unique per second.
+fn default_recording_path(extension: &str) -> String {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    format!("recording-{}.{}", now, extension)
+}
+
+/// Checks a frame rate limit, returning None for 0, which means no limit.
+/// Limits under 0.1 are rejected, as their frame interval would be too long.
+fn check_fps(fps: f64) -> Result<Option<f64>, String> {
+    if fps == 0.0 {
+        Ok(None)
+    } else if (0.1..=1000.0).contains(&fps) {
+        Ok(Some(fps))
+    } else {
+        Err(format!(
+            "Invalid frame rate {}, expected 0 or 0.1-1000",
+            fps
+        ))
+    }
+}
+
+/// Maps a button name to its Linux evdev button code.
+fn button_code(name: &str) -> Option<u32> {
+    match name {
+        "left" => Some(0x110),   // BTN_LEFT
+        "right" => Some(0x111),  // BTN_RIGHT
+        "middle" => Some(0x112), // BTN_MIDDLE
+        _ => None,
+    }
+}
+
+impl ServerHandler for MCPvilServer {
+    fn get_info(&self) -> ServerInfo {
+        ServerInfo {
+            instructions: Some("A Wayland Compositor to launch and control applications in".into()),
+            capabilities: ServerCapabilities::builder()
+                .enable_logging()
+                .enable_prompts()
+                .enable_resources()
+                .enable_resources_subscribe()
+                .enable_tools()
+                .build(),
+            ..Default::default()
+        }
+    }
+
+    async fn on_initialized(&self, context: NotificationContext<RoleServer>) {
+        tracing::info!(client = %self.client_label(&context.peer), "MCP client connected");
+        self.subscribers.lock().unwrap().push(context.peer);
+    }
+
+    async fn list_tools(
+        &self,
+        _request: Option<PaginatedRequestParam>,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<ListToolsResult, McpError> {
+        Ok(ListToolsResult::with_all_items(
+            structured::add_output_schemas(self.tool_router.list_all()),
+        ))
+    }
+
+    async fn list_resources(
+        &self,
+        _request: Option<PaginatedRequestParam>,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<ListResourcesResult, McpError> {
+        let mut scene = RawResource::new(SCENE_URI, "scene");
+        scene.description = Some(
+            "Outputs, mapped windows in stacking order and keyboard focus as JSON".to_string(),
+        );
+        scene.mime_type = Some("application/json".to_string());
+
+        let mut logs = RawResource::new(LOGS_URI, "logs");
+        logs.description = Some("Recent compositor log output, one record per line".to_string());
+        logs.mime_type = Some("text/plain".to_string());
+
+        let mut resources = vec![scene.no_annotation(), logs.no_annotation()];
+        if self.protocol_trace.is_some() {
+            let mut trace = RawResource::new(PROTOCOL_TRACE_URI, "protocol-trace");
+            trace.description = Some(
+                "Wayland messages of clients traced with set_protocol_trace, one per line"
+                    .to_string(),
+            );
+            trace.mime_type = Some("text/plain".to_string());
+            resources.push(trace.no_annotation());
+        }
+        resources.extend(self.resources.lock().unwrap().list());
+        Ok(ListResourcesResult::with_all_items(resources))
+    }
+
+    async fn read_resource(
         &self,
-        params: Parameters<ScrollRequest>,
-    ) -> Result<CallToolResult, McpError> {
-        use smithay::backend::input::Axis;
+        request: ReadResourceRequestParam,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<ReadResourceResult, McpError> {
+        if request.uri == SCENE_URI {
+            return Ok(ReadResourceResult {
+                contents: vec![ResourceContents::TextResourceContents {
+                    uri: request.uri,
+                    mime_type: Some("application/json".to_string()),
+                    text: self.scene.borrow().clone(),
+                    meta: None,
+                }],
+            });
+        }
+        if let (PROTOCOL_TRACE_URI, Some(trace)) = (request.uri.as_str(), &self.protocol_trace) {
+            let text = trace.lock().unwrap().text();
+            return Ok(ReadResourceResult {
+                contents: vec![ResourceContents::TextResourceContents {
+                    uri: request.uri,
+                    mime_type: Some("text/plain".to_string()),
+                    text,
+                    meta: None,
+                }],
+            });
+        }
+        if request.uri == LOGS_URI {
+            return Ok(ReadResourceResult {
+                contents: vec![ResourceContents::TextResourceContents {
+                    uri: request.uri,
+                    mime_type: Some("text/plain".to_string()),
+                    text: self.logs.lock().unwrap().text(),
+                    meta: None,
+                }],
+            });
+        }
+        let contents = self
+            .resources
+            .lock()
+            .unwrap()
+            .read(&request.uri)
+            .map_err(|e| McpError::resource_not_found(e, None))?;
+        Ok(ReadResourceResult {
+            contents: vec![contents],
+        })
+    }
 
-        let (axis, amount) = match params.0.direction.as_str() {
-            "up" => (Axis::Vertical, -(params.0.amount.unwrap_or(15.0))),
-            "down" => (Axis::Vertical, params.0.amount.unwrap_or(15.0)),
-            "left" => (Axis::Horizontal, -(params.0.amount.unwrap_or(15.0))),
-            "right" => (Axis::Horizontal, params.0.amount.unwrap_or(15.0)),
-            other => {
-                return Ok(CallToolResult::success(vec![Content::text(format!(
-                    "Unknown direction '{}'. Use 'up', 'down', 'left', or 'right'.",
-                    other
-                ))]));
-            }
-        };
+    async fn list_prompts(
+        &self,
+        _request: Option<PaginatedRequestParam>,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<ListPromptsResult, McpError> {
+        Ok(ListPromptsResult::with_all_items(prompts::list()))
+    }
 
-        let (response_tx, response_rx) = tokio::sync::oneshot::channel();
+    async fn get_prompt(
+        &self,
+        request: GetPromptRequestParam,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<GetPromptResult, McpError> {
+        prompts::get(&request.name, request.arguments.as_ref())
+            .map_err(|e| McpError::invalid_params(e, None))
+    }
 
-        self.command_tx
-            .send(McpCommand::Scroll {
-                x: params.0.x,
-                y: params.0.y,
-                axis,
-                amount,
-                response_tx,
-            })
-            .map_err(|e| {
-                McpError::internal_error(format!("Failed to send command: {}", e), None)
-            })?;
+    /// Streams compositor log records at `level` and above to this client as
+    /// `compositor` log notifications.
+    async fn set_level(
+        &self,
+        request: SetLevelRequestParam,
+        context: RequestContext<RoleServer>,
+    ) -> Result<(), McpError> {
+        let severity = logs::logging_level_severity(request.level);
+        self.log_subscribers
+            .lock()
+            .unwrap()
+            .insert(self.client_id.clone(), (context.peer, severity));
+        Ok(())
+    }
 
-        let result = response_rx.await.map_err(|_| {
-            McpError::internal_error("Event loop dropped response channel".to_string(), None)
-        })?;
+    /// Only the scene can be subscribed to; captures never change.
+    async fn subscribe(
+        &self,
+        request: SubscribeRequestParam,
+        context: RequestContext<RoleServer>,
+    ) -> Result<(), McpError> {
+        if request.uri != SCENE_URI {
+            return Err(McpError::invalid_params(
+                format!("Resource {} does not send updates", request.uri),
+                None,
+            ));
+        }
+        self.scene_subscribers
+            .lock()
+            .unwrap()
+            .insert(self.client_id.clone(), context.peer);
+        Ok(())
+    }
 
-        match result {
-            Ok(()) => Ok(CallToolResult::success(vec![Content::text(format!(
-                "Scrolled {} at ({}, {})",
-                params.0.direction, params.0.x, params.0.y
-            ))])),
-            Err(e) => Ok(CallToolResult::success(vec![Content::text(format!(
-                "Failed to scroll: {}",
-                e
-            ))])),
+    async fn unsubscribe(
+        &self,
+        request: UnsubscribeRequestParam,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<(), McpError> {
+        if request.uri == SCENE_URI {
+            self.scene_subscribers
+                .lock()
+                .unwrap()
+                .remove(&self.client_id);
         }
+        Ok(())
     }
 
-    #[tool(description = "Captures a screenshot of the compositor output and returns it as a base64-encoded PNG image")]
-    async fn capture_screenshot(
+    /// Dispatches a tool call, recording which client made it in the audit log
+    /// (the `audit` tracing target) and how long it took in the metrics.
+    async fn call_tool(
         &self,
-        #[allow(unused_variables)] params: Parameters<CaptureScreenshotRequest>,
+        request: CallToolRequestParam,
+        context: RequestContext<RoleServer>,
     ) -> Result<CallToolResult, McpError> {
-        let (response_tx, response_rx) = tokio::sync::oneshot::channel();
-
-        self.command_tx
-            .send(McpCommand::CaptureScreenshot { response_tx })
-            .map_err(|e| {
-                McpError::internal_error(format!("Failed to send command: {}", e), None)
-            })?;
-
-        let result = response_rx.await.map_err(|_| {
-            McpError::internal_error("Event loop dropped response channel".to_string(), None)
-        })?;
+        let client = self.client_label(&context.peer);
+        let arguments = request
+            .arguments
+            .as_ref()
+            .map(|arguments| serde_json::Value::Object(arguments.clone()))
+            .unwrap_or_default();
+        tracing::info!(target: "audit", %client, tool = %request.name, %arguments, "Tool call");
 
-        match result {
-            Ok((base64_data, width, height)) => Ok(CallToolResult::success(vec![
-                Content::image(base64_data, "image/png"),
-                Content::text(format!("Screenshot captured ({}x{})", width, height)),
-            ])),
-            Err(e) => Ok(CallToolResult::success(vec![Content::text(format!(
-                "Failed to capture screenshot: {}",
-                e
-            ))])),
+        let tool = request.name.clone();
+        let span = telemetry::tool_call_span(&tool, &client, &context.meta);
+        let start = std::time::Instant::now();
+        let timeout = self
+            .command_timeout
+            .saturating_add(cancel::requested_time(&arguments));
+        let cancelled = context.ct.clone();
+        let call = ToolCall::default();
+        let progress = Progress::new(&context.meta, &context.peer);
+        let tool_call = self
+            .tool_router
+            .call(ToolCallContext::new(self, request, context));
+        let result = tokio::select! {
+            result = call
+                .clone()
+                .scope(Progress::scope(progress, tool_call))
+                .instrument(span) => result,
+            _ = cancelled.cancelled() => {
+                call.abandon();
+                Err(McpError::internal_error("Request cancelled".to_string(), None))
+            }
+            _ = tokio::time::sleep(timeout) => {
+                call.abandon();
+                let message = format!("No response from the compositor within {:?}", timeout);
+                Ok(ToolError::new(ErrorKind::Timeout, message).into_result())
+            }
+        };
+        if let Err(e) = &result {
+            tracing::warn!(target: "audit", %client, %tool, "Tool call failed: {}", e.message);
         }
+        let failed = match &result {
+            Ok(result) => result.is_error == Some(true),
+            Err(_) => true,
+        };
+        self.tool_metrics
+            .lock()
+            .unwrap()
+            .record(&tool, start.elapsed(), failed);
+        result
     }
 }
 
-#[tool_handler]
-impl ServerHandler for MCPvilServer {
-    fn get_info(&self) -> ServerInfo {
-        ServerInfo {
-            instructions: Some("A Wayland Compositor to launch and control applications in".into()),
-            capabilities: ServerCapabilities::builder().enable_tools().build(),
-            ..Default::default()
-        }
-    }
+/// A way MCP clients connect; several can be served at once.
+enum Transport {
+    Stdio,
+    /// Streamable HTTP, with responses and notifications streamed as SSE
+    Http(std::net::SocketAddr),
+}
+
+/// Serves MCP over HTTP at /mcp, with a session per client, to requests
+/// carrying `token`.
+async fn serve_http(
+    service: MCPvilServer,
+    address: std::net::SocketAddr,
+    token: std::sync::Arc<str>,
+) -> std::io::Result<()> {
+    let next_client = std::sync::atomic::AtomicU64::new(1);
+    let service = StreamableHttpService::new(
+        move || {
+            let id = next_client.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+            Ok(service.for_client(format!("http-{}", id)))
+        },
+        LocalSessionManager::default().into(),
+        StreamableHttpServerConfig::default(),
+    );
+    let router = axum::Router::new().nest_service("/mcp", service).layer(
+        axum::middleware::from_fn_with_state(token, http::check_request),
+    );
+    let listener = tokio::net::TcpListener::bind(address).await?;
+    tracing::info!("Serving MCP on http://{}/mcp", address);
+    axum::serve(listener, router).await
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    if let Ok(env_filter) = tracing_subscriber::EnvFilter::try_from_default_env() {
-        tracing_subscriber::fmt()
-            .with_writer(std::io::stderr)
-            .with_env_filter(env_filter)
-            .init();
-    } else {
-        tracing_subscriber::fmt()
-            .with_writer(std::io::stderr)
-            .init();
-    }
+    // Before the OTLP exporter starts its threads, which would otherwise take
+    // SIGCHLD from the reaper
+    process::block_sigchld();
+
+    // Logged to stderr and kept for get_logs and the log resource
+    let logs = Logs::default();
+    let (log_tx, log_rx) = tokio::sync::mpsc::unbounded_channel();
+    let env_filter = tracing_subscriber::EnvFilter::try_from_default_env()
+        .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new("info"));
+    tracing_subscriber::registry()
+        .with(env_filter)
+        .with(tracing_subscriber::fmt::layer().with_writer(std::io::stderr))
+        .with(LogLayer::new(logs.clone(), log_tx))
+        .with(telemetry::layer())
+        .init();
 
     let mut event_loop: EventLoop<CalloopData> = EventLoop::try_new()?;
 
@@ -513,257 +6067,959 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         display_handle,
     };
 
-    crate::winit::init_winit(&mut event_loop, &mut data)?;
+    let mut args = std::env::args().skip(1).peekable();
+    let mut backend = String::from("winit");
+    let mut output_sizes = Vec::new();
+    let mut keymap = None;
+    let mut renderer = RendererKind::Auto;
+    let mut transport = String::from("stdio");
+    let mut listen = String::from("127.0.0.1:8080");
+    let mut token = std::env::var("MCPVIL_TOKEN").ok();
+    // Launched apps must not see it
+    std::env::remove_var("MCPVIL_TOKEN");
+    let mut metrics_address = None;
+    let mut session_dir = None;
+    let mut host_session_bus = false;
+    let mut keybindings = None;
+    let mut command_timeout = cancel::DEFAULT_COMMAND_TIMEOUT;
+    let mut allow_run_command = false;
+    loop {
+        match args.peek().map(String::as_str) {
+            Some("--headless") => backend = String::from("headless"),
+            Some("--backend") => {
+                args.next();
+                backend = args.peek().cloned().unwrap_or_default();
+            }
+            Some("--server-decorations") => data.state.server_decorations = true,
+            Some("--lockstep") => data.state.lockstep = Some(Lockstep::default()),
+            Some("--fps") => {
+                args.next();
+                let spec = args.peek().cloned().unwrap_or_default();
+                let fps = spec
+                    .parse()
+                    .map_err(|_| format!("Invalid frame rate '{}'", spec))?;
+                data.state.frame_pacing.fps = check_fps(fps)?;
+            }
+            Some("--frame-history") => {
+                args.next();
+                let spec = args.peek().cloned().unwrap_or_default();
+                let seconds = spec
+                    .parse()
+                    .map_err(|_| format!("Invalid frame history length '{}'", spec))?;
+                data.state.frame_history = Some(FrameHistory::new(seconds)?);
+            }
+            Some("--idle-when-no-damage") => data.state.frame_pacing.idle_when_no_damage = true,
+            Some("--background") => {
+                args.next();
+                let spec = args.peek().cloned().unwrap_or_default();
+                match pixel::Color::from_hex(&spec) {
+                    Ok(color) => data.state.background.set_color(color),
+                    Err(_) => data.state.background.set_wallpaper(&spec)?,
+                }
+            }
+            Some("--cursor-theme") => {
+                args.next();
+                let theme = args.peek().cloned().unwrap_or_default();
+                data.state.cursor_theme.configure(Some(theme), None);
+            }
+            Some("--cursor-size") => {
+                args.next();
+                let spec = args.peek().cloned().unwrap_or_default();
+                let size = spec
+                    .parse()
+                    .ok()
+                    .filter(|&size| size > 0)
+                    .ok_or_else(|| format!("Invalid cursor size '{}'", spec))?;
+                data.state.cursor_theme.configure(None, Some(size));
+            }
+            Some("--output") => {
+                args.next();
+                let spec = args.peek().cloned().unwrap_or_default();
+                output_sizes.push(render::parse_output_size(&spec)?);
+            }
+            Some("--keymap") => {
+                args.next();
+                keymap = args.peek().cloned();
+            }
+            Some("--key-repeat") => {
+                args.next();
+                let spec = args.peek().cloned().unwrap_or_default();
+                let repeat = keymap::parse_key_repeat_spec(&spec)?;
+                data.state.set_key_repeat(repeat);
+            }
+            Some("--keybindings") => {
+                args.next();
+                keybindings = args.peek().map(std::path::PathBuf::from);
+            }
+            Some("--renderer") => {
+                args.next();
+                renderer = render::parse_renderer(args.peek().map_or("", String::as_str))?;
+            }
+            Some("--transport") => {
+                args.next();
+                transport = args.peek().cloned().unwrap_or_default();
+            }
+            Some("--listen") => {
+                args.next();
+                listen = args.peek().cloned().unwrap_or_default();
+            }
+            Some("--token") => {
+                args.next();
+                token = args.peek().cloned();
+            }
+            Some("--metrics") => {
+                args.next();
+                let spec = args.peek().cloned().unwrap_or_default();
+                let address: std::net::SocketAddr = spec
+                    .parse()
+                    .map_err(|e| format!("Invalid --metrics address '{}': {}", spec, e))?;
+                metrics_address = Some(address);
+            }
+            Some("--host-session-bus") => host_session_bus = true,
+            Some("--allow-run-command") => allow_run_command = true,
+            Some("--protocol-trace") => {
+                data.state.protocol_trace = Some(ProtocolTrace::default());
+            }
+            Some("--session-dir") => {
+                args.next();
+                session_dir = args.peek().cloned();
+            }
+            Some("--command-timeout") => {
+                args.next();
+                let spec = args.peek().cloned().unwrap_or_default();
+                command_timeout = spec
+                    .parse()
+                    .ok()
+                    .and_then(|secs| std::time::Duration::try_from_secs_f64(secs).ok())
+                    .filter(|timeout| !timeout.is_zero())
+                    .ok_or_else(|| format!("Invalid command timeout '{}'", spec))?;
+            }
+            _ => break,
+        }
+        args.next();
+    }
+
+    let transports = transport
+        .split(',')
+        .map(|name| match name {
+            "stdio" => Ok(Transport::Stdio),
+            "http" => listen
+                .parse()
+                .map(Transport::Http)
+                .map_err(|e| format!("Invalid --listen address '{}': {}", listen, e)),
+            other => Err(format!(
+                "Unknown transport '{}', expected stdio, http or both",
+                other
+            )),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let serves_http = transports
+        .iter()
+        .any(|transport| matches!(transport, Transport::Http(_)));
+    let token: std::sync::Arc<str> = match token {
+        Some(token) if !token.is_empty() => token.into(),
+        Some(_) => return Err("The HTTP token must not be empty".into()),
+        None => {
+            let token = http::generate_token()
+                .map_err(|e| format!("Failed to generate an HTTP token: {}", e))?;
+            if serves_http {
+                // Not logged, so get_logs doesn't hand it out
+                eprintln!("HTTP clients must send 'Authorization: Bearer {}'", token);
+            }
+            token.into()
+        }
+    };
+    for transport in &transports {
+        if let Transport::Http(address) = transport {
+            if !http::is_loopback(address) {
+                tracing::warn!(
+                    "Listening on {}, which is reachable from other machines. Anyone with the \
+                     token can run commands and read and write files as this user",
+                    address
+                );
+            }
+        }
+    }
+
+    let session_dir = SessionDir::create(session_dir.as_deref())
+        .map_err(|e| format!("Failed to create the session directory: {}", e))?;
+    tracing::info!("Session directory: {}", session_dir.root().display());
+    data.state.session_dir = Some(session_dir.root().to_path_buf());
+
+    if let Some(spec) = keymap {
+        let (layout, variant, options) = keymap::parse_keymap_spec(&spec);
+        data.state.set_keyboard_layout(layout, variant, options)?;
+    }
+
+    if let Some(path) = keybindings.or_else(Keybindings::default_path) {
+        data.state.keybindings = Keybindings::load(&path)?;
+        tracing::info!("Keybindings from {}", path.display());
+    }
+
+    if renderer == RendererKind::Pixman && backend != "headless" {
+        return Err("The pixman renderer only works with the headless backend".into());
+    }
+    if data.state.lockstep.is_some() && backend != "headless" && backend != "winit" {
+        return Err("--lockstep only works with the winit and headless backends".into());
+    }
+
+    match backend.as_str() {
+        "winit" => {
+            if let Err(e) = crate::winit::init_winit(&mut event_loop, &mut data, &output_sizes) {
+                if renderer != RendererKind::Auto {
+                    return Err(e);
+                }
+                // No display or no EGL: keep serving clients without a window
+                tracing::warn!("Failed to open a winit window ({}), running headless", e);
+                let sizes = &output_sizes;
+                crate::headless::init_headless(&mut event_loop, &mut data, sizes, renderer)?;
+            }
+        }
+        "headless" => {
+            crate::headless::init_headless(&mut event_loop, &mut data, &output_sizes, renderer)?
+        }
+        #[cfg(feature = "x11")]
+        "x11" => crate::x11::init_x11(&mut event_loop, &mut data, &output_sizes)?,
+        #[cfg(not(feature = "x11"))]
+        "x11" => return Err("mcpvil was built without the x11 feature".into()),
+        #[cfg(feature = "drm")]
+        "drm" => crate::drm::init_drm(&mut event_loop, &mut data, &output_sizes)?,
+        #[cfg(not(feature = "drm"))]
+        "drm" => return Err("mcpvil was built without the drm feature".into()),
+        other => {
+            return Err(format!(
+                "Unknown backend '{}', expected winit, x11, headless or drm",
+                other
+            )
+            .into())
+        }
+    }
+
+    // Started now that WAYLAND_DISPLAY is set, for the services it activates to inherit
+    if !host_session_bus {
+        match dbus::SessionBus::start() {
+            Ok(bus) => {
+                tracing::info!("Session bus for apps: {}", bus.address);
+                std::env::set_var("DBUS_SESSION_BUS_ADDRESS", &bus.address);
+                data.state.session_bus = Some(bus);
+            }
+            Err(e) => tracing::warn!("{}; apps will use the host's session bus", e),
+        }
+    }
+
+    // X11 apps launched before Xwayland is ready won't see DISPLAY
+    data.state.start_xwayland();
 
-    let mut args = std::env::args().skip(1);
     match args.next().as_deref() {
         Some("-c") | Some("--command") => {
             if let Some(command) = args.next() {
-                std::process::Command::new(command).args(args).spawn().ok();
+                let options = LaunchOptions {
+                    command: command.clone(),
+                    args: args.collect(),
+                    ..Default::default()
+                };
+                if let Err(e) = data.state.spawn_app(options) {
+                    tracing::error!("Failed to run {}: {}", command, e);
+                }
             }
         }
         _ => {}
     }
 
     // let transport = (tokio::io::stdin(), tokio::io::stdout());
-    let (command_tx, command_rx) = smithay::reexports::calloop::channel::channel::<McpCommand>();
+    let (command_tx, command_rx) = telemetry::command_channel();
 
     event_loop
         .handle()
-        .insert_source(command_rx, |event, _, _data| match event {
-            smithay::reexports::calloop::channel::Event::Msg(msg) => match msg {
-                McpCommand::LaunchApp {
-                    command,
-                    args,
-                    response_tx,
-                } => {
-                    let mut cmd = std::process::Command::new(&command);
-                    cmd.args(&args);
-                    let result = match cmd.spawn() {
-                        Ok(child) => Ok(child.id()),
-                        Err(e) => {
-                            tracing::error!("Failed to launch app: {}", e);
-                            Err(e.to_string())
+        .insert_source(
+            command_rx,
+            telemetry::traced(|event, _, _data| match event {
+                smithay::reexports::calloop::channel::Event::Msg(msg) => match msg {
+                    McpCommand::LaunchApp {
+                        options,
+                        wait_for_window,
+                        response_tx,
+                    } => _data
+                        .state
+                        .launch_app(options, wait_for_window, response_tx),
+                    McpCommand::Screenshot {
+                        mut options,
+                        response_tx,
+                    } => match options.output.as_deref() {
+                        Some(name) if _data.state.output_by_name(name).is_none() => {
+                            let _ = response_tx.send(Err(ToolError::new(
+                                ErrorKind::OutputNotFound,
+                                format!("No output named '{}'", name),
+                            )));
                         }
-                    };
-                    let _ = response_tx.send(result);
-                }
-                McpCommand::Screenshot {
-                    filename,
-                    response_tx,
-                } => {
-                    _data.state.pending_screenshot = Some((filename, response_tx));
-                }
-                McpCommand::CaptureScreenshot { response_tx } => {
-                    _data.state.pending_capture_screenshot = Some(response_tx);
-                }
-                McpCommand::CloseApp { pid, response_tx } => {
-                    let result = unsafe { libc::kill(pid as i32, libc::SIGTERM) };
-                    if result == 0 {
-                        let _ = response_tx.send(Ok(()));
-                    } else {
-                        let err = std::io::Error::last_os_error();
-                        tracing::error!("Failed to kill process {}: {}", pid, err);
-                        let _ = response_tx.send(Err(err.to_string()));
+                        _ => {
+                            options.requested_at = Some(std::time::Instant::now());
+                            options.span = Some(tracing::Span::current());
+                            _data.state.pending_screenshots.push((options, response_tx));
+                            _data.state.request_frame();
+                        }
+                    },
+                    McpCommand::CloseApp { pid, response_tx } => {
+                        let _ = response_tx.send(_data.state.kill_app(pid, libc::SIGTERM));
                     }
-                }
-                McpCommand::MouseMove { x, y, response_tx } => {
-                    use smithay::input::pointer::MotionEvent;
-                    use smithay::utils::SERIAL_COUNTER;
-
-                    let pos = (x, y).into();
-                    let serial = SERIAL_COUNTER.next_serial();
-                    let pointer = _data.state.seat.get_pointer().unwrap();
-                    let under = _data.state.surface_under(pos);
-                    let time = _data.state.start_time.elapsed().as_millis() as u32;
-
-                    pointer.motion(
-                        &mut _data.state,
-                        under,
-                        &MotionEvent {
-                            location: pos,
+                    McpCommand::MouseMove {
+                        x,
+                        y,
+                        relative,
+                        response_tx,
+                    } => {
+                        let target = _data.state.move_pointer((x, y).into(), relative);
+                        let _ = response_tx.send(Ok(target));
+                    }
+                    McpCommand::MouseClick {
+                        x,
+                        y,
+                        button,
+                        count,
+                        response_tx,
+                    } => {
+                        let target = _data.state.synthetic_click((x, y).into(), button, count);
+                        let _ = response_tx.send(Ok(target.as_ref().map(WindowLabel::from_window)));
+                    }
+                    McpCommand::KeyPress { key, response_tx } => {
+                        use smithay::backend::input::KeyState;
+                        use smithay::input::keyboard::FilterResult;
+                        use smithay::utils::SERIAL_COUNTER;
+
+                        let serial = SERIAL_COUNTER.next_serial();
+                        let time = _data.state.start_time.elapsed().as_millis() as u32;
+                        let keyboard = _data.state.seat.get_keyboard().unwrap();
+
+                        keyboard.input::<(), _>(
+                            &mut _data.state,
+                            key.into(),
+                            KeyState::Pressed,
                             serial,
                             time,
-                        },
-                    );
-                    pointer.frame(&mut _data.state);
-                    let _ = response_tx.send(Ok(()));
-                }
-                McpCommand::MouseClick { x, y, button, response_tx } => {
-                    use smithay::backend::input::ButtonState;
-                    use smithay::input::pointer::{ButtonEvent, MotionEvent};
-                    use smithay::reexports::wayland_server::protocol::wl_surface::WlSurface;
-                    use smithay::utils::SERIAL_COUNTER;
-
-                    let pos = (x, y).into();
-                    let time = _data.state.start_time.elapsed().as_millis() as u32;
-
-                    // Move pointer to position first
-                    let serial = SERIAL_COUNTER.next_serial();
-                    let pointer = _data.state.seat.get_pointer().unwrap();
-                    let keyboard = _data.state.seat.get_keyboard().unwrap();
-                    let under = _data.state.surface_under(pos);
-
-                    pointer.motion(
-                        &mut _data.state,
-                        under,
-                        &MotionEvent {
-                            location: pos,
+                            |_, _, _| FilterResult::Forward,
+                        );
+
+                        let serial = SERIAL_COUNTER.next_serial();
+                        keyboard.input::<(), _>(
+                            &mut _data.state,
+                            key.into(),
+                            KeyState::Released,
                             serial,
                             time,
-                        },
-                    );
-                    pointer.frame(&mut _data.state);
+                            |_, _, _| FilterResult::Forward,
+                        );
+                        let _ = response_tx.send(Ok(()));
+                    }
+                    McpCommand::Scroll {
+                        position,
+                        axis,
+                        amount,
+                        steps,
+                        source,
+                        response_tx,
+                    } => {
+                        let target = _data.state.synthetic_scroll(
+                            position.map(Into::into),
+                            axis,
+                            amount,
+                            steps,
+                            source,
+                        );
+                        let _ = response_tx.send(Ok(target));
+                    }
+                    McpCommand::TypeText { text, response_tx } => {
+                        let result = _data.state.type_text(&text);
+                        if let Err(e) = &result {
+                            tracing::error!("Failed to type text: {}", e);
+                        }
+                        let _ = response_tx.send(result);
+                    }
+                    McpCommand::PressKeyCombo { keys, response_tx } => {
+                        let result = _data.state.press_key_combo(&keys);
+                        if let Err(e) = &result {
+                            tracing::error!("Failed to press {}: {}", keys, e);
+                        }
+                        let _ = response_tx.send(result);
+                    }
+                    McpCommand::ListWindows {
+                        output,
+                        response_tx,
+                    } => {
+                        let result = match output.as_deref() {
+                            Some(name) if _data.state.output_by_name(name).is_none() => {
+                                Err(format!("No output named '{}'", name))
+                            }
+                            _ => Ok(_data.state.list_windows(output.as_deref())),
+                        };
+                        let _ = response_tx.send(result);
+                    }
+                    McpCommand::Drag {
+                        path,
+                        button,
+                        duration,
+                        response_tx,
+                    } => {
+                        if _data.state.pending_drag.is_some() {
+                            let _ =
+                                response_tx.send(Err("Another drag is in progress".to_string()));
+                        } else {
+                            _data.state.start_drag(input::Drag {
+                                path: path.into_iter().map(Into::into).collect(),
+                                source: input::DragSource::Button(button),
+                                duration,
+                                started: std::time::Instant::now(),
+                                response_tx,
+                            });
+                        }
+                    }
+                    McpCommand::FocusWindow { id, response_tx } => {
+                        use smithay::utils::SERIAL_COUNTER;
 
-                    // Focus/raise logic (same as input.rs)
-                    let serial = SERIAL_COUNTER.next_serial();
-                    if !pointer.is_grabbed() {
-                        if let Some((window, _loc)) = _data
+                        let result = match _data.state.window_by_id(id) {
+                            Some(window) => {
+                                _data
+                                    .state
+                                    .focus_window(&window, SERIAL_COUNTER.next_serial());
+                                Ok(WindowLabel::from_window(&window))
+                            }
+                            None => Err(ToolError::window_not_found(id)),
+                        };
+                        let _ = response_tx.send(result);
+                    }
+                    McpCommand::CloseWindow {
+                        id,
+                        timeout,
+                        force,
+                        response_tx,
+                    } => match _data.state.window_by_id(id) {
+                        Some(window) => {
+                            _data
+                                .state
+                                .close_window(&window, timeout, force, response_tx);
+                        }
+                        None => {
+                            let _ = response_tx.send(Err(ToolError::window_not_found(id)));
+                        }
+                    },
+                    McpCommand::MoveWindow {
+                        id,
+                        x,
+                        y,
+                        response_tx,
+                    } => {
+                        let result = match _data.state.window_by_id(id) {
+                            Some(window) => {
+                                _data.state.move_window(&window, (x, y).into());
+                                Ok(_data.state.window_info(&window))
+                            }
+                            None => Err(ToolError::window_not_found(id)),
+                        };
+                        let _ = response_tx.send(result);
+                    }
+                    McpCommand::ResizeWindow {
+                        id,
+                        width,
+                        height,
+                        response_tx,
+                    } => {
+                        let result = match _data.state.window_by_id(id) {
+                            Some(window) => {
+                                _data.state.resize_window(&window, (width, height).into());
+                                Ok(_data.state.window_info(&window))
+                            }
+                            None => Err(ToolError::window_not_found(id)),
+                        };
+                        let _ = response_tx.send(result);
+                    }
+                    McpCommand::SetWindowMode {
+                        id,
+                        mode,
+                        response_tx,
+                    } => {
+                        let result = match _data.state.window_by_id(id) {
+                            Some(window) => {
+                                _data.state.set_window_mode(&window, mode);
+                                Ok(_data.state.window_info(&window))
+                            }
+                            None => Err(ToolError::window_not_found(id)),
+                        };
+                        let _ = response_tx.send(result);
+                    }
+                    McpCommand::StartRecording {
+                        options,
+                        response_tx,
+                    } => {
+                        let size = _data
                             .state
                             .space
-                            .element_under(pointer.current_location())
-                            .map(|(w, l)| (w.clone(), l))
-                        {
-                            _data.state.space.raise_element(&window, true);
-                            keyboard.set_focus(
-                                &mut _data.state,
-                                Some(window.toplevel().unwrap().wl_surface().clone()),
-                                serial,
-                            );
-                            _data.state.space.elements().for_each(|window| {
-                                window.toplevel().unwrap().send_pending_configure();
+                            .outputs()
+                            .next()
+                            .and_then(|output| output.current_mode())
+                            .map(|mode| mode.size);
+                        let result = match (&_data.state.recording, size) {
+                            (Some(recording), _) => {
+                                Err(format!("Already recording to {}", recording.path()))
+                            }
+                            (None, None) => Err("No output to record".to_string()),
+                            (None, Some(size)) => {
+                                let show_input = options.show_input;
+                                Recording::start(options, size.w as u32, size.h as u32).map(
+                                    |recording| {
+                                        let path = recording.path().to_string();
+                                        _data.state.recording = Some(recording);
+                                        if show_input {
+                                            _data.state.input_overlay =
+                                                Some(InputOverlay::default());
+                                        }
+                                        path
+                                    },
+                                )
+                            }
+                        };
+                        let _ = response_tx.send(result);
+                    }
+                    McpCommand::StopRecording { response_tx } => {
+                        _data.state.input_overlay = None;
+                        match _data.state.recording.take() {
+                            Some(recording) => recording.finish(response_tx),
+                            None => {
+                                let _ = response_tx.send(Err("Not recording".to_string()));
+                            }
+                        }
+                    }
+                    McpCommand::ListOutputs { response_tx } => {
+                        let _ = response_tx.send(Ok(_data.state.list_outputs()));
+                    }
+                    McpCommand::SetOutputMode {
+                        output,
+                        mode,
+                        response_tx,
+                    } => {
+                        let result = _data.state.set_output_mode(output.as_deref(), mode);
+                        let _ = response_tx.send(result);
+                    }
+                    McpCommand::SetSelection {
+                        target,
+                        mime_types,
+                        data,
+                        response_tx,
+                    } => {
+                        _data.state.set_selection(target, mime_types, data);
+                        let _ = response_tx.send(Ok(()));
+                    }
+                    McpCommand::GetSelection {
+                        target,
+                        mime_type,
+                        response_tx,
+                    } => {
+                        _data.state.get_selection(target, mime_type, response_tx);
+                    }
+                    McpCommand::Dnd {
+                        path,
+                        duration,
+                        mime_types,
+                        actions,
+                        data,
+                        response_tx,
+                        outcome_tx,
+                    } => {
+                        if _data.state.pending_drag.is_some() {
+                            let _ =
+                                response_tx.send(Err("Another drag is in progress".to_string()));
+                        } else {
+                            _data.state.pending_dnd = Some(clipboard::PendingDnd {
+                                mime_types,
+                                actions,
+                                data: data.into(),
+                                outcome: Default::default(),
+                                outcome_tx: Some(outcome_tx),
+                            });
+                            _data.state.start_drag(input::Drag {
+                                path: path.into_iter().map(Into::into).collect(),
+                                source: input::DragSource::Dnd,
+                                duration,
+                                started: std::time::Instant::now(),
+                                response_tx,
                             });
+                        }
+                    }
+                    McpCommand::KillApp {
+                        pid,
+                        signal,
+                        response_tx,
+                    } => {
+                        let _ = response_tx.send(_data.state.kill_app(pid, signal));
+                    }
+                    McpCommand::ListProcesses { response_tx } => {
+                        let _ = response_tx.send(Ok(_data.state.list_processes()));
+                    }
+                    McpCommand::GetAppOutput {
+                        pid,
+                        tail_lines,
+                        response_tx,
+                    } => {
+                        let _ = response_tx.send(_data.state.app_output(pid, tail_lines));
+                    }
+                    McpCommand::WaitForWindow { wait, timeout } => {
+                        _data.state.wait_for_window(wait, timeout);
+                    }
+                    McpCommand::WaitForIdle {
+                        quiet,
+                        timeout,
+                        response_tx,
+                    } => {
+                        _data.state.wait_for_idle(quiet, timeout, response_tx);
+                    }
+                    McpCommand::GetWindowGeometry { id, response_tx } => {
+                        let result = match _data.state.window_by_id(id) {
+                            Some(window) => _data.state.window_geometry(&window).ok_or_else(|| {
+                                ToolError::new(
+                                    ErrorKind::WindowNotFound,
+                                    format!("Window {} is not mapped", id),
+                                )
+                            }),
+                            None => Err(ToolError::window_not_found(id)),
+                        };
+                        let _ = response_tx.send(result);
+                    }
+                    McpCommand::GetInputState { response_tx } => {
+                        let _ = response_tx.send(Ok(_data.state.input_state()));
+                    }
+                    McpCommand::SetKeyboardLayout {
+                        layout,
+                        variant,
+                        options,
+                        response_tx,
+                    } => {
+                        let result = _data.state.set_keyboard_layout(&layout, &variant, options);
+                        let _ = response_tx.send(result);
+                    }
+                    McpCommand::Touch {
+                        path,
+                        duration,
+                        response_tx,
+                    } => {
+                        if _data.state.pending_drag.is_some() {
+                            let _ =
+                                response_tx.send(Err("Another drag is in progress".to_string()));
                         } else {
-                            _data.state.space.elements().for_each(|window| {
-                                window.set_activated(false);
-                                window.toplevel().unwrap().send_pending_configure();
+                            _data.state.start_drag(input::Drag {
+                                path: path.into_iter().map(Into::into).collect(),
+                                source: input::DragSource::Touch,
+                                duration,
+                                started: std::time::Instant::now(),
+                                response_tx,
                             });
-                            keyboard.set_focus(
-                                &mut _data.state,
-                                Option::<WlSurface>::None,
-                                serial,
+                        }
+                    }
+                    McpCommand::Gesture {
+                        x,
+                        y,
+                        kind,
+                        duration,
+                        response_tx,
+                    } => {
+                        if _data.state.pending_gesture.is_some() {
+                            let _ =
+                                response_tx.send(Err("Another gesture is in progress".to_string()));
+                        } else {
+                            _data.state.start_gesture(
+                                (x, y).into(),
+                                gesture::Gesture {
+                                    kind,
+                                    duration,
+                                    started: std::time::Instant::now(),
+                                    progress: 0.0,
+                                    response_tx,
+                                },
                             );
                         }
                     }
-
-                    // Press
-                    pointer.button(
-                        &mut _data.state,
-                        &ButtonEvent {
-                            button,
-                            state: ButtonState::Pressed,
-                            serial,
-                            time,
-                        },
-                    );
-                    pointer.frame(&mut _data.state);
-
-                    // Release
-                    let serial = SERIAL_COUNTER.next_serial();
-                    pointer.button(
-                        &mut _data.state,
-                        &ButtonEvent {
-                            button,
-                            state: ButtonState::Released,
-                            serial,
-                            time,
-                        },
-                    );
-                    pointer.frame(&mut _data.state);
-                    let _ = response_tx.send(Ok(()));
-                }
-                McpCommand::KeyPress { key, response_tx } => {
-                    use smithay::backend::input::KeyState;
-                    use smithay::input::keyboard::FilterResult;
-                    use smithay::utils::SERIAL_COUNTER;
-
-                    let serial = SERIAL_COUNTER.next_serial();
-                    let time = _data.state.start_time.elapsed().as_millis() as u32;
-                    let keyboard = _data.state.seat.get_keyboard().unwrap();
-
-                    keyboard.input::<(), _>(
-                        &mut _data.state,
-                        key.into(),
-                        KeyState::Pressed,
-                        serial,
-                        time,
-                        |_, _, _| FilterResult::Forward,
-                    );
-
-                    let serial = SERIAL_COUNTER.next_serial();
-                    keyboard.input::<(), _>(
-                        &mut _data.state,
-                        key.into(),
-                        KeyState::Released,
-                        serial,
-                        time,
-                        |_, _, _| FilterResult::Forward,
-                    );
-                    let _ = response_tx.send(Ok(()));
-                }
-                McpCommand::Scroll { x, y, axis, amount, response_tx } => {
-                    use smithay::backend::input::AxisSource;
-                    use smithay::input::pointer::{AxisFrame, MotionEvent};
-                    use smithay::utils::SERIAL_COUNTER;
-
-                    let pos = (x, y).into();
-                    let time = _data.state.start_time.elapsed().as_millis() as u32;
-
-                    // Move pointer to position first
-                    let serial = SERIAL_COUNTER.next_serial();
-                    let pointer = _data.state.seat.get_pointer().unwrap();
-                    let under = _data.state.surface_under(pos);
-
-                    pointer.motion(
-                        &mut _data.state,
-                        under,
-                        &MotionEvent {
-                            location: pos,
-                            serial,
-                            time,
-                        },
-                    );
-                    pointer.frame(&mut _data.state);
-
-                    // Scroll
-                    let frame = AxisFrame::new(time)
-                        .source(AxisSource::Wheel)
-                        .value(axis, amount);
-                    pointer.axis(&mut _data.state, frame);
-                    pointer.frame(&mut _data.state);
-                    let _ = response_tx.send(Ok(()));
+                    McpCommand::PenStroke {
+                        points,
+                        duration,
+                        response_tx,
+                    } => {
+                        if _data.state.pending_pen_stroke.is_some() {
+                            let _ = response_tx
+                                .send(Err("Another pen stroke is in progress".to_string()));
+                        } else {
+                            _data.state.start_pen_stroke(tablet::PenStroke {
+                                points,
+                                duration,
+                                started: std::time::Instant::now(),
+                                response_tx,
+                            });
+                        }
+                    }
+                    McpCommand::InputSequence {
+                        steps,
+                        response_tx,
+                        progress_tx,
+                    } => {
+                        _data.state.run_input_sequence(sequence::InputSequence {
+                            steps: steps.into(),
+                            done: 0,
+                            response_tx,
+                            progress_tx,
+                        });
+                    }
+                    McpCommand::StartInputRecording {
+                        session,
+                        response_tx,
+                    } => {
+                        let _ = response_tx.send(_data.state.start_macro_recording(session));
+                    }
+                    McpCommand::StopInputRecording { response_tx } => {
+                        let _ = response_tx.send(_data.state.stop_macro_recording());
+                    }
+                    McpCommand::ReplayInput {
+                        session,
+                        speed,
+                        response_tx,
+                    } => {
+                        _data.state.replay_macro(&session, speed, response_tx);
+                    }
+                    McpCommand::SetOutputScale {
+                        output,
+                        scale,
+                        response_tx,
+                    } => {
+                        let result = _data.state.set_output_scale(output.as_deref(), scale);
+                        let _ = response_tx.send(result);
+                    }
+                    McpCommand::GetFrameTiming {
+                        output,
+                        response_tx,
+                    } => {
+                        let _ = response_tx.send(_data.state.frame_timing(output.as_deref()));
+                    }
+                    McpCommand::RelativeMotion { delta, response_tx } => {
+                        let target = _data.state.relative_motion(delta.into());
+                        let _ = response_tx.send(Ok(target));
+                    }
+                    McpCommand::StartScreencast {
+                        output,
+                        response_tx,
+                    } => _data.state.start_screencast(output.as_deref(), response_tx),
+                    McpCommand::StopScreencast {
+                        node_id,
+                        response_tx,
+                    } => {
+                        let _ = response_tx.send(_data.state.stop_screencast(node_id));
+                    }
+                    McpCommand::DumpState { response_tx } => {
+                        let _ = response_tx.send(Ok(_data.state.dump_state()));
+                    }
+                    McpCommand::WaitForVisualStability {
+                        output,
+                        region,
+                        threshold,
+                        stable_for,
+                        timeout,
+                        response_tx,
+                    } => {
+                        _data.state.wait_for_visual_stability(
+                            output,
+                            region,
+                            threshold,
+                            stable_for,
+                            timeout,
+                            response_tx,
+                        );
+                    }
+                    McpCommand::AdvanceFrames { count, response_tx } => {
+                        _data.state.advance_frames(count, response_tx);
+                    }
+                    McpCommand::SetFrameRate {
+                        fps,
+                        idle_when_no_damage,
+                        response_tx,
+                    } => {
+                        _data.state.set_frame_rate(fps, idle_when_no_damage);
+                        let _ = response_tx.send(Ok(()));
+                    }
+                    McpCommand::HideCursor {
+                        hidden,
+                        response_tx,
+                    } => {
+                        _data.state.cursor_hidden = hidden;
+                        let _ = response_tx.send(Ok(()));
+                    }
+                    McpCommand::SetOutputTransform {
+                        output,
+                        transform,
+                        response_tx,
+                    } => {
+                        let result = _data
+                            .state
+                            .set_output_transform(output.as_deref(), transform);
+                        let _ = response_tx.send(result);
+                    }
+                    McpCommand::SetBackground {
+                        color,
+                        image_path,
+                        response_tx,
+                    } => {
+                        let result = _data.state.set_background(color, image_path.as_deref());
+                        let _ = response_tx.send(result);
+                    }
+                    McpCommand::RestartApp {
+                        pid,
+                        wait_for_window,
+                        response_tx,
+                    } => _data.state.restart_app(pid, wait_for_window, response_tx),
+                    McpCommand::SetKeyRepeat {
+                        rate,
+                        delay_ms,
+                        response_tx,
+                    } => {
+                        let current = _data.state.key_repeat;
+                        let result = KeyRepeat::new(
+                            rate.unwrap_or(current.rate),
+                            delay_ms.unwrap_or(current.delay_ms),
+                        );
+                        if let Ok(repeat) = result {
+                            _data.state.set_key_repeat(repeat);
+                        }
+                        let _ = response_tx.send(result);
+                    }
+                    McpCommand::HoldKey {
+                        keys,
+                        duration,
+                        response_tx,
+                    } => _data.state.hold_key_combo(&keys, duration, response_tx),
+                    McpCommand::SetProtocolTrace {
+                        pid,
+                        window_id,
+                        enabled,
+                        response_tx,
+                    } => {
+                        let result = _data.state.set_protocol_trace(pid, window_id, enabled);
+                        let _ = response_tx.send(result);
+                    }
+                    McpCommand::GetStats { response_tx } => {
+                        let _ = response_tx.send(_data.state.stats());
+                    }
+                    McpCommand::GetRecentFrames {
+                        seconds,
+                        max_frames,
+                        response_tx,
+                    } => {
+                        let result = match &_data.state.frame_history {
+                            Some(history) => Ok(history.recent(seconds, max_frames)),
+                            None => Err("mcpvil was started without --frame-history".to_string()),
+                        };
+                        let _ = response_tx.send(result);
+                    }
+                },
+                smithay::reexports::calloop::channel::Event::Closed => {
+                    tracing::info!("MCP command channel closed");
                 }
-            },
-            smithay::reexports::calloop::channel::Event::Closed => {
-                tracing::info!("MCP command channel closed");
-            }
-        })
+            }),
+        )
         .map_err(|e| Box::new(e) as Box<dyn std::error::Error>)?;
 
-    let service = MCPvilServer::new(command_tx);
-    let server = MCPvilServer::serve(service, stdio());
+    // Window lifecycle events go out as notifications once a client is connected
+    let (window_events_tx, window_events_rx) = tokio::sync::mpsc::unbounded_channel();
+    #[cfg(feature = "notifications")]
+    let notification_events_tx = window_events_tx.clone();
+    #[cfg(feature = "portal")]
+    let portal_events_tx = window_events_tx.clone();
+    data.state.window_events = Some(window_events_tx);
+
+    #[cfg(feature = "portal")]
+    let portal_command_tx = command_tx.clone();
+    let subscribers = Subscribers::default();
+    let scene_subscribers = SceneSubscribers::default();
+    let mut service = MCPvilServer::new(
+        command_tx,
+        subscribers.clone(),
+        data.state.scene.subscribe(),
+        scene_subscribers.clone(),
+        session_dir,
+        logs,
+        data.state.protocol_trace.clone(),
+    );
+    service.command_timeout = command_timeout;
+    // Shell access only for those who ask for it
+    if !allow_run_command {
+        service.tool_router.remove_route("run_command");
+    }
+
+    #[cfg(feature = "accessibility")]
+    accessibility::enable_for_apps();
 
     let rt = tokio::runtime::Builder::new_multi_thread()
         .enable_all()
         .build()?;
 
     let _guard = rt.enter();
-    rt.spawn(async move {
-        match server.await {
-            Ok(running_service) => {
-                if let Err(e) = running_service.waiting().await {
-                    tracing::error!("MCP server task error: {:?}", e);
-                }
+    #[cfg(feature = "portal")]
+    rt.spawn(portal::run_portal(
+        portal_command_tx,
+        service.session_dir.root().to_path_buf(),
+        service.file_chooser.clone(),
+        portal_events_tx,
+    ));
+    #[cfg(feature = "accessibility")]
+    rt.spawn(accessibility::run_bus());
+    #[cfg(feature = "notifications")]
+    rt.spawn(notifications::run_daemon(
+        service.notifications.clone(),
+        notification_events_tx,
+    ));
+    rt.spawn(events::forward_window_events(subscribers, window_events_rx));
+    rt.spawn(logs::forward_logs(service.log_subscribers.clone(), log_rx));
+    if let Some(address) = metrics_address {
+        let metrics = metrics::serve_metrics(
+            service.command_tx.clone(),
+            service.tool_metrics.clone(),
+            address,
+        );
+        rt.spawn(async move {
+            if let Err(e) = metrics.await {
+                tracing::error!("Metrics server error: {}", e);
             }
-            Err(e) => {
-                tracing::error!("MCP server initialization error: {:?}", e);
+        });
+    }
+    rt.spawn(scene::forward_scene_updates(
+        scene_subscribers,
+        data.state.scene.subscribe(),
+    ));
+    for transport in transports {
+        let service = service.clone();
+        match transport {
+            Transport::Stdio => {
+                rt.spawn(async move {
+                    match service.serve(stdio()).await {
+                        Ok(running_service) => {
+                            if let Err(e) = running_service.waiting().await {
+                                tracing::error!("MCP server task error: {:?}", e);
+                            }
+                        }
+                        Err(e) => {
+                            tracing::error!("MCP server initialization error: {:?}", e);
+                        }
+                    }
+                });
+            }
+            Transport::Http(address) => {
+                let token = token.clone();
+                rt.spawn(async move {
+                    if let Err(e) = serve_http(service, address, token).await {
+                        tracing::error!("MCP HTTP server error: {}", e);
+                    }
+                });
             }
         }
-    });
+    }
 
-    event_loop.run(None, &mut data, move |_| {
-        // Smallvil is running
+    event_loop.run(None, &mut data, move |data| {
+        // Smallvil is running; forget captures and waits nobody waits for
+        data.state.drop_abandoned_requests();
     })?;
+    telemetry::shutdown();
 
     Ok(())
 }
@@ -0,0 +1,260 @@
+use image::{imageops, GrayImage};
+use rmcp::schemars::{self, JsonSchema};
+use serde::Serialize;
+
+/// Matches are first searched for on images scaled down by up to this factor,
+/// then refined at full size.
+const MAX_PYRAMID_FACTOR: u32 = 8;
+/// Smallest template side, in pixels, searched at the coarse scale.
+const MIN_COARSE_SIDE: u32 = 8;
+/// Coarse candidates refined at full size per requested match.
+const CANDIDATES_PER_MATCH: usize = 4;
+
+/// Where a template was found.
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct ImageMatch {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+    /// Center of the match, for clicking
+    pub center_x: i32,
+    pub center_y: i32,
+    /// Normalized cross-correlation, -1.0-1.0; 1.0 is an exact match
+    pub confidence: f64,
+}
+
+/// A grayscale image with integral images of its values and their squares, so
+/// the mean and variance of any window take constant time.
+struct Integral {
+    image: GrayImage,
+    sums: Vec<f64>,
+    squares: Vec<f64>,
+}
+
+impl Integral {
+    fn new(image: GrayImage) -> Self {
+        let (width, height) = (image.width() as usize, image.height() as usize);
+        let stride = width + 1;
+        let mut sums = vec![0.0; stride * (height + 1)];
+        let mut squares = vec![0.0; stride * (height + 1)];
+        for y in 0..height {
+            let (mut row_sum, mut row_square) = (0.0, 0.0);
+            for x in 0..width {
+                let value = image.get_pixel(x as u32, y as u32).0[0] as f64;
+                row_sum += value;
+                row_square += value * value;
+                sums[(y + 1) * stride + x + 1] = sums[y * stride + x + 1] + row_sum;
+                squares[(y + 1) * stride + x + 1] = squares[y * stride + x + 1] + row_square;
+            }
+        }
+        Self {
+            image,
+            sums,
+            squares,
+        }
+    }
+
+    fn window(table: &[f64], stride: usize, x: u32, y: u32, width: u32, height: u32) -> f64 {
+        let (x0, y0) = (x as usize, y as usize);
+        let (x1, y1) = (x0 + width as usize, y0 + height as usize);
+        table[y1 * stride + x1] - table[y0 * stride + x1] - table[y1 * stride + x0]
+            + table[y0 * stride + x0]
+    }
+
+    /// Sum and sum of squares of the window at (x, y).
+    fn stats(&self, x: u32, y: u32, width: u32, height: u32) -> (f64, f64) {
+        let stride = self.image.width() as usize + 1;
+        (
+            Self::window(&self.sums, stride, x, y, width, height),
+            Self::window(&self.squares, stride, x, y, width, height),
+        )
+    }
+}
+
+/// A template with its mean removed, ready to correlate.
+struct Template {
+    width: u32,
+    height: u32,
+    /// Pixel values minus their mean, row by row
+    values: Vec<f64>,
+    /// Square root of the sum of the squared values
+    norm: f64,
+}
+
+impl Template {
+    fn new(image: &GrayImage) -> Option<Self> {
+        let count = (image.width() * image.height()) as f64;
+        let mean = image.pixels().map(|p| p.0[0] as f64).sum::<f64>() / count;
+        let values: Vec<f64> = image.pixels().map(|p| p.0[0] as f64 - mean).collect();
+        let norm = values.iter().map(|v| v * v).sum::<f64>().sqrt();
+        (norm > f64::EPSILON).then_some(Self {
+            width: image.width(),
+            height: image.height(),
+            values,
+            norm,
+        })
+    }
+
+    /// Zero-mean normalized cross-correlation with the window of `haystack` at
+    /// (x, y). Flat windows score 0.
+    fn correlate(&self, haystack: &Integral, x: u32, y: u32) -> f64 {
+        let count = (self.width * self.height) as f64;
+        let (sum, squares) = haystack.stats(x, y, self.width, self.height);
+        let variance = squares - sum * sum / count;
+        if variance <= f64::EPSILON {
+            return 0.0;
+        }
+        let mut cross = 0.0;
+        let mut values = self.values.iter();
+        for ty in 0..self.height {
+            for tx in 0..self.width {
+                let value = haystack.image.get_pixel(x + tx, y + ty).0[0] as f64;
+                cross += value * values.next().copied().unwrap_or(0.0);
+            }
+        }
+        cross / (self.norm * variance.sqrt())
+    }
+}
+
+/// Finds up to `max_results` non-overlapping places where the encoded
+/// `template` appears in the encoded `frame` with at least `confidence`, best
+/// first. Coordinates are offset by `origin`.
+pub fn find(
+    frame: &[u8],
+    template: &[u8],
+    confidence: f64,
+    max_results: usize,
+    origin: (i32, i32),
+) -> Result<Vec<ImageMatch>, String> {
+    let frame = image::load_from_memory(frame)
+        .map_err(|e| format!("Failed to decode capture: {}", e))?
+        .into_luma8();
+    let template = image::load_from_memory(template)
+        .map_err(|e| format!("Failed to decode template: {}", e))?
+        .into_luma8();
+    let (width, height) = template.dimensions();
+    if width == 0 || height == 0 || width > frame.width() || height > frame.height() {
+        return Err(format!(
+            "The {}x{} template doesn't fit in the {}x{} capture",
+            width,
+            height,
+            frame.width(),
+            frame.height()
+        ));
+    }
+
+    let mut factor = 1;
+    while factor < MAX_PYRAMID_FACTOR && width.min(height) / (factor * 2) >= MIN_COARSE_SIDE {
+        factor *= 2;
+    }
+    let full = Template::new(&template)
+        .ok_or_else(|| "The template is a single flat color; use get_pixel instead".to_string())?;
+    let haystack = Integral::new(frame);
+
+    let candidates = if factor == 1 {
+        search(&haystack, &full, 1)
+    } else {
+        let coarse_template = imageops::thumbnail(&template, width / factor, height / factor);
+        let coarse_frame = imageops::thumbnail(
+            &haystack.image,
+            haystack.image.width() / factor,
+            haystack.image.height() / factor,
+        );
+        match Template::new(&coarse_template) {
+            Some(coarse) => search(&Integral::new(coarse_frame), &coarse, factor),
+            None => search(&haystack, &full, 1),
+        }
+    };
+
+    // Refine the best coarse candidates at full size
+    let mut matches: Vec<(u32, u32, f64)> = candidates
+        .into_iter()
+        .take(max_results * CANDIDATES_PER_MATCH)
+        .map(|(cx, cy, _)| refine(&haystack, &full, cx, cy, factor))
+        .filter(|(_, _, score)| *score >= confidence)
+        .collect();
+    matches.sort_by(|a, b| b.2.total_cmp(&a.2));
+
+    let mut kept: Vec<(u32, u32, f64)> = Vec::new();
+    for candidate in matches {
+        let overlaps = kept
+            .iter()
+            .any(|(x, y, _)| x.abs_diff(candidate.0) < width && y.abs_diff(candidate.1) < height);
+        if !overlaps {
+            kept.push(candidate);
+        }
+        if kept.len() == max_results {
+            break;
+        }
+    }
+
+    Ok(kept
+        .into_iter()
+        .map(|(x, y, score)| ImageMatch {
+            x: origin.0 + x as i32,
+            y: origin.1 + y as i32,
+            width,
+            height,
+            center_x: origin.0 + (x + width / 2) as i32,
+            center_y: origin.1 + (y + height / 2) as i32,
+            confidence: score,
+        })
+        .collect())
+}
+
+/// Scores every position of `template` in `haystack` and returns the local
+/// maxima, best first, in full-size coordinates.
+fn search(haystack: &Integral, template: &Template, factor: u32) -> Vec<(u32, u32, f64)> {
+    let columns = haystack.image.width() - template.width + 1;
+    let rows = haystack.image.height() - template.height + 1;
+    let scores: Vec<f64> = (0..rows)
+        .flat_map(|y| (0..columns).map(move |x| (x, y)))
+        .map(|(x, y)| template.correlate(haystack, x, y))
+        .collect();
+
+    let score_at = |x: i64, y: i64| {
+        if x < 0 || y < 0 || x >= columns as i64 || y >= rows as i64 {
+            f64::NEG_INFINITY
+        } else {
+            scores[y as usize * columns as usize + x as usize]
+        }
+    };
+    let mut peaks = Vec::new();
+    for y in 0..rows as i64 {
+        for x in 0..columns as i64 {
+            let score = score_at(x, y);
+            let is_peak = (-1..=1)
+                .flat_map(|dy| (-1..=1).map(move |dx| (dx, dy)))
+                .filter(|&(dx, dy)| (dx, dy) != (0, 0))
+                .all(|(dx, dy)| score >= score_at(x + dx, y + dy));
+            if is_peak && score > 0.0 {
+                peaks.push((x as u32 * factor, y as u32 * factor, score));
+            }
+        }
+    }
+    peaks.sort_by(|a, b| b.2.total_cmp(&a.2));
+    peaks
+}
+
+/// The best full-size position within `factor` pixels of a coarse match.
+fn refine(
+    haystack: &Integral,
+    template: &Template,
+    x: u32,
+    y: u32,
+    factor: u32,
+) -> (u32, u32, f64) {
+    let max_x = haystack.image.width() - template.width;
+    let max_y = haystack.image.height() - template.height;
+    let mut best = (x.min(max_x), y.min(max_y), f64::NEG_INFINITY);
+    for ry in y.saturating_sub(factor)..=(y + factor).min(max_y) {
+        for rx in x.saturating_sub(factor)..=(x + factor).min(max_x) {
+            let score = template.correlate(haystack, rx, ry);
+            if score > best.2 {
+                best = (rx, ry, score);
+            }
+        }
+    }
+    best
+}
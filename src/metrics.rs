@@ -0,0 +1,244 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use axum::{
+    http::{header, StatusCode},
+    response::IntoResponse,
+};
+
+use crate::{stats::StatsInfo, telemetry::CommandSender, McpCommand};
+
+/// Upper bounds of the histogram buckets, in seconds, from a fraction of a
+/// frame to the longest waits tools do.
+const BUCKETS: [f64; 14] = [
+    0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0,
+];
+
+/// Durations counted into fixed buckets, as Prometheus histograms are.
+#[derive(Debug, Clone, Default)]
+pub struct Histogram {
+    /// Observations per bucket, not cumulative; slower ones only count towards +Inf
+    buckets: [u64; BUCKETS.len()],
+    count: u64,
+    sum: f64,
+}
+
+impl Histogram {
+    pub fn observe(&mut self, duration: Duration) {
+        let secs = duration.as_secs_f64();
+        if let Some(bucket) = BUCKETS.iter().position(|&bound| secs <= bound) {
+            self.buckets[bucket] += 1;
+        }
+        self.count += 1;
+        self.sum += secs;
+    }
+
+    /// The `_bucket`, `_sum` and `_count` series, with `labels` (e.g.
+    /// `tool="click"`) on each.
+    fn write(&self, out: &mut String, name: &str, labels: &str) {
+        let separator = if labels.is_empty() { "" } else { "," };
+        let mut cumulative = 0;
+        for (bound, count) in BUCKETS.iter().zip(self.buckets) {
+            cumulative += count;
+            let _ = writeln!(
+                out,
+                "{}_bucket{{{}{}le=\"{}\"}} {}",
+                name, labels, separator, bound, cumulative
+            );
+        }
+        let _ = writeln!(
+            out,
+            "{}_bucket{{{}{}le=\"+Inf\"}} {}",
+            name, labels, separator, self.count
+        );
+        let labels = if labels.is_empty() {
+            String::new()
+        } else {
+            format!("{{{}}}", labels)
+        };
+        let _ = writeln!(out, "{}_sum{} {}", name, labels, self.sum);
+        let _ = writeln!(out, "{}_count{} {}", name, labels, self.count);
+    }
+}
+
+/// Calls of one tool.
+#[derive(Debug, Default)]
+struct ToolCalls {
+    calls: u64,
+    /// Calls that returned an error, either as an MCP error or as an error result
+    errors: u64,
+    duration: Histogram,
+}
+
+/// Tool calls by tool name, shared by every MCP client.
+#[derive(Debug, Default)]
+pub struct ToolMetricsRegistry {
+    tools: BTreeMap<String, ToolCalls>,
+}
+
+pub type ToolMetrics = Arc<Mutex<ToolMetricsRegistry>>;
+
+impl ToolMetricsRegistry {
+    pub fn record(&mut self, tool: &str, duration: Duration, failed: bool) {
+        let calls = self.tools.entry(tool.to_string()).or_default();
+        calls.calls += 1;
+        if failed {
+            calls.errors += 1;
+        }
+        calls.duration.observe(duration);
+    }
+}
+
+fn describe(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+/// A label value with backslashes, quotes and newlines escaped.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// The metrics in the Prometheus text exposition format.
+fn render(stats: &StatsInfo, tools: &ToolMetricsRegistry) -> String {
+    let mut out = String::new();
+    let gauges = [
+        (
+            "mcpvil_uptime_seconds",
+            "Time since the compositor started.",
+            stats.uptime_secs,
+        ),
+        (
+            "mcpvil_clients",
+            "Wayland clients connected, including Xwayland.",
+            stats.clients as f64,
+        ),
+        ("mcpvil_windows", "Windows mapped.", stats.windows as f64),
+    ];
+    for (name, help, value) in gauges {
+        describe(&mut out, name, "gauge", help);
+        let _ = writeln!(out, "{} {}", name, value);
+    }
+    let counters = [
+        (
+            "mcpvil_frames_rendered_total",
+            "Frames rendered.",
+            stats.frames_rendered,
+        ),
+        (
+            "mcpvil_dropped_frames_total",
+            "Frames that took longer than the frame interval to render.",
+            stats.dropped_frames,
+        ),
+        (
+            "mcpvil_screenshots_total",
+            "Screenshots captured.",
+            stats.screenshots_taken,
+        ),
+    ];
+    for (name, help, value) in counters {
+        describe(&mut out, name, "counter", help);
+        let _ = writeln!(out, "{} {}", name, value);
+    }
+
+    describe(
+        &mut out,
+        "mcpvil_frame_time_seconds",
+        "histogram",
+        "Time to render a frame of every output.",
+    );
+    stats
+        .frame_time_histogram
+        .write(&mut out, "mcpvil_frame_time_seconds", "");
+    describe(
+        &mut out,
+        "mcpvil_screenshot_latency_seconds",
+        "histogram",
+        "Time from a screenshot request to its frame being captured.",
+    );
+    stats
+        .screenshot_latency_histogram
+        .write(&mut out, "mcpvil_screenshot_latency_seconds", "");
+
+    describe(
+        &mut out,
+        "mcpvil_tool_calls_total",
+        "counter",
+        "MCP tool calls.",
+    );
+    for (tool, calls) in &tools.tools {
+        let _ = writeln!(
+            out,
+            "mcpvil_tool_calls_total{{tool=\"{}\"}} {}",
+            escape(tool),
+            calls.calls
+        );
+    }
+    describe(
+        &mut out,
+        "mcpvil_tool_errors_total",
+        "counter",
+        "MCP tool calls that returned an error.",
+    );
+    for (tool, calls) in &tools.tools {
+        let _ = writeln!(
+            out,
+            "mcpvil_tool_errors_total{{tool=\"{}\"}} {}",
+            escape(tool),
+            calls.errors
+        );
+    }
+    describe(
+        &mut out,
+        "mcpvil_tool_duration_seconds",
+        "histogram",
+        "Time to answer an MCP tool call.",
+    );
+    for (tool, calls) in &tools.tools {
+        let labels = format!("tool=\"{}\"", escape(tool));
+        calls
+            .duration
+            .write(&mut out, "mcpvil_tool_duration_seconds", &labels);
+    }
+    out
+}
+
+/// Serves the metrics for Prometheus to scrape at /metrics, for as long as
+/// mcpvil runs.
+pub async fn serve_metrics(
+    command_tx: CommandSender,
+    tool_metrics: ToolMetrics,
+    address: std::net::SocketAddr,
+) -> std::io::Result<()> {
+    let scrape = move || {
+        let command_tx = command_tx.clone();
+        let tool_metrics = tool_metrics.clone();
+        async move {
+            let (response_tx, response_rx) = tokio::sync::oneshot::channel();
+            let stats = match command_tx.send(McpCommand::GetStats { response_tx }) {
+                Ok(()) => response_rx.await.ok(),
+                Err(_) => None,
+            };
+            let Some(stats) = stats else {
+                return (
+                    StatusCode::SERVICE_UNAVAILABLE,
+                    "The event loop is not running\n",
+                )
+                    .into_response();
+            };
+            let body = render(&stats, &tool_metrics.lock().unwrap());
+            ([(header::CONTENT_TYPE, "text/plain; version=0.0.4")], body).into_response()
+        }
+    };
+    let router = axum::Router::new().route("/metrics", axum::routing::get(scrape));
+    let listener = tokio::net::TcpListener::bind(address).await?;
+    tracing::info!("Serving metrics on http://{}/metrics", address);
+    axum::serve(listener, router).await
+}
@@ -0,0 +1,252 @@
+use std::{collections::HashMap, sync::OnceLock};
+
+use tokio::sync::mpsc::UnboundedSender;
+use zbus::{interface, object_server::SignalEmitter, zvariant::OwnedValue, Connection};
+
+use super::{Notification, NotificationAction, NotificationLog, Notifications};
+use crate::events::WindowEvent;
+
+const BUS_NAME: &str = "org.freedesktop.Notifications";
+const OBJECT_PATH: &str = "/org/freedesktop/Notifications";
+
+/// NotificationClosed reasons from the spec.
+const CLOSED_DISMISSED: u32 = 2;
+const CLOSED_BY_CALL: u32 = 3;
+
+/// Notifications kept; older ones are dropped.
+const HISTORY_LIMIT: usize = 256;
+
+/// The daemon's connection, for emitting signals from invoke_action.
+static CONNECTION: OnceLock<Connection> = OnceLock::new();
+
+/// Serves the notification daemon on the session bus for as long as mcpvil
+/// runs, recording notifications in `notifications` and announcing them to
+/// MCP clients through `events`.
+pub async fn run_daemon(notifications: Notifications, events: UnboundedSender<WindowEvent>) {
+    let daemon = Daemon {
+        notifications,
+        events,
+        resident: Vec::new(),
+    };
+    let result = async {
+        zbus::connection::Builder::session()?
+            .name(BUS_NAME)?
+            .serve_at(OBJECT_PATH, daemon)?
+            .build()
+            .await
+    }
+    .await;
+    match result {
+        Ok(connection) => {
+            tracing::info!("Serving desktop notifications as {}", BUS_NAME);
+            let _ = CONNECTION.set(connection);
+            std::future::pending::<()>().await;
+        }
+        Err(e) => tracing::warn!("Failed to serve desktop notifications: {}", e),
+    }
+}
+
+/// Invokes one of a notification's actions, as if the user had clicked it,
+/// then closes the notification unless it is resident.
+pub async fn invoke_action(
+    notifications: &Notifications,
+    id: u32,
+    action: &str,
+) -> Result<Notification, String> {
+    let connection = CONNECTION
+        .get()
+        .ok_or("The notification daemon is not running")?;
+    let notification = notifications
+        .lock()
+        .unwrap()
+        .get(id)
+        .cloned()
+        .ok_or_else(|| format!("No notification with id {}", id))?;
+    if notification.closed {
+        return Err(format!("Notification {} is already closed", id));
+    }
+    if !notification.actions.iter().any(|a| a.key == action) {
+        let keys: Vec<&str> = notification
+            .actions
+            .iter()
+            .map(|a| a.key.as_str())
+            .collect();
+        return Err(format!(
+            "Notification {} has no action '{}'; available: {}",
+            id,
+            action,
+            keys.join(", ")
+        ));
+    }
+
+    let iface = connection
+        .object_server()
+        .interface::<_, Daemon>(OBJECT_PATH)
+        .await
+        .map_err(|e| e.to_string())?;
+    let emitter = iface.signal_emitter();
+    Daemon::action_invoked(emitter, id, action)
+        .await
+        .map_err(|e| format!("Failed to send ActionInvoked: {}", e))?;
+    let daemon = iface.get().await;
+    if !daemon.resident.contains(&id) {
+        daemon.close(emitter, id, CLOSED_DISMISSED).await;
+    }
+    Ok(notification)
+}
+
+impl NotificationLog {
+    /// Records a notification, replacing the one with `replaces_id` if that is
+    /// set and still known, and returns its id.
+    fn add(&mut self, replaces_id: u32, mut notification: Notification) -> u32 {
+        if replaces_id != 0 {
+            if let Some(old) = self.get_mut(replaces_id) {
+                notification.id = replaces_id;
+                *old = notification;
+                return replaces_id;
+            }
+        }
+        self.last_id = self.last_id.wrapping_add(1).max(1);
+        notification.id = self.last_id;
+        if self.notifications.len() == HISTORY_LIMIT {
+            self.notifications.pop_front();
+        }
+        self.notifications.push_back(notification);
+        self.last_id
+    }
+
+    /// Marks a notification closed, returning it if it was open.
+    fn close(&mut self, id: u32) -> Option<Notification> {
+        let notification = self.get_mut(id).filter(|n| !n.closed)?;
+        notification.closed = true;
+        Some(notification.clone())
+    }
+
+    fn get(&self, id: u32) -> Option<&Notification> {
+        self.notifications.iter().find(|n| n.id == id)
+    }
+
+    fn get_mut(&mut self, id: u32) -> Option<&mut Notification> {
+        self.notifications.iter_mut().find(|n| n.id == id)
+    }
+}
+
+/// The org.freedesktop.Notifications server. Notifications never expire on
+/// their own, so they stay available to get_notifications until closed.
+struct Daemon {
+    notifications: Notifications,
+    events: UnboundedSender<WindowEvent>,
+    /// Notifications with the resident hint, which stay open after an action
+    resident: Vec<u32>,
+}
+
+impl Daemon {
+    async fn close(&self, emitter: &SignalEmitter<'_>, id: u32, reason: u32) {
+        let closed = self.notifications.lock().unwrap().close(id);
+        if let Some(notification) = closed {
+            let _ = Self::notification_closed(emitter, id, reason).await;
+            let _ = self
+                .events
+                .send(WindowEvent::NotificationClosed { notification });
+        }
+    }
+}
+
+#[interface(name = "org.freedesktop.Notifications")]
+impl Daemon {
+    #[allow(clippy::too_many_arguments)]
+    async fn notify(
+        &mut self,
+        app_name: String,
+        replaces_id: u32,
+        app_icon: String,
+        summary: String,
+        body: String,
+        actions: Vec<String>,
+        hints: HashMap<String, OwnedValue>,
+        expire_timeout: i32,
+    ) -> u32 {
+        let hint_str = |name: &str| {
+            hints
+                .get(name)
+                .and_then(|value| value.downcast_ref::<&str>().ok())
+                .map(String::from)
+        };
+        let timestamp_ms = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or_default();
+        let notification = Notification {
+            id: 0,
+            app_name,
+            summary,
+            body,
+            icon: Some(app_icon)
+                .filter(|icon| !icon.is_empty())
+                .or_else(|| hint_str("image-path")),
+            // Keys and labels alternate
+            actions: actions
+                .chunks_exact(2)
+                .map(|pair| NotificationAction {
+                    key: pair[0].clone(),
+                    label: pair[1].clone(),
+                })
+                .collect(),
+            urgency: hints
+                .get("urgency")
+                .and_then(|value| value.downcast_ref::<u8>().ok()),
+            desktop_entry: hint_str("desktop-entry"),
+            expire_timeout,
+            timestamp_ms,
+            closed: false,
+        };
+
+        let id = self
+            .notifications
+            .lock()
+            .unwrap()
+            .add(replaces_id, notification.clone());
+        let resident = hints
+            .get("resident")
+            .and_then(|value| value.downcast_ref::<bool>().ok())
+            .unwrap_or(false);
+        self.resident.retain(|&other| other != id);
+        if resident {
+            self.resident.push(id);
+        }
+        let _ = self.events.send(WindowEvent::NotificationShown {
+            notification: Notification { id, ..notification },
+        });
+        id
+    }
+
+    async fn close_notification(
+        &self,
+        id: u32,
+        #[zbus(signal_emitter)] emitter: SignalEmitter<'_>,
+    ) {
+        self.close(&emitter, id, CLOSED_BY_CALL).await;
+    }
+
+    fn get_capabilities(&self) -> Vec<&'static str> {
+        vec!["actions", "body", "icon-static", "persistence"]
+    }
+
+    fn get_server_information(&self) -> (&'static str, &'static str, &'static str, &'static str) {
+        ("mcpvil", "mcpvil", env!("CARGO_PKG_VERSION"), "1.2")
+    }
+
+    #[zbus(signal)]
+    async fn notification_closed(
+        emitter: &SignalEmitter<'_>,
+        id: u32,
+        reason: u32,
+    ) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    async fn action_invoked(
+        emitter: &SignalEmitter<'_>,
+        id: u32,
+        action_key: &str,
+    ) -> zbus::Result<()>;
+}
@@ -0,0 +1,82 @@
+//! Desktop notifications. With the `notifications` feature, mcpvil serves
+//! org.freedesktop.Notifications on the session bus, keeps what apps send so
+//! agents can check that a notification appeared, and invokes their actions
+//! as if they had been clicked.
+
+#[cfg(feature = "notifications")]
+mod daemon;
+
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+};
+
+use rmcp::schemars::{self, JsonSchema};
+use serde::Serialize;
+
+#[cfg(feature = "notifications")]
+pub use daemon::{invoke_action, run_daemon};
+
+/// A notification an app sent, as returned by get_notifications.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct Notification {
+    pub id: u32,
+    pub app_name: String,
+    pub summary: String,
+    pub body: String,
+    pub icon: Option<String>,
+    pub actions: Vec<NotificationAction>,
+    /// 0 (low), 1 (normal) or 2 (critical)
+    pub urgency: Option<u8>,
+    /// Desktop file id of the sending app, from the desktop-entry hint
+    pub desktop_entry: Option<String>,
+    /// How long the app asked for it to be shown: -1 for the server's default,
+    /// 0 for until dismissed
+    pub expire_timeout: i32,
+    /// When it was received, in milliseconds since the Unix epoch
+    pub timestamp_ms: u64,
+    /// Closed by the app, or dismissed through invoke_notification_action
+    pub closed: bool,
+}
+
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct NotificationAction {
+    /// Key passed to invoke_notification_action; "default" is clicking the
+    /// notification itself
+    pub key: String,
+    pub label: String,
+}
+
+/// Received notifications, oldest first.
+#[derive(Debug, Default)]
+pub struct NotificationLog {
+    notifications: VecDeque<Notification>,
+    #[cfg_attr(not(feature = "notifications"), allow(dead_code))]
+    last_id: u32,
+}
+
+pub type Notifications = Arc<Mutex<NotificationLog>>;
+
+impl NotificationLog {
+    /// Notifications newer than `since_id`, optionally only open ones.
+    pub fn list(&self, since_id: u32, include_closed: bool) -> Vec<Notification> {
+        self.notifications
+            .iter()
+            .filter(|n| n.id > since_id && (include_closed || !n.closed))
+            .cloned()
+            .collect()
+    }
+
+    pub fn clear(&mut self) {
+        self.notifications.clear();
+    }
+}
+
+#[cfg(not(feature = "notifications"))]
+pub async fn invoke_action(
+    _notifications: &Notifications,
+    _id: u32,
+    _action: &str,
+) -> Result<Notification, String> {
+    Err("mcpvil was built without the notifications feature".to_string())
+}
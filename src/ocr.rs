@@ -0,0 +1,70 @@
+use rmcp::schemars::{self, JsonSchema};
+use serde::Serialize;
+
+/// A line of recognized text and where it was found.
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct TextBox {
+    pub text: String,
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+    /// 0-100, as reported by tesseract
+    pub confidence: i32,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct OcrResult {
+    /// All recognized text, in reading order
+    pub text: String,
+    pub boxes: Vec<TextBox>,
+}
+
+/// Runs tesseract over an encoded image, offsetting the boxes by `origin` so
+/// they are in the coordinates of whatever the image was cropped from.
+#[cfg(feature = "ocr")]
+pub fn recognize(image: &[u8], origin: (i32, i32), language: &str) -> Result<OcrResult, String> {
+    use leptess::{capi::TessPageIteratorLevel_RIL_TEXTLINE, LepTess};
+
+    let mut tess = LepTess::new(None, language)
+        .map_err(|e| format!("Failed to load tesseract data for '{}': {}", language, e))?;
+    tess.set_image_from_mem(image)
+        .map_err(|e| format!("Failed to load image for OCR: {}", e))?;
+    let text = tess
+        .get_utf8_text()
+        .map_err(|e| format!("OCR produced invalid text: {}", e))?;
+
+    let mut boxes = Vec::new();
+    if let Some(lines) = tess.get_component_boxes(TessPageIteratorLevel_RIL_TEXTLINE, true) {
+        for line in &lines {
+            let b = line.as_ref();
+            let (x, y, width, height) = (b.x, b.y, b.w, b.h);
+            tess.set_rectangle(x, y, width, height);
+            let Ok(line_text) = tess.get_utf8_text() else {
+                continue;
+            };
+            let line_text = line_text.trim();
+            if line_text.is_empty() {
+                continue;
+            }
+            boxes.push(TextBox {
+                text: line_text.to_string(),
+                x: origin.0 + x,
+                y: origin.1 + y,
+                width,
+                height,
+                confidence: tess.mean_text_conf(),
+            });
+        }
+    }
+
+    Ok(OcrResult {
+        text: text.trim().to_string(),
+        boxes,
+    })
+}
+
+#[cfg(not(feature = "ocr"))]
+pub fn recognize(_image: &[u8], _origin: (i32, i32), _language: &str) -> Result<OcrResult, String> {
+    Err("mcpvil was built without the ocr feature".to_string())
+}
@@ -0,0 +1,132 @@
+use std::time::{Duration, Instant};
+
+use smithay::reexports::calloop::{
+    ping::Ping,
+    timer::{TimeoutAction, Timer},
+};
+
+use crate::Smallvil;
+
+/// When the backends render: at most `fps` frames per second, and with
+/// `idle_when_no_damage` not at all after a frame that changed nothing, until
+/// a client commits, input arrives or a capture is requested.
+#[derive(Default)]
+pub struct FramePacing {
+    /// Frame rate limit; the output's refresh rate if unset
+    pub fps: Option<f64>,
+    pub idle_when_no_damage: bool,
+    /// The last frame damaged nothing
+    idle: bool,
+    /// Something happened since the last frame that may need rendering
+    woken: bool,
+    last_frame: Option<Instant>,
+    damaged_frames: u64,
+    /// Wakes backends that only render when asked to, like winit
+    pub waker: Option<Ping>,
+    /// A wake-up is already scheduled for when the limit allows the next frame
+    wake_scheduled: bool,
+}
+
+impl FramePacing {
+    /// Time between frames under the limit, if any.
+    pub fn interval(&self) -> Option<Duration> {
+        self.fps.map(|fps| Duration::from_secs_f64(1.0 / fps))
+    }
+
+    /// How long until the limit allows the next frame.
+    fn next_frame_wait(&self) -> Duration {
+        self.interval()
+            .zip(self.last_frame)
+            .map_or(Duration::ZERO, |(interval, last)| {
+                interval.saturating_sub(last.elapsed())
+            })
+    }
+}
+
+impl Smallvil {
+    /// Whether there is anything to render. In lockstep, only when stepped or
+    /// for a capture; otherwise always, unless idling after a frame without
+    /// damage. Waits, recordings and playback always keep frames coming. The
+    /// frame limit is up to the backend.
+    pub fn frame_due(&self) -> bool {
+        let captures =
+            !self.pending_screenshots.is_empty() || !self.pending_screencopies.is_empty();
+        if let Some(lockstep) = &self.lockstep {
+            return lockstep.steps > 0 || captures;
+        }
+
+        let pacing = &self.frame_pacing;
+        let busy = captures
+            || self.recording.is_some()
+            || !self.idle_waits.is_empty()
+            || !self.stability_waits.is_empty()
+            || self.pending_drag.is_some()
+            || self.pending_gesture.is_some()
+            || self.pending_pen_stroke.is_some();
+        busy || !pacing.idle_when_no_damage || !pacing.idle || pacing.woken
+    }
+
+    /// Whether the frame being rendered advances clients and animations.
+    pub fn frame_advances(&self) -> bool {
+        self.lockstep
+            .as_ref()
+            .is_none_or(|lockstep| lockstep.steps > 0)
+    }
+
+    /// Notes that something may need rendering and wakes a backend that
+    /// stopped redrawing.
+    pub fn request_frame(&mut self) {
+        self.frame_pacing.woken = true;
+        self.wake_backend();
+    }
+
+    /// Called by backends that only render when asked to after each frame:
+    /// asks for the next one if there is anything to render.
+    pub fn schedule_next_frame(&mut self) {
+        if self.frame_due() {
+            self.wake_backend();
+        }
+    }
+
+    /// Wakes the backend now, or once the frame limit allows.
+    fn wake_backend(&mut self) {
+        let pacing = &mut self.frame_pacing;
+        let Some(waker) = pacing.waker.clone() else {
+            return;
+        };
+        let wait = pacing.next_frame_wait();
+        if wait.is_zero() {
+            waker.ping();
+        } else if !pacing.wake_scheduled {
+            pacing.wake_scheduled = true;
+            let timer =
+                self.loop_handle
+                    .insert_source(Timer::from_duration(wait), move |_, _, data| {
+                        data.state.frame_pacing.wake_scheduled = false;
+                        waker.ping();
+                        TimeoutAction::Drop
+                    });
+            if let Err(e) = timer {
+                tracing::error!("Failed to schedule a frame: {}", e);
+            }
+        }
+    }
+
+    /// Records that a frame was rendered, going idle if it damaged nothing.
+    pub fn frame_rendered(&mut self) {
+        let pacing = &mut self.frame_pacing;
+        pacing.idle = self.activity.damaged_frames == pacing.damaged_frames;
+        pacing.damaged_frames = self.activity.damaged_frames;
+        pacing.woken = false;
+        pacing.last_frame = Some(Instant::now());
+    }
+
+    /// Changes the frame limit and idling, waking the backend to apply them.
+    pub fn set_frame_rate(&mut self, fps: Option<f64>, idle_when_no_damage: Option<bool>) {
+        self.frame_pacing.fps = fps;
+        if let Some(idle_when_no_damage) = idle_when_no_damage {
+            self.frame_pacing.idle_when_no_damage = idle_when_no_damage;
+        }
+        self.request_frame();
+    }
+}
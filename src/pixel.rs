@@ -0,0 +1,83 @@
+use rmcp::schemars::{self, JsonSchema};
+use serde::Serialize;
+
+/// An RGBA color read back from the framebuffer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, JsonSchema)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub a: u8,
+}
+
+impl Color {
+    /// Parses `#RRGGBB` or `#RRGGBBAA`, with or without the `#`.
+    pub fn from_hex(hex: &str) -> Result<Self, String> {
+        let digits = hex.strip_prefix('#').unwrap_or(hex);
+        let invalid = || format!("Invalid color '{}', expected #RRGGBB or #RRGGBBAA", hex);
+        if !digits.is_ascii() || (digits.len() != 6 && digits.len() != 8) {
+            return Err(invalid());
+        }
+        let channel = |i: usize| u8::from_str_radix(&digits[i..i + 2], 16).map_err(|_| invalid());
+        Ok(Self {
+            r: channel(0)?,
+            g: channel(2)?,
+            b: channel(4)?,
+            a: if digits.len() == 8 { channel(6)? } else { 255 },
+        })
+    }
+
+    pub fn to_hex(self) -> String {
+        format!("#{:02x}{:02x}{:02x}{:02x}", self.r, self.g, self.b, self.a)
+    }
+
+    /// Largest difference in any channel.
+    pub fn distance(self, other: Color) -> u8 {
+        [
+            self.r.abs_diff(other.r),
+            self.g.abs_diff(other.g),
+            self.b.abs_diff(other.b),
+            self.a.abs_diff(other.a),
+        ]
+        .into_iter()
+        .max()
+        .unwrap_or(0)
+    }
+}
+
+/// The mean color of an encoded image.
+pub fn average(image: &[u8]) -> Result<Color, String> {
+    let img = image::load_from_memory(image)
+        .map_err(|e| format!("Failed to decode capture: {}", e))?
+        .to_rgba8();
+    let count = img.width() as u64 * img.height() as u64;
+    if count == 0 {
+        return Err("The capture is empty".to_string());
+    }
+    let mut sums = [0u64; 4];
+    for pixel in img.pixels() {
+        for (sum, channel) in sums.iter_mut().zip(pixel.0) {
+            *sum += channel as u64;
+        }
+    }
+    let mean = |sum: u64| ((sum + count / 2) / count) as u8;
+    Ok(Color {
+        r: mean(sums[0]),
+        g: mean(sums[1]),
+        b: mean(sums[2]),
+        a: mean(sums[3]),
+    })
+}
+
+/// What get_pixel reports.
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct PixelProbe {
+    pub x: i32,
+    pub y: i32,
+    pub color: Color,
+    pub hex: String,
+    /// Whether the color is within the tolerance of the expected one, if given
+    pub matches: Option<bool>,
+    /// Largest per-channel difference from the expected color
+    pub difference: Option<u8>,
+}
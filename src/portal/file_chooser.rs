@@ -0,0 +1,150 @@
+use std::{path::PathBuf, time::Duration};
+
+use tokio::sync::mpsc::UnboundedSender;
+use zbus::{interface, zvariant::OwnedObjectPath};
+
+use super::{
+    file_uri,
+    server::{owned, Options, RESPONSE_CANCELLED, RESPONSE_SUCCESS},
+    FileChooserRequest, FileChooserResponses, StagedResponse,
+};
+use crate::events::WindowEvent;
+
+/// How long a dialog waits for stage_file_chooser_response before it is cancelled.
+const DIALOG_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// The org.freedesktop.impl.portal.FileChooser backend. Dialogs are answered
+/// with what stage_file_chooser_response staged, or wait for it.
+pub struct FileChooser {
+    pub responses: FileChooserResponses,
+    pub events: UnboundedSender<WindowEvent>,
+}
+
+impl FileChooser {
+    async fn respond(&self, request: FileChooserRequest) -> (u32, Options) {
+        let _ = self.events.send(WindowEvent::FileChooserOpened {
+            request: request.clone(),
+        });
+        let staged = {
+            let mut responses = self.responses.lock().unwrap();
+            match responses.staged.pop_front() {
+                Some(response) => Ok(response),
+                None => {
+                    let (response_tx, response_rx) = tokio::sync::oneshot::channel();
+                    responses.waiting.push_back((request.clone(), response_tx));
+                    Err(response_rx)
+                }
+            }
+        };
+        let response = match staged {
+            Ok(response) => response,
+            Err(response_rx) => match tokio::time::timeout(DIALOG_TIMEOUT, response_rx).await {
+                Ok(Ok(response)) => response,
+                // Dropping the receiver takes the dialog off the waiting list
+                _ => return (RESPONSE_CANCELLED, Options::new()),
+            },
+        };
+
+        let paths = match response {
+            StagedResponse::Cancel => return (RESPONSE_CANCELLED, Options::new()),
+            StagedResponse::Files(paths) => paths,
+        };
+        // SaveFiles asks for a folder to save its files in
+        let paths: Vec<PathBuf> = match paths.as_slice() {
+            [folder] if request.kind == "save_files" => {
+                request.files.iter().map(|name| folder.join(name)).collect()
+            }
+            _ => paths,
+        };
+        let uris: Vec<String> = paths.iter().map(|path| file_uri(path)).collect();
+        (
+            RESPONSE_SUCCESS,
+            Options::from([("uris".to_string(), owned(uris))]),
+        )
+    }
+}
+
+#[interface(name = "org.freedesktop.impl.portal.FileChooser")]
+impl FileChooser {
+    async fn open_file(
+        &self,
+        _handle: OwnedObjectPath,
+        app_id: String,
+        _parent_window: String,
+        title: String,
+        options: Options,
+    ) -> (u32, Options) {
+        self.respond(request("open", app_id, title, &options)).await
+    }
+
+    async fn save_file(
+        &self,
+        _handle: OwnedObjectPath,
+        app_id: String,
+        _parent_window: String,
+        title: String,
+        options: Options,
+    ) -> (u32, Options) {
+        self.respond(request("save", app_id, title, &options)).await
+    }
+
+    async fn save_files(
+        &self,
+        _handle: OwnedObjectPath,
+        app_id: String,
+        _parent_window: String,
+        title: String,
+        options: Options,
+    ) -> (u32, Options) {
+        self.respond(request("save_files", app_id, title, &options))
+            .await
+    }
+
+    #[zbus(property, name = "version")]
+    fn version(&self) -> u32 {
+        4
+    }
+}
+
+/// Describes a dialog from the options the app opened it with.
+fn request(
+    kind: &'static str,
+    app_id: String,
+    title: String,
+    options: &Options,
+) -> FileChooserRequest {
+    let flag = |name: &str| {
+        options
+            .get(name)
+            .and_then(|value| value.downcast_ref::<bool>().ok())
+            .unwrap_or(false)
+    };
+    // Paths are passed as nul-terminated byte arrays
+    let path = |value: &zbus::zvariant::OwnedValue| {
+        let bytes = Vec::<u8>::try_from(value.try_clone().ok()?).ok()?;
+        let bytes = bytes.strip_suffix(&[0]).unwrap_or(&bytes);
+        Some(String::from_utf8_lossy(bytes).into_owned())
+    };
+    FileChooserRequest {
+        kind,
+        app_id,
+        title,
+        multiple: flag("multiple"),
+        directory: flag("directory"),
+        current_name: options
+            .get("current_name")
+            .and_then(|value| value.downcast_ref::<&str>().ok())
+            .map(String::from),
+        current_folder: options.get("current_folder").and_then(path),
+        files: options
+            .get("files")
+            .and_then(|value| Vec::<Vec<u8>>::try_from(value.try_clone().ok()?).ok())
+            .unwrap_or_default()
+            .iter()
+            .map(|file| {
+                let file = file.strip_suffix(&[0]).unwrap_or(file);
+                String::from_utf8_lossy(file).into_owned()
+            })
+            .collect(),
+    }
+}
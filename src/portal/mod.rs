@@ -0,0 +1,100 @@
+//! xdg-desktop-portal backends, through which sandboxed apps reach the
+//! desktop. With the `portal` feature, mcpvil serves them on the session bus:
+//! Screenshot captures the primary output, FileChooser answers open and save
+//! dialogs with paths an agent stages with stage_file_chooser_response, and,
+//! with the `screencast` feature, ScreenCast streams outputs into PipeWire.
+
+#[cfg(feature = "portal")]
+mod file_chooser;
+#[cfg(feature = "screencast")]
+mod screencast;
+#[cfg(feature = "portal")]
+mod screenshot;
+#[cfg(feature = "portal")]
+mod server;
+
+use std::{
+    collections::VecDeque,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use serde::Serialize;
+
+#[cfg(feature = "portal")]
+pub use server::run_portal;
+
+/// What a file chooser dialog returns.
+#[derive(Debug, Clone)]
+pub enum StagedResponse {
+    /// The files picked, or where to save; for a dialog saving several files,
+    /// a single folder to save them in
+    Files(Vec<PathBuf>),
+    Cancel,
+}
+
+/// A file chooser dialog an app opened, as reported from the `portals` logger.
+#[derive(Debug, Clone, Serialize)]
+pub struct FileChooserRequest {
+    /// "open", "save" or "save_files"
+    pub kind: &'static str,
+    pub app_id: String,
+    pub title: String,
+    /// More than one file may be picked
+    pub multiple: bool,
+    /// A folder is to be picked rather than a file
+    pub directory: bool,
+    /// File name suggested for saving
+    pub current_name: Option<String>,
+    pub current_folder: Option<String>,
+    /// Names of the files a save_files dialog saves
+    pub files: Vec<String>,
+}
+
+/// Responses staged for upcoming dialogs, and dialogs waiting for one.
+#[derive(Default)]
+pub struct FileChooserQueue {
+    staged: VecDeque<StagedResponse>,
+    waiting: VecDeque<(
+        FileChooserRequest,
+        tokio::sync::oneshot::Sender<StagedResponse>,
+    )>,
+}
+
+pub type FileChooserResponses = Arc<Mutex<FileChooserQueue>>;
+
+impl FileChooserQueue {
+    /// Answers the oldest dialog that is waiting, returning it, or keeps the
+    /// response for the next dialog to open.
+    pub fn stage(&mut self, response: StagedResponse) -> Option<FileChooserRequest> {
+        while let Some((request, response_tx)) = self.waiting.pop_front() {
+            // Dialogs that timed out have dropped their receiver
+            if response_tx.send(response.clone()).is_ok() {
+                return Some(request);
+            }
+        }
+        self.staged.push_back(response);
+        None
+    }
+
+    /// Responses not yet taken by a dialog.
+    pub fn staged(&self) -> usize {
+        self.staged.len()
+    }
+}
+
+/// A file:// URI for an absolute path, percent-encoding everything but
+/// unreserved characters and slashes.
+#[cfg(feature = "portal")]
+pub fn file_uri(path: &std::path::Path) -> String {
+    let mut uri = String::from("file://");
+    for &byte in path.as_os_str().as_encoded_bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                uri.push(byte as char)
+            }
+            _ => uri.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    uri
+}
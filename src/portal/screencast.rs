@@ -0,0 +1,155 @@
+use zbus::{
+    interface,
+    object_server::{ObjectServer, SignalEmitter},
+    zvariant::OwnedObjectPath,
+};
+
+use super::server::{owned, Options, RESPONSE_OTHER, RESPONSE_SUCCESS};
+use crate::{telemetry::CommandSender, McpCommand};
+
+const SOURCE_TYPE_MONITOR: u32 = 1;
+const CURSOR_MODE_HIDDEN: u32 = 1;
+
+/// The org.freedesktop.impl.portal.ScreenCast backend. There is no source
+/// picker: every session casts the primary output.
+pub struct ScreenCast {
+    pub command_tx: CommandSender,
+}
+
+#[interface(name = "org.freedesktop.impl.portal.ScreenCast")]
+impl ScreenCast {
+    async fn create_session(
+        &self,
+        _handle: OwnedObjectPath,
+        session_handle: OwnedObjectPath,
+        _app_id: String,
+        _options: Options,
+        #[zbus(object_server)] server: &ObjectServer,
+    ) -> (u32, Options) {
+        let session = Session {
+            command_tx: self.command_tx.clone(),
+            path: session_handle.clone(),
+            node_ids: Vec::new(),
+        };
+        match server.at(&session_handle, session).await {
+            Ok(_) => (RESPONSE_SUCCESS, Options::new()),
+            Err(e) => {
+                tracing::error!("Failed to create screencast session: {}", e);
+                (RESPONSE_OTHER, Options::new())
+            }
+        }
+    }
+
+    async fn select_sources(
+        &self,
+        _handle: OwnedObjectPath,
+        session_handle: OwnedObjectPath,
+        _app_id: String,
+        _options: Options,
+        #[zbus(object_server)] server: &ObjectServer,
+    ) -> (u32, Options) {
+        match server.interface::<_, Session>(&session_handle).await {
+            Ok(_) => (RESPONSE_SUCCESS, Options::new()),
+            Err(_) => (RESPONSE_OTHER, Options::new()),
+        }
+    }
+
+    async fn start(
+        &self,
+        _handle: OwnedObjectPath,
+        session_handle: OwnedObjectPath,
+        _app_id: String,
+        _parent_window: String,
+        _options: Options,
+        #[zbus(object_server)] server: &ObjectServer,
+    ) -> (u32, Options) {
+        let Ok(session) = server.interface::<_, Session>(&session_handle).await else {
+            return (RESPONSE_OTHER, Options::new());
+        };
+
+        let (response_tx, response_rx) = tokio::sync::oneshot::channel();
+        let command = McpCommand::StartScreencast {
+            output: None,
+            response_tx,
+        };
+        if self.command_tx.send(command).is_err() {
+            return (RESPONSE_OTHER, Options::new());
+        }
+        let info = match response_rx.await {
+            Ok(Ok(info)) => info,
+            Ok(Err(e)) => {
+                tracing::error!("Failed to start portal screencast: {}", e);
+                return (RESPONSE_OTHER, Options::new());
+            }
+            Err(_) => return (RESPONSE_OTHER, Options::new()),
+        };
+        session.get_mut().await.node_ids.push(info.node_id);
+
+        let properties = Options::from([
+            ("position".to_string(), owned((info.x, info.y))),
+            ("size".to_string(), owned((info.width, info.height))),
+            ("source_type".to_string(), owned(SOURCE_TYPE_MONITOR)),
+        ]);
+        let streams = vec![(info.node_id, properties)];
+        (
+            RESPONSE_SUCCESS,
+            Options::from([("streams".to_string(), owned(streams))]),
+        )
+    }
+
+    #[zbus(property)]
+    fn available_source_types(&self) -> u32 {
+        SOURCE_TYPE_MONITOR
+    }
+
+    /// Frames are captured without the cursor
+    #[zbus(property)]
+    fn available_cursor_modes(&self) -> u32 {
+        CURSOR_MODE_HIDDEN
+    }
+
+    #[zbus(property, name = "version")]
+    fn version(&self) -> u32 {
+        4
+    }
+}
+
+/// A portal session, whose screencasts end when it is closed.
+struct Session {
+    command_tx: CommandSender,
+    path: OwnedObjectPath,
+    node_ids: Vec<u32>,
+}
+
+#[interface(name = "org.freedesktop.impl.portal.Session")]
+impl Session {
+    async fn close(
+        &mut self,
+        #[zbus(object_server)] server: &ObjectServer,
+        #[zbus(signal_emitter)] emitter: SignalEmitter<'_>,
+    ) {
+        for node_id in self.node_ids.drain(..) {
+            let (response_tx, _) = tokio::sync::oneshot::channel();
+            let _ = self.command_tx.send(McpCommand::StopScreencast {
+                node_id,
+                response_tx,
+            });
+        }
+        let _ = Self::closed(&emitter).await;
+
+        // Removing the object waits for this call to return, so do it afterwards
+        let server = server.clone();
+        let path = self.path.clone();
+        tokio::spawn(async move {
+            let _ = server.remove::<Session, _>(&path).await;
+        });
+    }
+
+    #[zbus(signal)]
+    async fn closed(emitter: &SignalEmitter<'_>) -> zbus::Result<()>;
+
+    #[zbus(property, name = "version")]
+    fn version(&self) -> u32 {
+        1
+    }
+}
@@ -0,0 +1,80 @@
+use std::path::PathBuf;
+
+use zbus::{interface, zvariant::OwnedObjectPath};
+
+use super::{
+    file_uri,
+    server::{owned, Options, RESPONSE_OTHER, RESPONSE_SUCCESS},
+};
+use crate::{screenshot::ScreenshotOptions, telemetry::CommandSender, McpCommand};
+
+/// The org.freedesktop.impl.portal.Screenshot backend. There is no dialog:
+/// every request captures the primary output into the session directory.
+pub struct Screenshot {
+    pub command_tx: CommandSender,
+    pub dir: PathBuf,
+}
+
+#[interface(name = "org.freedesktop.impl.portal.Screenshot")]
+impl Screenshot {
+    async fn screenshot(
+        &self,
+        _handle: OwnedObjectPath,
+        app_id: String,
+        _parent_window: String,
+        _options: Options,
+    ) -> (u32, Options) {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_millis())
+            .unwrap_or_default();
+        let path = self.dir.join(format!("portal-screenshot-{}.png", now));
+        let options = ScreenshotOptions {
+            filename: Some(path.to_string_lossy().into_owned()),
+            ..Default::default()
+        };
+
+        let (response_tx, response_rx) = tokio::sync::oneshot::channel();
+        let command = McpCommand::Screenshot {
+            options,
+            response_tx,
+        };
+        if self.command_tx.send(command).is_err() {
+            return (RESPONSE_OTHER, Options::new());
+        }
+        match response_rx.await {
+            Ok(Ok(_)) => {
+                tracing::info!(
+                    "Took a portal screenshot for {}: {}",
+                    app_id,
+                    path.display()
+                );
+                (
+                    RESPONSE_SUCCESS,
+                    Options::from([("uri".to_string(), owned(file_uri(&path)))]),
+                )
+            }
+            Ok(Err(e)) => {
+                tracing::error!("Failed to take portal screenshot: {}", e);
+                (RESPONSE_OTHER, Options::new())
+            }
+            Err(_) => (RESPONSE_OTHER, Options::new()),
+        }
+    }
+
+    /// There is no color picker
+    async fn pick_color(
+        &self,
+        _handle: OwnedObjectPath,
+        _app_id: String,
+        _parent_window: String,
+        _options: Options,
+    ) -> (u32, Options) {
+        (RESPONSE_OTHER, Options::new())
+    }
+
+    #[zbus(property, name = "version")]
+    fn version(&self) -> u32 {
+        2
+    }
+}
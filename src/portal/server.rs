@@ -0,0 +1,75 @@
+use std::{collections::HashMap, path::PathBuf};
+
+use tokio::sync::mpsc::UnboundedSender;
+use zbus::{
+    zvariant::{OwnedValue, Value},
+    Connection,
+};
+
+use super::{file_chooser::FileChooser, screenshot::Screenshot, FileChooserResponses};
+use crate::{events::WindowEvent, telemetry::CommandSender};
+
+/// Name xdg-desktop-portal finds the backends under, as listed in mcpvil.portal
+const BUS_NAME: &str = "org.freedesktop.impl.portal.desktop.mcpvil";
+const OBJECT_PATH: &str = "/org/freedesktop/portal/desktop";
+
+pub const RESPONSE_SUCCESS: u32 = 0;
+pub const RESPONSE_CANCELLED: u32 = 1;
+pub const RESPONSE_OTHER: u32 = 2;
+
+pub type Options = HashMap<String, OwnedValue>;
+
+/// Serves the portal backends on the session bus for as long as mcpvil runs.
+/// Without a session bus, apps get no screenshots or file dialogs through
+/// portals, and screencasts are only available through start_screencast.
+pub async fn run_portal(
+    command_tx: CommandSender,
+    session_dir: PathBuf,
+    file_chooser: FileChooserResponses,
+    events: UnboundedSender<WindowEvent>,
+) {
+    match connect(command_tx, session_dir, file_chooser, events).await {
+        Ok(_connection) => {
+            tracing::info!("Serving the portal backends as {}", BUS_NAME);
+            std::future::pending::<()>().await;
+        }
+        Err(e) => tracing::warn!("Failed to serve the portal backends: {}", e),
+    }
+}
+
+async fn connect(
+    command_tx: CommandSender,
+    session_dir: PathBuf,
+    file_chooser: FileChooserResponses,
+    events: UnboundedSender<WindowEvent>,
+) -> zbus::Result<Connection> {
+    let builder = zbus::connection::Builder::session()?.name(BUS_NAME)?;
+    #[cfg(feature = "screencast")]
+    let builder = builder.serve_at(
+        OBJECT_PATH,
+        super::screencast::ScreenCast {
+            command_tx: command_tx.clone(),
+        },
+    )?;
+    builder
+        .serve_at(
+            OBJECT_PATH,
+            Screenshot {
+                command_tx,
+                dir: session_dir,
+            },
+        )?
+        .serve_at(
+            OBJECT_PATH,
+            FileChooser {
+                responses: file_chooser,
+                events,
+            },
+        )?
+        .build()
+        .await
+}
+
+pub fn owned<'a>(value: impl Into<Value<'a>>) -> OwnedValue {
+    OwnedValue::try_from(value.into()).expect("Portal results carry no file descriptors")
+}
@@ -0,0 +1,168 @@
+use std::{collections::VecDeque, time::Duration};
+
+use rmcp::schemars::{self, JsonSchema};
+use serde::Serialize;
+use smithay::{
+    backend::renderer::element::RenderElementStates,
+    desktop::{
+        layer_map_for_output,
+        utils::{
+            default_primary_scanout_output_compare,
+            surface_presentation_feedback_flags_from_states, surface_primary_scanout_output,
+            update_primary_scanout_output, OutputPresentationFeedback,
+        },
+    },
+    output::Output,
+    reexports::wayland_protocols::wp::presentation_time::server::wp_presentation_feedback,
+    wayland::presentation::Refresh,
+};
+
+use crate::Smallvil;
+
+/// Frame intervals kept per output for get_frame_timing.
+const INTERVAL_HISTORY: usize = 120;
+
+/// Presentation history of one output.
+#[derive(Debug, Default)]
+pub struct FrameTiming {
+    /// Frames presented so far, sent to clients as the sequence number
+    pub sequence: u64,
+    last_presented: Option<Duration>,
+    intervals: VecDeque<Duration>,
+}
+
+/// Frame timing statistics of an output, as returned by get_frame_timing.
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct FrameTimingInfo {
+    pub output: String,
+    pub frames: u64,
+    /// Refresh rate of the output's mode in Hz
+    pub refresh_hz: f64,
+    /// Rate at which frames were actually presented recently
+    pub measured_fps: Option<f64>,
+    /// Interval between recent frames, in milliseconds
+    pub average_interval_ms: Option<f64>,
+    pub min_interval_ms: Option<f64>,
+    pub max_interval_ms: Option<f64>,
+    /// Presentation time of the last frame on the CLOCK_MONOTONIC clock, in milliseconds
+    pub last_presented_ms: Option<f64>,
+}
+
+/// Collects the presentation feedback of the surfaces just rendered on
+/// `output`, updating which output each surface is primarily shown on.
+pub fn take_presentation_feedback(
+    state: &Smallvil,
+    output: &Output,
+    render_element_states: &RenderElementStates,
+) -> OutputPresentationFeedback {
+    let mut feedback = OutputPresentationFeedback::new(output);
+
+    for window in state.space.elements_for_output(output) {
+        window.with_surfaces(|surface, states| {
+            update_primary_scanout_output(
+                surface,
+                output,
+                states,
+                render_element_states,
+                default_primary_scanout_output_compare,
+            );
+        });
+        window.take_presentation_feedback(
+            &mut feedback,
+            surface_primary_scanout_output,
+            |surface, _| {
+                surface_presentation_feedback_flags_from_states(surface, render_element_states)
+            },
+        );
+    }
+
+    let map = layer_map_for_output(output);
+    for layer in map.layers() {
+        layer.with_surfaces(|surface, states| {
+            update_primary_scanout_output(
+                surface,
+                output,
+                states,
+                render_element_states,
+                default_primary_scanout_output_compare,
+            );
+        });
+        layer.take_presentation_feedback(
+            &mut feedback,
+            surface_primary_scanout_output,
+            |surface, _| {
+                surface_presentation_feedback_flags_from_states(surface, render_element_states)
+            },
+        );
+    }
+
+    feedback
+}
+
+impl Smallvil {
+    /// Marks a frame of `output` as presented now, answering the feedback
+    /// requests of the surfaces in it and recording its timing.
+    pub fn present_frame(&mut self, output: &Output, mut feedback: OutputPresentationFeedback) {
+        let now = self.clock.now();
+        let refresh = output
+            .current_mode()
+            .filter(|mode| mode.refresh > 0)
+            .map_or(Refresh::Unknown, |mode| {
+                Refresh::Fixed(Duration::from_secs(1000) / mode.refresh as u32)
+            });
+
+        let timing = self.frame_timing.entry(output.name()).or_default();
+        timing.sequence += 1;
+        let presented = Duration::from(now);
+        if let Some(last) = timing.last_presented {
+            if timing.intervals.len() == INTERVAL_HISTORY {
+                timing.intervals.pop_front();
+            }
+            timing.intervals.push_back(presented.saturating_sub(last));
+        }
+        timing.last_presented = Some(presented);
+
+        // Frames are neither tied to a display's vblank nor scanned out directly
+        feedback.presented(
+            now,
+            refresh,
+            timing.sequence,
+            wp_presentation_feedback::Kind::empty(),
+        );
+    }
+
+    /// Frame timing of the named output, or of all outputs.
+    pub fn frame_timing(&self, output: Option<&str>) -> Result<Vec<FrameTimingInfo>, String> {
+        if let Some(name) = output {
+            if self.output_by_name(name).is_none() {
+                return Err(format!("No output named '{}'", name));
+            }
+        }
+
+        Ok(self
+            .space
+            .outputs()
+            .filter(|o| output.is_none_or(|name| o.name() == name))
+            .map(|o| {
+                let timing = self.frame_timing.get(&o.name());
+                let intervals = timing.map(|t| &t.intervals).filter(|i| !i.is_empty());
+                let ms = |d: Duration| d.as_secs_f64() * 1000.0;
+                let average = intervals.map(|i| i.iter().sum::<Duration>() / i.len() as u32);
+                FrameTimingInfo {
+                    output: o.name(),
+                    frames: timing.map_or(0, |t| t.sequence),
+                    refresh_hz: o
+                        .current_mode()
+                        .map_or(0.0, |mode| mode.refresh as f64 / 1000.0),
+                    measured_fps: average
+                        .filter(|a| !a.is_zero())
+                        .map(|a| 1.0 / a.as_secs_f64()),
+                    average_interval_ms: average.map(ms),
+                    min_interval_ms: intervals.and_then(|i| i.iter().min().copied()).map(ms),
+                    max_interval_ms: intervals.and_then(|i| i.iter().max().copied()).map(ms),
+                    last_presented_ms: timing.and_then(|t| t.last_presented).map(ms),
+                }
+            })
+            .collect())
+    }
+}
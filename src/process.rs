@@ -0,0 +1,646 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{Read, Write},
+    os::{
+        fd::OwnedFd,
+        unix::process::{CommandExt, ExitStatusExt},
+    },
+    path::Path,
+    process::{Child, Command, ExitStatus, Stdio},
+    time::Duration,
+};
+
+use rmcp::schemars::{self, JsonSchema};
+use serde::Serialize;
+
+use smithay::{
+    desktop::Window,
+    reexports::calloop::{
+        generic::Generic,
+        signals::{Signal, Signals},
+        timer::{TimeoutAction, Timer},
+        EventLoop, Interest, Mode, PostAction,
+    },
+};
+
+use crate::{
+    desktop::DesktopInfo,
+    errors::{ErrorKind, ToolError},
+    events::WindowEvent,
+    limits::ResourceLimits,
+    sandbox::{self, SandboxOptions},
+    state::{WindowInfo, WindowLabel},
+    CalloopData, Smallvil,
+};
+
+/// Output kept per app; anything older is dropped.
+const OUTPUT_LIMIT: usize = 1024 * 1024;
+
+/// How long restart_app waits for the killed process to be reaped.
+const RESTART_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Signals that mean a process crashed rather than being asked to stop.
+const CRASH_SIGNALS: &[i32] = &[
+    libc::SIGSEGV,
+    libc::SIGBUS,
+    libc::SIGILL,
+    libc::SIGFPE,
+    libc::SIGABRT,
+    libc::SIGTRAP,
+    libc::SIGSYS,
+];
+
+/// What launch_app runs and how.
+#[derive(Debug, Clone, Default)]
+pub struct LaunchOptions {
+    pub command: String,
+    pub args: Vec<String>,
+    /// Set on top of mcpvil's own environment
+    pub env: HashMap<String, String>,
+    pub cwd: Option<String>,
+    /// Written to the app's stdin, which is then closed. Without it stdin is
+    /// /dev/null, so apps can't read from the MCP transport.
+    pub stdin: Option<String>,
+    /// Run inside a bubblewrap sandbox that only shares the compositor's sockets
+    pub sandbox: bool,
+    pub limits: ResourceLimits,
+    /// The desktop entry it was launched from by launch_desktop_app
+    pub desktop: Option<DesktopInfo>,
+}
+
+/// A launched app, and its first window when launch_app waited for one.
+#[derive(Debug, Clone)]
+pub struct LaunchedApp {
+    pub pid: u32,
+    pub window: Option<WindowLabel>,
+}
+
+pub type LaunchResponse = tokio::sync::oneshot::Sender<Result<LaunchedApp, ToolError>>;
+
+/// A restart_app call waiting for the old process to exit.
+pub struct PendingRestart {
+    wait_for_window: Option<Duration>,
+    response_tx: LaunchResponse,
+}
+
+/// An app started through launch_app. Entries stay after the process exits so
+/// its exit status can still be queried.
+pub struct TrackedProcess {
+    /// How it was launched, for restart_app
+    pub options: LaunchOptions,
+    pub child: Child,
+    /// Set once the process has exited and been reaped
+    pub status: Option<ExitStatus>,
+    /// Interleaved stdout and stderr, capped at OUTPUT_LIMIT
+    pub output: Vec<u8>,
+    /// Set once kill_app or restart_app has sent it a signal that terminates
+    /// it unless handled
+    pub killed: bool,
+}
+
+/// A tracked process and the windows it owns, as returned by `list_processes`.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct ProcessInfo {
+    pub pid: u32,
+    pub command: String,
+    pub args: Vec<String>,
+    pub running: bool,
+    /// Exit code, if the process exited normally
+    pub exit_code: Option<i32>,
+    /// Signal that terminated the process, if any
+    pub signal: Option<i32>,
+    /// "memory" or "cpu" if the process seems to have been ended by the resource
+    /// limit it was launched with
+    pub limit_exceeded: Option<String>,
+    /// Killed by a signal that means it crashed, e.g. SIGSEGV or SIGABRT
+    pub crashed: bool,
+    /// Ids of mapped windows whose client is this process or one it started
+    pub windows: Vec<u64>,
+}
+
+/// Blocks SIGCHLD in the calling thread, so that threads spawned from it
+/// leave the signal to init_child_reaper's signalfd. Must run before any
+/// thread is spawned, including those of the tracing layers.
+pub fn block_sigchld() {
+    unsafe {
+        let mut set: libc::sigset_t = std::mem::zeroed();
+        libc::sigemptyset(&mut set);
+        libc::sigaddset(&mut set, libc::SIGCHLD);
+        libc::pthread_sigmask(libc::SIG_BLOCK, &set, std::ptr::null_mut());
+    }
+}
+
+impl Smallvil {
+    /// Reaps exited children whenever SIGCHLD arrives. SIGCHLD must already
+    /// be blocked with block_sigchld, so no other thread takes it.
+    pub fn init_child_reaper(event_loop: &mut EventLoop<CalloopData>) {
+        let signals = Signals::new(&[Signal::SIGCHLD]).expect("Failed to watch SIGCHLD");
+        event_loop
+            .handle()
+            .insert_source(signals, |_, _, data| data.state.reap_children())
+            .expect("Failed to init the SIGCHLD event source.");
+    }
+
+    /// Spawns an app and replies with its pid, or, with `wait_for_window`, once
+    /// a window owned by its client has mapped or the timeout has passed.
+    pub fn launch_app(
+        &mut self,
+        options: LaunchOptions,
+        wait_for_window: Option<Duration>,
+        response_tx: LaunchResponse,
+    ) {
+        let pid = match self.spawn_app(options) {
+            Ok(pid) => pid,
+            Err(e) => {
+                tracing::error!("Failed to launch app: {}", e);
+                let _ = response_tx.send(Err(ToolError::new(
+                    ErrorKind::AppLaunchFailed,
+                    e.to_string(),
+                )));
+                return;
+            }
+        };
+        let Some(timeout) = wait_for_window else {
+            let _ = response_tx.send(Ok(LaunchedApp { pid, window: None }));
+            return;
+        };
+
+        self.pending_launches.insert(pid, response_tx);
+        let timer =
+            self.loop_handle
+                .insert_source(Timer::from_duration(timeout), move |_, _, data| {
+                    if let Some(response_tx) = data.state.pending_launches.remove(&pid) {
+                        let _ = response_tx.send(Err(ToolError::new(
+                            ErrorKind::Timeout,
+                            format!(
+                                "Process {} mapped no window within {} ms",
+                                pid,
+                                timeout.as_millis()
+                            ),
+                        )));
+                    }
+                    TimeoutAction::Drop
+                });
+        if let Err(e) = timer {
+            tracing::error!("Failed to schedule launch timeout: {}", e);
+        }
+    }
+
+    /// Notes which launched app a newly mapped window belongs to, and
+    /// completes a launch_app waiting for a window from it.
+    pub fn window_mapped(&mut self, window: &Window) {
+        let info = self.window_info(window);
+        let Some(pid) = info.launched_pid else {
+            return;
+        };
+        self.window_owners.insert(info.id, pid);
+        if let Some(response_tx) = self.pending_launches.remove(&pid) {
+            let _ = response_tx.send(Ok(LaunchedApp {
+                pid,
+                window: Some(WindowLabel::from_window(window)),
+            }));
+        }
+    }
+
+    /// Spawns an app and adds it to the process registry, returning its pid.
+    pub fn spawn_app(&mut self, options: LaunchOptions) -> std::io::Result<u32> {
+        // Lets the app's first window take focus through xdg-activation, as it
+        // would when started from a launcher
+        let (token, _) = self.xdg_activation_state.create_external_token(None);
+        let token = token.as_str().to_string();
+
+        let mut cmd = if options.sandbox {
+            let mut cmd = sandbox::bwrap(&self.sandbox_options(options.cwd.clone()));
+            cmd.arg(&options.command);
+            cmd
+        } else {
+            let mut cmd = Command::new(&options.command);
+            if let Some(cwd) = &options.cwd {
+                cmd.current_dir(cwd);
+            }
+            cmd
+        };
+        cmd.args(&options.args)
+            .env("XDG_ACTIVATION_TOKEN", &token)
+            .env("DESKTOP_STARTUP_ID", &token)
+            .envs(&options.env)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        if let Some(session_dir) = &self.session_dir {
+            cmd.env("MCPVIL_SESSION_DIR", session_dir);
+        }
+        if options.stdin.is_some() {
+            cmd.stdin(Stdio::piped());
+        } else {
+            cmd.stdin(Stdio::null());
+        }
+        // Don't hand our blocked SIGCHLD down to apps that rely on it
+        let limits = options.limits;
+        unsafe {
+            cmd.pre_exec(move || {
+                let mut set: libc::sigset_t = std::mem::zeroed();
+                libc::sigemptyset(&mut set);
+                libc::pthread_sigmask(libc::SIG_SETMASK, &set, std::ptr::null_mut());
+                limits.apply()
+            });
+        }
+
+        let mut child = cmd.spawn().map_err(|e| {
+            if options.sandbox {
+                std::io::Error::new(e.kind(), format!("Failed to run bwrap: {}", e))
+            } else {
+                e
+            }
+        })?;
+        let pid = child.id();
+        let stdout = child.stdout.take();
+        let stderr = child.stderr.take();
+
+        if let (Some(mut pipe), Some(input)) = (child.stdin.take(), options.stdin.clone()) {
+            // The app may not read its input right away; don't block the event loop
+            std::thread::spawn(move || {
+                if let Err(e) = pipe.write_all(input.as_bytes()) {
+                    tracing::warn!("Failed to write stdin of process {}: {}", pid, e);
+                }
+            });
+        }
+
+        self.processes.insert(
+            pid,
+            TrackedProcess {
+                options,
+                child,
+                status: None,
+                output: Vec::new(),
+                killed: false,
+            },
+        );
+
+        if let Some(stdout) = stdout {
+            self.watch_output(pid, stdout.into());
+        }
+        if let Some(stderr) = stderr {
+            self.watch_output(pid, stderr.into());
+        }
+        Ok(pid)
+    }
+
+    /// The compositor sockets a sandboxed app gets, and its working directory.
+    fn sandbox_options(&self, cwd: Option<String>) -> SandboxOptions {
+        let runtime_dir = std::env::var_os("XDG_RUNTIME_DIR").unwrap_or_else(|| "/tmp".into());
+        SandboxOptions {
+            wayland_socket: Path::new(&runtime_dir).join(&self.socket_name),
+            x11_socket: self
+                .xdisplay
+                .map(|display| format!("/tmp/.X11-unix/X{}", display).into()),
+            bus_socket: self
+                .session_bus
+                .as_ref()
+                .map(|bus| bus.socket().to_path_buf()),
+            cwd,
+            session_dir: self.session_dir.clone(),
+        }
+    }
+
+    /// Appends everything written to `pipe` to the output of process `pid`,
+    /// until the process closes it.
+    fn watch_output(&self, pid: u32, pipe: OwnedFd) {
+        let source = Generic::new(File::from(pipe), Interest::READ, Mode::Level);
+        let result = self
+            .loop_handle
+            .insert_source(source, move |_, pipe, data| {
+                let mut buf = [0; 4096];
+                match (&**pipe).read(&mut buf) {
+                    Ok(0) => Ok(PostAction::Remove),
+                    Ok(n) => {
+                        data.state.append_output(pid, &buf[..n]);
+                        Ok(PostAction::Continue)
+                    }
+                    Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {
+                        Ok(PostAction::Continue)
+                    }
+                    Err(e) => {
+                        tracing::warn!("Failed to read output of process {}: {}", pid, e);
+                        Ok(PostAction::Remove)
+                    }
+                }
+            });
+        if let Err(e) = result {
+            tracing::error!("Failed to watch output of process {}: {}", pid, e);
+        }
+    }
+
+    fn append_output(&mut self, pid: u32, bytes: &[u8]) {
+        let Some(process) = self.processes.get_mut(&pid) else {
+            return;
+        };
+        process.output.extend_from_slice(bytes);
+        if process.output.len() > OUTPUT_LIMIT {
+            let excess = process.output.len() - OUTPUT_LIMIT;
+            process.output.drain(..excess);
+        }
+    }
+
+    /// The last `tail_lines` lines a launched app wrote to stdout or stderr.
+    pub fn app_output(&self, pid: u32, tail_lines: usize) -> Result<String, ToolError> {
+        let process = self.processes.get(&pid).ok_or_else(|| {
+            ToolError::new(
+                ErrorKind::ProcessNotFound,
+                format!("No app with pid {} was launched", pid),
+            )
+        })?;
+
+        let output = String::from_utf8_lossy(&process.output);
+        let lines: Vec<&str> = output.lines().collect();
+        let start = lines.len().saturating_sub(tail_lines);
+        Ok(lines[start..].join("\n"))
+    }
+
+    /// Collects the exit status of tracked processes that have exited, so they
+    /// don't linger as zombies. Other children (ffmpeg, Xwayland) are waited on
+    /// by their owners.
+    pub fn reap_children(&mut self) {
+        let mut exited = Vec::new();
+        for (pid, process) in &mut self.processes {
+            if process.status.is_some() {
+                continue;
+            }
+            match process.child.try_wait() {
+                Ok(Some(status)) => {
+                    let command = &process.options.command;
+                    tracing::info!("Process {} ({}) exited: {}", pid, command, status);
+                    process.status = Some(status);
+                    if let Some(limit) = process.options.limits.exceeded(status, process.killed) {
+                        tracing::warn!("Process {} exceeded its {} limit", pid, limit);
+                    }
+                    exited.push(*pid);
+
+                    if let Some(response_tx) = self.pending_launches.remove(pid) {
+                        let _ = response_tx.send(Err(ToolError::new(
+                            ErrorKind::AppLaunchFailed,
+                            format!(
+                                "Process {} exited ({}) before mapping a window",
+                                pid, status
+                            ),
+                        )));
+                    }
+                }
+                Ok(None) => {}
+                Err(e) => tracing::warn!("Failed to check on process {}: {}", pid, e),
+            }
+        }
+
+        if exited.is_empty() {
+            return;
+        }
+        let windows = self.list_windows(None);
+        for pid in exited {
+            let process = self.process_info(pid, &self.processes[&pid], &windows);
+            self.emit_window_event(WindowEvent::AppExited { process });
+            if let Some(pending) = self.pending_restarts.remove(&pid) {
+                self.relaunch(pid, pending.wait_for_window, pending.response_tx);
+            }
+        }
+    }
+
+    /// All processes started by launch_app, running or not, ordered by pid.
+    pub fn list_processes(&self) -> Vec<ProcessInfo> {
+        let windows = self.list_windows(None);
+        self.processes
+            .iter()
+            .map(|(&pid, process)| self.process_info(pid, process, &windows))
+            .collect()
+    }
+
+    fn process_info(
+        &self,
+        pid: u32,
+        process: &TrackedProcess,
+        windows: &[WindowInfo],
+    ) -> ProcessInfo {
+        let signal = process.status.and_then(|status| status.signal());
+        ProcessInfo {
+            pid,
+            command: process.options.command.clone(),
+            args: process.options.args.clone(),
+            running: process.status.is_none(),
+            exit_code: process.status.and_then(|status| status.code()),
+            signal,
+            limit_exceeded: process
+                .status
+                .and_then(|status| process.options.limits.exceeded(status, process.killed))
+                .map(str::to_string),
+            crashed: !process.killed
+                && signal.is_some_and(|signal| CRASH_SIGNALS.contains(&signal)),
+            windows: windows
+                .iter()
+                .filter(|window| window.launched_pid == Some(pid))
+                .map(|window| window.id)
+                .collect(),
+        }
+    }
+
+    /// Whether the launched process `pid` has exited.
+    pub fn process_exited(&self, pid: u32) -> bool {
+        self.processes
+            .get(&pid)
+            .is_some_and(|process| process.status.is_some())
+    }
+
+    /// The launched app `pid` belongs to: the process itself if launch_app
+    /// started it, or else its nearest ancestor that it started. Sandboxed
+    /// apps are children of the bwrap process, and launcher scripts often
+    /// fork the real app.
+    pub fn launched_pid(&self, pid: u32) -> Option<u32> {
+        if self.processes.is_empty() {
+            return None;
+        }
+        let mut pid = Some(pid);
+        while let Some(current) = pid.filter(|&pid| pid > 1) {
+            if self.processes.contains_key(&current) {
+                return Some(current);
+            }
+            pid = parent_pid(current);
+        }
+        None
+    }
+
+    /// The desktop entry behind a window: that of the launched app owning it,
+    /// or else of a launched app whose StartupWMClass matches the window's
+    /// app_id, for apps that hand windows to an already running instance.
+    pub fn desktop_entry_of(
+        &self,
+        launched_pid: Option<u32>,
+        app_id: Option<&str>,
+    ) -> Option<DesktopInfo> {
+        if let Some(process) = launched_pid.and_then(|pid| self.processes.get(&pid)) {
+            return process.options.desktop.clone();
+        }
+        let app_id = app_id?;
+        self.processes
+            .values()
+            .filter_map(|process| process.options.desktop.as_ref())
+            .find(|desktop| desktop.startup_wm_class.as_deref() == Some(app_id))
+            .cloned()
+    }
+
+    /// Kills an app started by launch_app if it is still running, then
+    /// launches it again with the same command, arguments, environment, working
+    /// directory and options once reap_children has seen it exit. Replies like
+    /// launch_app, with the new pid.
+    pub fn restart_app(
+        &mut self,
+        pid: u32,
+        wait_for_window: Option<Duration>,
+        response_tx: LaunchResponse,
+    ) {
+        if self.pending_restarts.contains_key(&pid) {
+            let _ = response_tx.send(Err(ToolError::new(
+                ErrorKind::Other,
+                format!("Process {} is already restarting", pid),
+            )));
+            return;
+        }
+        let Some(process) = self.processes.get_mut(&pid) else {
+            let _ = response_tx.send(Err(ToolError::new(
+                ErrorKind::ProcessNotFound,
+                format!("No app with pid {} was launched", pid),
+            )));
+            return;
+        };
+        let running = process.status.is_none();
+        if running {
+            process.killed = true;
+            if let Err(e) = process.child.kill() {
+                tracing::error!("Failed to kill process {}: {}", pid, e);
+                let _ = response_tx.send(Err(ToolError::new(
+                    ErrorKind::Other,
+                    format!("Failed to kill process {}: {}", pid, e),
+                )));
+                return;
+            }
+        }
+        if let Some(pending) = self.pending_launches.remove(&pid) {
+            let _ = pending.send(Err(ToolError::new(
+                ErrorKind::AppLaunchFailed,
+                format!("Process {} was restarted", pid),
+            )));
+        }
+        if !running {
+            self.relaunch(pid, wait_for_window, response_tx);
+            return;
+        }
+
+        // Wait for it to go, so single-instance apps don't hand the new launch over to it
+        self.pending_restarts.insert(
+            pid,
+            PendingRestart {
+                wait_for_window,
+                response_tx,
+            },
+        );
+        let timer = self.loop_handle.insert_source(
+            Timer::from_duration(RESTART_TIMEOUT),
+            move |_, _, data| {
+                if let Some(pending) = data.state.pending_restarts.remove(&pid) {
+                    let _ = pending.response_tx.send(Err(ToolError::new(
+                        ErrorKind::Timeout,
+                        format!(
+                            "Process {} did not exit within {} ms of being killed",
+                            pid,
+                            RESTART_TIMEOUT.as_millis()
+                        ),
+                    )));
+                }
+                TimeoutAction::Drop
+            },
+        );
+        if let Err(e) = timer {
+            tracing::error!("Failed to schedule restart timeout: {}", e);
+        }
+    }
+
+    /// Launches the app that ran as `pid` again, with the options it was
+    /// launched with.
+    fn relaunch(
+        &mut self,
+        pid: u32,
+        wait_for_window: Option<Duration>,
+        response_tx: LaunchResponse,
+    ) {
+        let options = self.processes[&pid].options.clone();
+        self.launch_app(options, wait_for_window, response_tx);
+    }
+
+    /// Sends `signal` to a process started by launch_app.
+    pub fn kill_app(&mut self, pid: u32, signal: i32) -> Result<(), ToolError> {
+        let process = self.processes.get_mut(&pid).ok_or_else(|| {
+            ToolError::new(
+                ErrorKind::ProcessNotFound,
+                format!("No app with pid {} was launched", pid),
+            )
+        })?;
+        if process.status.is_some() {
+            return Err(ToolError::new(
+                ErrorKind::ProcessNotFound,
+                format!("Process {} has already exited", pid),
+            ));
+        }
+
+        if unsafe { libc::kill(pid as i32, signal) } == 0 {
+            process.killed |= terminates(signal);
+            Ok(())
+        } else {
+            let err = std::io::Error::last_os_error();
+            tracing::error!("Failed to signal process {}: {}", pid, err);
+            Err(ToolError::new(ErrorKind::Other, err.to_string()))
+        }
+    }
+}
+
+/// Whether `signal` ends a process that doesn't handle it, as opposed to
+/// stopping, resuming or being ignored by it.
+fn terminates(signal: i32) -> bool {
+    !matches!(
+        signal,
+        libc::SIGCHLD
+            | libc::SIGCONT
+            | libc::SIGSTOP
+            | libc::SIGTSTP
+            | libc::SIGTTIN
+            | libc::SIGTTOU
+            | libc::SIGURG
+            | libc::SIGWINCH
+    )
+}
+
+/// Parses a signal given by name, with or without the SIG prefix, or by number.
+pub fn parse_signal(name: &str) -> Option<i32> {
+    if let Ok(number) = name.parse::<i32>() {
+        return (1..=libc::SIGRTMAX()).contains(&number).then_some(number);
+    }
+
+    let name = name.to_ascii_uppercase();
+    let signal = match name.strip_prefix("SIG").unwrap_or(&name) {
+        "HUP" => libc::SIGHUP,
+        "INT" => libc::SIGINT,
+        "QUIT" => libc::SIGQUIT,
+        "KILL" => libc::SIGKILL,
+        "USR1" => libc::SIGUSR1,
+        "USR2" => libc::SIGUSR2,
+        "TERM" => libc::SIGTERM,
+        "CONT" => libc::SIGCONT,
+        "STOP" => libc::SIGSTOP,
+        _ => return None,
+    };
+    Some(signal)
+}
+
+/// The parent of process `pid`, from /proc.
+fn parent_pid(pid: u32) -> Option<u32> {
+    let stat = std::fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    // The command name before it is in parentheses and may contain spaces
+    let fields = &stat[stat.rfind(')')? + 1..];
+    fields.split_whitespace().nth(1)?.parse().ok()
+}
@@ -0,0 +1,118 @@
+//! Progress notifications of long-running tool calls. A client that sends a
+//! `progressToken` in a call's `_meta` gets `notifications/progress` while it
+//! runs: the steps done of an input sequence, or the seconds spent so far in
+//! a wait, GIF capture, replay or OCR, out of its timeout or duration when
+//! known.
+
+use std::{future::Future, time::Duration};
+
+use rmcp::{
+    model::{Meta, ProgressNotificationParam, ProgressToken},
+    Peer, RoleServer,
+};
+use tokio::time::Instant;
+
+/// How often timed calls report.
+const INTERVAL: Duration = Duration::from_secs(1);
+
+tokio::task_local! {
+    static PROGRESS: Progress;
+}
+
+/// Where the progress of a tool call goes.
+#[derive(Clone)]
+pub struct Progress {
+    peer: Peer<RoleServer>,
+    token: ProgressToken,
+}
+
+impl Progress {
+    /// The progress of a call, if its client asked for it.
+    pub fn new(meta: &Meta, peer: &Peer<RoleServer>) -> Option<Self> {
+        Some(Self {
+            peer: peer.clone(),
+            token: meta.get_progress_token()?,
+        })
+    }
+
+    /// Runs `f`, with `report` sending to `progress` if set.
+    pub async fn scope<F: Future>(progress: Option<Self>, f: F) -> F::Output {
+        match progress {
+            Some(progress) => PROGRESS.scope(progress, f).await,
+            None => f.await,
+        }
+    }
+}
+
+fn wanted() -> bool {
+    PROGRESS.try_with(|_| ()).is_ok()
+}
+
+/// Reports the progress of the tool call being handled, if its client asked
+/// for it.
+pub async fn report(progress: f64, total: Option<f64>, message: String) {
+    let Ok(Progress { peer, token }) = PROGRESS.try_with(Progress::clone) else {
+        return;
+    };
+    let notification = ProgressNotificationParam {
+        progress_token: token,
+        progress,
+        total,
+        message: Some(message),
+    };
+    if let Err(e) = peer.notify_progress(notification).await {
+        tracing::debug!("Failed to send progress: {}", e);
+    }
+}
+
+/// Awaits `f`, reporting every second how long `what` has taken, out of
+/// `total` if known.
+pub async fn timed<F: Future>(f: F, total: Option<Duration>, what: &str) -> F::Output {
+    if !wanted() {
+        return f.await;
+    }
+    let start = Instant::now();
+    let mut ticks = tokio::time::interval_at(start + INTERVAL, INTERVAL);
+    tokio::pin!(f);
+    loop {
+        tokio::select! {
+            output = &mut f => return output,
+            _ = ticks.tick() => {
+                let elapsed = match total {
+                    Some(total) => start.elapsed().min(total),
+                    None => start.elapsed(),
+                };
+                let message = match total {
+                    Some(total) => format!(
+                        "{}: {}s of {}s",
+                        what,
+                        elapsed.as_secs(),
+                        total.as_secs()
+                    ),
+                    None => format!("{}: {}s", what, elapsed.as_secs()),
+                };
+                let total = total.map(|total| total.as_secs_f64());
+                report(elapsed.as_secs_f64(), total, message).await;
+            }
+        }
+    }
+}
+
+/// Awaits `f`, reporting each count of steps done received from `steps`, out
+/// of `total`.
+pub async fn stepped<F: Future>(
+    f: F,
+    mut steps: tokio::sync::mpsc::UnboundedReceiver<usize>,
+    total: usize,
+) -> F::Output {
+    tokio::pin!(f);
+    loop {
+        tokio::select! {
+            output = &mut f => return output,
+            Some(done) = steps.recv() => {
+                let message = format!("Step {} of {}", done, total);
+                report(done as f64, Some(total as f64), message).await;
+            }
+        }
+    }
+}
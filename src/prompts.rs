@@ -0,0 +1,173 @@
+use rmcp::model::{
+    GetPromptResult, JsonObject, Prompt, PromptArgument, PromptMessage, PromptMessageRole,
+};
+
+/// A guided workflow offered through the MCP prompts capability.
+struct Template {
+    name: &'static str,
+    description: &'static str,
+    /// Name, description and whether it is required
+    arguments: &'static [(&'static str, &'static str, bool)],
+    render: fn(&Arguments) -> String,
+}
+
+/// Prompt arguments by name, as strings.
+struct Arguments<'a>(Option<&'a JsonObject>);
+
+impl Arguments<'_> {
+    fn get(&self, name: &str) -> Option<String> {
+        let value = self.0?.get(name)?;
+        match value {
+            serde_json::Value::String(s) => Some(s.clone()),
+            serde_json::Value::Null => None,
+            other => Some(other.to_string()),
+        }
+    }
+
+    fn get_or(&self, name: &str, default: &str) -> String {
+        self.get(name).unwrap_or_else(|| default.to_string())
+    }
+}
+
+const TEMPLATES: &[Template] = &[
+    Template {
+        name: "launch_and_screenshot",
+        description: "Launch an application, wait for its window and take a screenshot of it",
+        arguments: &[
+            ("command", "Command to run, e.g. gnome-calculator", true),
+            (
+                "app_id",
+                "Regular expression for the app_id of the window to wait for",
+                false,
+            ),
+        ],
+        render: launch_and_screenshot,
+    },
+    Template {
+        name: "fill_form",
+        description: "Fill in and submit a form in the focused window from field/value pairs",
+        arguments: &[
+            (
+                "fields",
+                "The fields to fill, one 'label: value' pair per line",
+                true,
+            ),
+            (
+                "submit",
+                "Label of the button that submits the form (default: the Enter key)",
+                false,
+            ),
+        ],
+        render: fill_form,
+    },
+    Template {
+        name: "compare_against_baseline",
+        description:
+            "Screenshot a window and compare it against a baseline image for visual regressions",
+        arguments: &[
+            ("baseline", "Path of the baseline image", true),
+            (
+                "window",
+                "Id of the window to capture (default: the whole output)",
+                false,
+            ),
+        ],
+        render: compare_against_baseline,
+    },
+];
+
+fn launch_and_screenshot(arguments: &Arguments) -> String {
+    let command = arguments.get_or("command", "");
+    let wait = match arguments.get("app_id") {
+        Some(app_id) => format!(
+            "then call wait_for_window with an app_id pattern of `{}`",
+            app_id
+        ),
+        None => "with wait_for_window set to true, so the call returns once its first window \
+                 has mapped"
+            .to_string(),
+    };
+    format!(
+        "Launch `{command}` in the compositor using launch_app, {wait}. \
+         Then call screenshot_window with the id of the window and inline set to true. \
+         Describe what the window shows, and report its id and the PID of the process so \
+         they can be used in later steps. If no window appears, check the process with \
+         list_processes and get_app_output and report its exit status and output."
+    )
+}
+
+fn fill_form(arguments: &Arguments) -> String {
+    let fields = arguments.get_or("fields", "");
+    let submit = match arguments.get("submit") {
+        Some(label) => format!("click the `{}` button", label),
+        None => "press Enter".to_string(),
+    };
+    format!(
+        "Fill in the form in the focused window with these fields:\n\n{fields}\n\n\
+         First take a screenshot to locate the form. For each field, click into its input \
+         (or move to it with Tab), select any existing text with ctrl+a and type the value \
+         with type_text. When every field is filled, take another screenshot to check the \
+         values, then {submit}. Finish with a screenshot of the result and report any \
+         validation errors the form shows."
+    )
+}
+
+fn compare_against_baseline(arguments: &Arguments) -> String {
+    let baseline = arguments.get_or("baseline", "");
+    let (window, capture) = match arguments.get("window") {
+        Some(id) => (
+            format!(" and window_id {}", id),
+            format!("call screenshot_window for window {}", id),
+        ),
+        None => (String::new(), "call screenshot".to_string()),
+    };
+    format!(
+        "Check the current rendering against the baseline image at `{baseline}`. \
+         Wait until the screen is idle with wait_for_idle, then call compare_screenshot with \
+         baseline_path `{baseline}`{window}. If it fails, {capture} with inline set to true, look \
+         at the diff image it saved and list every visible difference: layout shifts, \
+         missing or extra elements, text changes and color changes. Ignore differences in \
+         blinking cursors and clocks. Conclude with whether the capture matches the \
+         baseline."
+    )
+}
+
+pub fn list() -> Vec<Prompt> {
+    TEMPLATES
+        .iter()
+        .map(|template| {
+            let arguments = template
+                .arguments
+                .iter()
+                .map(|(name, description, required)| PromptArgument {
+                    name: name.to_string(),
+                    title: None,
+                    description: Some(description.to_string()),
+                    required: Some(*required),
+                })
+                .collect();
+            Prompt::new(template.name, Some(template.description), Some(arguments))
+        })
+        .collect()
+}
+
+pub fn get(name: &str, arguments: Option<&JsonObject>) -> Result<GetPromptResult, String> {
+    let template = TEMPLATES
+        .iter()
+        .find(|template| template.name == name)
+        .ok_or_else(|| format!("No prompt named '{}'", name))?;
+    let arguments = Arguments(arguments);
+    for (argument, _, required) in template.arguments {
+        if *required && arguments.get(argument).is_none() {
+            return Err(format!("Prompt '{}' requires '{}'", name, argument));
+        }
+    }
+
+    Ok(GetPromptResult {
+        description: Some(template.description.to_string()),
+        messages: vec![PromptMessage::new_text(
+            PromptMessageRole::User,
+            (template.render)(&arguments),
+        )],
+    })
+}
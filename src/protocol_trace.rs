@@ -0,0 +1,536 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    io,
+    net::Shutdown,
+    os::{
+        fd::{AsRawFd, FromRawFd, OwnedFd, RawFd},
+        unix::net::UnixStream,
+    },
+    sync::{
+        atomic::{AtomicBool, AtomicU32, Ordering},
+        Arc, Mutex, Weak,
+    },
+};
+
+use smithay::reexports::wayland_server::{
+    backend::{
+        protocol::{ArgumentType, Interface},
+        ClientId, Handle,
+    },
+    protocol::wl_display::WlDisplay,
+    DisplayHandle, Resource,
+};
+
+use crate::{state::ClientState, Smallvil};
+
+/// URI of the protocol trace resource.
+pub const PROTOCOL_TRACE_URI: &str = "mcpvil://protocol-trace";
+
+/// Traced messages kept; older ones are dropped.
+const HISTORY_LIMIT: usize = 10_000;
+
+/// Bytes read from a socket at a time.
+const READ_SIZE: usize = 16 * 1024;
+
+/// Room for the file descriptors sent with one read, well above the 28
+/// libwayland sends at most.
+const CMSG_WORDS: usize = 64;
+
+/// Traced messages of every relayed client, oldest first, and the clients.
+#[derive(Default)]
+pub struct TraceLog {
+    lines: VecDeque<String>,
+    clients: Vec<Weak<TracedClient>>,
+}
+
+pub type ProtocolTrace = Arc<Mutex<TraceLog>>;
+
+impl TraceLog {
+    fn push(&mut self, line: String) {
+        if self.lines.len() == HISTORY_LIMIT {
+            self.lines.pop_front();
+        }
+        self.lines.push_back(line);
+    }
+
+    /// The trace as text, one message per line, for the trace resource.
+    pub fn text(&self) -> String {
+        self.lines.iter().fold(String::new(), |mut text, line| {
+            text.push_str(line);
+            text.push('\n');
+            text
+        })
+    }
+
+    /// Connected clients started by process `pid`.
+    fn clients_of(&mut self, pid: i32) -> Vec<Arc<TracedClient>> {
+        self.clients.retain(|client| client.strong_count() > 0);
+        self.clients
+            .iter()
+            .filter_map(Weak::upgrade)
+            .filter(|client| client.pid == Some(pid))
+            .collect()
+    }
+}
+
+/// A client connected through a relay, so its messages can be traced.
+#[derive(Debug)]
+pub struct TracedClient {
+    /// Connection number, counting from 1
+    pub number: u32,
+    /// The process on the other end; the compositor itself only sees the relay
+    pub pid: Option<i32>,
+    pub enabled: AtomicBool,
+}
+
+impl TracedClient {
+    fn label(&self) -> String {
+        match self.pid {
+            Some(pid) => format!("client {} (pid {})", self.number, pid),
+            None => format!("client {}", self.number),
+        }
+    }
+}
+
+/// Inserts a client that connected to the listening socket with a relay in
+/// between, which forwards everything unchanged and, while tracing is on for
+/// the client, logs each message decoded like WAYLAND_DEBUG does.
+pub fn insert_relayed_client(
+    display_handle: &mut DisplayHandle,
+    stream: UnixStream,
+    log: &ProtocolTrace,
+) -> io::Result<()> {
+    static CONNECTIONS: AtomicU32 = AtomicU32::new(0);
+
+    stream.set_nonblocking(false)?;
+    let (server_end, relay_end) = UnixStream::pair()?;
+    let traced = Arc::new(TracedClient {
+        number: CONNECTIONS.fetch_add(1, Ordering::Relaxed) + 1,
+        pid: peer_pid(&stream),
+        enabled: AtomicBool::new(false),
+    });
+    let client = display_handle.insert_client(
+        server_end,
+        Arc::new(ClientState {
+            trace: Some(traced.clone()),
+            ..Default::default()
+        }),
+    )?;
+    log.lock().unwrap().clients.push(Arc::downgrade(&traced));
+
+    let decoder = Arc::new(Mutex::new(Decoder {
+        handle: display_handle.backend_handle(),
+        client: client.id(),
+        objects: HashMap::new(),
+    }));
+    for direction in [Direction::Request, Direction::Event] {
+        let (from, to) = match direction {
+            Direction::Request => (stream.try_clone()?, relay_end.try_clone()?),
+            Direction::Event => (relay_end.try_clone()?, stream.try_clone()?),
+        };
+        let relay = Relay {
+            direction,
+            traced: traced.clone(),
+            decoder: decoder.clone(),
+            log: log.clone(),
+            pending: Vec::new(),
+        };
+        std::thread::Builder::new()
+            .name(format!("wayland-relay-{}", traced.number))
+            .spawn(move || relay.run(from, to))?;
+    }
+    Ok(())
+}
+
+/// The pid of the process on the other end of a Unix socket.
+fn peer_pid(stream: &UnixStream) -> Option<i32> {
+    let mut credentials: libc::ucred = unsafe { std::mem::zeroed() };
+    let mut len = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+    let result = unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_PEERCRED,
+            (&mut credentials as *mut libc::ucred).cast(),
+            &mut len,
+        )
+    };
+    (result == 0 && credentials.pid > 0).then_some(credentials.pid)
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Direction {
+    /// Client to compositor
+    Request,
+    /// Compositor to client
+    Event,
+}
+
+/// One direction of a relayed connection.
+struct Relay {
+    direction: Direction,
+    traced: Arc<TracedClient>,
+    decoder: Arc<Mutex<Decoder>>,
+    log: ProtocolTrace,
+    /// The start of a message split across reads
+    pending: Vec<u8>,
+}
+
+impl Relay {
+    /// Forwards bytes and file descriptors until either side hangs up.
+    fn run(mut self, from: UnixStream, to: UnixStream) {
+        let mut buf = vec![0u8; READ_SIZE];
+        loop {
+            let mut fds = Vec::new();
+            let len = match recv(from.as_raw_fd(), &mut buf, &mut fds) {
+                Ok(0) | Err(_) => break,
+                Ok(len) => len,
+            };
+            if send(to.as_raw_fd(), &buf[..len], &fds).is_err() {
+                break;
+            }
+            // Our copies of the descriptors are closed here
+            drop(fds);
+            self.trace(&buf[..len]);
+        }
+        let _ = from.shutdown(Shutdown::Both);
+        let _ = to.shutdown(Shutdown::Both);
+    }
+
+    /// Decodes the complete messages received so far. Messages are always
+    /// decoded, so objects created before tracing was turned on are known.
+    fn trace(&mut self, data: &[u8]) {
+        self.pending.extend_from_slice(data);
+        let mut offset = 0;
+        while self.pending.len() - offset >= 8 {
+            let sender = read_u32(&self.pending, offset);
+            let word = read_u32(&self.pending, offset + 4);
+            let size = (word >> 16) as usize;
+            if size < 8 {
+                // Not a Wayland message; the compositor will disconnect the client
+                self.pending.clear();
+                return;
+            }
+            if self.pending.len() - offset < size {
+                break;
+            }
+            let args = &self.pending[offset + 8..offset + size];
+            let message = self.decoder.lock().unwrap().decode(
+                self.direction,
+                sender,
+                (word & 0xffff) as usize,
+                args,
+            );
+            if self.traced.enabled.load(Ordering::Relaxed) {
+                let arrow = match self.direction {
+                    Direction::Request => "->",
+                    Direction::Event => "<-",
+                };
+                let timestamp_ms = std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .map(|d| d.as_millis())
+                    .unwrap_or_default();
+                self.log.lock().unwrap().push(format!(
+                    "{} {} {} {}",
+                    timestamp_ms,
+                    self.traced.label(),
+                    arrow,
+                    message
+                ));
+            }
+            offset += size;
+        }
+        self.pending.drain(..offset);
+    }
+}
+
+/// Tracks the interface of each of a client's objects to decode its messages.
+struct Decoder {
+    handle: Handle,
+    client: ClientId,
+    objects: HashMap<u32, &'static Interface>,
+}
+
+impl Decoder {
+    fn interface(&mut self, id: u32) -> Option<&'static Interface> {
+        if id == 1 {
+            return Some(WlDisplay::interface());
+        }
+        if let Some(interface) = self.objects.get(&id) {
+            return Some(interface);
+        }
+        // Bound globals aren't typed on the wire; the compositor knows them
+        // once it has handled the bind
+        let mut found = None;
+        let _ = self
+            .handle
+            .with_all_objects_for(self.client.clone(), |object| {
+                if object.protocol_id() == id {
+                    found = Some(object.interface());
+                }
+            });
+        let interface = found?;
+        self.objects.insert(id, interface);
+        Some(interface)
+    }
+
+    /// Formats a message as `interface@id.name(args)`.
+    fn decode(&mut self, direction: Direction, sender: u32, opcode: usize, args: &[u8]) -> String {
+        let interface = self.interface(sender);
+        let desc = interface.and_then(|interface| match direction {
+            Direction::Request => interface.requests.get(opcode),
+            Direction::Event => interface.events.get(opcode),
+        });
+        let (Some(interface), Some(desc)) = (interface, desc) else {
+            let name = interface.map_or("unknown", |interface| interface.name);
+            return format!("{}@{}.#{}({} bytes)", name, sender, opcode, args.len());
+        };
+
+        let mut reader = ArgReader {
+            bytes: args,
+            pos: 0,
+        };
+        let mut parts = Vec::new();
+        for arg in desc.signature {
+            let part = match arg {
+                ArgumentType::Int => reader.u32().map(|v| (v as i32).to_string()),
+                ArgumentType::Uint => reader.u32().map(|v| v.to_string()),
+                ArgumentType::Fixed => reader.u32().map(|v| (v as i32 as f64 / 256.0).to_string()),
+                ArgumentType::Str(_) => reader.string().map(|s| match s {
+                    Some(s) => format!("{:?}", s),
+                    None => String::from("nil"),
+                }),
+                ArgumentType::Object(_) => reader.u32().map(|id| self.object(id)),
+                ArgumentType::NewId => match desc.child_interface {
+                    Some(child) => reader.u32().map(|id| {
+                        self.objects.insert(id, child);
+                        format!("new id {}@{}", child.name, id)
+                    }),
+                    // wl_registry.bind spells out the interface and version
+                    None => reader.untyped_new_id().map(|(name, version, id)| {
+                        self.objects.remove(&id);
+                        format!("new id {}@{} (version {})", name, id, version)
+                    }),
+                },
+                ArgumentType::Array => reader.array().map(|len| format!("array[{}]", len)),
+                ArgumentType::Fd => Some(String::from("fd")),
+            };
+            match part {
+                Some(part) => parts.push(part),
+                None => {
+                    parts.push(String::from("<truncated>"));
+                    break;
+                }
+            }
+        }
+        format!(
+            "{}@{}.{}({})",
+            interface.name,
+            sender,
+            desc.name,
+            parts.join(", ")
+        )
+    }
+
+    fn object(&mut self, id: u32) -> String {
+        if id == 0 {
+            return String::from("nil");
+        }
+        let name = self
+            .interface(id)
+            .map_or("unknown", |interface| interface.name);
+        format!("{}@{}", name, id)
+    }
+}
+
+/// Reads arguments in wire format: native-endian 32-bit words, with strings
+/// and arrays length-prefixed and padded to a word.
+struct ArgReader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl ArgReader<'_> {
+    fn u32(&mut self) -> Option<u32> {
+        if self.bytes.len() < self.pos + 4 {
+            return None;
+        }
+        let value = read_u32(self.bytes, self.pos);
+        self.pos += 4;
+        Some(value)
+    }
+
+    fn bytes(&mut self, len: usize) -> Option<&[u8]> {
+        let padded = len.next_multiple_of(4);
+        let bytes = self.bytes.get(self.pos..self.pos + len)?;
+        self.pos += padded;
+        Some(bytes)
+    }
+
+    /// A string, or None inside for a null one.
+    fn string(&mut self) -> Option<Option<String>> {
+        let len = self.u32()? as usize;
+        if len == 0 {
+            return Some(None);
+        }
+        let bytes = self.bytes(len)?;
+        // Drop the terminating NUL
+        let text = &bytes[..len - 1];
+        Some(Some(String::from_utf8_lossy(text).into_owned()))
+    }
+
+    /// A new_id of no fixed interface, as the interface name, version and id.
+    fn untyped_new_id(&mut self) -> Option<(String, u32, u32)> {
+        let name = self.string()?.unwrap_or_default();
+        Some((name, self.u32()?, self.u32()?))
+    }
+
+    /// Skips an array, returning its length in bytes.
+    fn array(&mut self) -> Option<usize> {
+        let len = self.u32()? as usize;
+        self.bytes(len)?;
+        Some(len)
+    }
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_ne_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+/// Reads from a socket, collecting the file descriptors sent along.
+fn recv(fd: RawFd, buf: &mut [u8], fds: &mut Vec<OwnedFd>) -> io::Result<usize> {
+    let mut cmsg_buf = [0u64; CMSG_WORDS];
+    let mut iov = libc::iovec {
+        iov_base: buf.as_mut_ptr().cast(),
+        iov_len: buf.len(),
+    };
+    let mut msg: libc::msghdr = unsafe { std::mem::zeroed() };
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+    msg.msg_control = cmsg_buf.as_mut_ptr().cast();
+    msg.msg_controllen = std::mem::size_of_val(&cmsg_buf) as _;
+
+    let len = loop {
+        let len = unsafe { libc::recvmsg(fd, &mut msg, libc::MSG_CMSG_CLOEXEC) };
+        if len >= 0 {
+            break len as usize;
+        }
+        let err = io::Error::last_os_error();
+        if err.kind() != io::ErrorKind::Interrupted {
+            return Err(err);
+        }
+    };
+
+    unsafe {
+        let mut cmsg = libc::CMSG_FIRSTHDR(&msg);
+        while !cmsg.is_null() {
+            if (*cmsg).cmsg_level == libc::SOL_SOCKET && (*cmsg).cmsg_type == libc::SCM_RIGHTS {
+                let data = libc::CMSG_DATA(cmsg) as *const RawFd;
+                let count = ((*cmsg).cmsg_len as usize - libc::CMSG_LEN(0) as usize)
+                    / std::mem::size_of::<RawFd>();
+                for i in 0..count {
+                    fds.push(OwnedFd::from_raw_fd(data.add(i).read_unaligned()));
+                }
+            }
+            cmsg = libc::CMSG_NXTHDR(&msg, cmsg);
+        }
+    }
+    if msg.msg_flags & libc::MSG_CTRUNC != 0 {
+        tracing::warn!("File descriptors were lost relaying a Wayland connection");
+    }
+    Ok(len)
+}
+
+/// Writes all of `data` to a socket, sending `fds` with the first part.
+fn send(fd: RawFd, data: &[u8], fds: &[OwnedFd]) -> io::Result<()> {
+    let mut offset = 0;
+    let mut fds = fds;
+    while offset < data.len() {
+        let mut cmsg_buf = [0u64; CMSG_WORDS];
+        let iov = libc::iovec {
+            iov_base: data[offset..].as_ptr() as *mut _,
+            iov_len: data.len() - offset,
+        };
+        let mut msg: libc::msghdr = unsafe { std::mem::zeroed() };
+        msg.msg_iov = &iov as *const libc::iovec as *mut _;
+        msg.msg_iovlen = 1;
+        if !fds.is_empty() {
+            let fds_len = (std::mem::size_of::<RawFd>() * fds.len()) as u32;
+            msg.msg_control = cmsg_buf.as_mut_ptr().cast();
+            msg.msg_controllen = unsafe { libc::CMSG_SPACE(fds_len) } as _;
+            unsafe {
+                let cmsg = libc::CMSG_FIRSTHDR(&msg);
+                (*cmsg).cmsg_level = libc::SOL_SOCKET;
+                (*cmsg).cmsg_type = libc::SCM_RIGHTS;
+                (*cmsg).cmsg_len = libc::CMSG_LEN(fds_len) as _;
+                let out = libc::CMSG_DATA(cmsg) as *mut RawFd;
+                for (i, fd) in fds.iter().enumerate() {
+                    out.add(i).write_unaligned(fd.as_raw_fd());
+                }
+            }
+        }
+
+        let len = unsafe { libc::sendmsg(fd, &msg, libc::MSG_NOSIGNAL) };
+        if len < 0 {
+            let err = io::Error::last_os_error();
+            if err.kind() == io::ErrorKind::Interrupted {
+                continue;
+            }
+            return Err(err);
+        }
+        offset += len as usize;
+        fds = &[];
+    }
+    Ok(())
+}
+
+impl Smallvil {
+    /// Turns tracing on or off for the client owning window `window_id`, or
+    /// for every client of process `pid`. Returns the clients changed.
+    pub fn set_protocol_trace(
+        &mut self,
+        pid: Option<u32>,
+        window_id: Option<u64>,
+        enabled: bool,
+    ) -> Result<Vec<String>, String> {
+        let log = self
+            .protocol_trace
+            .as_ref()
+            .ok_or("Protocol tracing needs mcpvil to be started with --protocol-trace")?;
+        let clients = match (window_id, pid) {
+            (Some(id), _) => {
+                let window = self
+                    .window_by_id(id)
+                    .ok_or_else(|| format!("No window with id {}", id))?;
+                let toplevel = window
+                    .toplevel()
+                    .ok_or("X11 windows are not traced; their client is Xwayland")?;
+                let client = self
+                    .display_handle
+                    .get_client(toplevel.wl_surface().id())
+                    .map_err(|_| format!("Window {} has no client", id))?;
+                let traced = client
+                    .get_data::<ClientState>()
+                    .and_then(|state| state.trace.clone())
+                    .ok_or("The window's client did not connect through the relay")?;
+                vec![traced]
+            }
+            (None, Some(pid)) => {
+                let clients = log.lock().unwrap().clients_of(pid as i32);
+                if clients.is_empty() {
+                    return Err(format!("No Wayland client of process {} is connected", pid));
+                }
+                clients
+            }
+            (None, None) => return Err("Give a window_id or a pid".to_string()),
+        };
+
+        Ok(clients
+            .into_iter()
+            .map(|client| {
+                client.enabled.store(enabled, Ordering::Relaxed);
+                client.label()
+            })
+            .collect())
+    }
+}
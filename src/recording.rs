@@ -0,0 +1,332 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Read, Write},
+    process::{Child, ChildStderr, ChildStdin, Command, Stdio},
+    sync::mpsc::{self, Receiver, SyncSender, TrySendError},
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+use image::{
+    codecs::gif::{GifEncoder, Repeat},
+    Delay, RgbaImage,
+};
+
+/// Number of frame buffers shared between the render loop and the encoder thread.
+/// When the encoder falls behind and all of them are in flight, frames are dropped
+/// instead of stalling the compositor.
+const FRAME_RING_SIZE: usize = 8;
+
+/// How much of ffmpeg's stderr is kept to explain a failure.
+const STDERR_TAIL: usize = 4096;
+
+#[cfg(not(feature = "h264"))]
+const CODEC_ARGS: &[&str] = &[
+    "-c:v",
+    "libvpx-vp9",
+    "-deadline",
+    "realtime",
+    "-cpu-used",
+    "8",
+    "-b:v",
+    "0",
+    "-crf",
+    "32",
+];
+#[cfg(feature = "h264")]
+const CODEC_ARGS: &[&str] = &["-c:v", "libx264", "-preset", "ultrafast", "-crf", "23"];
+
+/// File extension of the container matching the compiled-in codec.
+#[cfg(not(feature = "h264"))]
+pub const DEFAULT_EXTENSION: &str = "webm";
+#[cfg(feature = "h264")]
+pub const DEFAULT_EXTENSION: &str = "mp4";
+
+/// Default GIF frame rate; GIFs get large quickly, so keep this low.
+pub const DEFAULT_GIF_FPS: u32 = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecordingFormat {
+    /// Video via ffmpeg, using the compiled-in codec
+    Video,
+    /// Animated GIF with a per-frame quantized palette
+    Gif,
+}
+
+#[derive(Debug, Clone)]
+pub struct RecordingOptions {
+    /// Where to write the recording
+    pub path: String,
+    /// Frames per second to sample the output at
+    pub fps: u32,
+    pub format: RecordingFormat,
+    /// Draw injected key presses and clicks over the output while recording
+    pub show_input: bool,
+}
+
+/// Summary of a finished recording.
+#[derive(Debug, Clone)]
+pub struct RecordingSummary {
+    pub path: String,
+    pub duration: Duration,
+    pub frames: u64,
+    /// Frames skipped because the encoder was busy or the output was resized
+    pub dropped: u64,
+}
+
+pub type RecordingResponse = tokio::sync::oneshot::Sender<Result<RecordingSummary, String>>;
+
+/// An in-progress recording. Frames are tapped from the redraw handler and
+/// streamed to an `ffmpeg` child process or a GIF encoder on a dedicated thread.
+pub struct Recording {
+    options: RecordingOptions,
+    width: u32,
+    height: u32,
+    started: Instant,
+    last_frame: Option<Instant>,
+    frames: u64,
+    dropped: u64,
+    frame_tx: SyncSender<Vec<u8>>,
+    // Buffers handed back by the encoder thread once written
+    free_rx: Receiver<Vec<u8>>,
+    encoder: JoinHandle<Result<(), String>>,
+}
+
+impl Recording {
+    /// Starts the encoder for frames of the given size.
+    pub fn start(options: RecordingOptions, width: u32, height: u32) -> Result<Self, String> {
+        if options.fps == 0 {
+            return Err("fps must be at least 1".to_string());
+        }
+
+        let sink = match options.format {
+            RecordingFormat::Video => Sink::ffmpeg(&options.path, width, height, options.fps)?,
+            RecordingFormat::Gif => Sink::gif(&options.path, width, height, options.fps)?,
+        };
+
+        let (frame_tx, frame_rx) = mpsc::sync_channel::<Vec<u8>>(FRAME_RING_SIZE);
+        let (free_tx, free_rx) = mpsc::channel();
+        let frame_len = (width * height * 4) as usize;
+        for _ in 0..FRAME_RING_SIZE {
+            let _ = free_tx.send(Vec::with_capacity(frame_len));
+        }
+
+        let encoder = std::thread::Builder::new()
+            .name("recording-encoder".to_string())
+            .spawn(move || encode_frames(sink, frame_rx, free_tx))
+            .map_err(|e| format!("Failed to spawn encoder thread: {}", e))?;
+
+        Ok(Self {
+            options,
+            width,
+            height,
+            started: Instant::now(),
+            last_frame: None,
+            frames: 0,
+            dropped: 0,
+            frame_tx,
+            free_rx,
+            encoder,
+        })
+    }
+
+    pub fn path(&self) -> &str {
+        &self.options.path
+    }
+
+    /// Whether a frame is due, given the configured rate. Redraws in between
+    /// are not read back for the recording.
+    pub fn wants_frame(&self) -> bool {
+        let interval = Duration::from_secs(1) / self.options.fps;
+        self.last_frame
+            .is_none_or(|last| last.elapsed() >= interval)
+    }
+
+    /// Queues a rendered frame for encoding, dropping it if the encoder is behind.
+    pub fn push_frame(&mut self, img: &image::RgbaImage) {
+        self.last_frame = Some(Instant::now());
+
+        if img.width() != self.width || img.height() != self.height {
+            self.dropped += 1;
+            return;
+        }
+
+        let Ok(mut pixels) = self.free_rx.try_recv() else {
+            self.dropped += 1;
+            return;
+        };
+        pixels.clear();
+        pixels.extend_from_slice(img.as_raw());
+
+        match self.frame_tx.try_send(pixels) {
+            Ok(()) => self.frames += 1,
+            Err(TrySendError::Full(_)) | Err(TrySendError::Disconnected(_)) => self.dropped += 1,
+        }
+    }
+
+    /// Closes the frame stream and waits for the encoder on a separate thread,
+    /// so the event loop is not blocked while ffmpeg flushes.
+    pub fn finish(self, response_tx: RecordingResponse) {
+        let Recording {
+            options,
+            started,
+            frames,
+            dropped,
+            frame_tx,
+            encoder,
+            ..
+        } = self;
+        let duration = started.elapsed();
+        drop(frame_tx);
+
+        std::thread::spawn(move || {
+            let result = encoder
+                .join()
+                .map_err(|_| "Encoder thread panicked".to_string())
+                .and_then(|result| result)
+                .map(|()| RecordingSummary {
+                    path: options.path,
+                    duration,
+                    frames,
+                    dropped,
+                });
+            let _ = response_tx.send(result);
+        });
+    }
+}
+
+/// Where encoded frames end up.
+enum Sink {
+    /// ffmpeg, its stdin and the thread collecting the tail of its stderr
+    Ffmpeg(Child, ChildStdin, JoinHandle<String>),
+    Gif {
+        encoder: GifEncoder<BufWriter<File>>,
+        width: u32,
+        height: u32,
+        delay: Delay,
+    },
+}
+
+impl Sink {
+    fn ffmpeg(path: &str, width: u32, height: u32, fps: u32) -> Result<Self, String> {
+        let mut child = Command::new("ffmpeg")
+            .args(["-loglevel", "error", "-y"])
+            .args(["-f", "rawvideo", "-pix_fmt", "rgba"])
+            .args(["-s", &format!("{}x{}", width, height)])
+            .args(["-framerate", &fps.to_string()])
+            .args(["-i", "-"])
+            .args(CODEC_ARGS)
+            .args(["-pix_fmt", "yuv420p"])
+            .arg(path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("Failed to start ffmpeg: {}", e))?;
+        let stdin = child.stdin.take().unwrap();
+        // Read as it comes, so ffmpeg can't stall on a full pipe
+        let stderr = child.stderr.take().unwrap();
+        let stderr = std::thread::spawn(move || stderr_tail(stderr));
+
+        Ok(Self::Ffmpeg(child, stdin, stderr))
+    }
+
+    fn gif(path: &str, width: u32, height: u32, fps: u32) -> Result<Self, String> {
+        let file = File::create(path).map_err(|e| format!("Failed to create {}: {}", path, e))?;
+        // Speed 10 is the gif crate's default trade-off between palette quality and time
+        let mut encoder = GifEncoder::new_with_speed(BufWriter::new(file), 10);
+        encoder
+            .set_repeat(Repeat::Infinite)
+            .map_err(|e| format!("Failed to write GIF header: {}", e))?;
+
+        Ok(Self::Gif {
+            encoder,
+            width,
+            height,
+            delay: Delay::from_numer_denom_ms(1000, fps),
+        })
+    }
+
+    fn write_frame(&mut self, pixels: &[u8]) -> Result<(), String> {
+        match self {
+            Self::Ffmpeg(_, stdin, _) => stdin
+                .write_all(pixels)
+                .map_err(|e| format!("Failed to write frame to ffmpeg: {}", e)),
+            Self::Gif {
+                encoder,
+                width,
+                height,
+                delay,
+            } => {
+                let img = RgbaImage::from_raw(*width, *height, pixels.to_vec())
+                    .ok_or_else(|| "Frame has the wrong size".to_string())?;
+                encoder
+                    .encode_frame(image::Frame::from_parts(img, 0, 0, *delay))
+                    .map_err(|e| format!("Failed to encode GIF frame: {}", e))
+            }
+        }
+    }
+
+    fn finish(self) -> Result<(), String> {
+        match self {
+            Self::Ffmpeg(mut child, stdin, stderr) => {
+                drop(stdin);
+                let status = child
+                    .wait()
+                    .map_err(|e| format!("Failed to wait for ffmpeg: {}", e))?;
+                let stderr = stderr.join().unwrap_or_default();
+                if !status.success() {
+                    return Err(format!("ffmpeg exited with {}: {}", status, stderr.trim()));
+                }
+                Ok(())
+            }
+            Self::Gif { encoder, .. } => {
+                // The trailer is written and the file flushed when the encoder is dropped
+                drop(encoder);
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Reads `stderr` until ffmpeg closes it, returning the last STDERR_TAIL bytes.
+fn stderr_tail(mut stderr: ChildStderr) -> String {
+    let mut tail = Vec::new();
+    let mut buf = [0; 4096];
+    loop {
+        match stderr.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => {
+                tail.extend_from_slice(&buf[..n]);
+                if tail.len() > STDERR_TAIL {
+                    tail.drain(..tail.len() - STDERR_TAIL);
+                }
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(_) => break,
+        }
+    }
+    String::from_utf8_lossy(&tail).into_owned()
+}
+
+fn encode_frames(
+    mut sink: Sink,
+    frame_rx: Receiver<Vec<u8>>,
+    free_tx: mpsc::Sender<Vec<u8>>,
+) -> Result<(), String> {
+    let mut write_error = None;
+
+    for pixels in frame_rx {
+        if write_error.is_none() {
+            write_error = sink.write_frame(&pixels).err();
+        }
+        let _ = free_tx.send(pixels);
+    }
+
+    // Let ffmpeg's own error take precedence, it explains a broken pipe
+    sink.finish()?;
+    match write_error {
+        Some(e) => Err(e),
+        None => Ok(()),
+    }
+}
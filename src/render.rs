@@ -0,0 +1,519 @@
+use std::time::Duration;
+
+use smithay::{
+    backend::{
+        allocator::Fourcc,
+        renderer::{
+            damage::OutputDamageTracker,
+            element::{
+                memory::MemoryRenderBufferRenderElement, solid::SolidColorRenderElement,
+                surface::WaylandSurfaceRenderElement, AsRenderElements,
+            },
+            gles::{GlesRenderer, GlesTarget, GlesTexture},
+            pixman::PixmanRenderer,
+            Bind, ExportMem, ImportAll, ImportDma, ImportMem, Offscreen, Renderer, Texture,
+        },
+    },
+    desktop::{layer_map_for_output, utils::OutputPresentationFeedback, Space, Window},
+    output::{Mode, Output, PhysicalProperties, Subpixel},
+    reexports::{pixman::Image, wayland_server::DisplayHandle},
+    utils::{Physical, Scale, Size, Transform},
+    wayland::shell::wlr_layer::Layer,
+};
+
+use crate::{capture, decoration, presentation, transform, Smallvil};
+
+smithay::backend::renderer::element::render_elements! {
+    pub OutputRenderElement<R> where R: ImportAll + ImportMem;
+    Surface=WaylandSurfaceRenderElement<R>,
+    Decoration=SolidColorRenderElement,
+    // The wallpaper and the input overlay
+    Memory=MemoryRenderBufferRenderElement<R>,
+}
+
+/// Renderer picked with --renderer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RendererKind {
+    /// GLES on an EGL device, falling back to pixman when there is none
+    Auto,
+    Gles,
+    /// Pixman on the CPU, for containers without a GPU or working EGL
+    Pixman,
+}
+
+pub fn parse_renderer(name: &str) -> Result<RendererKind, String> {
+    match name {
+        "auto" => Ok(RendererKind::Auto),
+        "gles" => Ok(RendererKind::Gles),
+        "pixman" => Ok(RendererKind::Pixman),
+        _ => Err(format!(
+            "Unknown renderer '{}', expected auto, gles or pixman",
+            name
+        )),
+    }
+}
+
+/// A renderer that can compose outputs into offscreen buffers and read them
+/// back, which is all virtual outputs and captures need.
+pub trait OffscreenRenderer:
+    Renderer + ImportAll + ImportMem + ExportMem + Offscreen<Self::Buffer> + Bind<Self::Buffer>
+{
+    type Buffer: Texture;
+}
+
+impl OffscreenRenderer for GlesRenderer {
+    type Buffer = GlesTexture;
+}
+
+impl OffscreenRenderer for PixmanRenderer {
+    type Buffer = Image<'static, 'static>;
+}
+
+/// Everything visible on `output`, front to back: the input overlay, X11 menus
+/// and tooltips, overlay and top layer surfaces, each window followed by its
+/// server-side decoration (topmost window first), then bottom and background
+/// layer surfaces, and the wallpaper.
+pub fn output_elements<R>(
+    renderer: &mut R,
+    state: &Smallvil,
+    output: &Output,
+) -> Vec<OutputRenderElement<R>>
+where
+    R: Renderer + ImportAll + ImportMem,
+    R::TextureId: Send + Clone + 'static,
+{
+    let Some(output_geo) = state.space.output_geometry(output) else {
+        return Vec::new();
+    };
+    let scale = Scale::from(output.current_scale().fractional_scale());
+    let focused = state.focused_window();
+
+    let mut elements = Vec::new();
+    if let Some(overlay) = &state.input_overlay {
+        let is_primary = state.primary_output() == Some(output);
+        elements.extend(
+            overlay
+                .elements(renderer, output_geo, scale, is_primary)
+                .into_iter()
+                .map(OutputRenderElement::Memory),
+        );
+    }
+    for window in state.override_redirect_windows.iter().rev() {
+        let Some(surface) = window.x11_surface() else {
+            continue;
+        };
+        let geometry = surface.geometry();
+        if !geometry.overlaps(output_geo) {
+            continue;
+        }
+        let location = (geometry.loc - output_geo.loc).to_physical_precise_round(scale);
+        elements.extend(
+            AsRenderElements::<R>::render_elements::<WaylandSurfaceRenderElement<_>>(
+                window, renderer, location, scale, 1.0,
+            )
+            .into_iter()
+            .map(OutputRenderElement::Surface),
+        );
+    }
+    elements.extend(layer_elements(
+        renderer,
+        output,
+        &[Layer::Overlay, Layer::Top],
+        scale,
+    ));
+    for window in state.space.elements_for_output(output).rev() {
+        let Some(mut geometry) = state.space.element_geometry(window) else {
+            continue;
+        };
+        geometry.loc -= output_geo.loc;
+
+        let location = (geometry.loc - window.geometry().loc).to_physical_precise_round(scale);
+        elements.extend(
+            AsRenderElements::<R>::render_elements::<WaylandSurfaceRenderElement<_>>(
+                window, renderer, location, scale, 1.0,
+            )
+            .into_iter()
+            .map(OutputRenderElement::Surface),
+        );
+        elements.extend(
+            decoration::decoration_elements(
+                window,
+                geometry,
+                scale,
+                focused.as_ref() == Some(window),
+            )
+            .into_iter()
+            .map(OutputRenderElement::Decoration),
+        );
+    }
+    elements.extend(layer_elements(
+        renderer,
+        output,
+        &[Layer::Bottom, Layer::Background],
+        scale,
+    ));
+    elements.extend(
+        state
+            .background
+            .element(renderer, output_geo.size)
+            .map(OutputRenderElement::Memory),
+    );
+    elements
+}
+
+/// Render elements of the layer surfaces of `output` in `layers`, front to back.
+fn layer_elements<R>(
+    renderer: &mut R,
+    output: &Output,
+    layers: &[Layer],
+    scale: Scale<f64>,
+) -> Vec<OutputRenderElement<R>>
+where
+    R: Renderer + ImportAll + ImportMem,
+    R::TextureId: Clone + 'static,
+{
+    let map = layer_map_for_output(output);
+    let mut elements = Vec::new();
+    for &layer in layers {
+        for surface in map.layers_on(layer).rev() {
+            let Some(geometry) = map.layer_geometry(surface) else {
+                continue;
+            };
+            elements.extend(
+                AsRenderElements::<R>::render_elements::<WaylandSurfaceRenderElement<_>>(
+                    surface,
+                    renderer,
+                    geometry.loc.to_physical_precise_round(scale),
+                    scale,
+                    1.0,
+                )
+                .into_iter()
+                .map(OutputRenderElement::Surface),
+            );
+        }
+    }
+    elements
+}
+
+/// Advances the input gestures played back one step per frame. Every backend
+/// calls this before rendering its primary output.
+pub fn start_frame(state: &mut Smallvil) {
+    state.frame_started();
+    if !state.frame_advances() {
+        return;
+    }
+    state.advance_drag();
+    state.advance_gesture();
+    state.advance_pen_stroke();
+}
+
+/// The output shown in a window of the host session, for the nested backends
+/// (winit, X11). The backend owns the window and its buffers; this renders the
+/// space into whatever framebuffer it binds.
+pub struct HostOutput {
+    pub output: Output,
+    /// How the backend's framebuffers are oriented in the window
+    framebuffer_transform: Transform,
+    damage_tracker: OutputDamageTracker,
+    /// Framebuffer size, scale and transform the damage tracker was made for
+    tracked: (Size<i32, Physical>, f64, Transform),
+}
+
+impl HostOutput {
+    /// Creates the output, advertises it to clients and maps it at the origin.
+    /// `framebuffer_transform` is how the backend's framebuffers are oriented;
+    /// the output's own transform, set with set_output_transform, goes on top.
+    pub fn new(
+        state: &mut Smallvil,
+        display_handle: &DisplayHandle,
+        name: &str,
+        model: &str,
+        size: Size<i32, Physical>,
+        framebuffer_transform: Transform,
+    ) -> Self {
+        let mode = Mode {
+            size,
+            refresh: 60_000,
+        };
+
+        let output = Output::new(
+            name.to_string(),
+            PhysicalProperties {
+                size: (0, 0).into(),
+                subpixel: Subpixel::Unknown,
+                make: "Smithay".into(),
+                model: model.into(),
+            },
+        );
+        let _global = output.create_global::<Smallvil>(display_handle);
+        output.change_current_state(
+            Some(mode),
+            Some(Transform::Normal),
+            None,
+            Some((0, 0).into()),
+        );
+        output.set_preferred(mode);
+
+        state.space.map_output(&output, (0, 0));
+
+        Self {
+            output,
+            framebuffer_transform,
+            damage_tracker: OutputDamageTracker::new(size, 1.0, framebuffer_transform),
+            tracked: (size, 1.0, framebuffer_transform),
+        }
+    }
+
+    /// The host window was resized; keeps a refresh rate set through
+    /// set_output_mode.
+    pub fn resize(&self, state: &mut Smallvil, size: Size<i32, Physical>) {
+        let refresh = self
+            .output
+            .current_mode()
+            .map_or(60_000, |mode| mode.refresh);
+        self.output
+            .change_current_state(Some(Mode { size, refresh }), None, None, None);
+        arrange_outputs(&mut state.space);
+    }
+
+    /// Renders the space into `framebuffer`, of `size` and `age` frames old,
+    /// and serves the captures of this output. Returns the presentation
+    /// feedback to answer once the backend has shown the frame.
+    pub fn render(
+        &mut self,
+        renderer: &mut GlesRenderer,
+        framebuffer: &mut GlesTarget<'_>,
+        size: Size<i32, Physical>,
+        age: usize,
+        state: &mut Smallvil,
+    ) -> Option<OutputPresentationFeedback> {
+        let scale = self.output.current_scale().fractional_scale();
+        let transform =
+            transform::compose(self.output.current_transform(), self.framebuffer_transform);
+        if self.tracked != (size, scale, transform) {
+            self.damage_tracker = OutputDamageTracker::new(size, scale, transform);
+            self.tracked = (size, scale, transform);
+        }
+
+        let elements = output_elements(renderer, state, &self.output);
+        let rendered = self.damage_tracker.render_output(
+            renderer,
+            framebuffer,
+            age,
+            &elements,
+            state.background.color,
+        );
+        let result = match rendered {
+            Ok(result) => result,
+            Err(e) => {
+                tracing::error!("Failed to render output {}: {}", self.output.name(), e);
+                return None;
+            }
+        };
+        if result.damage.is_some_and(|damage| !damage.is_empty()) {
+            state.note_damage();
+        }
+
+        let feedback =
+            presentation::take_presentation_feedback(state, &self.output, &result.states);
+        let flipped = self.framebuffer_transform == Transform::Flipped180;
+        capture::capture_frame(state, renderer, framebuffer, &self.output, size, flipped);
+        Some(feedback)
+    }
+}
+
+/// An output with no window behind it, rendered into an offscreen texture.
+/// The headless backend consists only of these; the other backends add them
+/// next to their outputs.
+pub struct VirtualOutput<R: OffscreenRenderer> {
+    pub output: Output,
+    texture: R::Buffer,
+    damage_tracker: OutputDamageTracker,
+}
+
+impl<R> VirtualOutput<R>
+where
+    R: OffscreenRenderer,
+    R::TextureId: Send + Clone + 'static,
+{
+    /// Creates the output, advertises it to clients and maps it to the right
+    /// of the existing outputs.
+    pub fn new(
+        renderer: &mut R,
+        state: &mut Smallvil,
+        display_handle: &DisplayHandle,
+        name: String,
+        size: Size<i32, Physical>,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let mode = Mode {
+            size,
+            refresh: 60_000,
+        };
+
+        let output = Output::new(
+            name,
+            PhysicalProperties {
+                size: (0, 0).into(),
+                subpixel: Subpixel::Unknown,
+                make: "Smithay".into(),
+                model: "Virtual".into(),
+            },
+        );
+        let _global = output.create_global::<Smallvil>(display_handle);
+        // Offscreen textures are read back top-down, so no flip is needed
+        output.change_current_state(Some(mode), Some(Transform::Normal), None, None);
+        output.set_preferred(mode);
+
+        state.space.map_output(&output, (0, 0));
+        arrange_outputs(&mut state.space);
+
+        let damage_tracker = OutputDamageTracker::from_output(&output);
+        let texture = renderer
+            .create_buffer(
+                Fourcc::Abgr8888,
+                size.to_logical(1).to_buffer(1, Transform::Normal),
+            )
+            .map_err(|e| format!("Failed to create buffer of output {}: {}", output.name(), e))?;
+
+        Ok(Self {
+            output,
+            texture,
+            damage_tracker,
+        })
+    }
+
+    /// Renders the space as seen by this output and serves its captures.
+    pub fn render(&mut self, renderer: &mut R, state: &mut Smallvil) {
+        let Some(size) = self.output.current_mode().map(|mode| mode.size) else {
+            return;
+        };
+
+        // The mode was changed with set_output_mode; reallocate the backing texture
+        let buffer_size = size.to_logical(1).to_buffer(1, Transform::Normal);
+        if self.texture.size() != buffer_size {
+            match renderer.create_buffer(Fourcc::Abgr8888, buffer_size) {
+                Ok(texture) => {
+                    self.texture = texture;
+                    self.damage_tracker = OutputDamageTracker::from_output(&self.output);
+                }
+                Err(e) => {
+                    tracing::error!("Failed to resize output {}: {}", self.output.name(), e);
+                    return;
+                }
+            }
+        }
+
+        let mut framebuffer = match renderer.bind(&mut self.texture) {
+            Ok(framebuffer) => framebuffer,
+            Err(e) => {
+                tracing::error!("Failed to bind output {}: {}", self.output.name(), e);
+                return;
+            }
+        };
+
+        let elements = output_elements(renderer, state, &self.output);
+        let rendered = self.damage_tracker.render_output(
+            renderer,
+            &mut framebuffer,
+            0,
+            &elements,
+            state.background.color,
+        );
+        match rendered {
+            Ok(result) => {
+                if result.damage.is_some_and(|damage| !damage.is_empty()) {
+                    state.note_damage();
+                }
+                let feedback =
+                    presentation::take_presentation_feedback(state, &self.output, &result.states);
+                capture::capture_frame(state, renderer, &framebuffer, &self.output, size, false);
+                // Offscreen frames are final as soon as they are rendered
+                state.present_frame(&self.output, feedback);
+            }
+            Err(e) => tracing::error!("Failed to render output {}: {}", self.output.name(), e),
+        }
+    }
+}
+
+/// Places all outputs side by side, left to right in the order they were added,
+/// and lays out their layer surfaces for their current size.
+pub fn arrange_outputs(space: &mut Space<Window>) {
+    let outputs: Vec<Output> = space.outputs().cloned().collect();
+    let mut x = 0;
+    for output in outputs {
+        layer_map_for_output(&output).arrange();
+        space.map_output(&output, (x, 0));
+        x += space.output_geometry(&output).map_or(0, |geo| geo.size.w);
+    }
+}
+
+/// Advertises linux-dmabuf with the formats and modifiers `renderer` can
+/// import, so GPU clients can share buffers instead of copying them into shm.
+/// Only the first renderer of a backend is used; later calls do nothing.
+pub fn init_dmabuf(state: &mut Smallvil, renderer: &GlesRenderer) {
+    if state.dmabuf_global.is_some() {
+        return;
+    }
+    let formats = renderer.dmabuf_formats();
+    if formats.iter().next().is_none() {
+        tracing::warn!("The renderer can't import dmabufs; clients will use shm");
+        return;
+    }
+    let global = state
+        .dmabuf_state
+        .create_global::<Smallvil>(&state.display_handle, formats.iter().copied());
+    state.dmabuf_global = Some(global);
+    state.dmabuf_formats = Some(formats);
+}
+
+/// Parses an output size given as `WIDTHxHEIGHT`, e.g. `1920x1080`.
+pub fn parse_output_size(spec: &str) -> Result<Size<i32, Physical>, String> {
+    let (w, h) = spec
+        .split_once('x')
+        .ok_or_else(|| format!("Invalid output size '{}', expected WIDTHxHEIGHT", spec))?;
+    match (w.parse::<i32>(), h.parse::<i32>()) {
+        (Ok(w), Ok(h)) if w > 0 && h > 0 => Ok((w, h).into()),
+        _ => Err(format!(
+            "Invalid output size '{}', expected WIDTHxHEIGHT",
+            spec
+        )),
+    }
+}
+
+/// Sends frame callbacks and cleans up after a frame has been presented. In
+/// lockstep, frames rendered only for captures don't advance clients.
+pub fn finish_frame(state: &mut Smallvil, display: &mut DisplayHandle, output: &Output) {
+    if state.frame_advances() {
+        state
+            .space
+            .elements()
+            .chain(&state.override_redirect_windows)
+            .for_each(|window| {
+                window.send_frame(
+                    output,
+                    state.start_time.elapsed(),
+                    Some(Duration::ZERO),
+                    |_, _| Some(output.clone()),
+                )
+            });
+        for layer_output in state.space.outputs() {
+            for layer in layer_map_for_output(layer_output).layers() {
+                layer.send_frame(
+                    layer_output,
+                    state.start_time.elapsed(),
+                    Some(Duration::ZERO),
+                    |_, _| Some(layer_output.clone()),
+                );
+            }
+        }
+        state.finish_step();
+    }
+
+    state.space.refresh();
+    state.popups.cleanup();
+    state.check_idle_waits();
+    state.check_stability_waits();
+    state.record_frame_time();
+    state.frame_rendered();
+    let _ = display.flush_clients();
+}
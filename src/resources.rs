@@ -0,0 +1,145 @@
+use std::{
+    collections::VecDeque,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use base64::Engine;
+use rmcp::model::{AnnotateAble, RawResource, Resource, ResourceContents};
+
+/// Captures kept at most; older ones are dropped first.
+const MAX_RESOURCES: usize = 64;
+/// Total size of captures held in memory.
+const MAX_BYTES: usize = 256 * 1024 * 1024;
+
+enum ResourceData {
+    /// Encoded image kept in memory
+    Bytes(Vec<u8>),
+    /// Recording on disk, read when the resource is fetched
+    File(PathBuf),
+}
+
+struct StoredResource {
+    uri: String,
+    name: String,
+    mime_type: String,
+    data: ResourceData,
+}
+
+impl StoredResource {
+    fn memory_size(&self) -> usize {
+        match &self.data {
+            ResourceData::Bytes(bytes) => bytes.len(),
+            ResourceData::File(_) => 0,
+        }
+    }
+}
+
+/// Screenshots and recordings exposed as MCP resources, so clients can fetch
+/// them by URI without sharing a filesystem with the compositor.
+#[derive(Default)]
+pub struct ResourceStore {
+    resources: VecDeque<StoredResource>,
+    memory_size: usize,
+    next_id: u64,
+}
+
+/// The store shared by every client connection.
+pub type SharedResources = Arc<Mutex<ResourceStore>>;
+
+impl ResourceStore {
+    /// Keeps an encoded capture in memory and returns its URI.
+    pub fn add_bytes(&mut self, extension: &str, mime_type: &str, bytes: Vec<u8>) -> String {
+        self.add(extension, mime_type, ResourceData::Bytes(bytes))
+    }
+
+    /// Registers a capture saved to `path` and returns its URI. The file
+    /// itself is left alone when the resource expires.
+    pub fn add_file(&mut self, path: &str) -> String {
+        let path = PathBuf::from(path);
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .unwrap_or("bin")
+            .to_ascii_lowercase();
+        let mime_type = mime_type_for_extension(&extension);
+        self.add(&extension, mime_type, ResourceData::File(path))
+    }
+
+    fn add(&mut self, extension: &str, mime_type: &str, data: ResourceData) -> String {
+        self.next_id += 1;
+        let name = format!("capture-{}.{}", self.next_id, extension);
+        let resource = StoredResource {
+            uri: format!("mcpvil://captures/{}", name),
+            name,
+            mime_type: mime_type.to_string(),
+            data,
+        };
+        let uri = resource.uri.clone();
+        self.memory_size += resource.memory_size();
+        self.resources.push_back(resource);
+        self.evict();
+        uri
+    }
+
+    /// Drops the oldest captures until the store is within its limits, always
+    /// keeping the newest one.
+    fn evict(&mut self) {
+        while self.resources.len() > 1
+            && (self.resources.len() > MAX_RESOURCES || self.memory_size > MAX_BYTES)
+        {
+            if let Some(resource) = self.resources.pop_front() {
+                self.memory_size -= resource.memory_size();
+            }
+        }
+    }
+
+    pub fn list(&self) -> Vec<Resource> {
+        self.resources
+            .iter()
+            .map(|resource| {
+                let mut raw = RawResource::new(&resource.uri, &resource.name);
+                raw.mime_type = Some(resource.mime_type.clone());
+                if let ResourceData::Bytes(bytes) = &resource.data {
+                    raw.size = Some(bytes.len() as u32);
+                }
+                raw.no_annotation()
+            })
+            .collect()
+    }
+
+    pub fn read(&self, uri: &str) -> Result<ResourceContents, String> {
+        let resource = self
+            .resources
+            .iter()
+            .find(|resource| resource.uri == uri)
+            .ok_or_else(|| format!("No resource {}; it may have expired", uri))?;
+        let blob = match &resource.data {
+            ResourceData::Bytes(bytes) => base64::engine::general_purpose::STANDARD.encode(bytes),
+            ResourceData::File(path) => {
+                let bytes = std::fs::read(path)
+                    .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+                base64::engine::general_purpose::STANDARD.encode(bytes)
+            }
+        };
+        Ok(ResourceContents::BlobResourceContents {
+            uri: resource.uri.clone(),
+            mime_type: Some(resource.mime_type.clone()),
+            blob,
+            meta: None,
+        })
+    }
+}
+
+fn mime_type_for_extension(extension: &str) -> &'static str {
+    match extension {
+        "webm" => "video/webm",
+        "mp4" => "video/mp4",
+        "gif" => "image/gif",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "webp" => "image/webp",
+        "qoi" => "image/qoi",
+        _ => "application/octet-stream",
+    }
+}
@@ -0,0 +1,63 @@
+use std::{path::PathBuf, process::Command};
+
+/// System directories sandboxed apps can read; missing ones are skipped.
+const SYSTEM_PATHS: &[&str] = &[
+    "/usr", "/bin", "/sbin", "/lib", "/lib32", "/lib64", "/etc", "/opt",
+];
+
+/// Where a sandboxed app runs without a cwd: its private tmp.
+const DEFAULT_CWD: &str = "/tmp";
+
+/// What a sandboxed app may see of the host besides the system directories.
+pub struct SandboxOptions {
+    /// mcpvil's Wayland socket, the only way out to the compositor
+    pub wayland_socket: PathBuf,
+    /// Xwayland's socket, once it is running
+    pub x11_socket: Option<PathBuf>,
+    /// mcpvil's private session bus, unless the host's is used
+    pub bus_socket: Option<PathBuf>,
+    /// Bound read-write and used as the working directory
+    pub cwd: Option<String>,
+    /// The session directory, bound read-write at the same path
+    pub session_dir: Option<PathBuf>,
+}
+
+/// A bwrap command that runs whatever follows it with read-only system
+/// directories, an empty home, private /tmp and /dev, no network and no other
+/// host files than mcpvil's Wayland, X11 and session bus sockets. Append the
+/// command and its arguments.
+pub fn bwrap(options: &SandboxOptions) -> Command {
+    let mut cmd = Command::new("bwrap");
+    cmd.args(["--unshare-all", "--die-with-parent", "--new-session"]);
+    for &path in SYSTEM_PATHS {
+        cmd.args(["--ro-bind-try", path, path]);
+    }
+    cmd.args(["--proc", "/proc", "--dev", "/dev", "--tmpfs", "/tmp"]);
+    if let Some(home) = std::env::var_os("HOME") {
+        cmd.arg("--tmpfs").arg(home);
+    }
+
+    // Only the compositor's sockets are shared from the runtime dir and /tmp
+    let socket = &options.wayland_socket;
+    if let Some(runtime_dir) = socket.parent() {
+        cmd.arg("--dir").arg(runtime_dir);
+    }
+    cmd.arg("--bind").arg(socket).arg(socket);
+    if let Some(x11_socket) = &options.x11_socket {
+        cmd.arg("--bind-try").arg(x11_socket).arg(x11_socket);
+    }
+    if let Some(bus_socket) = &options.bus_socket {
+        cmd.arg("--bind").arg(bus_socket).arg(bus_socket);
+    }
+
+    if let Some(session_dir) = &options.session_dir {
+        cmd.arg("--bind").arg(session_dir).arg(session_dir);
+    }
+
+    let cwd = options.cwd.as_deref().unwrap_or(DEFAULT_CWD);
+    if options.cwd.is_some() {
+        cmd.args(["--bind", cwd, cwd]);
+    }
+    cmd.args(["--chdir", cwd, "--"]);
+    cmd
+}
@@ -0,0 +1,92 @@
+use smithay::{
+    desktop::layer_map_for_output,
+    output::{Output, Scale},
+    reexports::wayland_server::protocol::wl_surface::WlSurface,
+    wayland::{
+        compositor::{get_parent, send_surface_state, with_states, SurfaceData},
+        fractional_scale::with_fractional_scale,
+    },
+};
+
+use crate::{state::OutputInfo, Smallvil};
+
+/// Smallest and largest scale accepted by set_output_scale.
+pub const MIN_SCALE: f64 = 0.5;
+pub const MAX_SCALE: f64 = 4.0;
+
+/// Tells a surface shown on `output` which scale to render at: the exact one
+/// through wp_fractional_scale, rounded up through wl_surface for other clients.
+/// The output's transform goes along, so clients can pre-rotate their buffers.
+fn send_scale(surface: &WlSurface, states: &SurfaceData, output: &Output) {
+    let scale = output.current_scale();
+    with_fractional_scale(states, |fractional| {
+        fractional.set_preferred_scale(scale.fractional_scale())
+    });
+    send_surface_state(
+        surface,
+        states,
+        scale.integer_scale(),
+        output.current_transform(),
+    );
+}
+
+impl Smallvil {
+    /// Changes the scale factor of an output, so clients on it render HiDPI
+    /// buffers. Windows filling the output are resized to its new logical size.
+    pub fn set_output_scale(
+        &mut self,
+        name: Option<&str>,
+        scale: f64,
+    ) -> Result<OutputInfo, String> {
+        let output = match name {
+            Some(name) => self.output_by_name(name),
+            None => self.primary_output(),
+        }
+        .cloned()
+        .ok_or_else(|| format!("No output named '{}'", name.unwrap_or_default()))?;
+
+        // wp_fractional_scale can only express multiples of 1/120
+        let scale = (scale * 120.0).round() / 120.0;
+        output.change_current_state(None, None, Some(Scale::Fractional(scale)), None);
+        crate::render::arrange_outputs(&mut self.space);
+        self.refit_windows(&output);
+        self.send_surface_scales();
+
+        self.output_info(&output)
+    }
+
+    /// Sends every window, popup and layer surface the scale of the output it
+    /// is on.
+    pub fn send_surface_scales(&self) {
+        for window in self.space.elements() {
+            let Some(output) = self.output_for_window(window) else {
+                continue;
+            };
+            window.with_surfaces(|surface, states| send_scale(surface, states, &output));
+        }
+        for output in self.space.outputs() {
+            for layer in layer_map_for_output(output).layers() {
+                layer.with_surfaces(|surface, states| send_scale(surface, states, output));
+            }
+        }
+    }
+
+    /// Sends a surface that just asked for its fractional scale the scale of
+    /// the output its window is on, or of the primary output if it has none yet.
+    pub fn send_initial_scale(&self, surface: &WlSurface) {
+        let mut root = surface.clone();
+        while let Some(parent) = get_parent(&root) {
+            root = parent;
+        }
+        let output = self
+            .space
+            .elements()
+            .find(|window| window.wl_surface().as_deref() == Some(&root))
+            .and_then(|window| self.output_for_window(window))
+            .or_else(|| self.primary_output().cloned());
+
+        if let Some(output) = output {
+            with_states(surface, |states| send_scale(surface, states, &output));
+        }
+    }
+}
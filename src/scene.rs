@@ -0,0 +1,102 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use rmcp::{model::ResourceUpdatedNotificationParam, Peer, RoleServer};
+use serde::Serialize;
+use smithay::reexports::calloop::{
+    timer::{TimeoutAction, Timer},
+    EventLoop,
+};
+
+use crate::{
+    state::{OutputInfo, WindowId, WindowInfo},
+    CalloopData, Smallvil,
+};
+
+/// URI of the scene graph resource.
+pub const SCENE_URI: &str = "windows://scene";
+
+/// How often the scene is checked for changes.
+const SCENE_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Everything on screen, as published at `windows://scene`.
+#[derive(Debug, Serialize)]
+struct Scene {
+    outputs: Vec<OutputInfo>,
+    /// Mapped windows in stacking order, bottom to top
+    windows: Vec<WindowInfo>,
+    /// Id of the window with keyboard focus
+    focused: Option<u64>,
+}
+
+/// The latest scene as JSON. Every change wakes the receivers, which tell
+/// subscribed MCP clients the resource was updated.
+pub type SceneSender = tokio::sync::watch::Sender<String>;
+pub type SceneReceiver = tokio::sync::watch::Receiver<String>;
+
+/// Clients subscribed to the scene, by connection id.
+pub type SceneSubscribers = Arc<Mutex<HashMap<String, Peer<RoleServer>>>>;
+
+impl Smallvil {
+    /// Republishes the scene whenever the windows, their geometry, focus or the
+    /// outputs change. Polled on a timer, as the space has no change hook.
+    pub fn init_scene_publisher(event_loop: &mut EventLoop<CalloopData>) {
+        event_loop
+            .handle()
+            .insert_source(Timer::immediate(), |_, _, data| {
+                data.state.publish_scene();
+                TimeoutAction::ToDuration(SCENE_POLL_INTERVAL)
+            })
+            .expect("Failed to init the scene publisher.");
+    }
+
+    fn publish_scene(&self) {
+        let scene = Scene {
+            outputs: self.list_outputs(),
+            windows: self
+                .space
+                .elements()
+                .filter(|window| self.mapped_windows.contains_key(&WindowId::of(window).0))
+                .map(|window| self.window_info(window))
+                .collect(),
+            focused: self.focused_window().map(|window| WindowId::of(&window).0),
+        };
+        let json = match serde_json::to_string_pretty(&scene) {
+            Ok(json) => json,
+            Err(e) => {
+                tracing::warn!("Failed to serialize scene: {}", e);
+                return;
+            }
+        };
+        self.scene.send_if_modified(|current| {
+            if *current == json {
+                return false;
+            }
+            *current = json;
+            true
+        });
+    }
+}
+
+/// Tells subscribed clients whenever the scene changes, dropping clients that
+/// have gone away.
+pub async fn forward_scene_updates(subscribers: SceneSubscribers, mut scene: SceneReceiver) {
+    while scene.changed().await.is_ok() {
+        let peers: Vec<_> = subscribers.lock().unwrap().values().cloned().collect();
+        for peer in peers {
+            let notification = ResourceUpdatedNotificationParam {
+                uri: SCENE_URI.to_string(),
+            };
+            if let Err(e) = peer.notify_resource_updated(notification).await {
+                tracing::warn!("Failed to send scene update: {}", e);
+            }
+        }
+        subscribers
+            .lock()
+            .unwrap()
+            .retain(|_, peer| !peer.is_transport_closed());
+    }
+}
@@ -0,0 +1,95 @@
+//! Screencasting into PipeWire, the way screen-sharing apps and recorders
+//! expect to capture a desktop. Each cast is a `Video/Source` node fed with the
+//! frames of one output; the xdg-desktop-portal ScreenCast backend in
+//! [`crate::portal`] hands those nodes to apps that ask for them.
+
+#[cfg(feature = "screencast")]
+mod stream;
+
+use serde::Serialize;
+
+use crate::Smallvil;
+
+#[cfg(feature = "screencast")]
+pub use stream::Screencasts;
+
+/// A running screencast.
+#[derive(Debug, Clone, Serialize)]
+pub struct ScreencastInfo {
+    /// PipeWire node to connect to
+    pub node_id: u32,
+    pub output: String,
+    /// Position of the output in the global compositor space
+    pub x: i32,
+    pub y: i32,
+    /// Frame size in physical pixels
+    pub width: i32,
+    pub height: i32,
+}
+
+pub type ScreencastResponse = tokio::sync::oneshot::Sender<Result<ScreencastInfo, String>>;
+
+/// Stand-in when built without the screencast feature: every cast fails to start.
+#[cfg(not(feature = "screencast"))]
+#[derive(Default)]
+pub struct Screencasts;
+
+#[cfg(not(feature = "screencast"))]
+impl Screencasts {
+    pub fn start(&mut self, _info: ScreencastInfo, response_tx: ScreencastResponse) {
+        let _ = response_tx.send(Err(
+            "mcpvil was built without the screencast feature".to_string()
+        ));
+    }
+
+    pub fn stop(&mut self, _node_id: u32) -> Result<(), String> {
+        Err("mcpvil was built without the screencast feature".to_string())
+    }
+
+    pub fn wants_frame(&self, _output: &smithay::output::Output) -> bool {
+        false
+    }
+
+    pub fn push_frame(&mut self, _output: &smithay::output::Output, _img: &image::RgbaImage) {}
+}
+
+impl Smallvil {
+    /// Starts casting `output`, or the primary output, into a new PipeWire node.
+    /// `response_tx` is answered once the node exists.
+    pub fn start_screencast(&mut self, output: Option<&str>, response_tx: ScreencastResponse) {
+        let info = match output {
+            Some(name) => self
+                .output_by_name(name)
+                .ok_or_else(|| format!("No output named '{}'", name)),
+            None => self
+                .primary_output()
+                .ok_or_else(|| "There are no outputs".to_string()),
+        }
+        .and_then(|output| self.output_info(output));
+
+        match info {
+            Ok(info) => {
+                let (x, y) = info
+                    .geometry
+                    .map_or((0, 0), |geometry| (geometry.x, geometry.y));
+                let info = ScreencastInfo {
+                    node_id: 0,
+                    output: info.name,
+                    x,
+                    y,
+                    width: info.width,
+                    height: info.height,
+                };
+                self.screencasts.start(info, response_tx);
+            }
+            Err(e) => {
+                let _ = response_tx.send(Err(e));
+            }
+        }
+    }
+
+    /// Ends the screencast feeding PipeWire node `node_id`.
+    pub fn stop_screencast(&mut self, node_id: u32) -> Result<(), String> {
+        self.screencasts.stop(node_id)
+    }
+}
@@ -0,0 +1,407 @@
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    io::Cursor,
+    rc::Rc,
+    sync::{
+        atomic::{AtomicBool, AtomicU32, Ordering},
+        Arc,
+    },
+};
+
+use pipewire::{
+    self as pw,
+    spa::{
+        param::{
+            format::{FormatProperties, MediaSubtype, MediaType},
+            video::VideoFormat,
+            ParamType,
+        },
+        pod::{serialize::PodSerializer, Object, Pod, Property, PropertyFlags, Value},
+        utils::{Direction, Fraction, Rectangle, SpaTypes},
+    },
+    stream::{Stream, StreamFlags, StreamListener, StreamState},
+};
+use smithay::output::Output;
+
+use super::{ScreencastInfo, ScreencastResponse};
+
+const BYTES_PER_PIXEL: i32 = 4;
+
+/// State of one cast shared between the compositor and the PipeWire thread.
+#[derive(Default)]
+struct Shared {
+    /// Assigned once the stream is connected
+    node_id: AtomicU32,
+    /// A consumer is connected and the stream is running
+    streaming: AtomicBool,
+    /// The previous frame was handed to PipeWire, so the next one can be sent
+    idle: AtomicBool,
+}
+
+struct Cast {
+    id: u32,
+    info: ScreencastInfo,
+    shared: Arc<Shared>,
+}
+
+enum Command {
+    Create {
+        id: u32,
+        info: ScreencastInfo,
+        shared: Arc<Shared>,
+        response_tx: ScreencastResponse,
+    },
+    Frame {
+        id: u32,
+        pixels: Vec<u8>,
+    },
+    Destroy {
+        id: u32,
+    },
+}
+
+/// The running screencasts. PipeWire is connected to on a thread of its own
+/// when the first one starts.
+#[derive(Default)]
+pub struct Screencasts {
+    pipewire: Option<pw::channel::Sender<Command>>,
+    casts: Vec<Cast>,
+    next_id: u32,
+}
+
+impl Screencasts {
+    pub fn start(&mut self, info: ScreencastInfo, response_tx: ScreencastResponse) {
+        if self.pipewire.is_none() {
+            match spawn_pipewire_thread() {
+                Ok(pipewire) => self.pipewire = Some(pipewire),
+                Err(e) => {
+                    let _ = response_tx.send(Err(e));
+                    return;
+                }
+            }
+        }
+
+        self.next_id += 1;
+        let id = self.next_id;
+        let shared = Arc::new(Shared {
+            idle: AtomicBool::new(true),
+            ..Default::default()
+        });
+        let command = Command::Create {
+            id,
+            info: info.clone(),
+            shared: shared.clone(),
+            response_tx,
+        };
+        let sent = self
+            .pipewire
+            .as_ref()
+            .is_some_and(|pipewire| pipewire.send(command).is_ok());
+        if !sent {
+            // The thread is gone, and the response channel with the command
+            tracing::error!("PipeWire thread exited");
+            self.pipewire = None;
+            return;
+        }
+        self.casts.push(Cast { id, info, shared });
+    }
+
+    pub fn stop(&mut self, node_id: u32) -> Result<(), String> {
+        let index = self
+            .casts
+            .iter()
+            .position(|cast| cast.shared.node_id.load(Ordering::Relaxed) == node_id)
+            .ok_or_else(|| format!("No screencast with node id {}", node_id))?;
+        let cast = self.casts.remove(index);
+        if let Some(pipewire) = &self.pipewire {
+            let _ = pipewire.send(Command::Destroy { id: cast.id });
+        }
+        Ok(())
+    }
+
+    /// Whether a cast of `output` is streaming and ready for another frame.
+    pub fn wants_frame(&self, output: &Output) -> bool {
+        self.casts.iter().any(|cast| {
+            cast.info.output == output.name()
+                && cast.shared.streaming.load(Ordering::Relaxed)
+                && cast.shared.idle.load(Ordering::Relaxed)
+        })
+    }
+
+    /// Sends a rendered frame of `output` to its casts. Frames that don't match
+    /// the size the stream was negotiated with are dropped.
+    pub fn push_frame(&mut self, output: &Output, img: &image::RgbaImage) {
+        let Some(pipewire) = &self.pipewire else {
+            return;
+        };
+        for cast in &self.casts {
+            if cast.info.output != output.name()
+                || img.width() as i32 != cast.info.width
+                || img.height() as i32 != cast.info.height
+                || !cast.shared.streaming.load(Ordering::Relaxed)
+                || !cast.shared.idle.swap(false, Ordering::Relaxed)
+            {
+                continue;
+            }
+            let command = Command::Frame {
+                id: cast.id,
+                pixels: img.as_raw().clone(),
+            };
+            if pipewire.send(command).is_err() {
+                cast.shared.idle.store(true, Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+/// Connects to PipeWire on a new thread running its main loop, which owns the
+/// streams and is driven by commands from the compositor.
+fn spawn_pipewire_thread() -> Result<pw::channel::Sender<Command>, String> {
+    let (command_tx, command_rx) = pw::channel::channel();
+    let (init_tx, init_rx) = std::sync::mpsc::channel();
+
+    std::thread::Builder::new()
+        .name("pipewire".into())
+        .spawn(move || {
+            pw::init();
+            let setup = || -> Result<_, pw::Error> {
+                let mainloop = pw::main_loop::MainLoop::new(None)?;
+                let context = pw::context::Context::new(&mainloop)?;
+                let core = context.connect(None)?;
+                Ok((mainloop, context, core))
+            };
+            let (mainloop, _context, core) = match setup() {
+                Ok(connected) => connected,
+                Err(e) => {
+                    let _ = init_tx.send(Err(format!("Failed to connect to PipeWire: {}", e)));
+                    return;
+                }
+            };
+            let _ = init_tx.send(Ok(()));
+
+            let streams: RefCell<HashMap<u32, CastStream>> = RefCell::new(HashMap::new());
+            let _receiver = command_rx.attach(mainloop.loop_(), move |command| match command {
+                Command::Create {
+                    id,
+                    info,
+                    shared,
+                    response_tx,
+                } => {
+                    let response_tx = Rc::new(RefCell::new(Some(response_tx)));
+                    match CastStream::new(&core, info, shared, response_tx.clone()) {
+                        Ok(stream) => {
+                            streams.borrow_mut().insert(id, stream);
+                        }
+                        Err(e) => {
+                            if let Some(response_tx) = response_tx.borrow_mut().take() {
+                                let _ = response_tx
+                                    .send(Err(format!("Failed to create PipeWire stream: {}", e)));
+                            }
+                        }
+                    }
+                }
+                Command::Frame { id, pixels } => {
+                    if let Some(stream) = streams.borrow().get(&id) {
+                        *stream.pending.borrow_mut() = Some(pixels);
+                        if let Err(e) = stream.stream.trigger_process() {
+                            tracing::warn!("Failed to queue screencast frame: {}", e);
+                            stream.pending.borrow_mut().take();
+                            stream.shared.idle.store(true, Ordering::Relaxed);
+                        }
+                    }
+                }
+                Command::Destroy { id } => {
+                    if let Some(stream) = streams.borrow_mut().remove(&id) {
+                        let _ = stream.stream.disconnect();
+                    }
+                }
+            });
+
+            mainloop.run();
+        })
+        .map_err(|e| format!("Failed to spawn PipeWire thread: {}", e))?;
+
+    init_rx
+        .recv()
+        .map_err(|_| "PipeWire thread exited during setup".to_string())??;
+    Ok(command_tx)
+}
+
+/// Data the stream callbacks work on.
+struct StreamData {
+    info: ScreencastInfo,
+    shared: Arc<Shared>,
+    pending: Rc<RefCell<Option<Vec<u8>>>>,
+    /// Answered with the node id once the stream is connected
+    response_tx: Rc<RefCell<Option<ScreencastResponse>>>,
+}
+
+struct CastStream {
+    stream: Stream,
+    _listener: StreamListener<StreamData>,
+    /// The latest frame, copied into a buffer when the stream processes
+    pending: Rc<RefCell<Option<Vec<u8>>>>,
+    shared: Arc<Shared>,
+}
+
+impl CastStream {
+    fn new(
+        core: &pw::core::Core,
+        info: ScreencastInfo,
+        shared: Arc<Shared>,
+        response_tx: Rc<RefCell<Option<ScreencastResponse>>>,
+    ) -> Result<Self, pw::Error> {
+        let (width, height) = (info.width as u32, info.height as u32);
+        let stream = Stream::new(
+            core,
+            &format!("mcpvil-{}", info.output),
+            pw::properties::properties! {
+                *pw::keys::MEDIA_CLASS => "Video/Source",
+                *pw::keys::MEDIA_TYPE => "Video",
+                *pw::keys::MEDIA_CATEGORY => "Capture",
+                *pw::keys::MEDIA_ROLE => "Screen",
+            },
+        )?;
+
+        let pending = Rc::new(RefCell::new(None));
+        let data = StreamData {
+            info,
+            shared: shared.clone(),
+            pending: pending.clone(),
+            response_tx,
+        };
+        let listener = stream
+            .add_local_listener_with_user_data(data)
+            .state_changed(|stream, data, _old, new| {
+                match &new {
+                    StreamState::Paused | StreamState::Streaming => {
+                        data.shared
+                            .node_id
+                            .store(stream.node_id(), Ordering::Relaxed);
+                        if let Some(response_tx) = data.response_tx.borrow_mut().take() {
+                            let info = ScreencastInfo {
+                                node_id: stream.node_id(),
+                                ..data.info.clone()
+                            };
+                            let _ = response_tx.send(Ok(info));
+                        }
+                    }
+                    StreamState::Error(e) => {
+                        tracing::error!("Screencast of {} failed: {}", data.info.output, e);
+                        if let Some(response_tx) = data.response_tx.borrow_mut().take() {
+                            let _ = response_tx.send(Err(format!("PipeWire stream failed: {}", e)));
+                        }
+                    }
+                    _ => {}
+                }
+                data.shared
+                    .streaming
+                    .store(matches!(new, StreamState::Streaming), Ordering::Relaxed);
+            })
+            .param_changed(|stream, data, id, param| {
+                if param.is_none() || id != ParamType::Format.as_raw() {
+                    return;
+                }
+                // Buffers hold tightly packed RGBA rows, allocated and mapped by PipeWire
+                let stride = data.info.width * BYTES_PER_PIXEL;
+                let buffers = serialize(Object {
+                    type_: SpaTypes::ObjectParamBuffers.as_raw(),
+                    id: ParamType::Buffers.as_raw(),
+                    properties: vec![
+                        int_property(pw::spa::sys::SPA_PARAM_BUFFERS_buffers, 4),
+                        int_property(pw::spa::sys::SPA_PARAM_BUFFERS_blocks, 1),
+                        int_property(
+                            pw::spa::sys::SPA_PARAM_BUFFERS_size,
+                            stride * data.info.height,
+                        ),
+                        int_property(pw::spa::sys::SPA_PARAM_BUFFERS_stride, stride),
+                        int_property(
+                            pw::spa::sys::SPA_PARAM_BUFFERS_dataType,
+                            1 << pw::spa::sys::SPA_DATA_MemPtr,
+                        ),
+                    ],
+                });
+                let Some(buffers) = Pod::from_bytes(&buffers) else {
+                    return;
+                };
+                if let Err(e) = stream.update_params(&mut [buffers]) {
+                    tracing::error!("Failed to set screencast buffer params: {}", e);
+                }
+            })
+            .process(|stream, data| {
+                let Some(pixels) = data.pending.borrow_mut().take() else {
+                    return;
+                };
+                data.shared.idle.store(true, Ordering::Relaxed);
+                let Some(mut buffer) = stream.dequeue_buffer() else {
+                    // The consumer holds every buffer; drop the frame
+                    return;
+                };
+                let Some(buffer_data) = buffer.datas_mut().first_mut() else {
+                    return;
+                };
+                let Some(memory) = buffer_data.data() else {
+                    return;
+                };
+                let size = memory.len().min(pixels.len());
+                memory[..size].copy_from_slice(&pixels[..size]);
+
+                let chunk = buffer_data.chunk_mut();
+                *chunk.offset_mut() = 0;
+                *chunk.stride_mut() = data.info.width * BYTES_PER_PIXEL;
+                *chunk.size_mut() = size as u32;
+            })
+            .register()?;
+
+        let format = serialize(Object {
+            type_: SpaTypes::ObjectParamFormat.as_raw(),
+            id: ParamType::EnumFormat.as_raw(),
+            properties: vec![
+                pw::spa::pod::property!(FormatProperties::MediaType, Id, MediaType::Video),
+                pw::spa::pod::property!(FormatProperties::MediaSubtype, Id, MediaSubtype::Raw),
+                pw::spa::pod::property!(FormatProperties::VideoFormat, Id, VideoFormat::RGBA),
+                pw::spa::pod::property!(
+                    FormatProperties::VideoSize,
+                    Rectangle,
+                    Rectangle { width, height }
+                ),
+                // Frames are pushed as they are rendered, at a variable rate
+                pw::spa::pod::property!(
+                    FormatProperties::VideoFramerate,
+                    Fraction,
+                    Fraction { num: 0, denom: 1 }
+                ),
+            ],
+        });
+        let format = Pod::from_bytes(&format).ok_or(pw::Error::CreationFailed)?;
+
+        stream.connect(
+            Direction::Output,
+            None,
+            StreamFlags::DRIVER | StreamFlags::ALLOC_BUFFERS | StreamFlags::MAP_BUFFERS,
+            &mut [format],
+        )?;
+
+        Ok(Self {
+            stream,
+            _listener: listener,
+            pending,
+            shared,
+        })
+    }
+}
+
+fn int_property(key: u32, value: i32) -> Property {
+    Property {
+        key,
+        flags: PropertyFlags::empty(),
+        value: Value::Int(value),
+    }
+}
+
+fn serialize(object: Object) -> Vec<u8> {
+    PodSerializer::serialize(Cursor::new(Vec::new()), &Value::Object(object))
+        .map(|(cursor, _)| cursor.into_inner())
+        .unwrap_or_default()
+}
@@ -0,0 +1,264 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use smithay::{
+    output::Output,
+    reexports::{
+        wayland_protocols_wlr::screencopy::v1::server::{
+            zwlr_screencopy_frame_v1::{self, ZwlrScreencopyFrameV1},
+            zwlr_screencopy_manager_v1::{self, ZwlrScreencopyManagerV1},
+        },
+        wayland_server::{
+            backend::GlobalId,
+            protocol::{wl_buffer::WlBuffer, wl_shm},
+            Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch, New, Resource,
+        },
+    },
+    utils::{Logical, Physical, Rectangle},
+    wayland::shm::{with_buffer_contents, with_buffer_contents_mut},
+};
+
+use crate::Smallvil;
+
+const VERSION: u32 = 3;
+
+/// The `zwlr_screencopy_manager_v1` global, which lets external tools such as
+/// grim capture outputs through the same frame read-back as screenshots.
+pub struct ScreencopyManagerState {
+    _global: GlobalId,
+}
+
+impl ScreencopyManagerState {
+    pub fn new(display_handle: &DisplayHandle) -> Self {
+        let global =
+            display_handle.create_global::<Smallvil, ZwlrScreencopyManagerV1, _>(VERSION, ());
+        Self { _global: global }
+    }
+}
+
+/// What a screencopy frame captures. `output` is `None` when the client asked
+/// for an output or region that can't be captured.
+pub struct ScreencopyFrame {
+    pub output: Option<Output>,
+    /// Captured area in the output's physical pixels
+    pub region: Rectangle<i32, Physical>,
+    copied: AtomicBool,
+}
+
+/// A frame whose client has attached a buffer, filled after the next render
+/// of its output.
+pub struct PendingScreencopy {
+    pub frame: ZwlrScreencopyFrameV1,
+    pub buffer: WlBuffer,
+    pub with_damage: bool,
+}
+
+impl PendingScreencopy {
+    pub fn output(&self) -> Option<&Output> {
+        self.frame
+            .data::<ScreencopyFrame>()
+            .and_then(|data| data.output.as_ref())
+    }
+
+    /// Copies the frame's region of `img`, the upright contents of its output,
+    /// into the client's buffer and tells the client it is ready.
+    pub fn complete(self, img: &image::RgbaImage) {
+        let Some(data) = self.frame.data::<ScreencopyFrame>() else {
+            return;
+        };
+        let region = data.region;
+        // The output may have changed mode since the client was told the size
+        if region.loc.x + region.size.w > img.width() as i32
+            || region.loc.y + region.size.h > img.height() as i32
+        {
+            self.frame.failed();
+            return;
+        }
+
+        let copied = with_buffer_contents_mut(&self.buffer, |ptr, len, info| {
+            let stride = info.stride as usize;
+            let offset = info.offset as usize;
+            if offset + stride * region.size.h as usize > len {
+                return false;
+            }
+            let pixels = unsafe { std::slice::from_raw_parts_mut(ptr.add(offset), len - offset) };
+            for y in 0..region.size.h {
+                let row = &mut pixels[y as usize * stride..];
+                for x in 0..region.size.w {
+                    let [r, g, b, a] = img
+                        .get_pixel((region.loc.x + x) as u32, (region.loc.y + y) as u32)
+                        .0;
+                    // ARGB8888 is stored as little-endian BGRA
+                    row[x as usize * 4..x as usize * 4 + 4].copy_from_slice(&[b, g, r, a]);
+                }
+            }
+            true
+        });
+        if !matches!(copied, Ok(true)) {
+            tracing::warn!("Failed to copy a screencopy frame into its buffer");
+            self.frame.failed();
+            return;
+        }
+
+        self.frame.flags(zwlr_screencopy_frame_v1::Flags::empty());
+        if self.with_damage {
+            // Every frame is sent in full, so report it all as damaged
+            self.frame
+                .damage(0, 0, region.size.w as u32, region.size.h as u32);
+        }
+        let mut now = libc::timespec {
+            tv_sec: 0,
+            tv_nsec: 0,
+        };
+        unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut now) };
+        let secs = now.tv_sec as u64;
+        self.frame
+            .ready((secs >> 32) as u32, secs as u32, now.tv_nsec as u32);
+    }
+}
+
+impl GlobalDispatch<ZwlrScreencopyManagerV1, ()> for Smallvil {
+    fn bind(
+        _state: &mut Self,
+        _handle: &DisplayHandle,
+        _client: &Client,
+        resource: New<ZwlrScreencopyManagerV1>,
+        _global_data: &(),
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        data_init.init(resource, ());
+    }
+}
+
+impl Dispatch<ZwlrScreencopyManagerV1, ()> for Smallvil {
+    fn request(
+        _state: &mut Self,
+        _client: &Client,
+        _manager: &ZwlrScreencopyManagerV1,
+        request: zwlr_screencopy_manager_v1::Request,
+        _data: &(),
+        _dh: &DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        // The cursor is never drawn into frames, so overlay_cursor is ignored
+        let (frame, output, logical_region) = match request {
+            zwlr_screencopy_manager_v1::Request::CaptureOutput { frame, output, .. } => {
+                (frame, output, None)
+            }
+            zwlr_screencopy_manager_v1::Request::CaptureOutputRegion {
+                frame,
+                output,
+                x,
+                y,
+                width,
+                height,
+                ..
+            } => (
+                frame,
+                output,
+                Some(Rectangle::<i32, Logical>::new(
+                    (x, y).into(),
+                    (width, height).into(),
+                )),
+            ),
+            zwlr_screencopy_manager_v1::Request::Destroy => return,
+            _ => unreachable!(),
+        };
+
+        let target = Output::from_resource(&output).and_then(|output| {
+            let mode = output.current_mode()?;
+            let bounds = Rectangle::from_size(mode.size);
+            let region = match logical_region {
+                Some(region) => region
+                    .to_f64()
+                    .to_physical(output.current_scale().fractional_scale())
+                    .to_i32_round()
+                    .intersection(bounds)?,
+                None => bounds,
+            };
+            Some((output, region))
+        });
+
+        let Some((output, region)) = target else {
+            let frame = data_init.init(
+                frame,
+                ScreencopyFrame {
+                    output: None,
+                    region: Rectangle::default(),
+                    copied: AtomicBool::new(false),
+                },
+            );
+            frame.failed();
+            return;
+        };
+        let frame = data_init.init(
+            frame,
+            ScreencopyFrame {
+                output: Some(output),
+                region,
+                copied: AtomicBool::new(false),
+            },
+        );
+        frame.buffer(
+            wl_shm::Format::Argb8888,
+            region.size.w as u32,
+            region.size.h as u32,
+            region.size.w as u32 * 4,
+        );
+        if frame.version() >= 3 {
+            frame.buffer_done();
+        }
+    }
+}
+
+impl Dispatch<ZwlrScreencopyFrameV1, ScreencopyFrame> for Smallvil {
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        frame: &ZwlrScreencopyFrameV1,
+        request: zwlr_screencopy_frame_v1::Request,
+        data: &ScreencopyFrame,
+        _dh: &DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+        let (buffer, with_damage) = match request {
+            zwlr_screencopy_frame_v1::Request::Copy { buffer } => (buffer, false),
+            zwlr_screencopy_frame_v1::Request::CopyWithDamage { buffer } => (buffer, true),
+            zwlr_screencopy_frame_v1::Request::Destroy => return,
+            _ => unreachable!(),
+        };
+
+        if data.copied.swap(true, Ordering::Relaxed) {
+            frame.post_error(
+                zwlr_screencopy_frame_v1::Error::AlreadyUsed,
+                "frame was already copied",
+            );
+            return;
+        }
+        if data.output.is_none() {
+            frame.failed();
+            return;
+        }
+
+        let region = data.region;
+        let valid = with_buffer_contents(&buffer, |_, _, info| {
+            info.format == wl_shm::Format::Argb8888
+                && info.width == region.size.w
+                && info.height == region.size.h
+                && info.stride == region.size.w * 4
+        });
+        if !matches!(valid, Ok(true)) {
+            frame.post_error(
+                zwlr_screencopy_frame_v1::Error::InvalidBuffer,
+                "buffer must be an ARGB8888 shm buffer of the advertised size",
+            );
+            return;
+        }
+
+        state.pending_screencopies.push(PendingScreencopy {
+            frame: frame.clone(),
+            buffer,
+            with_damage,
+        });
+        state.request_frame();
+    }
+}
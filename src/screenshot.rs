@@ -0,0 +1,232 @@
+use std::io::Cursor;
+
+use base64::Engine;
+use image::{
+    codecs::{
+        jpeg::JpegEncoder,
+        png::{CompressionType, FilterType as PngFilterType, PngEncoder},
+        webp::WebPEncoder,
+    },
+    DynamicImage,
+};
+
+use crate::{
+    annotate::{self, Annotation},
+    errors::{ErrorKind, ToolError},
+    state::Geometry,
+};
+
+/// How a screenshot should be framed, encoded and delivered.
+#[derive(Debug, Clone, Default)]
+pub struct ScreenshotOptions {
+    /// Save the image to this path
+    pub filename: Option<String>,
+    /// Return the image base64-encoded in the response
+    pub inline: bool,
+    /// What to capture
+    pub mode: CaptureMode,
+    /// Output to capture by name; the primary output if unset. Ignored for window captures
+    pub output: Option<String>,
+    /// Encoding of the saved/returned image
+    pub format: ImageFormat,
+    /// 1-100; JPEG quality, or PNG compression effort (higher is faster and larger)
+    pub quality: Option<u8>,
+    /// Draw the pointer's cursor into the image
+    pub include_cursor: bool,
+    /// Callouts drawn onto the image before it is encoded
+    pub annotations: Vec<Annotation>,
+    /// When the screenshot was asked for, to measure its latency for get_stats
+    pub requested_at: Option<std::time::Instant>,
+    /// Span of the request, which capturing and encoding the image run in
+    pub span: Option<tracing::Span>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ImageFormat {
+    #[default]
+    Png,
+    Jpeg,
+    Webp,
+    Qoi,
+}
+
+impl ImageFormat {
+    pub fn from_name(name: &str) -> Result<Self, String> {
+        match name.to_ascii_lowercase().as_str() {
+            "png" => Ok(Self::Png),
+            "jpeg" | "jpg" => Ok(Self::Jpeg),
+            "webp" => Ok(Self::Webp),
+            "qoi" => Ok(Self::Qoi),
+            _ => Err(format!(
+                "Unknown format '{}'. Use 'png', 'jpeg', 'webp', or 'qoi'.",
+                name
+            )),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Png => "PNG",
+            Self::Jpeg => "JPEG",
+            Self::Webp => "WebP",
+            Self::Qoi => "QOI",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Png => "png",
+            Self::Jpeg => "jpg",
+            Self::Webp => "webp",
+            Self::Qoi => "qoi",
+        }
+    }
+
+    pub fn mime_type(self) -> &'static str {
+        match self {
+            Self::Png => "image/png",
+            Self::Jpeg => "image/jpeg",
+            Self::Webp => "image/webp",
+            Self::Qoi => "image/qoi",
+        }
+    }
+}
+
+/// What part of the scene a screenshot frames.
+#[derive(Debug, Clone, Copy, Default)]
+pub enum CaptureMode {
+    /// The whole output, exactly as rendered
+    #[default]
+    FullOutput,
+    /// The window with keyboard focus, rendered on its own
+    FocusedWindow,
+    /// A window by id, rendered on its own
+    Window(u64),
+    /// A rectangle of the output, relative to its top left corner
+    Region(Geometry),
+}
+
+/// A finished screenshot.
+#[derive(Debug, Clone)]
+pub struct Screenshot {
+    pub width: u32,
+    pub height: u32,
+    /// Where the image was saved, if requested
+    pub path: Option<String>,
+    pub mime_type: &'static str,
+    /// Base64-encoded image, if requested inline
+    pub base64_data: Option<String>,
+    /// Size of the encoded image
+    pub size_bytes: usize,
+    /// The encoded image, kept as an MCP resource
+    pub data: Vec<u8>,
+    pub format: ImageFormat,
+}
+
+pub type ScreenshotResponse = tokio::sync::oneshot::Sender<Result<Screenshot, ToolError>>;
+
+/// Crops to `region`, clamped to the image bounds.
+pub fn crop_to_region(img: DynamicImage, region: Geometry) -> Result<DynamicImage, String> {
+    let (width, height) = (img.width() as i64, img.height() as i64);
+
+    let x0 = (region.x as i64).clamp(0, width);
+    let y0 = (region.y as i64).clamp(0, height);
+    let x1 = (region.x as i64 + region.width as i64).clamp(0, width);
+    let y1 = (region.y as i64 + region.height as i64).clamp(0, height);
+
+    if x1 <= x0 || y1 <= y0 {
+        return Err(format!(
+            "Region {}x{}+{}+{} lies outside the {}x{} capture",
+            region.width, region.height, region.x, region.y, width, height
+        ));
+    }
+
+    Ok(img.crop_imm(x0 as u32, y0 as u32, (x1 - x0) as u32, (y1 - y0) as u32))
+}
+
+/// Encodes the image in the requested format and quality.
+pub fn encode(
+    img: &DynamicImage,
+    format: ImageFormat,
+    quality: Option<u8>,
+) -> Result<Vec<u8>, String> {
+    let mut buf = Cursor::new(Vec::new());
+
+    let result = match format {
+        ImageFormat::Png => {
+            // Quality maps onto compression effort: high quality favours speed over size
+            let compression = match quality {
+                Some(q) if q >= 90 => CompressionType::Fast,
+                Some(q) if q < 50 => CompressionType::Best,
+                _ => CompressionType::Default,
+            };
+            img.write_with_encoder(PngEncoder::new_with_quality(
+                &mut buf,
+                compression,
+                PngFilterType::Adaptive,
+            ))
+        }
+        // JPEG has no alpha channel
+        ImageFormat::Jpeg => DynamicImage::ImageRgb8(img.to_rgb8()).write_with_encoder(
+            JpegEncoder::new_with_quality(&mut buf, quality.unwrap_or(80).clamp(1, 100)),
+        ),
+        // The WebP encoder is lossless only, so quality does not apply
+        ImageFormat::Webp => img.write_with_encoder(WebPEncoder::new_lossless(&mut buf)),
+        ImageFormat::Qoi => img.write_to(&mut buf, image::ImageFormat::Qoi),
+    };
+    result.map_err(|e| format!("Failed to encode {}: {}", format.name(), e))?;
+
+    Ok(buf.into_inner())
+}
+
+/// Saves and/or encodes the image according to `options`.
+pub fn deliver(img: &DynamicImage, options: &ScreenshotOptions) -> Result<Screenshot, String> {
+    let annotated = (!options.annotations.is_empty()).then(|| {
+        let mut img = img.to_rgba8();
+        annotate::draw(&mut img, &options.annotations);
+        DynamicImage::ImageRgba8(img)
+    });
+    let img = annotated.as_ref().unwrap_or(img);
+    let data = encode(img, options.format, options.quality)?;
+
+    if let Some(filename) = &options.filename {
+        std::fs::write(filename, &data).map_err(|e| format!("Failed to save screenshot: {}", e))?;
+    }
+
+    let base64_data = options
+        .inline
+        .then(|| base64::engine::general_purpose::STANDARD.encode(&data));
+
+    Ok(Screenshot {
+        width: img.width(),
+        height: img.height(),
+        path: options.filename.clone(),
+        mime_type: options.format.mime_type(),
+        base64_data,
+        size_bytes: data.len(),
+        data,
+        format: options.format,
+    })
+}
+
+/// Encodes and saves a captured image on a worker thread, so large frames don't
+/// stall composition, and answers `response_tx` once done.
+pub fn deliver_in_background(
+    img: Result<DynamicImage, ToolError>,
+    options: ScreenshotOptions,
+    response_tx: ScreenshotResponse,
+) {
+    let span = options.span.clone().unwrap_or_else(tracing::Span::current);
+    let spawned = std::thread::Builder::new()
+        .name("screenshot-encoder".into())
+        .spawn(move || {
+            let _span = tracing::info_span!(parent: &span, "encode_screenshot").entered();
+            let result = img.and_then(|img| {
+                deliver(&img, &options).map_err(|e| ToolError::new(ErrorKind::CaptureFailed, e))
+            });
+            let _ = response_tx.send(result);
+        });
+    if let Err(e) = spawned {
+        tracing::error!("Failed to spawn screenshot encoder thread: {}", e);
+    }
+}
@@ -0,0 +1,91 @@
+use std::{collections::VecDeque, time::Duration};
+
+use smithay::{
+    reexports::calloop::timer::{TimeoutAction, Timer},
+    utils::{Logical, Point},
+};
+
+use crate::Smallvil;
+
+/// One step of an input_sequence call.
+#[derive(Debug, Clone)]
+pub enum InputStep {
+    Move(Point<f64, Logical>),
+    Click {
+        location: Point<f64, Logical>,
+        button: u32,
+        count: u32,
+    },
+    /// A key combination, as for press_key
+    Key(String),
+    Type(String),
+    Wait(Duration),
+}
+
+/// The steps of an input_sequence call that have yet to run.
+pub struct InputSequence {
+    pub steps: VecDeque<InputStep>,
+    /// Number of steps already run
+    pub done: usize,
+    pub response_tx: tokio::sync::oneshot::Sender<Result<usize, String>>,
+    /// Receives the number of steps run after each one
+    pub progress_tx: tokio::sync::mpsc::UnboundedSender<usize>,
+}
+
+impl Smallvil {
+    /// Runs steps back to back until a wait, then resumes from a timer once it
+    /// has passed. Replies with the number of steps run, or stops at the first
+    /// step that fails.
+    pub fn run_input_sequence(&mut self, mut sequence: InputSequence) {
+        while let Some(step) = sequence.steps.pop_front() {
+            sequence.done += 1;
+            let result = match &step {
+                InputStep::Move(location) => {
+                    self.synthetic_motion(*location);
+                    Ok(())
+                }
+                InputStep::Click {
+                    location,
+                    button,
+                    count,
+                } => {
+                    self.synthetic_click(*location, *button, *count);
+                    Ok(())
+                }
+                InputStep::Key(combo) => self.press_key_combo(combo),
+                InputStep::Type(text) => self.type_text(text).map(|_| ()),
+                InputStep::Wait(delay) => {
+                    let delay = *delay;
+                    let mut sequence = Some(sequence);
+                    let timer = self.loop_handle.insert_source(
+                        Timer::from_duration(delay),
+                        move |_, _, data| {
+                            // Nobody wants the rest once the call is cancelled or timed out
+                            if let Some(sequence) = sequence.take() {
+                                if !sequence.response_tx.is_closed() {
+                                    data.state.run_input_sequence(sequence);
+                                }
+                            }
+                            TimeoutAction::Drop
+                        },
+                    );
+                    if let Err(e) = timer {
+                        tracing::error!("Failed to schedule input sequence wait: {}", e);
+                    }
+                    return;
+                }
+            };
+
+            if let Err(e) = result {
+                let _ = sequence.response_tx.send(Err(format!(
+                    "Step {} ({:?}) failed: {}",
+                    sequence.done, step, e
+                )));
+                return;
+            }
+            let _ = sequence.progress_tx.send(sequence.done);
+        }
+
+        let _ = sequence.response_tx.send(Ok(sequence.done));
+    }
+}
@@ -0,0 +1,199 @@
+use std::time::{Duration, Instant};
+
+use image::{imageops, GrayImage, RgbaImage};
+use smithay::output::Output;
+
+use crate::{
+    errors::{ErrorKind, ToolError},
+    state::Geometry,
+    Smallvil,
+};
+
+/// Frames are compared at this fraction of their size, which is cheap and
+/// ignores single-pixel noise.
+const DOWNSCALE: u32 = 8;
+/// Change in a downscaled pixel's brightness, 0-255, that counts as a difference.
+const PIXEL_TOLERANCE: u8 = 8;
+
+#[derive(Debug)]
+pub struct Stability {
+    pub waited: Duration,
+    /// Frames compared while waiting
+    pub frames: u32,
+}
+
+pub type StabilityResponse = tokio::sync::oneshot::Sender<Result<Stability, ToolError>>;
+
+/// A wait_for_visual_stability call, fed every frame rendered for its output.
+pub struct StabilityWait {
+    /// Output to watch by name; the primary output if unset
+    pub output: Option<String>,
+    /// Part of the output to watch, relative to its top left corner
+    pub region: Option<Geometry>,
+    /// Fraction of downscaled pixels that may change between frames
+    pub threshold: f64,
+    pub stable_for: Duration,
+    pub timeout: Duration,
+    pub started: Instant,
+    previous: Option<GrayImage>,
+    stable_since: Option<Instant>,
+    frames: u32,
+    /// Fraction of pixels that changed between the last two frames
+    last_difference: f64,
+    pub response_tx: StabilityResponse,
+}
+
+impl StabilityWait {
+    fn watches(&self, output: &Output, is_primary: bool) -> bool {
+        self.output
+            .as_deref()
+            .map_or(is_primary, |name| name == output.name())
+    }
+
+    fn is_stable(&self) -> bool {
+        self.stable_since
+            .is_some_and(|since| since.elapsed() >= self.stable_for)
+    }
+
+    /// Compares `frame` with the previous one, restarting the stable period if
+    /// too much of it changed.
+    fn feed(&mut self, frame: &RgbaImage) {
+        let (x, y, width, height) = match self.region {
+            Some(region) => {
+                let x = region.x.clamp(0, frame.width() as i32) as u32;
+                let y = region.y.clamp(0, frame.height() as i32) as u32;
+                let width = (region.width as u32).min(frame.width() - x);
+                let height = (region.height as u32).min(frame.height() - y);
+                (x, y, width, height)
+            }
+            None => (0, 0, frame.width(), frame.height()),
+        };
+        if width == 0 || height == 0 {
+            return;
+        }
+        let thumbnail = downscale(&*imageops::crop_imm(frame, x, y, width, height));
+        self.frames += 1;
+
+        let now = Instant::now();
+        match &self.previous {
+            Some(previous) if previous.dimensions() == thumbnail.dimensions() => {
+                self.last_difference = difference(previous, &thumbnail);
+                if self.last_difference > self.threshold {
+                    self.stable_since = Some(now);
+                }
+            }
+            _ => self.stable_since = Some(now),
+        }
+        self.previous = Some(thumbnail);
+    }
+}
+
+fn downscale<I>(frame: &I) -> GrayImage
+where
+    I: image::GenericImageView<Pixel = image::Rgba<u8>>,
+{
+    let (width, height) = frame.dimensions();
+    let thumbnail = imageops::thumbnail(
+        frame,
+        (width / DOWNSCALE).max(1),
+        (height / DOWNSCALE).max(1),
+    );
+    image::DynamicImage::ImageRgba8(thumbnail).into_luma8()
+}
+
+/// Fraction of pixels whose brightness differs by more than the tolerance.
+fn difference(a: &GrayImage, b: &GrayImage) -> f64 {
+    let changed = a
+        .pixels()
+        .zip(b.pixels())
+        .filter(|(a, b)| a.0[0].abs_diff(b.0[0]) > PIXEL_TOLERANCE)
+        .count();
+    changed as f64 / (a.width() as f64 * a.height() as f64)
+}
+
+impl Smallvil {
+    /// Replies once consecutive frames of the output have differed by at most
+    /// `threshold` for `stable_for`, or with an error after `timeout`.
+    pub fn wait_for_visual_stability(
+        &mut self,
+        output: Option<String>,
+        region: Option<Geometry>,
+        threshold: f64,
+        stable_for: Duration,
+        timeout: Duration,
+        response_tx: StabilityResponse,
+    ) {
+        if let Some(name) = output.as_deref() {
+            if self.output_by_name(name).is_none() {
+                let _ = response_tx.send(Err(ToolError::new(
+                    ErrorKind::OutputNotFound,
+                    format!("No output named '{}'", name),
+                )));
+                return;
+            }
+        }
+        self.stability_waits.push(StabilityWait {
+            output,
+            region,
+            threshold,
+            stable_for,
+            timeout,
+            started: Instant::now(),
+            previous: None,
+            stable_since: None,
+            frames: 0,
+            last_difference: 0.0,
+            response_tx,
+        });
+    }
+
+    pub fn wants_stability_frame(&self, output: &Output, is_primary: bool) -> bool {
+        self.stability_waits
+            .iter()
+            .any(|wait| wait.watches(output, is_primary))
+    }
+
+    /// Feeds a rendered frame of `output` to the waits watching it.
+    pub fn feed_stability_waits(&mut self, output: &Output, is_primary: bool, frame: &RgbaImage) {
+        for wait in &mut self.stability_waits {
+            if wait.watches(output, is_primary) {
+                wait.feed(frame);
+            }
+        }
+    }
+
+    /// Resolves the waits that have been stable long enough or timed out.
+    /// Checked after every frame, so an output that stops redrawing counts as
+    /// stable from its last frame.
+    pub fn check_stability_waits(&mut self) {
+        if self.stability_waits.is_empty() {
+            return;
+        }
+
+        let (done, pending): (Vec<StabilityWait>, Vec<StabilityWait>) =
+            std::mem::take(&mut self.stability_waits)
+                .into_iter()
+                .partition(|wait| wait.is_stable() || wait.started.elapsed() >= wait.timeout);
+        self.stability_waits = pending;
+
+        for wait in done {
+            let result = if wait.is_stable() {
+                Ok(Stability {
+                    waited: wait.started.elapsed(),
+                    frames: wait.frames,
+                })
+            } else {
+                Err(ToolError::new(
+                    ErrorKind::Timeout,
+                    format!(
+                        "Still changing after {} ms ({:.2}% of the frame changed last, over {} frames)",
+                        wait.timeout.as_millis(),
+                        wait.last_difference * 100.0,
+                        wait.frames
+                    ),
+                ))
+            };
+            let _ = wait.response_tx.send(result);
+        }
+    }
+}
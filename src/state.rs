@@ -1,54 +1,250 @@
-use std::{ffi::OsString, sync::Arc};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    ffi::OsString,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+use rmcp::schemars::{self, JsonSchema};
+use serde::Serialize;
+use tokio::sync::mpsc::UnboundedSender;
 
 use smithay::{
-    desktop::{PopupManager, Space, Window, WindowSurfaceType},
-    input::{Seat, SeatState},
+    backend::{allocator::format::FormatSet, renderer::utils::with_renderer_surface_state},
+    desktop::{
+        layer_map_for_output, PopupManager, Space, Window, WindowSurface, WindowSurfaceType,
+    },
+    input::{keyboard::Keycode, pointer::CursorImageStatus, Seat, SeatState},
+    output::{Mode as OutputMode, Output},
     reexports::{
-        calloop::{generic::Generic, EventLoop, Interest, LoopSignal, Mode, PostAction},
+        calloop::{
+            generic::Generic, EventLoop, Interest, LoopHandle, LoopSignal, Mode, PostAction,
+        },
+        wayland_protocols::xdg::shell::server::xdg_toplevel,
         wayland_server::{
             backend::{ClientData, ClientId, DisconnectReason},
             protocol::wl_surface::WlSurface,
-            Display, DisplayHandle,
+            Client, Display, DisplayHandle, Resource,
         },
     },
-    utils::{Logical, Point},
+    utils::{Clock, Logical, Monotonic, Point, Rectangle, Size},
     wayland::{
-        compositor::{CompositorClientState, CompositorState},
+        compositor::{with_states, CompositorClientState, CompositorState},
+        dmabuf::{DmabufGlobal, DmabufState},
+        fractional_scale::FractionalScaleManagerState,
         output::OutputManagerState,
-        selection::data_device::DataDeviceState,
-        shell::xdg::XdgShellState,
+        pointer_constraints::PointerConstraintsState,
+        pointer_gestures::PointerGesturesState,
+        presentation::PresentationState,
+        relative_pointer::RelativePointerManagerState,
+        selection::{data_device::DataDeviceState, primary_selection::PrimarySelectionState},
+        shell::{
+            wlr_layer::{Layer, WlrLayerShellState},
+            xdg::{decoration::XdgDecorationState, XdgShellState, XdgToplevelSurfaceData},
+        },
         shm::ShmState,
         socket::ListeningSocketSource,
+        tablet_manager::TabletManagerState,
+        viewporter::ViewporterState,
+        xdg_activation::XdgActivationState,
+        xwayland_shell::XWaylandShellState,
     },
+    xwayland::X11Wm,
 };
 
-use crate::CalloopData;
+use crate::{
+    background::Background,
+    clipboard::PendingDnd,
+    cursor::CursorTheme,
+    dbus::SessionBus,
+    desktop::DesktopInfo,
+    events::{WindowEvent, WindowWait},
+    frame_history::FrameHistory,
+    gesture::Gesture,
+    handlers::{PendingClose, WindowMode},
+    idle::{Activity, IdleWait},
+    input::Drag,
+    input_overlay::InputOverlay,
+    keybindings::Keybindings,
+    keymap::{KeyRepeat, Keymap},
+    lockstep::Lockstep,
+    macros::{InputMacro, MacroRecording},
+    pacing::FramePacing,
+    presentation::FrameTiming,
+    process::{LaunchResponse, PendingRestart, TrackedProcess},
+    protocol_trace::{self, ProtocolTrace, TracedClient},
+    recording::Recording,
+    scene::SceneSender,
+    screencast::Screencasts,
+    screencopy::{PendingScreencopy, ScreencopyManagerState},
+    screenshot::{ScreenshotOptions, ScreenshotResponse},
+    stability::StabilityWait,
+    stats::Stats,
+    tablet::PenStroke,
+    transform::transform_name,
+    CalloopData,
+};
 
 pub struct Smallvil {
     pub start_time: std::time::Instant,
+    // CLOCK_MONOTONIC, which presentation timestamps are given in
+    pub clock: Clock<Monotonic>,
     pub socket_name: OsString,
     pub display_handle: DisplayHandle,
 
     pub space: Space<Window>,
     pub loop_signal: LoopSignal,
+    pub loop_handle: LoopHandle<'static, CalloopData>,
 
     // Smithay State
     pub compositor_state: CompositorState,
     pub xdg_shell_state: XdgShellState,
+    pub xdg_decoration_state: XdgDecorationState,
+    pub xdg_activation_state: XdgActivationState,
+    pub layer_shell_state: WlrLayerShellState,
     pub shm_state: ShmState,
+    pub dmabuf_state: DmabufState,
+    pub viewporter_state: ViewporterState,
+    pub presentation_state: PresentationState,
+    pub fractional_scale_manager_state: FractionalScaleManagerState,
     pub output_manager_state: OutputManagerState,
     pub seat_state: SeatState<Smallvil>,
     pub data_device_state: DataDeviceState,
+    pub primary_selection_state: PrimarySelectionState,
+    pub xwayland_shell_state: XWaylandShellState,
+    pub pointer_gestures_state: PointerGesturesState,
+    pub pointer_constraints_state: PointerConstraintsState,
+    pub relative_pointer_manager_state: RelativePointerManagerState,
+    pub tablet_manager_state: TabletManagerState,
+    pub screencopy_state: ScreencopyManagerState,
     pub popups: PopupManager,
+    // linux-dmabuf global and the formats it advertises, once the backend's renderer exists
+    pub dmabuf_global: Option<DmabufGlobal>,
+    pub dmabuf_formats: Option<FormatSet>,
+    // Whether clients are offered server-side title bars and borders (--server-decorations)
+    pub server_decorations: bool,
+    // Frame stepping state, when running with --lockstep
+    pub lockstep: Option<Lockstep>,
+    // Frame limit and idling (--fps, --idle-when-no-damage, set_frame_rate)
+    pub frame_pacing: FramePacing,
 
     pub seat: Seat<Self>,
+    pub keymap: Keymap,
+    // Repeat rate and delay announced to clients (--key-repeat, set_key_repeat)
+    pub key_repeat: KeyRepeat,
+    // Keys currently held down, from both winit and synthetic input. Synthetic key
+    // combinations use this to avoid pressing or releasing a modifier the user is holding.
+    pub pressed_keys: HashSet<Keycode>,
+    // What the focused client last asked the pointer to look like, and the theme
+    // for named cursors; only drawn into captures that ask for it, unless hidden
+    pub cursor_status: CursorImageStatus,
+    pub cursor_theme: CursorTheme,
+    pub cursor_hidden: bool,
+    // Clear color and wallpaper (--background, set_background)
+    pub background: Background,
+    // Pointer buttons currently held down, from both winit and synthetic input
+    pub pressed_buttons: HashSet<u32>,
+    // Virtual terminal asked for with Ctrl+Alt+Fn, switched to by the DRM backend
+    pub pending_vt_switch: Option<i32>,
+    // Key combinations handled by the compositor (--keybindings)
+    pub keybindings: Keybindings,
+
+    // MIME types offered by the clients owning the clipboard and primary selection
+    pub clipboard_mime_types: Vec<String>,
+    pub primary_mime_types: Vec<String>,
+
+    // X11 window manager and display number, once Xwayland is ready
+    pub xwm: Option<X11Wm>,
+    pub xdisplay: Option<u32>,
+    // X11 menus and tooltips, bottom to top. They place themselves, so they
+    // are drawn over the space instead of being managed as windows
+    pub override_redirect_windows: Vec<Window>,
+
+    // Directory read_file and write_file work in, shared with launched apps
+    pub session_dir: Option<PathBuf>,
+
+    // Private session bus of launched apps, unless --host-session-bus is given
+    pub session_bus: Option<SessionBus>,
+
+    // Relay and trace log of Wayland clients, with --protocol-trace
+    pub protocol_trace: Option<ProtocolTrace>,
+
+    // Screenshots to take after the next frame is rendered
+    pub pending_screenshots: Vec<(ScreenshotOptions, ScreenshotResponse)>,
+
+    // wlr-screencopy frames to fill after the next frame of their output
+    pub pending_screencopies: Vec<PendingScreencopy>,
+
+    // Video recording fed from the redraw handler, if one is running
+    pub recording: Option<Recording>,
+
+    // Injected keys and clicks drawn over the outputs while recording (show_input)
+    pub input_overlay: Option<InputOverlay>,
+
+    // Recent frames of the primary output for get_recent_frames (--frame-history)
+    pub frame_history: Option<FrameHistory>,
+
+    // PipeWire screencasts fed from the redraw handler
+    pub screencasts: Screencasts,
+
+    // Drag gesture being played back across redraws
+    pub pending_drag: Option<Drag>,
+
+    // Touchpad gesture being played back across redraws
+    pub pending_gesture: Option<Gesture>,
+
+    // Input being recorded into a macro, and the recorded sessions by name
+    pub macro_recording: Option<MacroRecording>,
+    pub input_macros: HashMap<String, InputMacro>,
+
+    // Pen stroke on the virtual tablet being played back across redraws
+    pub pending_pen_stroke: Option<PenStroke>,
+
+    // Drag-and-drop offered by the compositor, until the target finishes or cancels it
+    pub pending_dnd: Option<PendingDnd>,
+
+    // Windows asked to close via close_window, keyed by window id
+    pub pending_closes: HashMap<u64, PendingClose>,
+
+    // Apps started by launch_app, keyed by pid
+    pub processes: BTreeMap<u32, TrackedProcess>,
+
+    // launch_app calls waiting for the app's first window, keyed by pid
+    pub pending_launches: HashMap<u32, LaunchResponse>,
+
+    // restart_app calls waiting for the old process to exit, keyed by its pid
+    pub pending_restarts: HashMap<u32, PendingRestart>,
+
+    // Launched app each mapped window belongs to, by window id, kept so the
+    // windows of an app that exited can still be told
+    pub window_owners: HashMap<u64, u32>,
+
+    // Size of every window that has shown a buffer, keyed by window id
+    pub mapped_windows: HashMap<u64, Size<i32, Logical>>,
+
+    // Presentation history of each output, keyed by output name
+    pub frame_timing: HashMap<String, FrameTiming>,
+
+    // Frame times, dropped frames and screenshot latency for get_stats
+    pub stats: Stats,
 
-    // Pending screenshot request: (filename, response_tx)
-    pub pending_screenshot: Option<(String, tokio::sync::oneshot::Sender<Result<String, String>>)>,
+    // Commit and damage counters, and wait_for_idle calls checked after every frame
+    pub activity: Activity,
+    pub idle_waits: Vec<IdleWait>,
+    pub stability_waits: Vec<StabilityWait>,
 
-    // Pending capture_screenshot request: response_tx returns (base64_data, width, height)
-    #[allow(clippy::type_complexity)]
-    pub pending_capture_screenshot: Option<tokio::sync::oneshot::Sender<Result<(String, u32, u32), String>>>,
+    // wait_for_window calls that no window has matched yet
+    pub window_waits: HashMap<u64, WindowWait>,
+    pub next_window_wait: u64,
+
+    // Lifecycle events for the MCP client, set once the server is running
+    pub window_events: Option<UnboundedSender<WindowEvent>>,
+
+    // JSON scene graph published as the windows://scene resource
+    pub scene: SceneSender,
 }
 
 impl Smallvil {
@@ -59,10 +255,25 @@ impl Smallvil {
 
         let compositor_state = CompositorState::new::<Self>(&dh);
         let xdg_shell_state = XdgShellState::new::<Self>(&dh);
+        let xdg_decoration_state = XdgDecorationState::new::<Self>(&dh);
+        let xdg_activation_state = XdgActivationState::new::<Self>(&dh);
+        let layer_shell_state = WlrLayerShellState::new::<Self>(&dh);
         let shm_state = ShmState::new::<Self>(&dh, vec![]);
+        let dmabuf_state = DmabufState::new();
+        let viewporter_state = ViewporterState::new::<Self>(&dh);
+        let clock = Clock::new();
+        let presentation_state = PresentationState::new::<Self>(&dh, clock.id() as u32);
+        let fractional_scale_manager_state = FractionalScaleManagerState::new::<Self>(&dh);
         let output_manager_state = OutputManagerState::new_with_xdg_output::<Self>(&dh);
         let mut seat_state = SeatState::new();
         let data_device_state = DataDeviceState::new::<Self>(&dh);
+        let primary_selection_state = PrimarySelectionState::new::<Self>(&dh);
+        let xwayland_shell_state = XWaylandShellState::new::<Self>(&dh);
+        let pointer_gestures_state = PointerGesturesState::new::<Self>(&dh);
+        let pointer_constraints_state = PointerConstraintsState::new::<Self>(&dh);
+        let relative_pointer_manager_state = RelativePointerManagerState::new::<Self>(&dh);
+        let tablet_manager_state = TabletManagerState::new::<Self>(&dh);
+        let screencopy_state = ScreencopyManagerState::new(&dh);
         let popups = PopupManager::default();
 
         // A seat is a group of keyboards, pointer and touch devices.
@@ -71,12 +282,20 @@ impl Smallvil {
 
         // Notify clients that we have a keyboard, for the sake of the example we assume that keyboard is always present.
         // You may want to track keyboard hot-plug in real compositor.
-        seat.add_keyboard(Default::default(), 200, 25).unwrap();
+        let key_repeat = KeyRepeat::default();
+        seat.add_keyboard(Default::default(), key_repeat.delay_ms, key_repeat.rate)
+            .unwrap();
+
+        // Reverse keysym lookup for synthetic text input, compiled from the same (default) XKB names.
+        let keymap = Keymap::new("", "", None).expect("Failed to compile default keymap");
 
         // Notify clients that we have a pointer (mouse)
         // Here we assume that there is always pointer plugged in
         seat.add_pointer();
 
+        // A virtual touchscreen for synthetic touch input
+        seat.add_touch();
+
         // A space represents a two-dimensional plane. Windows and Outputs can be mapped onto it.
         //
         // Windows get a position and stacking order through mapping.
@@ -84,28 +303,95 @@ impl Smallvil {
         let space = Space::default();
 
         let socket_name = Self::init_wayland_listener(display, event_loop);
+        Self::init_child_reaper(event_loop);
+        Self::init_scene_publisher(event_loop);
 
         // Get the loop signal, used to stop the event loop
         let loop_signal = event_loop.get_signal();
+        let loop_handle = event_loop.handle();
 
         Self {
             start_time,
+            clock,
             display_handle: dh,
 
             space,
             loop_signal,
+            loop_handle,
             socket_name,
 
             compositor_state,
             xdg_shell_state,
+            xdg_decoration_state,
+            xdg_activation_state,
+            layer_shell_state,
             shm_state,
+            dmabuf_state,
+            viewporter_state,
+            presentation_state,
+            fractional_scale_manager_state,
             output_manager_state,
             seat_state,
             data_device_state,
+            primary_selection_state,
+            xwayland_shell_state,
+            pointer_gestures_state,
+            pointer_constraints_state,
+            relative_pointer_manager_state,
+            tablet_manager_state,
+            screencopy_state,
             popups,
+            dmabuf_global: None,
+            dmabuf_formats: None,
+            server_decorations: false,
+            lockstep: None,
+            frame_pacing: FramePacing::default(),
             seat,
-            pending_screenshot: None,
-            pending_capture_screenshot: None,
+            keymap,
+            key_repeat,
+            pressed_keys: HashSet::new(),
+            cursor_status: CursorImageStatus::default_named(),
+            cursor_theme: CursorTheme::default(),
+            cursor_hidden: false,
+            background: Background::default(),
+            pressed_buttons: HashSet::new(),
+            pending_vt_switch: None,
+            keybindings: Keybindings::default(),
+            clipboard_mime_types: Vec::new(),
+            primary_mime_types: Vec::new(),
+            xwm: None,
+            xdisplay: None,
+            override_redirect_windows: Vec::new(),
+            session_dir: None,
+            session_bus: None,
+            protocol_trace: None,
+            pending_screenshots: Vec::new(),
+            pending_screencopies: Vec::new(),
+            recording: None,
+            input_overlay: None,
+            frame_history: None,
+            screencasts: Screencasts::default(),
+            pending_drag: None,
+            pending_gesture: None,
+            pending_pen_stroke: None,
+            macro_recording: None,
+            input_macros: HashMap::new(),
+            pending_dnd: None,
+            pending_closes: HashMap::new(),
+            processes: BTreeMap::new(),
+            pending_launches: HashMap::new(),
+            pending_restarts: HashMap::new(),
+            window_owners: HashMap::new(),
+            mapped_windows: HashMap::new(),
+            frame_timing: HashMap::new(),
+            stats: Stats::default(),
+            activity: Activity::default(),
+            idle_waits: Vec::new(),
+            stability_waits: Vec::new(),
+            window_waits: HashMap::new(),
+            next_window_wait: 0,
+            window_events: None,
+            scene: SceneSender::new(String::new()),
         }
     }
 
@@ -127,6 +413,16 @@ impl Smallvil {
                 // Inside the callback, you should insert the client into the display.
                 //
                 // You may also associate some data with the client when inserting the client.
+                if let Some(log) = &state.state.protocol_trace {
+                    if let Err(e) = protocol_trace::insert_relayed_client(
+                        &mut state.display_handle,
+                        client_stream,
+                        log,
+                    ) {
+                        tracing::error!("Failed to relay a Wayland client: {}", e);
+                    }
+                    return;
+                }
                 state
                     .display_handle
                     .insert_client(client_stream, Arc::new(ClientState::default()))
@@ -154,26 +450,447 @@ impl Smallvil {
         socket_name
     }
 
+    /// The surface at `pos`: X11 menus and tooltips come first, then overlay
+    /// and top layer surfaces, windows, and bottom and background ones.
     pub fn surface_under(
         &self,
         pos: Point<f64, Logical>,
     ) -> Option<(WlSurface, Point<f64, Logical>)> {
-        self.space
-            .element_under(pos)
-            .and_then(|(window, location)| {
-                window
-                    .surface_under(pos - location.to_f64(), WindowSurfaceType::ALL)
-                    .map(|(s, p)| (s, (p + location).to_f64()))
-            })
+        let override_redirect_surface = || {
+            self.override_redirect_windows
+                .iter()
+                .rev()
+                .find_map(|window| {
+                    let location = window.x11_surface()?.geometry().loc;
+                    window
+                        .surface_under(pos - location.to_f64(), WindowSurfaceType::ALL)
+                        .map(|(s, p)| (s, (p + location).to_f64()))
+                })
+        };
+        let window_surface = || {
+            self.space
+                .element_under(pos)
+                .and_then(|(window, location)| {
+                    window
+                        .surface_under(pos - location.to_f64(), WindowSurfaceType::ALL)
+                        .map(|(s, p)| (s, (p + location).to_f64()))
+                })
+        };
+
+        override_redirect_surface()
+            .or_else(|| self.layer_surface_under(pos, &[Layer::Overlay, Layer::Top]))
+            .or_else(window_surface)
+            .or_else(|| self.layer_surface_under(pos, &[Layer::Bottom, Layer::Background]))
+    }
+
+    /// The surface of a layer surface in one of `layers` at `pos`, topmost first.
+    pub fn layer_surface_under(
+        &self,
+        pos: Point<f64, Logical>,
+        layers: &[Layer],
+    ) -> Option<(WlSurface, Point<f64, Logical>)> {
+        let output = self.space.output_under(pos).next()?;
+        let output_geo = self.space.output_geometry(output)?;
+        let map = layer_map_for_output(output);
+        let local = pos - output_geo.loc.to_f64();
+
+        layers.iter().find_map(|&layer| {
+            let surface = map.layer_under(layer, local)?;
+            let location = map.layer_geometry(surface)?.loc + output_geo.loc;
+            surface
+                .surface_under(pos - location.to_f64(), WindowSurfaceType::ALL)
+                .map(|(s, p)| (s, (p + location).to_f64()))
+        })
+    }
+
+    /// An overlay or top layer surface at `pos` that takes keyboard focus when
+    /// clicked, such as a launcher or on-screen keyboard.
+    pub fn focusable_layer_at(&self, pos: Point<f64, Logical>) -> Option<WlSurface> {
+        let output = self.space.output_under(pos).next()?;
+        let output_geo = self.space.output_geometry(output)?;
+        let map = layer_map_for_output(output);
+
+        [Layer::Overlay, Layer::Top]
+            .into_iter()
+            .find_map(|layer| map.layer_under(layer, pos - output_geo.loc.to_f64()))
+            .filter(|surface| surface.can_receive_keyboard_focus())
+            .map(|surface| surface.wl_surface().clone())
     }
 }
 
 #[derive(Default)]
 pub struct ClientState {
     pub compositor_state: CompositorClientState,
+    /// Set for clients connected through the --protocol-trace relay
+    pub trace: Option<Arc<TracedClient>>,
 }
 
 impl ClientData for ClientState {
     fn initialized(&self, _client_id: ClientId) {}
     fn disconnected(&self, _client_id: ClientId, _reason: DisconnectReason) {}
 }
+
+/// Stable identifier handed out to every toplevel when it is created, stored in
+/// the window's user data. Tools that act on a window take this id.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct WindowId(pub u64);
+
+impl WindowId {
+    pub fn of(window: &Window) -> Self {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+        *window
+            .user_data()
+            .get_or_insert(|| WindowId(NEXT_ID.fetch_add(1, Ordering::Relaxed)))
+    }
+}
+
+/// The id, app_id and title of a toplevel, as reported back to MCP clients.
+#[derive(Debug, Clone, Default, Serialize, JsonSchema)]
+pub struct WindowLabel {
+    pub id: u64,
+    pub app_id: Option<String>,
+    pub title: Option<String>,
+}
+
+impl WindowLabel {
+    pub fn from_window(window: &Window) -> Self {
+        let id = WindowId::of(window).0;
+        match window.underlying_surface() {
+            WindowSurface::Wayland(toplevel) => with_states(toplevel.wl_surface(), |states| {
+                let data = states
+                    .data_map
+                    .get::<XdgToplevelSurfaceData>()
+                    .unwrap()
+                    .lock()
+                    .unwrap();
+                Self {
+                    id,
+                    app_id: data.app_id.clone(),
+                    title: data.title.clone(),
+                }
+            }),
+            // WM_CLASS is the closest X11 equivalent of an app_id
+            WindowSurface::X11(surface) => Self {
+                id,
+                app_id: Some(surface.class()).filter(|class| !class.is_empty()),
+                title: Some(surface.title()).filter(|title| !title.is_empty()),
+            },
+        }
+    }
+}
+
+impl std::fmt::Display for WindowLabel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "#{} {} ({})",
+            self.id,
+            self.title.as_deref().unwrap_or("<untitled>"),
+            self.app_id.as_deref().unwrap_or("<no app_id>")
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, JsonSchema)]
+pub struct Geometry {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+impl From<Rectangle<i32, Logical>> for Geometry {
+    fn from(rect: Rectangle<i32, Logical>) -> Self {
+        Self {
+            x: rect.loc.x,
+            y: rect.loc.y,
+            width: rect.size.w,
+            height: rect.size.h,
+        }
+    }
+}
+
+/// Where a window is and how its buffer maps onto it, as returned by
+/// `get_window_geometry` and as part of every window result.
+#[derive(Debug, Clone, Copy, Serialize, JsonSchema)]
+pub struct WindowGeometry {
+    /// Visible bounds in the global compositor space, excluding client-side shadows
+    #[serde(flatten)]
+    pub bounds: Geometry,
+    /// Position of the surface origin in the global compositor space
+    pub surface_x: i32,
+    pub surface_y: i32,
+    /// xdg window geometry, relative to the surface origin
+    pub xdg_geometry: Geometry,
+    /// Size of the attached buffer in buffer pixels
+    pub buffer_width: Option<i32>,
+    pub buffer_height: Option<i32>,
+    pub buffer_scale: i32,
+    /// Scale of the output the window is mostly on
+    pub output_scale: f64,
+}
+
+/// Metadata for a mapped toplevel, as returned by `list_windows`.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct WindowInfo {
+    pub id: u64,
+    pub app_id: Option<String>,
+    pub title: Option<String>,
+    pub geometry: Option<WindowGeometry>,
+    pub activated: bool,
+    pub fullscreen: bool,
+    pub maximized: bool,
+    /// PID of the owning client, from its socket credentials
+    pub pid: Option<i32>,
+    /// PID of the app started by launch_app the window belongs to: its client,
+    /// or an ancestor of it, e.g. for sandboxed apps and launcher scripts
+    pub launched_pid: Option<u32>,
+    /// The app owning the window was started by launch_app and has since exited
+    /// or crashed, so the window may no longer respond
+    pub exited: bool,
+    /// The desktop entry its app was started from with launch_desktop_app,
+    /// with the entry's Icon and StartupWMClass
+    pub desktop_entry: Option<DesktopInfo>,
+    /// Names of the outputs the window overlaps
+    pub outputs: Vec<String>,
+}
+
+/// Name, layout and mode of an output, as returned by `list_outputs`.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct OutputInfo {
+    pub name: String,
+    /// Position and size in the global compositor space
+    pub geometry: Option<Geometry>,
+    /// Mode size in physical pixels
+    pub width: i32,
+    pub height: i32,
+    /// Refresh rate in mHz
+    pub refresh: i32,
+    /// Scale factor, possibly fractional; the logical size is the mode size divided by it
+    pub scale: f64,
+    /// Rotation and flip: "normal", "90", "180", "270", "flipped" or "flipped-90" etc.
+    pub transform: String,
+    pub primary: bool,
+}
+
+impl Smallvil {
+    pub fn window_by_id(&self, id: u64) -> Option<Window> {
+        self.space
+            .elements()
+            .find(|window| WindowId::of(window).0 == id)
+            .cloned()
+    }
+
+    /// The toplevel that currently has keyboard focus.
+    pub fn focused_window(&self) -> Option<Window> {
+        let focus = self.seat.get_keyboard()?.current_focus()?;
+        self.space
+            .elements()
+            .find(|window| window.wl_surface().as_deref() == Some(&focus))
+            .cloned()
+    }
+
+    /// PID of a client's process. Clients behind the protocol trace relay are
+    /// known by the pid the relay saw, as their socket belongs to mcpvil.
+    pub fn client_pid(&self, client: &Client) -> Option<i32> {
+        let relayed = client
+            .get_data::<ClientState>()
+            .and_then(|state| state.trace.as_ref())
+            .and_then(|trace| trace.pid);
+        relayed.or_else(|| {
+            client
+                .get_credentials(&self.display_handle)
+                .map(|credentials| credentials.pid)
+                .ok()
+        })
+    }
+
+    pub fn window_info(&self, window: &Window) -> WindowInfo {
+        let label = WindowLabel::from_window(window);
+        let (activated, fullscreen, maximized, pid) = match window.underlying_surface() {
+            WindowSurface::Wayland(toplevel) => {
+                let states = toplevel.current_state().states;
+                let pid = self
+                    .display_handle
+                    .get_client(toplevel.wl_surface().id())
+                    .ok()
+                    .and_then(|client| self.client_pid(&client));
+                (
+                    states.contains(xdg_toplevel::State::Activated),
+                    states.contains(xdg_toplevel::State::Fullscreen),
+                    states.contains(xdg_toplevel::State::Maximized),
+                    pid,
+                )
+            }
+            // All X11 clients share the Xwayland connection, so use _NET_WM_PID instead
+            WindowSurface::X11(surface) => (
+                surface.is_activated(),
+                surface.is_fullscreen(),
+                surface.is_maximized(),
+                surface.pid().map(|pid| pid as i32),
+            ),
+        };
+
+        let launched_pid = self
+            .window_owners
+            .get(&label.id)
+            .copied()
+            .or_else(|| pid.and_then(|pid| self.launched_pid(pid as u32)));
+        let desktop_entry = self.desktop_entry_of(launched_pid, label.app_id.as_deref());
+        WindowInfo {
+            id: label.id,
+            app_id: label.app_id,
+            title: label.title,
+            geometry: self.window_geometry(window),
+            activated,
+            fullscreen,
+            maximized,
+            pid,
+            launched_pid,
+            exited: launched_pid.is_some_and(|pid| self.process_exited(pid)),
+            desktop_entry,
+            outputs: self
+                .space
+                .outputs_for_element(window)
+                .iter()
+                .map(Output::name)
+                .collect(),
+        }
+    }
+
+    /// All mapped windows, bottom to top in stacking order, optionally only
+    /// those overlapping the named output.
+    pub fn list_windows(&self, output: Option<&str>) -> Vec<WindowInfo> {
+        self.space
+            .elements()
+            .map(|window| self.window_info(window))
+            .filter(|info| output.is_none_or(|name| info.outputs.iter().any(|o| o == name)))
+            .collect()
+    }
+
+    /// The first output, which the backend maps at the origin. Recordings and
+    /// screenshots without an explicit output use it.
+    pub fn primary_output(&self) -> Option<&Output> {
+        self.space.outputs().next()
+    }
+
+    /// All outputs, left to right.
+    pub fn list_outputs(&self) -> Vec<OutputInfo> {
+        self.space
+            .outputs()
+            .enumerate()
+            .map(|(i, output)| {
+                let mode = output.current_mode();
+                OutputInfo {
+                    name: output.name(),
+                    geometry: self.space.output_geometry(output).map(Into::into),
+                    width: mode.map_or(0, |mode| mode.size.w),
+                    height: mode.map_or(0, |mode| mode.size.h),
+                    refresh: mode.map_or(0, |mode| mode.refresh),
+                    scale: output.current_scale().fractional_scale(),
+                    transform: transform_name(output.current_transform()).to_string(),
+                    primary: i == 0,
+                }
+            })
+            .collect()
+    }
+
+    /// Switches an output to a new mode, notifying clients through wl_output and
+    /// xdg_output. The backend resizes the surface behind it on the next frame.
+    pub fn set_output_mode(
+        &mut self,
+        name: Option<&str>,
+        mode: OutputMode,
+    ) -> Result<OutputInfo, String> {
+        let output = match name {
+            Some(name) => self.output_by_name(name),
+            None => self.primary_output(),
+        }
+        .cloned()
+        .ok_or_else(|| format!("No output named '{}'", name.unwrap_or_default()))?;
+
+        output.change_current_state(Some(mode), None, None, None);
+        output.set_preferred(mode);
+        crate::render::arrange_outputs(&mut self.space);
+        self.refit_windows(&output);
+
+        self.output_info(&output)
+    }
+
+    /// Keeps maximized and fullscreen windows filling `output` after its
+    /// logical size changed.
+    pub fn refit_windows(&mut self, output: &Output) {
+        let windows: Vec<Window> = self
+            .space
+            .elements()
+            .filter(|window| self.output_for_window(window).as_ref() == Some(output))
+            .cloned()
+            .collect();
+        for window in windows {
+            let info = self.window_info(&window);
+            if info.fullscreen {
+                self.set_window_mode(&window, WindowMode::Fullscreen);
+            } else if info.maximized {
+                self.set_window_mode(&window, WindowMode::Maximized);
+            }
+        }
+    }
+
+    pub fn output_info(&self, output: &Output) -> Result<OutputInfo, String> {
+        self.list_outputs()
+            .into_iter()
+            .find(|info| info.name == output.name())
+            .ok_or_else(|| "Output disappeared".to_string())
+    }
+
+    pub fn output_by_name(&self, name: &str) -> Option<&Output> {
+        self.space.outputs().find(|output| output.name() == name)
+    }
+
+    /// The output showing most of `window`, falling back to the primary output.
+    pub fn window_geometry(&self, window: &Window) -> Option<WindowGeometry> {
+        let bounds = self.space.element_geometry(window)?;
+        let xdg_geometry = window.geometry();
+        let surface_location = bounds.loc - xdg_geometry.loc;
+
+        let (buffer_size, buffer_scale) = window
+            .wl_surface()
+            .and_then(|surface| {
+                with_renderer_surface_state(&surface, |state| {
+                    (state.buffer_size(), state.buffer_scale())
+                })
+            })
+            .unwrap_or((None, 1));
+        // buffer_size is in surface coordinates; undo the buffer scale
+        let buffer_size = buffer_size.map(|size| size.to_f64().upscale(buffer_scale as f64));
+
+        Some(WindowGeometry {
+            bounds: bounds.into(),
+            surface_x: surface_location.x,
+            surface_y: surface_location.y,
+            xdg_geometry: xdg_geometry.into(),
+            buffer_width: buffer_size.map(|size| size.w.round() as i32),
+            buffer_height: buffer_size.map(|size| size.h.round() as i32),
+            buffer_scale,
+            output_scale: self
+                .output_for_window(window)
+                .map_or(1.0, |output| output.current_scale().fractional_scale()),
+        })
+    }
+
+    pub fn output_for_window(&self, window: &Window) -> Option<Output> {
+        let window_geo = self.space.element_geometry(window);
+        self.space
+            .outputs()
+            // min_by_key keeps the first of equal candidates, i.e. the primary output
+            .min_by_key(|output| {
+                let overlap = window_geo
+                    .zip(self.space.output_geometry(output))
+                    .and_then(|(window_geo, output_geo)| window_geo.intersection(output_geo))
+                    .map_or(0, |overlap| overlap.size.w * overlap.size.h);
+                std::cmp::Reverse(overlap)
+            })
+            .cloned()
+    }
+}
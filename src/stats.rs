@@ -0,0 +1,151 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use rmcp::schemars::{self, JsonSchema};
+use serde::Serialize;
+
+use crate::{metrics::Histogram, Smallvil};
+
+/// Frame times kept for the averages and percentiles of get_stats.
+const FRAME_HISTORY: usize = 600;
+
+/// Screenshot latencies kept for get_stats.
+const SCREENSHOT_HISTORY: usize = 100;
+
+/// Health counters collected in the redraw loop, for get_stats.
+#[derive(Debug, Default)]
+pub struct Stats {
+    /// When the frame being rendered started, between start_frame and finish_frame
+    frame_start: Option<Instant>,
+    frames: u64,
+    /// Frames that took longer than the frame interval to render
+    dropped_frames: u64,
+    frame_times: VecDeque<Duration>,
+    frame_time_histogram: Histogram,
+    screenshots: u64,
+    screenshot_latencies: VecDeque<Duration>,
+    screenshot_latency_histogram: Histogram,
+}
+
+/// Summary of a series of durations, in milliseconds.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct Percentiles {
+    pub average: f64,
+    pub p50: f64,
+    pub p95: f64,
+    pub p99: f64,
+    pub max: f64,
+}
+
+impl Percentiles {
+    fn of(samples: &VecDeque<Duration>) -> Option<Self> {
+        if samples.is_empty() {
+            return None;
+        }
+        let mut sorted: Vec<Duration> = samples.iter().copied().collect();
+        sorted.sort_unstable();
+        let ms = |d: Duration| d.as_secs_f64() * 1000.0;
+        // Nearest rank
+        let at = |p: f64| ms(sorted[((p * sorted.len() as f64).ceil() as usize).max(1) - 1]);
+        Some(Self {
+            average: ms(sorted.iter().sum::<Duration>() / sorted.len() as u32),
+            p50: at(0.5),
+            p95: at(0.95),
+            p99: at(0.99),
+            max: ms(sorted[sorted.len() - 1]),
+        })
+    }
+}
+
+/// Compositor health, as returned by get_stats.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct StatsInfo {
+    pub uptime_secs: f64,
+    /// Wayland clients connected, including Xwayland
+    pub clients: usize,
+    pub windows: usize,
+    pub frames_rendered: u64,
+    /// Time to render a frame of every output, over the last frames
+    pub frame_time_ms: Option<Percentiles>,
+    pub frame_samples: usize,
+    /// Frames that took longer than the frame interval, which the frame limit
+    /// or the primary output's refresh rate sets
+    pub dropped_frames: u64,
+    pub screenshots_taken: u64,
+    /// Time from a screenshot request to its frame being captured, over the
+    /// last screenshots; encoding the image comes on top
+    pub screenshot_latency_ms: Option<Percentiles>,
+    /// Every frame time and screenshot latency so far, for the metrics endpoint
+    #[serde(skip)]
+    pub frame_time_histogram: Histogram,
+    #[serde(skip)]
+    pub screenshot_latency_histogram: Histogram,
+}
+
+fn push_sample(samples: &mut VecDeque<Duration>, limit: usize, sample: Duration) {
+    if samples.len() == limit {
+        samples.pop_front();
+    }
+    samples.push_back(sample);
+}
+
+impl Smallvil {
+    /// Notes the start of a frame; render::start_frame calls this.
+    pub fn frame_started(&mut self) {
+        self.stats.frame_start = Some(Instant::now());
+    }
+
+    /// Records the time the frame took; render::finish_frame calls this.
+    pub fn record_frame_time(&mut self) {
+        let Some(start) = self.stats.frame_start.take() else {
+            return;
+        };
+        let elapsed = start.elapsed();
+        let interval = self.frame_pacing.interval().or_else(|| {
+            self.primary_output()
+                .and_then(|output| output.current_mode())
+                .filter(|mode| mode.refresh > 0)
+                .map(|mode| Duration::from_secs(1000) / mode.refresh as u32)
+        });
+
+        let stats = &mut self.stats;
+        stats.frames += 1;
+        if interval.is_some_and(|interval| elapsed > interval) {
+            stats.dropped_frames += 1;
+        }
+        stats.frame_time_histogram.observe(elapsed);
+        push_sample(&mut stats.frame_times, FRAME_HISTORY, elapsed);
+    }
+
+    /// Records a screenshot captured for a request made at `requested`.
+    pub fn record_screenshot(&mut self, requested: Instant) {
+        let latency = requested.elapsed();
+        let stats = &mut self.stats;
+        stats.screenshots += 1;
+        stats.screenshot_latency_histogram.observe(latency);
+        push_sample(&mut stats.screenshot_latencies, SCREENSHOT_HISTORY, latency);
+    }
+
+    /// Current health metrics.
+    pub fn stats(&self) -> StatsInfo {
+        let mut clients = 0;
+        self.display_handle
+            .backend_handle()
+            .with_all_clients(|_| clients += 1);
+        StatsInfo {
+            uptime_secs: self.start_time.elapsed().as_secs_f64(),
+            clients,
+            windows: self.space.elements().count(),
+            frames_rendered: self.stats.frames,
+            frame_time_ms: Percentiles::of(&self.stats.frame_times),
+            frame_samples: self.stats.frame_times.len(),
+            dropped_frames: self.stats.dropped_frames,
+            screenshots_taken: self.stats.screenshots,
+            screenshot_latency_ms: Percentiles::of(&self.stats.screenshot_latencies),
+            frame_time_histogram: self.stats.frame_time_histogram.clone(),
+            screenshot_latency_histogram: self.stats.screenshot_latency_histogram.clone(),
+        }
+    }
+}
@@ -0,0 +1,275 @@
+//! Structured results of tool calls. The tools in `output_schema` return
+//! their result as JSON in `structuredContent`, alongside the text they
+//! always returned, and advertise its schema in tools/list, so client code can
+//! read fields instead of parsing prose. Calls that fail come back as error
+//! results with a message instead.
+
+use std::sync::Arc;
+
+use rmcp::{
+    model::{CallToolResult, Content, JsonObject, Tool},
+    schemars::{self, JsonSchema},
+    ErrorData as McpError,
+};
+use serde::Serialize;
+
+use crate::{
+    accessibility::AccessibleNode,
+    command::CommandOutput,
+    compare::Comparison,
+    desktop::InstalledApp,
+    dump::StateDump,
+    input::InputState,
+    logs::LogRecord,
+    matching::ImageMatch,
+    notifications::Notification,
+    ocr::OcrResult,
+    pixel::PixelProbe,
+    presentation::FrameTimingInfo,
+    process::ProcessInfo,
+    state::{OutputInfo, WindowGeometry, WindowInfo, WindowLabel},
+    stats::StatsInfo,
+};
+
+/// A started app, as returned by launch_app.
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct LaunchResult {
+    pub pid: u32,
+    pub command: String,
+    pub args: Vec<String>,
+    /// The first window it mapped, if launch_app waited for one
+    pub window: Option<WindowLabel>,
+}
+
+/// A restarted app, as returned by restart_app.
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct RestartResult {
+    /// The pid it had before the restart
+    pub previous_pid: u32,
+    pub pid: u32,
+    /// The first window it mapped, if restart_app waited for one
+    pub window: Option<WindowLabel>,
+}
+
+/// A signal sent by kill_app.
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct SignalResult {
+    pub pid: u32,
+    /// The signal as given, e.g. "TERM"
+    pub signal: String,
+}
+
+/// Recent output of an app, as returned by get_app_output.
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct AppOutput {
+    pub pid: u32,
+    /// The last lines it wrote to stdout and stderr; empty if there were none
+    pub output: String,
+}
+
+/// A captured image, as returned by screenshot and screenshot_window.
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct ScreenshotResult {
+    pub width: u32,
+    pub height: u32,
+    pub mime_type: String,
+    /// Size of the encoded image
+    pub size_bytes: usize,
+    /// Where the image was saved, if a filename was given
+    pub path: Option<String>,
+    /// The MCP resource holding the image
+    pub uri: String,
+}
+
+/// Windows captured by screenshot_all_windows; the images follow as content.
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct WindowCaptures {
+    /// The captured windows, in the order of their thumbnails or tiles
+    pub windows: Vec<WindowInfo>,
+    /// The MCP resource holding the contact sheet, if one was made
+    pub uri: Option<String>,
+    /// Windows that could not be captured
+    pub skipped: Vec<SkippedWindow>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct SkippedWindow {
+    pub id: u64,
+    pub message: String,
+}
+
+/// Frames of get_recent_frames, oldest first; the images follow as content
+/// unless they were saved.
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct RecentFrameList {
+    pub frames: Vec<RecentFrameInfo>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct RecentFrameInfo {
+    pub uptime_ms: u64,
+    /// How long before the call it was rendered, in seconds
+    pub age_secs: f64,
+    pub width: u32,
+    pub height: u32,
+    /// Where it was saved, if a directory was given
+    pub path: Option<String>,
+}
+
+/// A recording or GIF capture, as returned by stop_recording and capture_gif.
+/// Only the path is set while capture_gif keeps capturing.
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct RecordingResult {
+    pub path: String,
+    /// The MCP resource holding the finished file
+    pub uri: Option<String>,
+    pub duration_secs: Option<f64>,
+    pub frames: Option<u64>,
+    /// Frames skipped because the encoder was busy or the output was resized
+    pub dropped: Option<u64>,
+}
+
+/// How long wait_for_idle waited.
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct IdleResult {
+    /// The quiet period that was waited for
+    pub quiet_ms: u64,
+    pub waited_ms: u64,
+}
+
+/// How long wait_for_visual_stability waited.
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct StabilityResult {
+    /// The stable period that was waited for
+    pub stable_ms: u64,
+    pub waited_ms: u64,
+    /// Frames compared while waiting
+    pub frames: u32,
+}
+
+/// The data of the clipboard or primary selection, as returned by
+/// get_clipboard and get_primary_selection.
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct SelectionContent {
+    pub mime_type: String,
+    pub size_bytes: usize,
+    /// The data, if it is UTF-8 text
+    pub text: Option<String>,
+    /// The data as base64, if it is an image or not UTF-8
+    pub base64: Option<String>,
+}
+
+/// Log records of get_logs, oldest first.
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct LogList {
+    pub records: Vec<LogRecord>,
+    /// Id of the newest record, to pass as `since` when polling
+    pub last_id: u64,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct WindowList {
+    pub windows: Vec<WindowInfo>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct OutputList {
+    pub outputs: Vec<OutputInfo>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct ProcessList {
+    pub processes: Vec<ProcessInfo>,
+}
+
+/// Matches of find_image, best first; empty if there was none.
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct ImageMatches {
+    pub matches: Vec<ImageMatch>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct InstalledAppList {
+    pub apps: Vec<InstalledApp>,
+}
+
+/// Notifications of get_notifications, oldest first.
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct NotificationList {
+    pub notifications: Vec<Notification>,
+}
+
+/// The schema of a tool's structured result, if it has one. Schemas only
+/// gain fields, so clients can rely on the ones they know.
+fn output_schema(tool: &str) -> Option<Arc<JsonObject>> {
+    let schema = match tool {
+        "launch_app" | "launch_desktop_app" | "launch_flatpak" => {
+            schemars::schema_for!(LaunchResult)
+        }
+        "restart_app" => schemars::schema_for!(RestartResult),
+        "kill_app" => schemars::schema_for!(SignalResult),
+        "get_app_output" => schemars::schema_for!(AppOutput),
+        "screenshot" | "screenshot_window" => schemars::schema_for!(ScreenshotResult),
+        "screenshot_all_windows" => schemars::schema_for!(WindowCaptures),
+        "get_recent_frames" => schemars::schema_for!(RecentFrameList),
+        "stop_recording" | "capture_gif" => schemars::schema_for!(RecordingResult),
+        "list_windows" => schemars::schema_for!(WindowList),
+        "wait_for_window" | "move_window" | "resize_window" | "maximize_window"
+        | "fullscreen_window" | "restore_window" => schemars::schema_for!(WindowInfo),
+        "focus_window" => schemars::schema_for!(WindowLabel),
+        "wait_for_idle" => schemars::schema_for!(IdleResult),
+        "wait_for_visual_stability" => schemars::schema_for!(StabilityResult),
+        "get_window_geometry" => schemars::schema_for!(WindowGeometry),
+        "list_outputs" => schemars::schema_for!(OutputList),
+        "list_processes" => schemars::schema_for!(ProcessList),
+        "get_input_state" => schemars::schema_for!(InputState),
+        "get_frame_timing" => schemars::schema_for!(FrameTimingInfo),
+        "find_image" => schemars::schema_for!(ImageMatches),
+        "run_command" => schemars::schema_for!(CommandOutput),
+        "get_stats" => schemars::schema_for!(StatsInfo),
+        "get_pixel" => schemars::schema_for!(PixelProbe),
+        "compare_screenshot" => schemars::schema_for!(Comparison),
+        "read_text" => schemars::schema_for!(OcrResult),
+        "dump_state" => schemars::schema_for!(StateDump),
+        "list_installed_apps" => schemars::schema_for!(InstalledAppList),
+        "get_notifications" => schemars::schema_for!(NotificationList),
+        "get_clipboard" | "get_primary_selection" => schemars::schema_for!(SelectionContent),
+        "get_logs" => schemars::schema_for!(LogList),
+        "query_accessibility_tree" => schemars::schema_for!(AccessibleNode),
+        _ => return None,
+    };
+    match serde_json::to_value(schema) {
+        Ok(serde_json::Value::Object(schema)) => Some(Arc::new(schema)),
+        _ => None,
+    }
+}
+
+/// Adds the output schemas of tools with structured results for tools/list.
+pub fn add_output_schemas(mut tools: Vec<Tool>) -> Vec<Tool> {
+    for tool in &mut tools {
+        if let Some(schema) = output_schema(&tool.name) {
+            tool.output_schema = Some(schema);
+        }
+    }
+    tools
+}
+
+/// A successful result with `value` as its structured content, and `content`
+/// for clients that only read text and images.
+pub fn success<T: Serialize>(content: Vec<Content>, value: &T) -> Result<CallToolResult, McpError> {
+    let value = serde_json::to_value(value).map_err(|e| {
+        McpError::internal_error(format!("Failed to serialize result: {}", e), None)
+    })?;
+    let mut result = CallToolResult::success(content);
+    result.structured_content = Some(value);
+    Ok(result)
+}
+
+/// A successful result with `value` as its structured content and as
+/// pretty-printed JSON text.
+pub fn json<T: Serialize>(value: &T) -> Result<CallToolResult, McpError> {
+    let text = serde_json::to_string_pretty(value).map_err(|e| {
+        McpError::internal_error(format!("Failed to serialize result: {}", e), None)
+    })?;
+    success(vec![Content::text(text)], value)
+}
@@ -0,0 +1,154 @@
+use std::time::{Duration, Instant};
+
+use smithay::{
+    backend::input::TabletToolType,
+    utils::{Logical, Point, SERIAL_COUNTER},
+    wayland::tablet_manager::{
+        TabletDescriptor, TabletHandle, TabletSeatTrait, TabletToolCapabilities,
+        TabletToolDescriptor, TabletToolHandle,
+    },
+};
+
+use crate::{
+    input::{segment_at, PointerTarget},
+    Smallvil,
+};
+
+/// A point of a pen stroke.
+#[derive(Debug, Clone, Copy)]
+pub struct PenPoint {
+    pub location: Point<f64, Logical>,
+    /// 0.0 to 1.0
+    pub pressure: f64,
+    /// Tilt in degrees along the x and y axes
+    pub tilt: (f64, f64),
+}
+
+/// A pen stroke played back over several frames: the pen comes into
+/// proximity and touches down at the first point, follows the rest with the
+/// pressure and tilt interpolated between points, then lifts and leaves.
+pub struct PenStroke {
+    pub points: Vec<PenPoint>,
+    pub duration: Duration,
+    pub started: Instant,
+    pub response_tx: tokio::sync::oneshot::Sender<Result<PointerTarget, String>>,
+}
+
+impl PenStroke {
+    /// The pen's state after `progress` (0.0 to 1.0) of the stroke's length.
+    fn point_at(&self, progress: f64) -> PenPoint {
+        let path: Vec<_> = self.points.iter().map(|point| point.location).collect();
+        let (segment, t) = segment_at(&path, progress);
+        let start = self.points[segment];
+        let Some(end) = self.points.get(segment + 1) else {
+            return start;
+        };
+
+        let lerp = |a: f64, b: f64| a + (b - a) * t;
+        PenPoint {
+            location: start.location + (end.location - start.location).upscale(t),
+            pressure: lerp(start.pressure, end.pressure),
+            tilt: (
+                lerp(start.tilt.0, end.tilt.0),
+                lerp(start.tilt.1, end.tilt.1),
+            ),
+        }
+    }
+}
+
+impl Smallvil {
+    /// The virtual tablet and its pen, created the first time they are used.
+    fn virtual_pen(&mut self) -> (TabletHandle, TabletToolHandle) {
+        let tablet_seat = self.seat.tablet_seat();
+        let dh = self.display_handle.clone();
+
+        let tablet = tablet_seat.add_tablet::<Self>(
+            &dh,
+            &TabletDescriptor {
+                name: "mcpvil virtual tablet".to_string(),
+                usb_id: None,
+                syspath: None,
+            },
+        );
+        let tool = tablet_seat.add_tool::<Self>(
+            self,
+            &dh,
+            &TabletToolDescriptor {
+                tool_type: TabletToolType::Pen,
+                hardware_serial: 1,
+                hardware_id_wacom: 0,
+                capabilities: TabletToolCapabilities::PRESSURE | TabletToolCapabilities::TILT,
+            },
+        );
+        (tablet, tool)
+    }
+
+    /// Brings the pen into proximity at the first point of `stroke` and puts it
+    /// down there. The rest is played back by `advance_pen_stroke` on each redraw.
+    pub fn start_pen_stroke(&mut self, stroke: PenStroke) {
+        let (tablet, tool) = self.virtual_pen();
+        let start = stroke.points[0];
+        let time = self.start_time.elapsed().as_millis() as u32;
+
+        let Some(under) = self.surface_under(start.location) else {
+            let _ = stroke.response_tx.send(Err(format!(
+                "No surface at ({}, {}) to draw on",
+                start.location.x, start.location.y
+            )));
+            return;
+        };
+        self.focus_at(start.location, SERIAL_COUNTER.next_serial());
+
+        tool.proximity_in(
+            start.location,
+            under,
+            &tablet,
+            SERIAL_COUNTER.next_serial(),
+            time,
+        );
+        tool.pressure(start.pressure);
+        tool.tilt(start.tilt);
+        tool.tip_down(SERIAL_COUNTER.next_serial(), time);
+        tool.frame(time);
+
+        self.pending_pen_stroke = Some(stroke);
+    }
+
+    /// Moves the pen along an in-progress stroke according to the elapsed
+    /// time, and lifts it once the stroke's duration has passed.
+    pub fn advance_pen_stroke(&mut self) {
+        let Some(stroke) = self.pending_pen_stroke.as_ref() else {
+            return;
+        };
+
+        let progress = if stroke.duration.is_zero() {
+            1.0
+        } else {
+            (stroke.started.elapsed().as_secs_f64() / stroke.duration.as_secs_f64()).min(1.0)
+        };
+        let point = stroke.point_at(progress);
+
+        let (tablet, tool) = self.virtual_pen();
+        let time = self.start_time.elapsed().as_millis() as u32;
+        let under = self.surface_under(point.location);
+        tool.motion(
+            point.location,
+            under,
+            &tablet,
+            SERIAL_COUNTER.next_serial(),
+            time,
+        );
+        tool.pressure(point.pressure);
+        tool.tilt(point.tilt);
+        tool.frame(time);
+
+        if progress >= 1.0 {
+            let stroke = self.pending_pen_stroke.take().unwrap();
+            tool.tip_up(time);
+            tool.frame(time);
+            tool.proximity_out(time);
+            tool.frame(time);
+            let _ = stroke.response_tx.send(Ok(self.target_at(point.location)));
+        }
+    }
+}
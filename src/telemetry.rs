@@ -0,0 +1,151 @@
+//! Tracing across the calloop boundary, and, with the `otel` feature, export
+//! of the spans to an OpenTelemetry collector. Every MCP tool call runs in a
+//! `tool_call` span; commands carry it into the event loop, where a
+//! `handle_command` span nests under it, and captures done in a later frame
+//! nest under that, so a trace shows the whole request. Commands also carry
+//! the tool call they were sent for, and are skipped if it was abandoned
+//! before the event loop got to them.
+
+use std::sync::mpsc::SendError;
+
+use smithay::reexports::calloop::channel::{self, Channel, Event};
+use tracing::Span;
+
+use crate::{cancel::ToolCall, CalloopData, McpCommand};
+
+/// Sends commands into the event loop along with the span and tool call they
+/// were sent in.
+#[derive(Debug, Clone)]
+pub struct CommandSender(channel::Sender<(Span, ToolCall, McpCommand)>);
+
+impl CommandSender {
+    pub fn send(&self, command: McpCommand) -> Result<(), SendError<McpCommand>> {
+        self.0
+            .send((Span::current(), ToolCall::current(), command))
+            .map_err(|SendError((_, _, command))| SendError(command))
+    }
+}
+
+/// The channel MCP servers send commands into the event loop through.
+pub fn command_channel() -> (CommandSender, Channel<(Span, ToolCall, McpCommand)>) {
+    let (command_tx, command_rx) = channel::channel();
+    (CommandSender(command_tx), command_rx)
+}
+
+/// Wraps the event loop's command handler, running it in a span under the
+/// one each command was sent in. Commands of abandoned tool calls are
+/// dropped unhandled.
+pub fn traced<F>(
+    mut handle: F,
+) -> impl FnMut(Event<(Span, ToolCall, McpCommand)>, &mut (), &mut CalloopData)
+where
+    F: FnMut(Event<McpCommand>, &mut (), &mut CalloopData),
+{
+    move |event, metadata, data| match event {
+        Event::Msg((span, call, command)) => {
+            let _span =
+                tracing::info_span!(parent: &span, "handle_command", command = ?command).entered();
+            if call.is_abandoned() {
+                tracing::debug!("Skipped, the tool call was abandoned");
+                return;
+            }
+            handle(Event::Msg(command), metadata, data)
+        }
+        Event::Closed => handle(Event::Closed, metadata, data),
+    }
+}
+
+/// The span a tool call runs in. With the `otel` feature, a W3C
+/// `traceparent` in the request's `_meta` makes it part of the caller's trace.
+pub fn tool_call_span(tool: &str, client: &str, meta: &rmcp::model::Meta) -> Span {
+    let span = tracing::info_span!("tool_call", tool, client);
+    #[cfg(feature = "otel")]
+    otel::set_remote_parent(&span, meta);
+    #[cfg(not(feature = "otel"))]
+    let _ = meta;
+    span
+}
+
+#[cfg(feature = "otel")]
+pub use otel::{layer, shutdown};
+
+/// Built without the `otel` feature, spans stay local.
+#[cfg(not(feature = "otel"))]
+pub fn layer() -> Option<tracing_subscriber::layer::Identity> {
+    None
+}
+
+#[cfg(not(feature = "otel"))]
+pub fn shutdown() {}
+
+#[cfg(feature = "otel")]
+mod otel {
+    use std::{collections::HashMap, sync::OnceLock};
+
+    use opentelemetry::{propagation::TextMapPropagator, trace::TracerProvider as _};
+    use opentelemetry_sdk::{
+        propagation::TraceContextPropagator,
+        trace::{SdkTracer, SdkTracerProvider},
+        Resource,
+    };
+    use tracing::{Span, Subscriber};
+    use tracing_opentelemetry::{OpenTelemetryLayer, OpenTelemetrySpanExt};
+    use tracing_subscriber::registry::LookupSpan;
+
+    static PROVIDER: OnceLock<SdkTracerProvider> = OnceLock::new();
+
+    /// A layer exporting spans over OTLP/HTTP, if a collector is configured
+    /// through OTEL_EXPORTER_OTLP_ENDPOINT or OTEL_EXPORTER_OTLP_TRACES_ENDPOINT.
+    /// The other standard OTEL_* variables apply as well.
+    pub fn layer<S>() -> Option<OpenTelemetryLayer<S, SdkTracer>>
+    where
+        S: Subscriber + for<'span> LookupSpan<'span>,
+    {
+        let configured = [
+            "OTEL_EXPORTER_OTLP_ENDPOINT",
+            "OTEL_EXPORTER_OTLP_TRACES_ENDPOINT",
+        ]
+        .iter()
+        .any(|name| std::env::var_os(name).is_some_and(|value| !value.is_empty()));
+        if !configured {
+            return None;
+        }
+        // Logging isn't set up yet, so errors go straight to stderr
+        let exporter = match opentelemetry_otlp::SpanExporter::builder()
+            .with_http()
+            .build()
+        {
+            Ok(exporter) => exporter,
+            Err(e) => {
+                eprintln!("Failed to create the OTLP exporter: {}", e);
+                return None;
+            }
+        };
+        let provider = SdkTracerProvider::builder()
+            .with_batch_exporter(exporter)
+            .with_resource(Resource::builder().with_service_name("mcpvil").build())
+            .build();
+        let tracer = provider.tracer("mcpvil");
+        let _ = PROVIDER.set(provider);
+        Some(tracing_opentelemetry::layer().with_tracer(tracer))
+    }
+
+    /// Exports the spans still buffered; called before exiting.
+    pub fn shutdown() {
+        if let Some(provider) = PROVIDER.get() {
+            if let Err(e) = provider.shutdown() {
+                tracing::warn!("Failed to flush OpenTelemetry spans: {}", e);
+            }
+        }
+    }
+
+    pub(super) fn set_remote_parent(span: &Span, meta: &rmcp::model::Meta) {
+        let carrier: HashMap<String, String> = ["traceparent", "tracestate"]
+            .into_iter()
+            .filter_map(|key| Some((key.to_string(), meta.get(key)?.as_str()?.to_string())))
+            .collect();
+        if carrier.contains_key("traceparent") {
+            span.set_parent(TraceContextPropagator::new().extract(&carrier));
+        }
+    }
+}
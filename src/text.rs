@@ -0,0 +1,64 @@
+use font8x8::{UnicodeFonts, BASIC_FONTS, LATIN_FONTS};
+use image::{Pixel, Rgba, RgbaImage};
+
+/// Side of a glyph in the bitmap font, before scaling.
+const GLYPH_SIZE: u32 = 8;
+
+/// Width of `text` drawn at `scale`, in pixels.
+pub fn text_width(text: &str, scale: u32) -> u32 {
+    text.chars().count() as u32 * GLYPH_SIZE * scale
+}
+
+/// Height of a line of text drawn at `scale`, in pixels.
+pub fn line_height(scale: u32) -> u32 {
+    GLYPH_SIZE * scale
+}
+
+/// `text` cut to at most `width` pixels at `scale`, ending in "..." if cut.
+pub fn truncate(text: &str, width: u32, scale: u32) -> String {
+    let fits = (width / (GLYPH_SIZE * scale)) as usize;
+    if text.chars().count() <= fits {
+        return text.to_string();
+    }
+    let kept: String = text.chars().take(fits.saturating_sub(3)).collect();
+    kept + &".".repeat(fits.min(3))
+}
+
+/// Draws `text` with its top left corner at (x, y) in an 8x8 bitmap font
+/// scaled by `scale`. Characters outside Latin-1 are drawn as '?'; anything
+/// outside the image is clipped.
+pub fn draw_text(img: &mut RgbaImage, x: i32, y: i32, text: &str, scale: u32, color: Rgba<u8>) {
+    let advance = (GLYPH_SIZE * scale) as i32;
+    for (i, c) in text.chars().enumerate() {
+        let glyph = BASIC_FONTS
+            .get(c)
+            .or_else(|| LATIN_FONTS.get(c))
+            .or_else(|| BASIC_FONTS.get('?'))
+            .unwrap_or_default();
+        let left = x + i as i32 * advance;
+        for (row, bits) in glyph.iter().enumerate() {
+            for column in 0..GLYPH_SIZE {
+                // The lowest bit is the leftmost pixel
+                if bits & (1 << column) == 0 {
+                    continue;
+                }
+                let px = left + (column * scale) as i32;
+                let py = y + (row as u32 * scale) as i32;
+                fill_rect(img, px, py, scale, scale, color);
+            }
+        }
+    }
+}
+
+/// Fills a rectangle, blending in `color` by its alpha, clipped to the image.
+pub fn fill_rect(img: &mut RgbaImage, x: i32, y: i32, width: u32, height: u32, color: Rgba<u8>) {
+    let x0 = x.clamp(0, img.width() as i32) as u32;
+    let y0 = y.clamp(0, img.height() as i32) as u32;
+    let x1 = (x + width as i32).clamp(0, img.width() as i32) as u32;
+    let y1 = (y + height as i32).clamp(0, img.height() as i32) as u32;
+    for py in y0..y1 {
+        for px in x0..x1 {
+            img.get_pixel_mut(px, py).blend(&color);
+        }
+    }
+}
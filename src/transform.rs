@@ -0,0 +1,89 @@
+use image::RgbaImage;
+use smithay::utils::{Logical, Point, Size, Transform};
+
+use crate::{state::OutputInfo, Smallvil};
+
+/// Every transform, by the name set_output_transform takes.
+const TRANSFORMS: &[(&str, Transform)] = &[
+    ("normal", Transform::Normal),
+    ("90", Transform::_90),
+    ("180", Transform::_180),
+    ("270", Transform::_270),
+    ("flipped", Transform::Flipped),
+    ("flipped-90", Transform::Flipped90),
+    ("flipped-180", Transform::Flipped180),
+    ("flipped-270", Transform::Flipped270),
+];
+
+pub fn parse_transform(name: &str) -> Result<Transform, String> {
+    TRANSFORMS
+        .iter()
+        .find(|(candidate, _)| name.eq_ignore_ascii_case(candidate))
+        .map(|(_, transform)| *transform)
+        .ok_or_else(|| {
+            format!(
+                "Unknown transform '{}'. Use normal, 90, 180, 270, flipped, flipped-90, \
+                 flipped-180 or flipped-270.",
+                name
+            )
+        })
+}
+
+pub fn transform_name(transform: Transform) -> &'static str {
+    TRANSFORMS
+        .iter()
+        .find(|(_, candidate)| *candidate == transform)
+        .map_or("normal", |(name, _)| name)
+}
+
+/// The transform that applies `first`, then `second`. Worked out by mapping a
+/// point, so it agrees with how smithay applies transforms.
+pub fn compose(first: Transform, second: Transform) -> Transform {
+    let area = Size::<i32, Logical>::from((10, 10));
+    let point = Point::<i32, Logical>::from((1, 2));
+    let expected = second.transform_point_in(first.transform_point_in(point, &area), &area);
+    TRANSFORMS
+        .iter()
+        .map(|(_, transform)| *transform)
+        .find(|transform| transform.transform_point_in(point, &area) == expected)
+        .unwrap_or(Transform::Normal)
+}
+
+/// Turns a frame read back from an output with `transform` the right way up,
+/// as it looks on the rotated screen.
+pub fn upright(img: &RgbaImage, transform: Transform) -> RgbaImage {
+    let size = transform
+        .invert()
+        .transform_size(Size::<u32, Logical>::from((img.width(), img.height())));
+    let last = Size::<u32, Logical>::from((size.w.max(1) - 1, size.h.max(1) - 1));
+    RgbaImage::from_fn(size.w, size.h, |x, y| {
+        let source = transform.transform_point_in(Point::from((x, y)), &last);
+        *img.get_pixel(source.x, source.y)
+    })
+}
+
+impl Smallvil {
+    /// Rotates or flips an output, as if the screen were turned. Clients are
+    /// told through wl_output, and windows filling the output are resized to
+    /// its new logical size.
+    pub fn set_output_transform(
+        &mut self,
+        name: Option<&str>,
+        transform: Transform,
+    ) -> Result<OutputInfo, String> {
+        let output = match name {
+            Some(name) => self.output_by_name(name),
+            None => self.primary_output(),
+        }
+        .cloned()
+        .ok_or_else(|| format!("No output named '{}'", name.unwrap_or_default()))?;
+
+        output.change_current_state(None, Some(transform), None, None);
+        crate::render::arrange_outputs(&mut self.space);
+        self.refit_windows(&output);
+        self.send_surface_scales();
+        self.request_frame();
+
+        self.output_info(&output)
+    }
+}
@@ -1,63 +1,66 @@
-use std::io::Cursor;
-use std::time::Duration;
+use std::{cell::RefCell, rc::Rc};
 
-use base64::Engine;
-use smithay::backend::allocator::Fourcc;
 use smithay::{
-    backend::{
-        renderer::{
-            damage::OutputDamageTracker,
-            element::surface::WaylandSurfaceRenderElement,
-            gles::{GlesRenderer, GlesTarget},
-            ExportMem, Texture,
-        },
-        winit::{self, WinitEvent},
-    },
-    output::{Mode, Output, PhysicalProperties, Subpixel},
-    reexports::calloop::EventLoop,
-    utils::{Rectangle, Transform},
+    backend::winit::{self, WinitEvent},
+    reexports::calloop::{ping, EventLoop},
+    utils::{Physical, Rectangle, Size, Transform},
 };
 
-use crate::{CalloopData, Smallvil};
+use crate::{
+    render::{self, HostOutput, VirtualOutput},
+    CalloopData,
+};
 
+/// Initializes the winit backend. The window shows the primary output; each
+/// entry in `extra_outputs` adds a virtual output rendered offscreen next to it.
 pub fn init_winit(
     event_loop: &mut EventLoop<CalloopData>,
     data: &mut CalloopData,
+    extra_outputs: &[Size<i32, Physical>],
 ) -> Result<(), Box<dyn std::error::Error>> {
     let display_handle = &mut data.display_handle;
     let state = &mut data.state;
 
     let (mut backend, winit) = winit::init()?;
-
-    let mode = Mode {
-        size: backend.window_size(),
-        refresh: 60_000,
-    };
-
-    let output = Output::new(
-        "winit".to_string(),
-        PhysicalProperties {
-            size: (0, 0).into(),
-            subpixel: Subpixel::Unknown,
-            make: "Smithay".into(),
-            model: "Winit".into(),
-        },
-    );
-    let _global = output.create_global::<Smallvil>(display_handle);
-    output.change_current_state(
-        Some(mode),
-        Some(Transform::Flipped180),
-        None,
-        Some((0, 0).into()),
+    render::init_dmabuf(state, backend.renderer());
+
+    // Redraws are only requested while frames are due, under the frame limit
+    let (waker, wake_source) = ping::make_ping()?;
+    state.frame_pacing.waker = Some(waker);
+
+    let mut host = HostOutput::new(
+        state,
+        display_handle,
+        "winit",
+        "Winit",
+        backend.window_size(),
+        Transform::Flipped180,
     );
-    output.set_preferred(mode);
 
-    state.space.map_output(&output, (0, 0));
-
-    let mut damage_tracker = OutputDamageTracker::from_output(&output);
+    let mut virtual_outputs = extra_outputs
+        .iter()
+        .enumerate()
+        .map(|(i, size)| {
+            VirtualOutput::new(
+                backend.renderer(),
+                state,
+                display_handle,
+                format!("virtual-{}", i + 1),
+                *size,
+            )
+        })
+        .collect::<Result<Vec<_>, _>>()?;
 
     std::env::set_var("WAYLAND_DISPLAY", &state.socket_name);
 
+    let backend = Rc::new(RefCell::new(backend));
+    let wake_backend = backend.clone();
+    event_loop
+        .handle()
+        .insert_source(wake_source, move |_, _, _| {
+            wake_backend.borrow().window().request_redraw();
+        })?;
+
     event_loop
         .handle()
         .insert_source(winit, move |event, _, data| {
@@ -65,82 +68,45 @@ pub fn init_winit(
             let state = &mut data.state;
 
             match event {
-                WinitEvent::Resized { size, .. } => {
-                    output.change_current_state(
-                        Some(Mode {
-                            size,
-                            refresh: 60_000,
-                        }),
-                        None,
-                        None,
-                        None,
-                    );
-                }
+                WinitEvent::Resized { size, .. } => host.resize(state, size),
                 WinitEvent::Input(event) => state.process_input_event(event),
+                // Idle or in lockstep; request_frame wakes the window again
+                WinitEvent::Redraw if !state.frame_due() => {}
                 WinitEvent::Redraw => {
+                    let mut backend = backend.borrow_mut();
+                    render::start_frame(state);
+
+                    // The mode was changed with set_output_mode; resize the window to match.
+                    // Until the compositor hosting us resizes it, the old size is rendered.
+                    if let Some(mode) = host.output.current_mode() {
+                        if mode.size != backend.window_size() {
+                            let _ = backend.window().request_inner_size(
+                                smithay::reexports::winit::dpi::PhysicalSize::new(
+                                    mode.size.w as u32,
+                                    mode.size.h as u32,
+                                ),
+                            );
+                        }
+                    }
+
                     let size = backend.window_size();
                     let damage = Rectangle::from_size(size);
 
-                    {
+                    let feedback = {
                         let (renderer, mut framebuffer) = backend.bind().unwrap();
-                        smithay::desktop::space::render_output::<
-                            _,
-                            WaylandSurfaceRenderElement<GlesRenderer>,
-                            _,
-                            _,
-                        >(
-                            &output,
-                            renderer,
-                            &mut framebuffer,
-                            1.0,
-                            0,
-                            [&state.space],
-                            &[],
-                            &mut damage_tracker,
-                            [0.1, 0.1, 0.1, 1.0],
-                        )
-                        .unwrap();
-
-                        // Handle pending screenshot
-                        if let Some((filename, response_tx)) = state.pending_screenshot.take() {
-                            let screenshot_result = take_screenshot(
-                                renderer,
-                                &framebuffer,
-                                size,
-                                &state.space,
-                                &filename,
-                            );
-                            let _ = response_tx.send(screenshot_result);
-                        }
+                        host.render(renderer, &mut framebuffer, size, 0, state)
+                    };
+                    backend.submit(Some(&[damage])).unwrap();
+                    if let Some(feedback) = feedback {
+                        state.present_frame(&host.output, feedback);
+                    }
 
-                        // Handle pending capture_screenshot
-                        if let Some(response_tx) = state.pending_capture_screenshot.take() {
-                            let capture_result = capture_screenshot(
-                                renderer,
-                                &framebuffer,
-                                size,
-                                &state.space,
-                            );
-                            let _ = response_tx.send(capture_result);
-                        }
+                    for virtual_output in &mut virtual_outputs {
+                        virtual_output.render(backend.renderer(), state);
                     }
-                    backend.submit(Some(&[damage])).unwrap();
 
-                    state.space.elements().for_each(|window| {
-                        window.send_frame(
-                            &output,
-                            state.start_time.elapsed(),
-                            Some(Duration::ZERO),
-                            |_, _| Some(output.clone()),
-                        )
-                    });
-
-                    state.space.refresh();
-                    state.popups.cleanup();
-                    let _ = display.flush_clients();
-
-                    // Ask for redraw to schedule new frame.
-                    backend.window().request_redraw();
+                    render::finish_frame(state, display, &host.output);
+                    state.schedule_next_frame();
                 }
                 WinitEvent::CloseRequested => {
                     state.loop_signal.stop();
@@ -151,101 +117,3 @@ pub fn init_winit(
 
     Ok(())
 }
-
-fn take_screenshot(
-    renderer: &mut GlesRenderer,
-    framebuffer: &GlesTarget<'_>,
-    size: smithay::utils::Size<i32, smithay::utils::Physical>,
-    space: &smithay::desktop::Space<smithay::desktop::Window>,
-    filename: &str,
-) -> Result<String, String> {
-    let region = Rectangle::from_size((size.w, size.h).into());
-
-    let mapping = renderer
-        .copy_framebuffer(framebuffer, region, Fourcc::Abgr8888)
-        .map_err(|e| format!("Failed to copy framebuffer: {}", e))?;
-
-    let pixels = renderer
-        .map_texture(&mapping)
-        .map_err(|e| format!("Failed to map texture: {}", e))?;
-
-    let width = mapping.width();
-    let height = mapping.height();
-
-    // Create image from raw pixels and flip vertically
-    // (OpenGL framebuffer origin is bottom-left)
-    let mut img = image::RgbaImage::from_raw(width, height, pixels.to_vec())
-        .ok_or_else(|| "Failed to create image from pixel data".to_string())?;
-    image::imageops::flip_vertical_in_place(&mut img);
-
-    // Crop to the first window's bounds if one exists
-    let img: image::DynamicImage = if let Some(window) = space.elements().next() {
-        if let Some(geo) = space.element_geometry(window) {
-            let x = geo.loc.x.max(0) as u32;
-            let y = geo.loc.y.max(0) as u32;
-            let w = (geo.size.w as u32).min(width.saturating_sub(x));
-            let h = (geo.size.h as u32).min(height.saturating_sub(y));
-            image::DynamicImage::ImageRgba8(img).crop_imm(x, y, w, h)
-        } else {
-            image::DynamicImage::ImageRgba8(img)
-        }
-    } else {
-        image::DynamicImage::ImageRgba8(img)
-    };
-
-    img.save(filename)
-        .map_err(|e| format!("Failed to save screenshot: {}", e))?;
-
-    Ok(format!(
-        "Screenshot saved to {} ({}x{})",
-        filename,
-        img.width(),
-        img.height()
-    ))
-}
-
-fn capture_screenshot(
-    renderer: &mut GlesRenderer,
-    framebuffer: &GlesTarget<'_>,
-    size: smithay::utils::Size<i32, smithay::utils::Physical>,
-    space: &smithay::desktop::Space<smithay::desktop::Window>,
-) -> Result<(String, u32, u32), String> {
-    let region = Rectangle::from_size((size.w, size.h).into());
-
-    let mapping = renderer
-        .copy_framebuffer(framebuffer, region, Fourcc::Abgr8888)
-        .map_err(|e| format!("Failed to copy framebuffer: {}", e))?;
-
-    let pixels = renderer
-        .map_texture(&mapping)
-        .map_err(|e| format!("Failed to map texture: {}", e))?;
-
-    let width = mapping.width();
-    let height = mapping.height();
-
-    let mut img = image::RgbaImage::from_raw(width, height, pixels.to_vec())
-        .ok_or_else(|| "Failed to create image from pixel data".to_string())?;
-    image::imageops::flip_vertical_in_place(&mut img);
-
-    let img: image::DynamicImage = if let Some(window) = space.elements().next() {
-        if let Some(geo) = space.element_geometry(window) {
-            let x = geo.loc.x.max(0) as u32;
-            let y = geo.loc.y.max(0) as u32;
-            let w = (geo.size.w as u32).min(width.saturating_sub(x));
-            let h = (geo.size.h as u32).min(height.saturating_sub(y));
-            image::DynamicImage::ImageRgba8(img).crop_imm(x, y, w, h)
-        } else {
-            image::DynamicImage::ImageRgba8(img)
-        }
-    } else {
-        image::DynamicImage::ImageRgba8(img)
-    };
-
-    let mut buf = Cursor::new(Vec::new());
-    img.write_to(&mut buf, image::ImageFormat::Png)
-        .map_err(|e| format!("Failed to encode PNG: {}", e))?;
-
-    let base64_data = base64::engine::general_purpose::STANDARD.encode(buf.into_inner());
-
-    Ok((base64_data, img.width(), img.height()))
-}
@@ -0,0 +1,131 @@
+use std::collections::HashSet;
+
+use smithay::{
+    backend::{
+        allocator::{
+            dmabuf::DmabufAllocator,
+            gbm::{GbmAllocator, GbmBufferFlags, GbmDevice},
+        },
+        egl::{EGLContext, EGLDisplay},
+        renderer::{gles::GlesRenderer, Bind},
+        x11::{WindowBuilder, X11Backend, X11Event},
+    },
+    reexports::calloop::EventLoop,
+    utils::{DeviceFd, Physical, Size, Transform},
+};
+
+use crate::{
+    render::{self, HostOutput, VirtualOutput},
+    CalloopData,
+};
+
+/// Initializes the X11 backend, for hosts running an X11 session. Like winit,
+/// a window shows the primary output, and each entry in `extra_outputs` adds a
+/// virtual output rendered offscreen next to it.
+pub fn init_x11(
+    event_loop: &mut EventLoop<CalloopData>,
+    data: &mut CalloopData,
+    extra_outputs: &[Size<i32, Physical>],
+) -> Result<(), Box<dyn std::error::Error>> {
+    let display_handle = &mut data.display_handle;
+    let state = &mut data.state;
+
+    let backend = X11Backend::new()?;
+    let handle = backend.handle();
+
+    // Buffers are allocated on the GPU the X server renders with and presented with DRI3
+    let (_node, fd) = handle.drm_node()?;
+    let device = GbmDevice::new(DeviceFd::from(fd))?;
+    let display = unsafe { EGLDisplay::new(device.clone())? };
+    let context = EGLContext::new(&display)?;
+    let modifiers: HashSet<_> = context
+        .dmabuf_render_formats()
+        .iter()
+        .map(|format| format.modifier)
+        .collect();
+
+    let window = WindowBuilder::new().title("MCPvil").build(&handle)?;
+    let mut surface = handle.create_surface(
+        &window,
+        DmabufAllocator(GbmAllocator::new(device, GbmBufferFlags::RENDERING)),
+        modifiers.into_iter(),
+    )?;
+
+    let mut renderer = unsafe { GlesRenderer::new(context)? };
+    render::init_dmabuf(state, &renderer);
+
+    let window_size = window.size();
+    let mut size: Size<i32, Physical> = (window_size.w as i32, window_size.h as i32).into();
+    let mut host = HostOutput::new(state, display_handle, "x11", "X11", size, Transform::Normal);
+
+    let mut virtual_outputs = extra_outputs
+        .iter()
+        .enumerate()
+        .map(|(i, size)| {
+            VirtualOutput::new(
+                &mut renderer,
+                state,
+                display_handle,
+                format!("virtual-{}", i + 1),
+                *size,
+            )
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    std::env::set_var("WAYLAND_DISPLAY", &state.socket_name);
+
+    event_loop
+        .handle()
+        .insert_source(backend, move |event, _, data| {
+            let display = &mut data.display_handle;
+            let state = &mut data.state;
+
+            match event {
+                X11Event::Resized { new_size, .. } => {
+                    size = (new_size.w as i32, new_size.h as i32).into();
+                    host.resize(state, size);
+                }
+                X11Event::Input { event, .. } => state.process_input_event(event),
+                // Exposure, or the last frame is on screen: render the next one
+                X11Event::Refresh { .. } | X11Event::PresentCompleted { .. } => {
+                    render::start_frame(state);
+
+                    let (mut buffer, age) = match surface.buffer() {
+                        Ok(buffer) => buffer,
+                        Err(e) => {
+                            tracing::error!("Failed to allocate an X11 buffer: {}", e);
+                            return;
+                        }
+                    };
+                    let feedback = match renderer.bind(&mut buffer) {
+                        Ok(mut framebuffer) => {
+                            host.render(&mut renderer, &mut framebuffer, size, age as usize, state)
+                        }
+                        Err(e) => {
+                            tracing::error!("Failed to bind X11 buffer: {}", e);
+                            None
+                        }
+                    };
+                    if let Err(e) = surface.submit() {
+                        tracing::error!("Failed to present X11 buffer: {}", e);
+                    }
+                    if let Some(feedback) = feedback {
+                        state.present_frame(&host.output, feedback);
+                    }
+
+                    for virtual_output in &mut virtual_outputs {
+                        virtual_output.render(&mut renderer, state);
+                    }
+
+                    render::finish_frame(state, display, &host.output);
+                }
+                // Also keeps the window alive, which is destroyed with its last handle
+                X11Event::CloseRequested { window_id } if window_id == window.id() => {
+                    state.loop_signal.stop()
+                }
+                _ => {}
+            }
+        })?;
+
+    Ok(())
+}